@@ -0,0 +1,73 @@
+//
+// Copyright (c) 2022 ZettaScale Technology
+//
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+// Contributors:
+//   ZettaScale Zenoh Team, <zenoh@zettascale.tech>
+//
+use std::fmt;
+
+/// The hint ending the message of a RetryLater error, which identifies it across the library boundary
+pub const RETRY_LATER_HINT: &str = "(retry later)";
+
+/// Error returned by a File System Storage when it's temporarily unable to perform an operation
+/// (e.g. its directory is being replaced, or its disk is full), and the operation might succeed
+/// if retried later. Its message contains RETRY_LATER_HINT: the storage manager, which loads the backend as
+/// a separate library, can't reliably downcast it, but can detect it with `is_retry_later()`.
+#[derive(Debug)]
+pub struct RetryLater {
+    pub reason: String,
+}
+
+impl RetryLater {
+    pub(crate) fn new(reason: String) -> Self {
+        RetryLater { reason }
+    }
+}
+
+impl fmt::Display for RetryLater {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.reason, RETRY_LATER_HINT)
+    }
+}
+
+impl std::error::Error for RetryLater {}
+
+/// Check if an error returned by a File System Storage is a RetryLater error (or contains one in its message)
+pub fn is_retry_later(error: &dyn fmt::Display) -> bool {
+    error.to_string().contains(RETRY_LATER_HINT)
+}
+
+// Check if an IO error is due to a full disk
+pub(crate) fn is_disk_full(e: &std::io::Error) -> bool {
+    match e.raw_os_error() {
+        #[cfg(unix)]
+        Some(28) => true, // ENOSPC
+        #[cfg(windows)]
+        Some(39) | Some(112) => true, // ERROR_HANDLE_DISK_FULL, ERROR_DISK_FULL
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zenoh_core::zerror;
+
+    #[test]
+    fn retry_later_errors_are_detected_by_their_message() {
+        let error: zenoh_core::Error = RetryLater::new("disk full".to_string()).into();
+        assert!(is_retry_later(&error));
+        // (also once wrapped in another error)
+        let wrapped: zenoh_core::Error = zerror!("PUT on a/b failed: {}", error).into();
+        assert!(is_retry_later(&wrapped));
+        let other: zenoh_core::Error = zerror!("PUT on a/b failed").into();
+        assert!(!is_retry_later(&other));
+    }
+}
@@ -0,0 +1,204 @@
+//
+// Copyright (c) 2022 ZettaScale Technology
+//
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+// Contributors:
+//   ZettaScale Zenoh Team, <zenoh@zettascale.tech>
+//
+use std::fs::{read_to_string, remove_file};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use zenoh::buffers::reader::HasReader;
+use zenoh::buffers::{WBuf, ZBuf};
+use zenoh::time::Timestamp;
+use zenoh::Result as ZResult;
+use zenoh_core::{bail, zerror};
+use zenoh_protocol::io::{WBufCodec, ZBufCodec};
+
+// Names of the journal of the bulk deletion in progress in a storage directory (its timestamp and keys),
+// and of its cursor (the number of keys processed so far)
+const JOURNAL_FILENAME: &str = ".zenoh_bulk_delete";
+const CURSOR_FILENAME: &str = ".zenoh_bulk_delete_cursor";
+
+// The journal of a bulk deletion: the keys (zenoh paths, without the strip_prefix) deleted as DELETE with
+// a same timestamp, by batches. It's written before the first batch and the cursor is updated after each batch,
+// so an interrupted bulk deletion (e.g. by a crash) is resumed from its last batch when the storage is created.
+pub(crate) struct BulkDeleteJournal {
+    journal_file: PathBuf,
+    cursor_file: PathBuf,
+    pub(crate) timestamp: Timestamp,
+    pub(crate) zpaths: Vec<String>,
+    // number of keys processed (i.e. the first keys to skip when resumed)
+    pub(crate) processed: usize,
+}
+
+impl BulkDeleteJournal {
+    // Write the journal of a new bulk deletion in a storage directory
+    pub(crate) fn create(
+        base_dir: &Path,
+        timestamp: Timestamp,
+        zpaths: Vec<String>,
+    ) -> ZResult<Self> {
+        let journal = BulkDeleteJournal {
+            journal_file: base_dir.join(JOURNAL_FILENAME),
+            cursor_file: base_dir.join(CURSOR_FILENAME),
+            timestamp,
+            zpaths,
+            processed: 0,
+        };
+        if journal.journal_file.exists() {
+            bail!(
+                "A bulk deletion is already in progress in {:?} (journal {:?})",
+                base_dir,
+                journal.journal_file
+            )
+        }
+        let mut buf = WBuf::new(64, true);
+        let mut write_ok =
+            buf.write_timestamp(&journal.timestamp) && buf.write_zint(journal.zpaths.len() as u64);
+        for zpath in &journal.zpaths {
+            write_ok = write_ok && buf.write_string(zpath);
+        }
+        if !write_ok {
+            bail!("Failed to encode bulk deletion journal")
+        }
+        // the cursor of a previous bulk deletion might be left if interrupted while completing
+        let _ = remove_file(&journal.cursor_file);
+        write_synced(&journal.journal_file, buf.get_first_slice(..))?;
+        Ok(journal)
+    }
+
+    // Read the journal of the bulk deletion in progress in a storage directory, if any
+    pub(crate) fn open(base_dir: &Path) -> ZResult<Option<Self>> {
+        let journal_file = base_dir.join(JOURNAL_FILENAME);
+        if !journal_file.exists() {
+            return Ok(None);
+        }
+        let bytes = std::fs::read(&journal_file).map_err(|e| {
+            zerror!(
+                "Failed to read bulk deletion journal {:?}: {}",
+                journal_file,
+                e
+            )
+        })?;
+        let buf = ZBuf::from(bytes);
+        let mut reader = buf.reader();
+        let timestamp = reader.read_timestamp();
+        let count = reader.read_zint();
+        let (timestamp, count) = match (timestamp, count) {
+            (Some(timestamp), Some(count)) => (timestamp, count),
+            _ => bail!("Failed to decode bulk deletion journal {:?}", journal_file),
+        };
+        let mut zpaths = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            match reader.read_string() {
+                Some(zpath) => zpaths.push(zpath),
+                None => bail!("Failed to decode bulk deletion journal {:?}", journal_file),
+            }
+        }
+        let cursor_file = base_dir.join(CURSOR_FILENAME);
+        let processed = read_to_string(&cursor_file)
+            .ok()
+            .and_then(|s| s.trim().parse::<usize>().ok())
+            .unwrap_or(0)
+            .min(zpaths.len());
+        Ok(Some(BulkDeleteJournal {
+            journal_file,
+            cursor_file,
+            timestamp,
+            zpaths,
+            processed,
+        }))
+    }
+
+    // Record that the first `processed` keys are processed
+    pub(crate) fn record_processed(&mut self, processed: usize) -> ZResult<()> {
+        write_synced(&self.cursor_file, processed.to_string().as_bytes())?;
+        self.processed = processed;
+        Ok(())
+    }
+
+    // Remove the journal of a completed bulk deletion
+    pub(crate) fn complete(self) -> ZResult<()> {
+        remove_file(&self.journal_file).map_err(|e| {
+            zerror!(
+                "Failed to remove bulk deletion journal {:?}: {}",
+                self.journal_file,
+                e
+            )
+        })?;
+        let _ = remove_file(&self.cursor_file);
+        Ok(())
+    }
+}
+
+// Write a file atomically (via a temporary file renamed), synced to the disk
+fn write_synced(file: &Path, content: &[u8]) -> ZResult<()> {
+    let dir = file.parent().unwrap_or_else(|| Path::new("."));
+    let mut tmp = tempfile::Builder::new()
+        .prefix(".zenoh_bulk_delete_")
+        .tempfile_in(dir)
+        .map_err(|e| zerror!("Failed to write {:?}: {}", file, e))?;
+    tmp.write_all(content)
+        .and_then(|()| tmp.as_file().sync_all())
+        .map_err(|e| zerror!("Failed to write {:?}: {}", file, e))?;
+    tmp.persist(file)
+        .map_err(|e| zerror!("Failed to write {:?}: {}", file, e.error))?;
+    Ok(())
+}
+
+// Progress of the bulk deletion of a storage, for its admin status
+#[derive(Default)]
+pub(crate) struct BulkDeleteProgress {
+    // set while a bulk deletion is in progress (at most one at a time)
+    pub(crate) running: AtomicBool,
+    pub(crate) total: AtomicU64,
+    // number of keys processed: deleted, skipped (modified after the bulk deletion started, or immutable),
+    // or whose deletion failed
+    pub(crate) deleted: AtomicU64,
+    pub(crate) skipped: AtomicU64,
+    pub(crate) failed: AtomicU64,
+    // the error which interrupted the last bulk deletion, if any (it's resumed when the storage is created again)
+    pub(crate) error: Mutex<Option<String>>,
+}
+
+impl BulkDeleteProgress {
+    // Start tracking a bulk deletion, or return false if one is already in progress
+    pub(crate) fn start(&self, journal: &BulkDeleteJournal) -> bool {
+        if self
+            .running
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+            .is_err()
+        {
+            return false;
+        }
+        self.total
+            .store(journal.zpaths.len() as u64, Ordering::Relaxed);
+        // (the keys processed before an interruption are counted as deleted)
+        self.deleted
+            .store(journal.processed as u64, Ordering::Relaxed);
+        self.skipped.store(0, Ordering::Relaxed);
+        self.failed.store(0, Ordering::Relaxed);
+        *self.error.lock().unwrap() = None;
+        true
+    }
+
+    pub(crate) fn status(&self) -> serde_json::Value {
+        serde_json::json!({
+            "running": self.running.load(Ordering::Relaxed),
+            "total": self.total.load(Ordering::Relaxed),
+            "deleted": self.deleted.load(Ordering::Relaxed),
+            "skipped": self.skipped.load(Ordering::Relaxed),
+            "failed": self.failed.load(Ordering::Relaxed),
+            "error": self.error.lock().unwrap().clone(),
+        })
+    }
+}
@@ -12,12 +12,18 @@
 //   ZettaScale Zenoh Team, <zenoh@zettascale.tech>
 //
 use async_std::sync::{Arc, Mutex};
+use async_std::task;
 use async_trait::async_trait;
-use log::{trace, warn};
-use rocksdb::{IteratorMode, DB};
+use rocksdb::checkpoint::Checkpoint;
+use rocksdb::{Direction, IteratorMode, Options, WriteBatch, DB};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryInto;
+use std::fs::rename;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, trace, warn};
 use zenoh::buffers::reader::HasReader;
 use zenoh::buffers::{WBuf, ZBuf};
 use zenoh::prelude::*;
@@ -27,14 +33,92 @@ use zenoh_collections::{Timed, TimedEvent, Timer};
 use zenoh_core::{bail, zerror};
 use zenoh_protocol::io::{WBufCodec, ZBufCodec};
 
+use crate::fault_injection::*;
+
 lazy_static::lazy_static! {
     static ref GC_PERIOD: Duration = Duration::new(30, 0);
     static ref MIN_DELAY_BEFORE_REMOVAL: NTP64 = NTP64::from(Duration::new(5, 0));
 }
 
+// Hash of a file's content, with the file's modification time when hashed
+// (a different modification time means the file was modified externally, and the hash is obsolete)
+pub(crate) struct ContentHash {
+    pub(crate) hash: String,
+    pub(crate) mtime: u64,
+}
+
+impl ContentHash {
+    // The identifier of the version of a file (ETag) written with this content hash at `timestamp`
+    pub(crate) fn etag(&self, timestamp: &Timestamp) -> String {
+        format!(
+            "{:x}-{}",
+            timestamp.get_time().as_u64(),
+            &self.hash[..16.min(self.hash.len())]
+        )
+    }
+}
+
+// Number of data-infos read by a scan of the whole database while holding it, before letting the other operations
+// on data-infos run (e.g. the ones of the queries)
+const SCAN_CHUNK_SIZE: usize = 1000;
+
+// Flag of the data-infos of the files with a base64-encoded content
+const FLAG_BASE64: u64 = 0x01;
+
+// Version of the encoding of the data-infos, recorded in the database:
+//  - 1: the timestamp and the encoding, optionally followed by the content hash, its mtime and the flags
+//  - 2: the version 1, optionally followed by the extras (after the flags)
+// A database without version is of version 1.
+pub(crate) const SCHEMA_VERSION: u64 = 2;
+// Key of the schema version (big-endian) in the column family of the database's metadata
+const SCHEMA_VERSION_KEY: &[u8] = b"schema_version";
+
+// Identifiers of the extras of a data-info, each encoded after its identifier
+// (the size is the one of the value put, recorded only if it differs from the size of its file, e.g. for a value
+// written base64-encoded)
+const EXTRA_SIZE: u64 = 1;
+const EXTRA_ETAG: u64 = 2;
+const EXTRA_ACCESS_COUNT: u64 = 3;
+const EXTRA_TAG: u64 = 4;
+
+// The optional information recorded in a data-info besides its timestamp, encoding, content hash (i.e. the
+// checksum of the file) and flags. They're specific to a version of the file: a PUT or DELETE resets them.
+#[derive(Default)]
+pub(crate) struct DataInfoExtras {
+    // the size of the value put, if it differs from the size of its file (e.g. written base64-encoded)
+    pub(crate) size: Option<u64>,
+    // the identifier of the version put (see ContentHash::etag()), if its content hash is known
+    pub(crate) etag: Option<String>,
+    // the number of reads of the version put
+    pub(crate) access_count: Option<u64>,
+    // custom tags, as (name, value)
+    pub(crate) tags: Vec<(String, String)>,
+}
+
+impl DataInfoExtras {
+    fn is_empty(&self) -> bool {
+        self.size.is_none()
+            && self.etag.is_none()
+            && self.access_count.is_none()
+            && self.tags.is_empty()
+    }
+}
+
+// Result of the compaction of the data-info database
+pub(crate) struct DataInfoCompaction {
+    // number of data-infos of files missing for longer than the garbage collection delay
+    pub(crate) orphans_removed: u64,
+    // number of entries removed from the index by encoding (referencing no data-info, or with another encoding)
+    pub(crate) index_entries_removed: u64,
+    pub(crate) records_kept: u64,
+}
+
 pub(crate) struct DataInfoMgr {
     // Note: rocksdb isn't thread-safe. See https://github.com/rust-rocksdb/rust-rocksdb/issues/404
-    db: Arc<Mutex<DB>>,
+    // The database is None while closed (i.e. while the storage directory is replaced)
+    db: Arc<Mutex<Option<DB>>>,
+    // set while the storage is quiesced: the garbage collection is skipped
+    paused: Arc<AtomicBool>,
     // Note: Timer is kept to not be dropped and keep the GC periodic event running
     #[allow(dead_code)]
     timer: Timer,
@@ -43,30 +127,241 @@ pub(crate) struct DataInfoMgr {
 impl DataInfoMgr {
     // Name of the RocksDB directory for the data-info database
     pub(crate) const DB_FILENAME: &'static str = ".zenoh_datainfo";
+    // Names of the RocksDB directories of a compaction: the new database being written, and the replaced one
+    const COMPACT_DB_FILENAME: &'static str = ".zenoh_datainfo_compact";
+    const REPLACED_DB_FILENAME: &'static str = ".zenoh_datainfo_replaced";
+    // Name of the column family indexing the files by encoding
+    const CF_BY_ENCODING: &'static str = "cf_by_encoding";
+    // Name of the column family recording the quarantined files, with the reason of their quarantine
+    const CF_QUARANTINE: &'static str = "cf_quarantine";
+    // Name of the column family recording the samples dropped as out-of-date ("conflict_log"),
+    // indexed by a sequence number (big-endian, i.e. in order of recording)
+    const CF_CONFLICTS: &'static str = "cf_conflicts";
+    // Name of the column family recording the keys immutable until a date ("retention" operation),
+    // with this date in milliseconds since the UNIX epoch (big-endian)
+    const CF_RETENTION: &'static str = "cf_retention";
+    // Name of the column family recording the metadata of the database (i.e. its schema version)
+    const CF_META: &'static str = "cf_meta";
 
     pub(crate) async fn new(base_dir: &Path) -> ZResult<Self> {
-        let mut backup_file = PathBuf::from(base_dir);
-        backup_file.push(DataInfoMgr::DB_FILENAME);
-
-        let db = DB::open_default(&backup_file).map_err(|e| {
-            zerror!(
-                "Failed to open data-info database from {:?}: {}",
-                backup_file,
-                e
-            )
-        })?;
-        let db = Arc::new(Mutex::new(db));
+        let db = Arc::new(Mutex::new(Some(open_db(base_dir)?)));
+        let paused = Arc::new(AtomicBool::new(false));
 
         // start periodic GC event
         let timer = Timer::default();
-        let gc = TimedEvent::periodic(*GC_PERIOD, GarbageCollectionEvent { db: db.clone() });
+        let gc = TimedEvent::periodic(
+            *GC_PERIOD,
+            GarbageCollectionEvent {
+                db: db.clone(),
+                paused: paused.clone(),
+            },
+        );
         timer.add_async(gc).await;
 
-        Ok(DataInfoMgr { db, timer })
+        Ok(DataInfoMgr { db, paused, timer })
+    }
+
+    // Pause (or resume) the garbage collection, while the storage is quiesced
+    pub(crate) fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::SeqCst);
+    }
+
+    // Flush the records of all the column families from memory to the database's files
+    pub(crate) async fn flush(&self) -> ZResult<()> {
+        let db = self.db.lock().await;
+        let db = opened(&db)?;
+        let flush_error = |e: rocksdb::Error| {
+            zerror!("Failed to flush data-info database {:?}: {}", db.path(), e)
+        };
+        db.flush().map_err(flush_error)?;
+        for cf in [
+            get_cf_by_encoding(db)?,
+            get_cf_quarantine(db)?,
+            get_cf_conflicts(db)?,
+            get_cf_retention(db)?,
+            get_cf_meta(db)?,
+        ] {
+            db.flush_cf(cf).map_err(flush_error)?;
+        }
+        Ok(())
+    }
+
+    // Close the database without destroying it (e.g. while the storage directory is replaced).
+    // Until it's re-opened, all the operations on data-infos fail.
+    pub(crate) async fn suspend(&self) {
+        if let Some(db) = self.db.lock().await.take() {
+            if let Err(e) = db.flush() {
+                warn!("Failed to flush data-info database {:?}: {}", db.path(), e);
+            }
+        }
+    }
+
+    // Re-open the database, from a storage directory that might have been replaced
+    pub(crate) async fn reopen(&self, base_dir: &Path) -> ZResult<()> {
+        let mut db = self.db.lock().await;
+        // the previous database must be closed before, as it might be the same
+        *db = None;
+        *db = Some(open_db(base_dir)?);
+        Ok(())
+    }
+
+    // Create a checkpoint of the database in a new directory (using hard links if on the same file system)
+    pub(crate) async fn backup_to(&self, dest: &Path) -> ZResult<()> {
+        let db = self.db.lock().await;
+        Checkpoint::new(opened(&db)?)
+            .and_then(|checkpoint| checkpoint.create_checkpoint(dest))
+            .map_err(|e| zerror!("Failed to backup data-info database to {:?}: {}", dest, e).into())
+    }
+
+    // Replace the database with a new one, containing the records of a checkpoint created by backup_to()
+    pub(crate) async fn restore_from(&self, base_dir: &Path, src: &Path) -> ZResult<()> {
+        // open the checkpoint in read-only mode, as it might be used for other restorations
+        let cfs = DB::list_cf(&Options::default(), src)
+            .map_err(|e| zerror!("Failed to open data-info backup from {:?}: {}", src, e))?;
+        let backup = DB::open_cf_for_read_only(&Options::default(), src, &cfs, false)
+            .map_err(|e| zerror!("Failed to open data-info backup from {:?}: {}", src, e))?;
+
+        let mut db = self.db.lock().await;
+        // the database must be closed before to be destroyed
+        *db = None;
+        let result = restore_db(base_dir, &backup);
+        match result {
+            Ok(restored) => {
+                *db = Some(restored);
+                Ok(())
+            }
+            Err(e) => {
+                // try to re-open the database (possibly partially restored) to not leave it closed
+                *db = open_db(base_dir).ok();
+                Err(e)
+            }
+        }
+    }
+
+    // Rewrite the database in a new one without the data-infos of the `removed` files, nor of the files missing
+    // for longer than the garbage collection delay, then replace the database with it. The index by encoding
+    // is re-built. The other operations on data-infos wait until the compaction is completed.
+    pub(crate) async fn compact(
+        &self,
+        base_dir: &Path,
+        removed: &HashSet<PathBuf>,
+    ) -> ZResult<DataInfoCompaction> {
+        let db_path = base_dir.join(DataInfoMgr::DB_FILENAME);
+        let compact_path = base_dir.join(DataInfoMgr::COMPACT_DB_FILENAME);
+        let replaced_path = base_dir.join(DataInfoMgr::REPLACED_DB_FILENAME);
+        let mut db = self.db.lock().await;
+
+        // write the new database
+        let compaction = {
+            let old = opened(&db)?;
+            // a leftover of an interrupted compaction
+            DB::destroy(&Options::default(), &compact_path).map_err(|e| {
+                zerror!(
+                    "Failed to destroy data-info database {:?}: {}",
+                    compact_path,
+                    e
+                )
+            })?;
+            let new = open_db_at(&compact_path)?;
+            let time_limit = NTP64::from(SystemTime::now().duration_since(UNIX_EPOCH).unwrap())
+                - *MIN_DELAY_BEFORE_REMOVAL;
+            let mut batch = WriteBatch::default();
+            let (mut records_kept, mut orphans_removed) = (0, 0);
+            for (key, value) in old.iterator(IteratorMode::Start) {
+                if let Some(path) = db_key_to_path(&key) {
+                    if removed.contains(&path) {
+                        continue;
+                    }
+                    if !path.exists()
+                        && decode_timestamp_from_value(&value)
+                            .map_or(false, |timestamp| timestamp.get_time() < &time_limit)
+                    {
+                        trace!("Cleanup old data-info for {:?}", path);
+                        orphans_removed += 1;
+                        continue;
+                    }
+                }
+                batch.put(key, value);
+                records_kept += 1;
+            }
+            for cf_name in [
+                DataInfoMgr::CF_QUARANTINE,
+                DataInfoMgr::CF_CONFLICTS,
+                DataInfoMgr::CF_RETENTION,
+                DataInfoMgr::CF_META,
+            ] {
+                if let (Some(old_cf), Some(new_cf)) =
+                    (old.cf_handle(cf_name), new.cf_handle(cf_name))
+                {
+                    for (key, value) in old.iterator_cf(old_cf, IteratorMode::Start) {
+                        batch.put_cf(new_cf, key, value);
+                    }
+                }
+            }
+            new.write(batch).map_err(|e| {
+                zerror!(
+                    "Failed to write data-info database {:?}: {}",
+                    compact_path,
+                    e
+                )
+            })?;
+            build_encoding_index(&new)?;
+            let old_index_entries = old
+                .iterator_cf(get_cf_by_encoding(old)?, IteratorMode::Start)
+                .count() as u64;
+            let new_index_entries = new
+                .iterator_cf(get_cf_by_encoding(&new)?, IteratorMode::Start)
+                .count() as u64;
+            new.flush().map_err(|e| {
+                zerror!(
+                    "Failed to flush data-info database {:?}: {}",
+                    compact_path,
+                    e
+                )
+            })?;
+            DataInfoCompaction {
+                orphans_removed,
+                index_entries_removed: old_index_entries.saturating_sub(new_index_entries),
+                records_kept,
+            }
+        };
+
+        // replace the database, closed before to be moved
+        *db = None;
+        DB::destroy(&Options::default(), &replaced_path).map_err(|e| {
+            zerror!(
+                "Failed to destroy data-info database {:?}: {}",
+                replaced_path,
+                e
+            )
+        })?;
+        if let Err(e) = rename(&db_path, &replaced_path) {
+            *db = open_db(base_dir).ok();
+            bail!("Failed to move data-info database {:?}: {}", db_path, e);
+        }
+        if let Err(e) = rename(&compact_path, &db_path) {
+            // put back the previous database
+            let _ = rename(&replaced_path, &db_path);
+            *db = open_db(base_dir).ok();
+            bail!(
+                "Failed to move data-info database {:?}: {}",
+                compact_path,
+                e
+            );
+        }
+        *db = Some(open_db(base_dir)?);
+        if let Err(e) = DB::destroy(&Options::default(), &replaced_path) {
+            warn!(
+                "Failed to destroy data-info database {:?}: {}",
+                replaced_path, e
+            );
+        }
+        Ok(compaction)
     }
 
     pub(crate) async fn close(&self) -> ZResult<()> {
         let db = self.db.lock().await;
+        let db = opened(&db)?;
         // Flush before to close
         db.flush()
             .and_then(|()| DB::destroy(&rocksdb::Options::default(), db.path()))
@@ -79,38 +374,108 @@ impl DataInfoMgr {
         encoding: &Encoding,
         timestamp: &Timestamp,
     ) -> ZResult<()> {
-        let key = file.as_ref().to_string_lossy();
-        trace!("Put data-info for {}", key);
-        let mut value: WBuf = WBuf::new(32, true);
-        // note: encode timestamp at first for faster decoding when only this one is required
-        let write_ok = value.write_timestamp(timestamp)
-            && value.write_zint(u8::from(*encoding.prefix()).into())
-            && value.write_string(encoding.suffix());
-        if !write_ok {
-            bail!("Failed to encode data-info for {:?}", file.as_ref())
-        } else {
-            self.db
-                .lock()
-                .await
-                .put(key.as_bytes(), value.get_first_slice(..))
-                .map_err(|e| {
-                    zerror!("Failed to save data-info for {:?}: {}", file.as_ref(), e).into()
-                })
+        self.put_data_info_ext(file, encoding, timestamp, None, false, None)
+            .await
+    }
+
+    // Put a data-info, with the hash of the file's content if known (and the ETag derived from it), if the file's
+    // content is base64-encoded (i.e. to be decoded when read), and the size of the value put if it differs from
+    // the file's size
+    pub(crate) async fn put_data_info_ext<P: AsRef<Path>>(
+        &self,
+        file: P,
+        encoding: &Encoding,
+        timestamp: &Timestamp,
+        content_hash: Option<&ContentHash>,
+        base64: bool,
+        value_size: Option<u64>,
+    ) -> ZResult<()> {
+        let key = db_key(file.as_ref());
+        trace!("Put data-info for {:?}", file.as_ref());
+        let extras = DataInfoExtras {
+            size: value_size,
+            etag: content_hash
+                .filter(|content_hash| !content_hash.hash.is_empty())
+                .map(|content_hash| content_hash.etag(timestamp)),
+            ..Default::default()
+        };
+        let value = encode_data_info(
+            file.as_ref(),
+            encoding,
+            timestamp,
+            content_hash,
+            base64,
+            Some(&extras),
+        )?;
+        let db = self.db.lock().await;
+        let db = opened(&db)?;
+        let cf = get_cf_by_encoding(db)?;
+        // update the data-info and the index by encoding at once
+        let mut batch = WriteBatch::default();
+        add_data_info_to_batch(db, cf, &mut batch, &key, encoding, &value);
+        fault_point(FaultPoint::DbPut)
+            .map_err(|e| zerror!("Failed to save data-info for {:?}: {}", file.as_ref(), e))?;
+        db.write(batch)
+            .map_err(|e| zerror!("Failed to save data-info for {:?}: {}", file.as_ref(), e).into())
+    }
+
+    // Put several data-infos (without content hash) in a single write, i.e. all or none of them are saved.
+    // Each file must appear only once.
+    pub(crate) async fn put_data_infos(
+        &self,
+        infos: &[(&Path, &Encoding, &Timestamp, bool, Option<u64>)],
+    ) -> ZResult<()> {
+        let db = self.db.lock().await;
+        let db = opened(&db)?;
+        let cf = get_cf_by_encoding(db)?;
+        let mut batch = WriteBatch::default();
+        for (file, encoding, timestamp, base64, value_size) in infos {
+            let key = db_key(file);
+            trace!("Put data-info for {:?} (batched)", file);
+            let extras = DataInfoExtras {
+                size: *value_size,
+                ..Default::default()
+            };
+            let value = encode_data_info(file, encoding, timestamp, None, *base64, Some(&extras))?;
+            add_data_info_to_batch(db, cf, &mut batch, &key, encoding, &value);
         }
+        fault_point(FaultPoint::DbPut)
+            .map_err(|e| zerror!("Failed to save {} data-infos: {}", infos.len(), e))?;
+        db.write(batch)
+            .map_err(|e| zerror!("Failed to save {} data-infos: {}", infos.len(), e).into())
+    }
+
+    pub(crate) async fn del_data_info<P: AsRef<Path>>(&self, file: P) -> ZResult<()> {
+        let key = db_key(file.as_ref());
+        trace!("Delete data-info for {:?}", file.as_ref());
+        let db = self.db.lock().await;
+        delete_with_index(opened(&db)?, &key).map_err(|e| {
+            zerror!("Failed to delete data-info for {:?}: {}", file.as_ref(), e).into()
+        })
     }
 
     pub(crate) async fn rename_key<P: AsRef<Path>>(&self, from: P, to: P) -> ZResult<()> {
-        let from_key = from.as_ref().to_string_lossy();
-        let to_key = to.as_ref().to_string_lossy();
-        trace!("Changing data-info from {} to {}", from_key, to_key);
+        let from_key = db_key(from.as_ref());
+        let to_key = db_key(to.as_ref());
+        trace!(
+            "Changing data-info from {:?} to {:?}",
+            from.as_ref(),
+            to.as_ref()
+        );
         let db_instance = self.db.lock().await;
-        let val = db_instance.get_pinned(from_key.as_bytes());
+        let db_instance = opened(&db_instance)?;
+        let cf = get_cf_by_encoding(db_instance)?;
+        let val = db_instance.get_pinned(&from_key);
         match val {
             Ok(Some(pin_val)) => {
-                db_instance.put(to_key.as_bytes(), pin_val).map_err(|e| {
-                    zerror!("Failed to save data-info for {:?}: {}", to.as_ref(), e)
-                })?;
-                db_instance.delete(from_key.as_bytes()).map_err(|e| {
+                let mut batch = WriteBatch::default();
+                if let Ok((encoding, _)) = decode_encoding_timestamp_from_value(&pin_val) {
+                    batch.delete_cf(cf, encoding_index_key(&encoding, &from_key));
+                    batch.put_cf(cf, encoding_index_key(&encoding, &to_key), b"");
+                }
+                batch.put(&to_key, &pin_val);
+                batch.delete(&from_key);
+                db_instance.write(batch).map_err(|e| {
                     zerror!("Failed to save data-info for {:?}: {}", to.as_ref(), e).into()
                 })
             }
@@ -125,13 +490,53 @@ impl DataInfoMgr {
         }
     }
 
+    // Re-key the data-infos of the files in a directory as files in another directory (e.g. if the storage
+    // directory is now referred to by another path). Return the number of data-infos re-keyed.
+    pub(crate) async fn rebase(&self, from: &Path, to: &Path) -> ZResult<u64> {
+        let db = self.db.lock().await;
+        let db = opened(&db)?;
+        let cf = get_cf_by_encoding(db)?;
+        let prefix = db_key(from).into_owned();
+        let mut batch = WriteBatch::default();
+        let mut count = 0;
+        for (key, value) in db.iterator(IteratorMode::From(&prefix, Direction::Forward)) {
+            if !key.starts_with(&prefix) {
+                break;
+            }
+            // the prefix may also be the one of another directory (e.g. "dir" for "dir2/file")
+            let new_key = match db_key_to_path(&key)
+                .as_deref()
+                .and_then(|path| path.strip_prefix(from).ok())
+            {
+                Some(relative) => db_key(&to.join(relative)).into_owned(),
+                None => continue,
+            };
+            if let Ok((encoding, _)) = decode_encoding_timestamp_from_value(&value) {
+                batch.delete_cf(cf, encoding_index_key(&encoding, &key));
+                batch.put_cf(cf, encoding_index_key(&encoding, &new_key), b"");
+            }
+            batch.delete(&key);
+            batch.put(&new_key, &value);
+            count += 1;
+        }
+        db.write(batch).map_err(|e| {
+            zerror!(
+                "Failed to re-key data-infos from {:?} to {:?}: {}",
+                from,
+                to,
+                e
+            )
+        })?;
+        Ok(count)
+    }
+
     pub(crate) async fn get_encoding_and_timestamp<P: AsRef<Path>>(
         &self,
         file: P,
     ) -> ZResult<Option<(Encoding, Timestamp)>> {
-        let key = file.as_ref().to_string_lossy();
-        trace!("Get data-info for {}", key);
-        match self.db.lock().await.get_pinned(key.as_bytes()) {
+        let key = db_key(file.as_ref());
+        trace!("Get data-info for {:?}", file.as_ref());
+        match opened(&*self.db.lock().await)?.get_pinned(&key) {
             Ok(Some(pin_val)) => decode_encoding_timestamp_from_value(pin_val.as_ref()).map(Some),
             Ok(None) => {
                 trace!("data-info for {:?} not found", file.as_ref());
@@ -141,13 +546,95 @@ impl DataInfoMgr {
         }
     }
 
+    // Get the encoding and the content hash of a file, if its data-info has a content hash
+    pub(crate) async fn get_content_hash<P: AsRef<Path>>(
+        &self,
+        file: P,
+    ) -> ZResult<Option<(Encoding, ContentHash)>> {
+        let key = db_key(file.as_ref());
+        trace!("Get content hash for {:?}", file.as_ref());
+        match opened(&*self.db.lock().await)?.get_pinned(&key) {
+            Ok(Some(pin_val)) => Ok(decode_content_hash_from_value(pin_val.as_ref())),
+            Ok(None) => Ok(None),
+            Err(e) => bail!("Failed to get data-info for {:?}: {}", file.as_ref(), e),
+        }
+    }
+
+    // Check if the content of a file is base64-encoded, according to its data-info
+    pub(crate) async fn is_base64_encoded<P: AsRef<Path>>(&self, file: P) -> ZResult<bool> {
+        let key = db_key(file.as_ref());
+        match opened(&*self.db.lock().await)?.get_pinned(&key) {
+            Ok(Some(pin_val)) => Ok(decode_flags_from_value(pin_val.as_ref()) & FLAG_BASE64 != 0),
+            Ok(None) => Ok(false),
+            Err(e) => bail!("Failed to get data-info for {:?}: {}", file.as_ref(), e),
+        }
+    }
+
+    // Get the extras of a file's data-info, if it has a data-info
+    pub(crate) async fn get_extras<P: AsRef<Path>>(
+        &self,
+        file: P,
+    ) -> ZResult<Option<DataInfoExtras>> {
+        let key = db_key(file.as_ref());
+        match opened(&*self.db.lock().await)?.get_pinned(&key) {
+            Ok(Some(pin_val)) => decode_extras_from_value(pin_val.as_ref()).map(Some),
+            Ok(None) => Ok(None),
+            Err(e) => bail!("Failed to get data-info for {:?}: {}", file.as_ref(), e),
+        }
+    }
+
+    // Modify the extras of a file's data-info, keeping its other fields.
+    // Return false if the file has no data-info.
+    pub(crate) async fn update_extras<P: AsRef<Path>>(
+        &self,
+        file: P,
+        update: impl FnOnce(&mut DataInfoExtras),
+    ) -> ZResult<bool> {
+        let key = db_key(file.as_ref());
+        let db = self.db.lock().await;
+        let db = opened(&db)?;
+        let value = match db.get_pinned(&key) {
+            Ok(Some(pin_val)) => {
+                let value = pin_val.as_ref();
+                let (encoding, timestamp) = decode_encoding_timestamp_from_value(value)?;
+                let content_hash = decode_content_hash_from_value(value).map(|(_, hash)| hash);
+                let base64 = decode_flags_from_value(value) & FLAG_BASE64 != 0;
+                let mut extras = decode_extras_from_value(value)?;
+                update(&mut extras);
+                encode_data_info(
+                    file.as_ref(),
+                    &encoding,
+                    &timestamp,
+                    content_hash.as_ref(),
+                    base64,
+                    Some(&extras),
+                )?
+            }
+            Ok(None) => return Ok(false),
+            Err(e) => bail!("Failed to get data-info for {:?}: {}", file.as_ref(), e),
+        };
+        trace!("Put data-info extras for {:?}", file.as_ref());
+        // (the encoding is unchanged, as its entry in the index)
+        db.put(&key, value.get_first_slice(..))
+            .map_err(|e| zerror!("Failed to save data-info for {:?}: {}", file.as_ref(), e))?;
+        Ok(true)
+    }
+
+    // Count a read of a file in the access count of its data-info, if it has a data-info
+    pub(crate) async fn increment_access_count<P: AsRef<Path>>(&self, file: P) -> ZResult<bool> {
+        self.update_extras(file, |extras| {
+            extras.access_count = Some(extras.access_count.unwrap_or(0).saturating_add(1))
+        })
+        .await
+    }
+
     pub(crate) async fn get_timestamp<P: AsRef<Path>>(
         &self,
         file: P,
     ) -> ZResult<Option<Timestamp>> {
-        let key = file.as_ref().to_string_lossy();
-        trace!("Get timestamp for {}", key);
-        match self.db.lock().await.get_pinned(key.as_bytes()) {
+        let key = db_key(file.as_ref());
+        trace!("Get timestamp for {:?}", file.as_ref());
+        match opened(&*self.db.lock().await)?.get_pinned(&key) {
             Ok(Some(pin_val)) => decode_timestamp_from_value(pin_val.as_ref()).map(Some),
             Ok(None) => {
                 trace!("timestamp for {:?} not found", file.as_ref());
@@ -157,26 +644,694 @@ impl DataInfoMgr {
         }
     }
 
-    pub async fn get_deleted_entries(&self) -> Vec<(String, Timestamp)> {
+    // Get the timestamps of all the files referenced in the data-info database, indexed by file path
+    pub(crate) async fn get_all_timestamps(&self) -> HashMap<PathBuf, Timestamp> {
+        match &*self.db.lock().await {
+            Some(db) => decode_all_timestamps(db),
+            None => HashMap::new(),
+        }
+    }
+
+    // Get the paths of all the existing files stored with an encoding, using the index by encoding
+    pub(crate) async fn get_by_encoding(&self, encoding: &Encoding) -> ZResult<Vec<PathBuf>> {
+        let db = self.db.lock().await;
+        let db = opened(&db)?;
+        let cf = get_cf_by_encoding(db)?;
+        let prefix = encoding_index_key(encoding, b"");
         let mut result = Vec::new();
+        for (key, _) in db.iterator_cf(cf, IteratorMode::From(&prefix, Direction::Forward)) {
+            if !key.starts_with(&prefix) {
+                break;
+            }
+            if let Some(file) = db_key_to_path(&key[prefix.len()..]) {
+                // skip the deleted files
+                if file.is_file() {
+                    result.push(file);
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    // Record a file as quarantined, with the reason of its quarantine
+    pub(crate) async fn put_quarantine(&self, file: &str, reason: &str) -> ZResult<()> {
+        trace!("Put quarantine record for {}", file);
         let db = self.db.lock().await;
-        for (key, value) in db.iterator(IteratorMode::Start) {
-            if let Ok(path) = std::str::from_utf8(&key).map(Path::new) {
+        let db = opened(&db)?;
+        db.put_cf(get_cf_quarantine(db)?, file, reason)
+            .map_err(|e| zerror!("Failed to save quarantine record for {}: {}", file, e).into())
+    }
+
+    pub(crate) async fn del_quarantine(&self, file: &str) -> ZResult<()> {
+        trace!("Delete quarantine record for {}", file);
+        let db = self.db.lock().await;
+        let db = opened(&db)?;
+        db.delete_cf(get_cf_quarantine(db)?, file)
+            .map_err(|e| zerror!("Failed to delete quarantine record for {}: {}", file, e).into())
+    }
+
+    // Get all the quarantined files, with the reason of their quarantine
+    pub(crate) async fn get_quarantine(&self) -> ZResult<HashMap<String, String>> {
+        let db = self.db.lock().await;
+        let db = opened(&db)?;
+        Ok(db
+            .iterator_cf(get_cf_quarantine(db)?, IteratorMode::Start)
+            .filter_map(|(key, value)| {
+                Some((
+                    std::str::from_utf8(&key).ok()?.to_string(),
+                    String::from_utf8_lossy(&value).into_owned(),
+                ))
+            })
+            .collect())
+    }
+
+    // Record that a key (zenoh path) is immutable until a date, in milliseconds since the UNIX epoch
+    pub(crate) async fn put_retention(&self, zpath: &str, until_ms: u64) -> ZResult<()> {
+        trace!("Put retention record for {}", zpath);
+        let db = self.db.lock().await;
+        let db = opened(&db)?;
+        db.put_cf(get_cf_retention(db)?, zpath, until_ms.to_be_bytes())
+            .map_err(|e| zerror!("Failed to save retention record for {}: {}", zpath, e).into())
+    }
+
+    pub(crate) async fn get_retention(&self, zpath: &str) -> ZResult<Option<u64>> {
+        let db = self.db.lock().await;
+        let db = opened(&db)?;
+        match db.get_pinned_cf(get_cf_retention(db)?, zpath) {
+            Ok(Some(value)) => Ok(value.as_ref().try_into().ok().map(u64::from_be_bytes)),
+            Ok(None) => Ok(None),
+            Err(e) => bail!("Failed to get retention record for {}: {}", zpath, e),
+        }
+    }
+
+    pub(crate) async fn del_retention(&self, zpath: &str) -> ZResult<()> {
+        trace!("Delete retention record for {}", zpath);
+        let db = self.db.lock().await;
+        let db = opened(&db)?;
+        db.delete_cf(get_cf_retention(db)?, zpath)
+            .map_err(|e| zerror!("Failed to delete retention record for {}: {}", zpath, e).into())
+    }
+
+    // Record a sample dropped as out-of-date, removing the oldest records beyond the capacity
+    pub(crate) async fn put_conflict(&self, record: &[u8], capacity: u64) -> ZResult<()> {
+        let db = self.db.lock().await;
+        let db = opened(&db)?;
+        let cf = get_cf_conflicts(db)?;
+        let last_seq = db
+            .iterator_cf(cf, IteratorMode::End)
+            .next()
+            .and_then(|(key, _)| decode_seq(&key));
+        let seq = last_seq.map_or(0, |s| s + 1);
+        let mut batch = WriteBatch::default();
+        batch.put_cf(cf, seq.to_be_bytes(), record);
+        if seq >= capacity {
+            batch.delete_range_cf(cf, 0u64.to_be_bytes(), (seq + 1 - capacity).to_be_bytes());
+        }
+        db.write(batch)
+            .map_err(|e| zerror!("Failed to save conflict record: {}", e).into())
+    }
+
+    // Get the most recent records of samples dropped as out-of-date, the newest first
+    pub(crate) async fn get_conflicts(&self, limit: usize) -> ZResult<Vec<Vec<u8>>> {
+        let db = self.db.lock().await;
+        let db = opened(&db)?;
+        Ok(db
+            .iterator_cf(get_cf_conflicts(db)?, IteratorMode::End)
+            .take(limit)
+            .map(|(_, value)| value.to_vec())
+            .collect())
+    }
+
+    // Get the number of data-infos (of stored files and of deleted keys) estimated by RocksDB, without iterating them.
+    // As it must not wait, it fails if the database is in use.
+    pub(crate) fn estimate_count(&self) -> ZResult<u64> {
+        let db = match self.db.try_lock() {
+            Some(db) => db,
+            None => bail!("The data-info database is in use"),
+        };
+        Ok(opened(&db)?
+            .property_int_value("rocksdb.estimate-num-keys")
+            .map_err(|e| zerror!("Failed to estimate the number of data-infos: {}", e))?
+            .unwrap_or(0))
+    }
+
+    // Count the data-infos of the files which no longer exist (i.e. of deleted keys) and are accepted by a filter,
+    // without collecting them
+    pub(crate) async fn count_deleted_entries(&self, filter: impl Fn(&Path) -> bool) -> u64 {
+        let mut count = 0;
+        self.scan_by_chunks(None, |key, _| {
+            if let Some(path) = db_key_to_path(key) {
+                if !path.exists() && filter(&path) {
+                    count += 1;
+                }
+            }
+        })
+        .await;
+        count
+    }
+
+    // Scan all the data-infos in keys order, by chunks of SCAN_CHUNK_SIZE: the database is released and the task
+    // yields between the chunks, so the scan of a large database doesn't block the other operations until its end.
+    // If a pause is specified, the task sleeps for this duration between the chunks instead (to smooth the IO).
+    // The data-infos modified during the scan might be scanned in their previous or new version.
+    async fn scan_by_chunks(&self, pause: Option<Duration>, mut f: impl FnMut(&[u8], &[u8])) {
+        self.scan_prefix_by_chunks(&[], pause, |key, value| {
+            f(key, value);
+            true
+        })
+        .await
+    }
+
+    // Scan the data-infos whose key starts with a prefix as scan_by_chunks() does, until `f` returns false
+    async fn scan_prefix_by_chunks(
+        &self,
+        prefix: &[u8],
+        pause: Option<Duration>,
+        mut f: impl FnMut(&[u8], &[u8]) -> bool,
+    ) {
+        let mut from: Option<Box<[u8]>> = None;
+        loop {
+            let mut last = None;
+            {
+                let db = self.db.lock().await;
+                let db = match &*db {
+                    Some(db) => db,
+                    None => return,
+                };
+                let mode = match &from {
+                    Some(key) => IteratorMode::From(key, Direction::Forward),
+                    None => IteratorMode::From(prefix, Direction::Forward),
+                };
+                let mut count = 0;
+                for (key, value) in db.iterator(mode) {
+                    // (the last key of the previous chunk, unless it was deleted meanwhile)
+                    if from.as_ref() == Some(&key) {
+                        continue;
+                    }
+                    if !key.starts_with(prefix) || !f(&key, &value) {
+                        return;
+                    }
+                    count += 1;
+                    if count == SCAN_CHUNK_SIZE {
+                        last = Some(key);
+                        break;
+                    }
+                }
+            }
+            match last {
+                Some(key) => from = Some(key),
+                None => return,
+            }
+            match pause {
+                Some(pause) => task::sleep(pause).await,
+                None => task::yield_now().await,
+            }
+        }
+    }
+
+    // Record the deletion of a file removed by another process, i.e. replace its data-info by a tombstone with a
+    // new timestamp, only if the file still doesn't exist and its data-info is still at the expected timestamp
+    // (otherwise the file was recreated, or its key put or deleted via zenoh meanwhile). Return true if recorded.
+    pub(crate) async fn put_tombstone_if_unchanged(
+        &self,
+        file: &Path,
+        expected: &Timestamp,
+        timestamp: &Timestamp,
+    ) -> ZResult<bool> {
+        let key = db_key(file);
+        let db = self.db.lock().await;
+        let db = opened(&db)?;
+        match db.get_pinned(&key) {
+            Ok(Some(pin_val)) => {
+                if decode_timestamp_from_value(pin_val.as_ref())? != *expected {
+                    return Ok(false);
+                }
+            }
+            Ok(None) => return Ok(false),
+            Err(e) => bail!("Failed to get data-info for {:?}: {}", file, e),
+        }
+        // (checked while holding the database, as a PUT via zenoh writes the file before its data-info)
+        if file.exists() {
+            return Ok(false);
+        }
+        trace!("Put tombstone for {:?} deleted externally", file);
+        let encoding: Encoding = KnownEncoding::Empty.into();
+        let value = encode_data_info(file, &encoding, timestamp, None, false, None)?;
+        let cf = get_cf_by_encoding(db)?;
+        let mut batch = WriteBatch::default();
+        add_data_info_to_batch(db, cf, &mut batch, &key, &encoding, &value);
+        db.write(batch)
+            .map_err(|e| zerror!("Failed to save data-info for {:?}: {}", file, e))?;
+        Ok(true)
+    }
+
+    // Find the data-info of a file under a directory which no longer exists (i.e. of a deleted key), with a timestamp
+    // after a time and accepted by a filter. Only the data-infos under the directory are scanned, and only the files
+    // of those after the time are checked.
+    pub(crate) async fn find_deleted_after(
+        &self,
+        dir: &Path,
+        time: &NTP64,
+        filter: impl Fn(&Path) -> bool,
+    ) -> Option<(PathBuf, Timestamp)> {
+        let mut found = None;
+        self.scan_prefix_by_chunks(&db_key(dir), None, |key, value| {
+            if let (Some(path), Ok(timestamp)) =
+                (db_key_to_path(key), decode_timestamp_from_value(value))
+            {
+                if timestamp.get_time() > time && filter(&path) && !path.exists() {
+                    found = Some((path, timestamp));
+                    return false;
+                }
+            }
+            true
+        })
+        .await;
+        found
+    }
+
+    // Get the tombstones, pausing between the chunks of the scan if specified (see scan_by_chunks())
+    pub async fn get_deleted_entries(&self, pause: Option<Duration>) -> Vec<(PathBuf, Timestamp)> {
+        let mut result = Vec::new();
+        self.scan_by_chunks(pause, |key, value| {
+            if let Some(path) = db_key_to_path(key) {
                 if !path.exists() {
-                    match decode_timestamp_from_value(&value) {
-                        Ok(timestamp) => {
-                            result
-                                .push((std::str::from_utf8(&key).unwrap().to_string(), timestamp));
-                        }
+                    match decode_timestamp_from_value(value) {
+                        Ok(timestamp) => result.push((path, timestamp)),
                         Err(e) => warn!("Failed to decode data-info for file {:?}: {}", path, e),
                     }
                 }
             }
-        }
+        })
+        .await;
         result
     }
 }
 
+// Open (or create) the data-info database of a storage directory
+fn open_db(base_dir: &Path) -> ZResult<DB> {
+    let mut backup_file = PathBuf::from(base_dir);
+    backup_file.push(DataInfoMgr::DB_FILENAME);
+
+    // if a compaction was interrupted while replacing the database, put back the previous one
+    let replaced_file = base_dir.join(DataInfoMgr::REPLACED_DB_FILENAME);
+    if !backup_file.exists() && replaced_file.exists() {
+        warn!(
+            "Restore data-info database {:?} replaced by an interrupted compaction",
+            backup_file
+        );
+        rename(&replaced_file, &backup_file).map_err(|e| {
+            zerror!(
+                "Failed to restore data-info database from {:?}: {}",
+                replaced_file,
+                e
+            )
+        })?;
+    }
+
+    // the index by encoding must be built if the database exists without it
+    let build_index = DB::list_cf(&Options::default(), &backup_file)
+        .map(|cfs| !cfs.iter().any(|cf| cf == DataInfoMgr::CF_BY_ENCODING))
+        .unwrap_or(false);
+
+    let db = open_db_at(&backup_file)?;
+    migrate_schema(&db, &backup_file)?;
+    if build_index {
+        debug!(
+            "Build index by encoding of data-info database {:?}",
+            backup_file
+        );
+        build_encoding_index(&db)?;
+    }
+    Ok(db)
+}
+
+// Open (or create) a database with all the column families
+fn open_db_at(db_path: &Path) -> ZResult<DB> {
+    let mut opts = Options::default();
+    opts.create_if_missing(true);
+    opts.create_missing_column_families(true);
+    DB::open_cf(
+        &opts,
+        db_path,
+        [
+            DataInfoMgr::CF_BY_ENCODING,
+            DataInfoMgr::CF_QUARANTINE,
+            DataInfoMgr::CF_CONFLICTS,
+            DataInfoMgr::CF_RETENTION,
+            DataInfoMgr::CF_META,
+        ],
+    )
+    .map_err(|e| {
+        zerror!(
+            "Failed to open data-info database from {:?}: {}",
+            db_path,
+            e
+        )
+        .into()
+    })
+}
+
+// Destroy the database of a storage directory and re-create it with the records of a backup, in all the column
+// families. The index by encoding is re-built rather than copied, and the schema version is the one of the
+// re-created database (as the restored data-infos are upgraded).
+fn restore_db(base_dir: &Path, backup: &DB) -> ZResult<DB> {
+    let mut db_path = PathBuf::from(base_dir);
+    db_path.push(DataInfoMgr::DB_FILENAME);
+    DB::destroy(&Options::default(), &db_path)
+        .map_err(|e| zerror!("Failed to destroy data-info database {:?}: {}", db_path, e))?;
+    // the backup might be of a previous schema version
+    let version = read_schema_version(backup)?;
+    check_schema_version(version, backup.path())?;
+    let db = open_db(base_dir)?;
+
+    let mut batch = WriteBatch::default();
+    for (key, value) in backup.iterator(IteratorMode::Start) {
+        match upgrade_data_info(&value, version)? {
+            Some(upgraded) => batch.put(key, upgraded),
+            None => batch.put(key, value),
+        }
+    }
+    for cf_name in [
+        DataInfoMgr::CF_QUARANTINE,
+        DataInfoMgr::CF_CONFLICTS,
+        DataInfoMgr::CF_RETENTION,
+        DataInfoMgr::CF_META,
+    ] {
+        if let (Some(backup_cf), Some(cf)) = (backup.cf_handle(cf_name), db.cf_handle(cf_name)) {
+            for (key, value) in backup.iterator_cf(backup_cf, IteratorMode::Start) {
+                if cf_name == DataInfoMgr::CF_META && key.as_ref() == SCHEMA_VERSION_KEY {
+                    continue;
+                }
+                batch.put_cf(cf, key, value);
+            }
+        }
+    }
+    db.write(batch)
+        .map_err(|e| zerror!("Failed to restore data-info database {:?}: {}", db_path, e))?;
+    build_encoding_index(&db)?;
+    Ok(db)
+}
+
+// Get the schema version of a database (1 if not recorded)
+fn read_schema_version(db: &DB) -> ZResult<u64> {
+    let cf = match db.cf_handle(DataInfoMgr::CF_META) {
+        Some(cf) => cf,
+        None => return Ok(1),
+    };
+    match db.get_pinned_cf(cf, SCHEMA_VERSION_KEY) {
+        Ok(Some(value)) => value
+            .as_ref()
+            .try_into()
+            .map(u64::from_be_bytes)
+            .map_err(|_| zerror!("Invalid schema version in data-info database").into()),
+        Ok(None) => Ok(1),
+        Err(e) => bail!("Failed to get schema version of data-info database: {}", e),
+    }
+}
+
+// Refuse a database of a schema version more recent than the supported one (i.e. written by a newer backend)
+fn check_schema_version(version: u64, db_path: &Path) -> ZResult<()> {
+    if version > SCHEMA_VERSION {
+        bail!(
+            "Data-info database {:?} is of schema version {}, while the supported one is {}: it was written by a more recent version of the backend",
+            db_path,
+            version,
+            SCHEMA_VERSION
+        )
+    }
+    Ok(())
+}
+
+// Upgrade in place a database of a previous schema version: all its data-infos are re-encoded
+// with the schema version recorded in a single write, i.e. the database is upgraded completely or not at all
+fn migrate_schema(db: &DB, db_path: &Path) -> ZResult<()> {
+    let version = read_schema_version(db)?;
+    check_schema_version(version, db_path)?;
+    if version == SCHEMA_VERSION {
+        return Ok(());
+    }
+    let mut batch = WriteBatch::default();
+    for (key, value) in db.iterator(IteratorMode::Start) {
+        if let Some(upgraded) = upgrade_data_info(&value, version)? {
+            batch.put(key, upgraded);
+        }
+    }
+    batch.put_cf(
+        get_cf_meta(db)?,
+        SCHEMA_VERSION_KEY,
+        SCHEMA_VERSION.to_be_bytes(),
+    );
+    db.write(batch).map_err(|e| {
+        zerror!(
+            "Failed to upgrade data-info database {:?} from schema version {}: {}",
+            db_path,
+            version,
+            e
+        )
+    })?;
+    debug!(
+        "Upgraded data-info database {:?} from schema version {} to {}",
+        db_path, version, SCHEMA_VERSION
+    );
+    Ok(())
+}
+
+// Re-encode a data-info of a previous schema version with the current one, or None if it's unchanged
+// (a data-info that can't be decoded is kept as is, to not lose it)
+fn upgrade_data_info(_value: &[u8], version: u64) -> ZResult<Option<Vec<u8>>> {
+    match version {
+        // the version 2 only appends the (optional) extras to the version 1
+        1 | SCHEMA_VERSION => Ok(None),
+        _ => bail!("Unsupported data-info schema version {}", version),
+    }
+}
+
+// Get the opened database, or an error if it's closed
+fn opened(db: &Option<DB>) -> ZResult<&DB> {
+    db.as_ref()
+        .ok_or_else(|| zerror!("The data-info database is closed").into())
+}
+
+// Get the timestamps of all the files referenced in the data-info database of a storage directory,
+// indexed by file path. The database is opened in read-only mode, as it might be used by another storage.
+pub(crate) fn read_all_timestamps(base_dir: &Path) -> ZResult<HashMap<PathBuf, Timestamp>> {
+    let mut db_path = PathBuf::from(base_dir);
+    db_path.push(DataInfoMgr::DB_FILENAME);
+    if !db_path.exists() {
+        return Ok(HashMap::new());
+    }
+    let db =
+        DB::open_for_read_only(&rocksdb::Options::default(), &db_path, false).map_err(|e| {
+            zerror!(
+                "Failed to open data-info database from {:?}: {}",
+                db_path,
+                e
+            )
+        })?;
+    Ok(decode_all_timestamps(&db))
+}
+
+fn decode_all_timestamps(db: &DB) -> HashMap<PathBuf, Timestamp> {
+    db.iterator(IteratorMode::Start)
+        .filter_map(|(key, value)| {
+            let key = db_key_to_path(&key)?;
+            match decode_timestamp_from_value(&value) {
+                Ok(timestamp) => Some((key, timestamp)),
+                Err(e) => {
+                    warn!("Failed to decode data-info for file {:?}: {}", key, e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+// Key of a file's data-info: the file's path. On Unix, this is the path's raw bytes,
+// so the paths which are not valid UTF-8 (see "filename_encoding") have distinct keys.
+#[cfg(unix)]
+fn db_key(file: &Path) -> Cow<'_, [u8]> {
+    use std::os::unix::ffi::OsStrExt;
+    Cow::from(file.as_os_str().as_bytes())
+}
+
+#[cfg(windows)]
+fn db_key(file: &Path) -> Cow<'_, [u8]> {
+    match file.to_string_lossy() {
+        Cow::Borrowed(s) => Cow::from(s.as_bytes()),
+        Cow::Owned(s) => Cow::from(s.into_bytes()),
+    }
+}
+
+// Get a file's path from the key of its data-info
+#[cfg(unix)]
+fn db_key_to_path(key: &[u8]) -> Option<PathBuf> {
+    use std::os::unix::ffi::OsStrExt;
+    Some(PathBuf::from(std::ffi::OsStr::from_bytes(key)))
+}
+
+#[cfg(windows)]
+fn db_key_to_path(key: &[u8]) -> Option<PathBuf> {
+    std::str::from_utf8(key).ok().map(PathBuf::from)
+}
+
+// Key in the index by encoding: the encoding and the file path, separated by a '\0'
+fn encoding_index_key(encoding: &Encoding, file: &[u8]) -> Vec<u8> {
+    let mut key = encoding.to_string().into_bytes();
+    key.push(0);
+    key.extend_from_slice(file);
+    key
+}
+
+// Encode a data-info, with the hash of the file's content if known and the flags
+fn encode_data_info(
+    file: &Path,
+    encoding: &Encoding,
+    timestamp: &Timestamp,
+    content_hash: Option<&ContentHash>,
+    base64: bool,
+    extras: Option<&DataInfoExtras>,
+) -> ZResult<WBuf> {
+    let mut value: WBuf = WBuf::new(32, true);
+    // note: encode timestamp at first for faster decoding when only this one is required
+    let mut write_ok = value.write_timestamp(timestamp)
+        && value.write_zint(u8::from(*encoding.prefix()).into())
+        && value.write_string(encoding.suffix());
+    // note: the content hash (empty if unknown) and the flags are optional and encoded at last,
+    // to be ignored if not required
+    let extras = extras.filter(|extras| !extras.is_empty());
+    if content_hash.is_some() || base64 || extras.is_some() {
+        let (hash, mtime) = content_hash.map_or(("", 0), |h| (h.hash.as_str(), h.mtime));
+        write_ok = write_ok
+            && value.write_string(hash)
+            && value.write_zint(mtime)
+            && value.write_zint(if base64 { FLAG_BASE64 } else { 0 });
+    }
+    // the extras are encoded as a sequence of identifier and value, until the end
+    if let Some(extras) = extras {
+        if let Some(size) = extras.size {
+            write_ok = write_ok && value.write_zint(EXTRA_SIZE) && value.write_zint(size);
+        }
+        if let Some(etag) = &extras.etag {
+            write_ok = write_ok && value.write_zint(EXTRA_ETAG) && value.write_string(etag);
+        }
+        if let Some(access_count) = extras.access_count {
+            write_ok =
+                write_ok && value.write_zint(EXTRA_ACCESS_COUNT) && value.write_zint(access_count);
+        }
+        for (name, tag) in &extras.tags {
+            write_ok = write_ok
+                && value.write_zint(EXTRA_TAG)
+                && value.write_string(name)
+                && value.write_string(tag);
+        }
+    }
+    if !write_ok {
+        bail!("Failed to encode data-info for {:?}", file)
+    }
+    Ok(value)
+}
+
+// Add the put of a data-info to a batch, replacing its entry in the index by encoding
+fn add_data_info_to_batch(
+    db: &DB,
+    cf: &rocksdb::ColumnFamily,
+    batch: &mut WriteBatch,
+    key: &[u8],
+    encoding: &Encoding,
+    value: &WBuf,
+) {
+    if let Ok(Some(old_value)) = db.get_pinned(key) {
+        if let Ok((old_encoding, _)) = decode_encoding_timestamp_from_value(&old_value) {
+            batch.delete_cf(cf, encoding_index_key(&old_encoding, key));
+        }
+    }
+    batch.put(key, value.get_first_slice(..));
+    batch.put_cf(cf, encoding_index_key(encoding, key), b"");
+}
+
+fn get_cf_by_encoding(db: &DB) -> ZResult<&rocksdb::ColumnFamily> {
+    db.cf_handle(DataInfoMgr::CF_BY_ENCODING).ok_or_else(|| {
+        zerror!(
+            "Column family {} not found in data-info database",
+            DataInfoMgr::CF_BY_ENCODING
+        )
+        .into()
+    })
+}
+
+fn get_cf_conflicts(db: &DB) -> ZResult<&rocksdb::ColumnFamily> {
+    db.cf_handle(DataInfoMgr::CF_CONFLICTS).ok_or_else(|| {
+        zerror!(
+            "Column family {} not found in data-info database",
+            DataInfoMgr::CF_CONFLICTS
+        )
+        .into()
+    })
+}
+
+fn decode_seq(key: &[u8]) -> Option<u64> {
+    Some(u64::from_be_bytes(key.try_into().ok()?))
+}
+
+fn get_cf_retention(db: &DB) -> ZResult<&rocksdb::ColumnFamily> {
+    db.cf_handle(DataInfoMgr::CF_RETENTION).ok_or_else(|| {
+        zerror!(
+            "Column family {} not found in data-info database",
+            DataInfoMgr::CF_RETENTION
+        )
+        .into()
+    })
+}
+
+fn get_cf_meta(db: &DB) -> ZResult<&rocksdb::ColumnFamily> {
+    db.cf_handle(DataInfoMgr::CF_META).ok_or_else(|| {
+        zerror!(
+            "Column family {} not found in data-info database",
+            DataInfoMgr::CF_META
+        )
+        .into()
+    })
+}
+
+fn get_cf_quarantine(db: &DB) -> ZResult<&rocksdb::ColumnFamily> {
+    db.cf_handle(DataInfoMgr::CF_QUARANTINE).ok_or_else(|| {
+        zerror!(
+            "Column family {} not found in data-info database",
+            DataInfoMgr::CF_QUARANTINE
+        )
+        .into()
+    })
+}
+
+// Delete a data-info and its entry in the index by encoding
+fn delete_with_index(db: &DB, key: &[u8]) -> Result<(), rocksdb::Error> {
+    let mut batch = WriteBatch::default();
+    if let (Some(cf), Ok(Some(value))) = (
+        db.cf_handle(DataInfoMgr::CF_BY_ENCODING),
+        db.get_pinned(key),
+    ) {
+        if let Ok((encoding, _)) = decode_encoding_timestamp_from_value(&value) {
+            batch.delete_cf(cf, encoding_index_key(&encoding, key));
+        }
+    }
+    batch.delete(key);
+    db.write(batch)
+}
+
+// Build the index by encoding from all the data-infos
+fn build_encoding_index(db: &DB) -> ZResult<()> {
+    let cf = get_cf_by_encoding(db)?;
+    let mut batch = WriteBatch::default();
+    for (key, value) in db.iterator(IteratorMode::Start) {
+        if let Ok((encoding, _)) = decode_encoding_timestamp_from_value(&value) {
+            batch.put_cf(cf, encoding_index_key(&encoding, &key), b"");
+        }
+    }
+    db.write(batch)
+        .map_err(|e| zerror!("Failed to build data-info index by encoding: {}", e).into())
+}
+
 fn decode_encoding_timestamp_from_value(val: &[u8]) -> ZResult<(Encoding, Timestamp)> {
     let buf = ZBuf::from(val.to_vec());
     let mut buf = buf.reader();
@@ -200,6 +1355,74 @@ fn decode_encoding_timestamp_from_value(val: &[u8]) -> ZResult<(Encoding, Timest
     Ok((encoding, timestamp))
 }
 
+// Decode the encoding and the content hash of a data-info, if it has a content hash
+fn decode_content_hash_from_value(val: &[u8]) -> Option<(Encoding, ContentHash)> {
+    let (encoding, _) = decode_encoding_timestamp_from_value(val).ok()?;
+    let buf = ZBuf::from(val.to_vec());
+    let mut buf = buf.reader();
+    // skip the timestamp and the encoding
+    buf.read_timestamp()?;
+    buf.read_zint()?;
+    buf.read_string()?;
+    let hash = buf.read_string()?;
+    let mtime = buf.read_zint()?;
+    if hash.is_empty() {
+        return None;
+    }
+    Some((encoding, ContentHash { hash, mtime }))
+}
+
+// Decode the flags of a data-info (0 if it has none)
+fn decode_flags_from_value(val: &[u8]) -> u64 {
+    fn decode(val: &[u8]) -> Option<u64> {
+        let buf = ZBuf::from(val.to_vec());
+        let mut buf = buf.reader();
+        // skip the timestamp, the encoding and the content hash
+        buf.read_timestamp()?;
+        buf.read_zint()?;
+        buf.read_string()?;
+        buf.read_string()?;
+        buf.read_zint()?;
+        buf.read_zint()
+    }
+    decode(val).unwrap_or(0)
+}
+
+// Decode the extras of a data-info (empty if it has none)
+fn decode_extras_from_value(val: &[u8]) -> ZResult<DataInfoExtras> {
+    let buf = ZBuf::from(val.to_vec());
+    let mut buf = buf.reader();
+    let mut extras = DataInfoExtras::default();
+    // skip the timestamp, the encoding, the content hash and the flags
+    if buf.read_timestamp().is_none()
+        || buf.read_zint().is_none()
+        || buf.read_string().is_none()
+        || buf.read_string().is_none()
+        || buf.read_zint().is_none()
+        || buf.read_zint().is_none()
+    {
+        return Ok(extras);
+    }
+    while let Some(id) = buf.read_zint() {
+        let decoded = match id {
+            EXTRA_SIZE => buf.read_zint().map(|size| extras.size = Some(size)),
+            EXTRA_ETAG => buf.read_string().map(|etag| extras.etag = Some(etag)),
+            EXTRA_ACCESS_COUNT => buf
+                .read_zint()
+                .map(|access_count| extras.access_count = Some(access_count)),
+            EXTRA_TAG => buf
+                .read_string()
+                .and_then(|name| Some((name, buf.read_string()?)))
+                .map(|tag| extras.tags.push(tag)),
+            _ => bail!("Failed to decode data-info (unknown extra {})", id),
+        };
+        if decoded.is_none() {
+            bail!("Failed to decode data-info (extra {})", id)
+        }
+    }
+    Ok(extras)
+}
+
 fn decode_timestamp_from_value(val: &[u8]) -> ZResult<Timestamp> {
     let buf = ZBuf::from(val.to_vec());
     let mut buf = buf.reader();
@@ -211,25 +1434,39 @@ fn decode_timestamp_from_value(val: &[u8]) -> ZResult<Timestamp> {
 
 // Periodic event cleaning-up data info for no-longer existing files
 struct GarbageCollectionEvent {
-    db: Arc<Mutex<DB>>,
+    db: Arc<Mutex<Option<DB>>>,
+    paused: Arc<AtomicBool>,
 }
 
 #[async_trait]
 impl Timed for GarbageCollectionEvent {
     async fn run(&mut self) {
+        if self.paused.load(Ordering::SeqCst) {
+            trace!("Skip garbage collection of data-infos: the storage is quiesced");
+            return;
+        }
         trace!("Start garbage collection of obsolete data-infos");
         let time_limit = NTP64::from(SystemTime::now().duration_since(UNIX_EPOCH).unwrap())
             - *MIN_DELAY_BEFORE_REMOVAL;
         let db = self.db.lock().await;
+        let db = match &*db {
+            Some(db) => db,
+            None => {
+                // the database is closed while the storage directory is replaced: don't cleanup
+                // the data-infos for files which are missing only because of that
+                trace!("Skip garbage collection of data-infos: database is closed");
+                return;
+            }
+        };
         for (key, value) in db.iterator(IteratorMode::Start) {
-            if let Ok(path) = std::str::from_utf8(&key).map(Path::new) {
+            if let Some(path) = db_key_to_path(&key) {
                 if !path.exists() {
                     // check if path was marked as deleted for a long time
                     match decode_timestamp_from_value(&value) {
                         Ok(timestamp) => {
                             if timestamp.get_time() < &time_limit {
                                 trace!("Cleanup old data-info for {:?}", path);
-                                db.delete(&key).unwrap_or_else(|e| {
+                                delete_with_index(db, &key).unwrap_or_else(|e| {
                                     warn!("Failed to delete data-info for file {:?}: {}", path, e)
                                 });
                             }
@@ -242,3 +1479,282 @@ impl Timed for GarbageCollectionEvent {
         trace!("End garbage collection of obsolete data-infos");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zenoh::time::new_reception_timestamp;
+
+    #[test]
+    fn a_version_1_database_is_upgraded_without_data_loss() {
+        task::block_on(async {
+            let dir = tempfile::tempdir().unwrap();
+            let (plain, hashed) = (dir.path().join("plain"), dir.path().join("hashed"));
+            let (ts1, ts2) = (new_reception_timestamp(), new_reception_timestamp());
+            let hash = ContentHash {
+                hash: "0123abcd".to_string(),
+                mtime: 42,
+            };
+            // a database of version 1: the data-infos without extras, no column families and no version
+            {
+                let db = DB::open_default(dir.path().join(DataInfoMgr::DB_FILENAME)).unwrap();
+                for (file, encoding, ts, content_hash, base64) in &[
+                    (&plain, Encoding::TEXT_PLAIN, &ts1, None, false),
+                    (&hashed, Encoding::APP_JSON, &ts2, Some(&hash), true),
+                ] {
+                    let value =
+                        encode_data_info(file, encoding, ts, *content_hash, *base64, None).unwrap();
+                    db.put(db_key(file), value.get_first_slice(..)).unwrap();
+                }
+                assert_eq!(read_schema_version(&db).unwrap(), 1);
+            }
+
+            let mgr = DataInfoMgr::new(dir.path()).await.unwrap();
+            assert_eq!(
+                read_schema_version(opened(&*mgr.db.lock().await).unwrap()).unwrap(),
+                SCHEMA_VERSION
+            );
+            assert_eq!(
+                mgr.get_encoding_and_timestamp(&plain).await.unwrap(),
+                Some((Encoding::TEXT_PLAIN, ts1))
+            );
+            assert_eq!(
+                mgr.get_encoding_and_timestamp(&hashed).await.unwrap(),
+                Some((Encoding::APP_JSON, ts2))
+            );
+            let (_, content_hash) = mgr.get_content_hash(&hashed).await.unwrap().unwrap();
+            assert_eq!(
+                (content_hash.hash.as_str(), content_hash.mtime),
+                ("0123abcd", 42)
+            );
+            assert!(mgr.is_base64_encoded(&hashed).await.unwrap());
+            assert!(!mgr.is_base64_encoded(&plain).await.unwrap());
+            assert!(mgr.get_extras(&plain).await.unwrap().unwrap().is_empty());
+
+            // the extras are added after the fields of the version 1, which are kept
+            assert!(mgr
+                .update_extras(&hashed, |extras| {
+                    extras.etag = Some("etag".to_string());
+                    extras.tags.push(("owner".to_string(), "ops".to_string()));
+                })
+                .await
+                .unwrap());
+            let extras = mgr.get_extras(&hashed).await.unwrap().unwrap();
+            assert_eq!(extras.etag.as_deref(), Some("etag"));
+            assert_eq!(extras.tags, vec![("owner".to_string(), "ops".to_string())]);
+            assert_eq!(
+                mgr.get_encoding_and_timestamp(&hashed).await.unwrap(),
+                Some((Encoding::APP_JSON, ts2))
+            );
+            let (_, content_hash) = mgr.get_content_hash(&hashed).await.unwrap().unwrap();
+            assert_eq!(
+                (content_hash.hash.as_str(), content_hash.mtime),
+                ("0123abcd", 42)
+            );
+            assert!(mgr.is_base64_encoded(&hashed).await.unwrap());
+        })
+    }
+
+    #[test]
+    fn a_more_recent_database_is_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(check_schema_version(SCHEMA_VERSION, dir.path()).is_ok());
+        assert!(check_schema_version(1, dir.path()).is_ok());
+        assert!(check_schema_version(SCHEMA_VERSION + 1, dir.path()).is_err());
+    }
+
+    #[test]
+    fn files_are_indexed_by_encoding() {
+        task::block_on(async {
+            let dir = tempfile::tempdir().unwrap();
+            let mgr = DataInfoMgr::new(dir.path()).await.unwrap();
+            let encodings = [
+                Encoding::APP_JSON,
+                Encoding::TEXT_PLAIN,
+                Encoding::from("text/plain;charset=utf-8"),
+            ];
+            let mut files = Vec::new();
+            for i in 0..9 {
+                let file = dir.path().join(format!("file{}", i));
+                std::fs::write(&file, "").unwrap();
+                mgr.put_data_info(&file, &encodings[i % 3], &new_reception_timestamp())
+                    .await
+                    .unwrap();
+                files.push(file);
+            }
+            // a file re-written with another encoding is only indexed with the new one
+            mgr.put_data_info(&files[0], &encodings[1], &new_reception_timestamp())
+                .await
+                .unwrap();
+            // a deleted file is not listed
+            std::fs::remove_file(&files[8]).unwrap();
+
+            let by_encoding = |i: usize| {
+                let mgr = &mgr;
+                let encoding = encodings[i].clone();
+                async move {
+                    let mut files = mgr.get_by_encoding(&encoding).await.unwrap();
+                    files.sort();
+                    files
+                }
+            };
+            assert_eq!(
+                by_encoding(0).await,
+                vec![files[3].clone(), files[6].clone()]
+            );
+            assert_eq!(
+                by_encoding(1).await,
+                vec![
+                    files[0].clone(),
+                    files[1].clone(),
+                    files[4].clone(),
+                    files[7].clone()
+                ]
+            );
+            assert_eq!(
+                by_encoding(2).await,
+                vec![files[2].clone(), files[5].clone()]
+            );
+        })
+    }
+
+    #[test]
+    fn all_the_records_are_restored_from_a_backup() {
+        task::block_on(async {
+            let (dir, backup_dir) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+            let backup = backup_dir.path().join("datainfo");
+            let mgr = DataInfoMgr::new(dir.path()).await.unwrap();
+            let mut records = Vec::new();
+            for i in 0..100 {
+                let file = dir.path().join(format!("file{}", i));
+                let ts = new_reception_timestamp();
+                mgr.put_data_info(&file, &Encoding::APP_JSON, &ts)
+                    .await
+                    .unwrap();
+                records.push((file, ts));
+            }
+            mgr.put_retention("file0", 1234).await.unwrap();
+            mgr.put_conflict(b"conflict", 10).await.unwrap();
+            mgr.backup_to(&backup).await.unwrap();
+
+            // records lost or modified after the backup
+            for (file, _) in &records[..50] {
+                mgr.del_data_info(file).await.unwrap();
+            }
+            mgr.del_retention("file0").await.unwrap();
+            mgr.put_retention("file1", 5678).await.unwrap();
+            for (file, _) in &records[50..] {
+                mgr.put_data_info(file, &Encoding::TEXT_PLAIN, &new_reception_timestamp())
+                    .await
+                    .unwrap();
+            }
+
+            mgr.restore_from(dir.path(), &backup).await.unwrap();
+            for (file, ts) in &records {
+                assert_eq!(
+                    mgr.get_encoding_and_timestamp(file).await.unwrap(),
+                    Some((Encoding::APP_JSON, *ts))
+                );
+            }
+            // the records of the other column families too
+            assert_eq!(mgr.get_retention("file0").await.unwrap(), Some(1234));
+            assert_eq!(mgr.get_retention("file1").await.unwrap(), None);
+            assert_eq!(
+                mgr.get_conflicts(10).await.unwrap(),
+                vec![b"conflict".to_vec()]
+            );
+            let db = mgr.db.lock().await;
+            assert_eq!(
+                read_schema_version(opened(&db).unwrap()).unwrap(),
+                SCHEMA_VERSION
+            );
+        })
+    }
+
+    #[test]
+    fn lookups_run_between_the_chunks_of_a_scan() {
+        use async_std::future::poll_fn;
+        use std::future::Future;
+        use std::task::Poll;
+        task::block_on(async {
+            let dir = tempfile::tempdir().unwrap();
+            let mgr = DataInfoMgr::new(dir.path()).await.unwrap();
+            // tombstones of 2.5 chunks
+            let count = SCAN_CHUNK_SIZE * 5 / 2;
+            for i in 0..count {
+                mgr.put_data_info(
+                    dir.path().join(format!("file{:05}", i)),
+                    &KnownEncoding::Empty.into(),
+                    &new_reception_timestamp(),
+                )
+                .await
+                .unwrap();
+            }
+            let looked_up = dir.path().join("file00000");
+
+            // each time the scan is pending, a lookup of a data-info is performed
+            let mut scan = Box::pin(mgr.get_deleted_entries(None));
+            let mut lookups = 0;
+            let entries = loop {
+                let polled = poll_fn(|cx| {
+                    Poll::Ready(match scan.as_mut().poll(cx) {
+                        Poll::Ready(entries) => Some(entries),
+                        Poll::Pending => None,
+                    })
+                })
+                .await;
+                match polled {
+                    Some(entries) => break entries,
+                    None => {
+                        assert!(mgr.get_timestamp(&looked_up).await.unwrap().is_some());
+                        lookups += 1;
+                    }
+                }
+            };
+            assert_eq!(entries.len(), count);
+            // the scan yielded between each of its 3 chunks
+            assert_eq!(lookups, 2);
+        })
+    }
+
+    #[test]
+    fn a_paced_scan_sleeps_between_its_chunks_with_the_database_released() {
+        use async_std::future::poll_fn;
+        use std::future::Future;
+        use std::task::Poll;
+        use std::time::Instant;
+        task::block_on(async {
+            let dir = tempfile::tempdir().unwrap();
+            let mgr = DataInfoMgr::new(dir.path()).await.unwrap();
+            // tombstones of 2.5 chunks
+            let count = SCAN_CHUNK_SIZE * 5 / 2;
+            for i in 0..count {
+                mgr.put_data_info(
+                    dir.path().join(format!("file{:05}", i)),
+                    &KnownEncoding::Empty.into(),
+                    &new_reception_timestamp(),
+                )
+                .await
+                .unwrap();
+            }
+            let pause = Duration::from_millis(200);
+            let start = Instant::now();
+            let mut scan = Box::pin(mgr.get_deleted_entries(Some(pause)));
+            // the scan pauses after its first chunk
+            let pending = poll_fn(|cx| Poll::Ready(scan.as_mut().poll(cx).is_pending())).await;
+            assert!(pending);
+            // meanwhile, the database can be used
+            let lookup = Instant::now();
+            assert!(mgr
+                .get_timestamp(&dir.path().join("file00000"))
+                .await
+                .unwrap()
+                .is_some());
+            assert!(lookup.elapsed() < pause);
+            let entries = scan.await;
+            assert_eq!(entries.len(), count);
+            // (paused between each of its 3 chunks)
+            assert!(start.elapsed() >= 2 * pause);
+        })
+    }
+}
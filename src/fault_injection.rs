@@ -0,0 +1,130 @@
+//
+// Copyright (c) 2022 ZettaScale Technology
+//
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+// Contributors:
+//   ZettaScale Zenoh Team, <zenoh@zettascale.tech>
+//
+
+// Operations of the write path where a fault can be injected, to test the behaviour of the storage
+// in the crash windows between them. Without the "fault-injection" feature, fault_point() is a no-op.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum FaultPoint {
+    // write of a file's content (in place, or in a temporary file with "pre_write_hook")
+    FileWrite,
+    // rename of a file (replacement by a temporary file, or move to a conflict file)
+    Rename,
+    // put of a data-info (or a tombstone) in the database
+    DbPut,
+    // sync of a file or a directory, with "fsync"
+    Fsync,
+}
+
+#[cfg(not(feature = "fault-injection"))]
+#[inline(always)]
+pub(crate) fn fault_point(_point: FaultPoint) -> std::io::Result<()> {
+    Ok(())
+}
+
+#[cfg(feature = "fault-injection")]
+pub(crate) use injection::*;
+
+#[cfg(feature = "fault-injection")]
+mod injection {
+    use super::FaultPoint;
+    use std::collections::HashMap;
+    use std::io::{Error, ErrorKind};
+    use std::sync::Mutex;
+    use std::time::Duration;
+    use tracing::warn;
+
+    // The environment variable configuring the faults injected at startup,
+    // as a comma-separated list of `<point>=<fault>` (e.g. "rename=fail,db_put=delay:500,fsync=abort")
+    pub(crate) const FAULTS_ENV_VAR: &str = "ZBACKEND_FS_FAULTS";
+
+    #[derive(Clone, Copy, Debug)]
+    pub(crate) enum Fault {
+        // the operation fails with an I/O error
+        Fail,
+        // the operation is delayed (blocking the calling thread, as a slow disk would do)
+        Delay(Duration),
+        // the process aborts before the operation, simulating a crash
+        Abort,
+    }
+
+    lazy_static::lazy_static! {
+        static ref FAULTS: Mutex<HashMap<FaultPoint, Fault>> = Mutex::new(faults_from_env());
+    }
+
+    // Inject a fault on each operation at this point, until cleared
+    #[allow(dead_code)]
+    pub(crate) fn inject(point: FaultPoint, fault: Fault) {
+        FAULTS.lock().unwrap().insert(point, fault);
+    }
+
+    // Clear the fault injected at this point
+    #[allow(dead_code)]
+    pub(crate) fn clear(point: FaultPoint) {
+        FAULTS.lock().unwrap().remove(&point);
+    }
+
+    pub(crate) fn fault_point(point: FaultPoint) -> std::io::Result<()> {
+        let fault = FAULTS.lock().unwrap().get(&point).copied();
+        match fault {
+            Some(Fault::Fail) => Err(Error::new(
+                ErrorKind::Other,
+                format!("fault injected at {:?}", point),
+            )),
+            Some(Fault::Delay(delay)) => {
+                std::thread::sleep(delay);
+                Ok(())
+            }
+            Some(Fault::Abort) => {
+                warn!("Fault injected at {:?}: abort", point);
+                std::process::abort()
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn faults_from_env() -> HashMap<FaultPoint, Fault> {
+        let mut faults = HashMap::new();
+        let spec = match std::env::var(FAULTS_ENV_VAR) {
+            Ok(spec) => spec,
+            Err(_) => return faults,
+        };
+        for item in spec.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            match parse_fault(item) {
+                Some((point, fault)) => {
+                    faults.insert(point, fault);
+                }
+                None => warn!("Invalid fault {:?} in {} - ignored", item, FAULTS_ENV_VAR),
+            }
+        }
+        faults
+    }
+
+    fn parse_fault(item: &str) -> Option<(FaultPoint, Fault)> {
+        let (point, fault) = item.split_once('=')?;
+        let point = match point {
+            "file_write" => FaultPoint::FileWrite,
+            "rename" => FaultPoint::Rename,
+            "db_put" => FaultPoint::DbPut,
+            "fsync" => FaultPoint::Fsync,
+            _ => return None,
+        };
+        let fault = match fault.split_once(':') {
+            None if fault == "fail" => Fault::Fail,
+            None if fault == "abort" => Fault::Abort,
+            Some(("delay", ms)) => Fault::Delay(Duration::from_millis(ms.parse().ok()?)),
+            _ => return None,
+        };
+        Some((point, fault))
+    }
+}
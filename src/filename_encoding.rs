@@ -0,0 +1,208 @@
+//
+// Copyright (c) 2022 ZettaScale Technology
+//
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+// Contributors:
+//   ZettaScale Zenoh Team, <zenoh@zettascale.tech>
+//
+use std::borrow::Cow;
+use std::ffi::{OsStr, OsString};
+use std::path::Path;
+
+use crate::files_mgt::{fspath_to_zpath, zpath_to_fspath};
+
+// The character escaping the bytes of a file name in a key, in "os_string" mode
+#[cfg(unix)]
+const ESCAPE_CHAR: char = '%';
+
+// How the file names are converted from and to the zenoh keys ("filename_encoding")
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum FilenameEncoding {
+    // the file names are the keys, as UTF-8: the files whose name is not valid UTF-8 are ignored
+    Utf8,
+    // the file names are arbitrary byte sequences (Unix only): in the keys, the bytes which are not valid UTF-8
+    // and the '%' are escaped as "%XX" (e.g. the file "caf\xE9" is the key "caf%E9", the file "100%" is "100%25")
+    OsString,
+}
+
+impl FilenameEncoding {
+    // Convert a zenoh path to a (relative) file path
+    pub(crate) fn zpath_to_fspath(self, zpath: &str) -> Cow<'_, OsStr> {
+        match self {
+            FilenameEncoding::Utf8 => match zpath_to_fspath(zpath) {
+                Cow::Borrowed(s) => Cow::Borrowed(OsStr::new(s)),
+                Cow::Owned(s) => Cow::Owned(OsString::from(s)),
+            },
+            FilenameEncoding::OsString => Cow::Owned(unescape_os_string(zpath)),
+        }
+    }
+
+    // Convert the part of a file path after its first prefix_len bytes (e.g. the storage directory)
+    // to a coarse zenoh path, or None if it can't be converted (a file name not valid UTF-8, in "utf8" mode)
+    pub(crate) fn fspath_to_zpath(self, fspath: &Path, prefix_len: usize) -> Option<Cow<'_, str>> {
+        match self {
+            FilenameEncoding::Utf8 => fspath.to_str()?.get(prefix_len..).map(fspath_to_zpath),
+            FilenameEncoding::OsString => escape_os_str(fspath.as_os_str(), prefix_len),
+        }
+    }
+
+    // Check if a zenoh path is the conversion of a file path. In "os_string" mode, each '%' in the key
+    // must escape a byte (other than '/' and '\0') with 2 uppercase hexadecimal digits.
+    pub(crate) fn is_encodable(self, zpath: &str) -> bool {
+        match self {
+            FilenameEncoding::Utf8 => true,
+            FilenameEncoding::OsString => {
+                let fspath = unescape_os_string(zpath);
+                escape_os_str(&fspath, 0).map_or(false, |escaped| escaped == zpath)
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn escape_os_str(fspath: &OsStr, prefix_len: usize) -> Option<Cow<'_, str>> {
+    use std::fmt::Write;
+    use std::os::unix::ffi::OsStrExt;
+
+    let mut bytes = fspath.as_bytes().get(prefix_len..)?;
+    match std::str::from_utf8(bytes) {
+        Ok(s) if !s.contains(ESCAPE_CHAR) => return Some(Cow::from(s)),
+        _ => (),
+    }
+    let mut zpath = String::with_capacity(bytes.len() * 3);
+    loop {
+        let (valid, invalid) = match std::str::from_utf8(bytes) {
+            Ok(s) => (s, &[][..]),
+            Err(e) => {
+                // the bytes up to valid_up_to() are valid UTF-8
+                let valid = unsafe { std::str::from_utf8_unchecked(&bytes[..e.valid_up_to()]) };
+                let invalid_len = e.error_len().unwrap_or(bytes.len() - e.valid_up_to());
+                (
+                    valid,
+                    &bytes[e.valid_up_to()..e.valid_up_to() + invalid_len],
+                )
+            }
+        };
+        for c in valid.chars() {
+            if c == ESCAPE_CHAR {
+                zpath.push_str("%25");
+            } else {
+                zpath.push(c);
+            }
+        }
+        for b in invalid {
+            let _ = write!(zpath, "%{:02X}", b);
+        }
+        bytes = &bytes[valid.len() + invalid.len()..];
+        if bytes.is_empty() {
+            return Some(Cow::from(zpath));
+        }
+    }
+}
+
+#[cfg(unix)]
+fn unescape_os_string(zpath: &str) -> OsString {
+    use std::os::unix::ffi::OsStringExt;
+
+    let bytes = zpath.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == ESCAPE_CHAR as u8 {
+            // '/' and '\0' are not unescaped, as a file name can't contain them
+            match zpath
+                .get(i + 1..i + 3)
+                .filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()))
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                Some(b) if b != b'/' && b != 0 => {
+                    result.push(b);
+                    i += 3;
+                    continue;
+                }
+                _ => (),
+            }
+        }
+        result.push(bytes[i]);
+        i += 1;
+    }
+    OsString::from_vec(result)
+}
+
+// On Windows, the file names are always UTF-16 converted from the keys ("os_string" mode is refused)
+#[cfg(windows)]
+fn escape_os_str(fspath: &OsStr, prefix_len: usize) -> Option<Cow<'_, str>> {
+    fspath.to_str()?.get(prefix_len..).map(fspath_to_zpath)
+}
+
+#[cfg(windows)]
+fn unescape_os_string(zpath: &str) -> OsString {
+    OsString::from(zpath_to_fspath(zpath).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn non_bmp_keys_round_trip() {
+        for encoding in [FilenameEncoding::Utf8, FilenameEncoding::OsString] {
+            for zpath in ["\u{1F600}/\u{1D11E}", "a/\u{10FFFF}b", "caf\u{E9}"] {
+                let fspath = encoding.zpath_to_fspath(zpath);
+                assert_eq!(
+                    encoding.fspath_to_zpath(Path::new(&*fspath), 0).as_deref(),
+                    Some(zpath)
+                );
+                assert!(encoding.is_encodable(zpath));
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_file_names_round_trip() {
+        use std::os::unix::ffi::{OsStrExt, OsStringExt};
+
+        let encoding = FilenameEncoding::OsString;
+        for (bytes, zpath) in [
+            (&b"caf\xE9"[..], "caf%E9"),
+            (&b"100%"[..], "100%25"),
+            (&b"a/\xFF\xFE/b"[..], "a/%FF%FE/b"),
+            (&b"\xF0\x9F\x98"[..], "%F0%9F%98"),
+            (&b"\xF0\x9F\x98\x80%\xC0"[..], "\u{1F600}%25%C0"),
+        ] {
+            let fspath = OsString::from_vec(bytes.to_vec());
+            assert_eq!(
+                encoding.fspath_to_zpath(Path::new(&fspath), 0).as_deref(),
+                Some(zpath)
+            );
+            assert_eq!(encoding.zpath_to_fspath(zpath).as_bytes(), bytes);
+            assert!(encoding.is_encodable(zpath));
+        }
+        // the prefix (e.g. the storage directory) is not part of the key
+        let fspath = OsString::from_vec(b"/base/caf\xE9".to_vec());
+        assert_eq!(
+            encoding
+                .fspath_to_zpath(Path::new(&fspath), "/base/".len())
+                .as_deref(),
+            Some("caf%E9")
+        );
+        // in "utf8" mode, the file names not valid UTF-8 are ignored
+        let fspath = OsString::from_vec(b"caf\xE9".to_vec());
+        assert_eq!(
+            FilenameEncoding::Utf8.fspath_to_zpath(Path::new(&fspath), 0),
+            None
+        );
+        // the keys not in the canonical form are not encodable
+        for zpath in ["caf%e9", "100%", "%2F", "%00", "%G0", "%E"] {
+            assert!(!encoding.is_encodable(zpath), "{}", zpath);
+            assert!(FilenameEncoding::Utf8.is_encodable(zpath));
+        }
+    }
+}
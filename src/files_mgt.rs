@@ -12,31 +12,254 @@
 //   ZettaScale Zenoh Team, <zenoh@zettascale.tech>
 //
 use async_std::task;
-use log::{debug, trace, warn};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use flate2::read::MultiGzDecoder;
+use fs2::FileExt;
+use glob::{glob_with, MatchOptions, Paths, Pattern};
+use rayon::prelude::*;
+use sha2::{Digest, Sha256};
 use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::convert::TryFrom;
 use std::fmt;
-use std::fs::{metadata, remove_dir, remove_dir_all, remove_file, rename, DirBuilder, File};
+use std::fs::{
+    hard_link, metadata, remove_dir, remove_dir_all, remove_file, rename, DirBuilder, File,
+};
+use std::future::Future;
 use std::io::prelude::*;
 use std::iter::Iterator;
 use std::path::{Path, PathBuf};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{debug, error, trace, warn};
 use walkdir::{IntoIter, WalkDir};
 use zenoh::buffers::ZBuf;
 use zenoh::prelude::*;
-use zenoh::time::{Timestamp, TimestampId};
+use zenoh::time::{new_reception_timestamp, Timestamp, TimestampId, NTP64};
 use zenoh::Result as ZResult;
 use zenoh_core::{bail, zerror};
 
+use crate::backpressure::*;
 use crate::data_info_mgt::*;
+use crate::fault_injection::*;
+use crate::filename_encoding::*;
+use crate::io_watchdog::*;
+use crate::key_locks::*;
+use crate::mapped_files::*;
+use crate::mime_guessing::*;
+use crate::path_template::*;
+use crate::read_cache::*;
+use crate::rotation::*;
+use crate::storage_events::*;
+use crate::text_detection::*;
+use crate::write_hook::*;
 
 pub const CONFLICT_SUFFIX: &str = ".##z";
 
+// Name of the marker file recording the shard depth used to store the files
+pub(crate) const SHARD_DEPTH_FILENAME: &str = ".zenoh_shard_depth";
+
+// Maximum shard depth (each level consumes 1 byte of the 64 bits hash)
+pub(crate) const MAX_SHARD_DEPTH: usize = 8;
+
+// Prefix of the storage's internal files and directories (data-info database, marker files...).
+// Those are always reserved, whatever the configured reserved prefix.
+pub(crate) const INTERNAL_PREFIX: &str = ".zenoh";
+
+// Default prefix reserved for the storage's internal use
+pub(crate) const DEFAULT_RESERVED_PREFIX: &str = INTERNAL_PREFIX;
+
+// Delay after which the modification time of a directory is considered settled, by "watch_external_deletes_ms"
+const DIR_MTIME_SETTLE: Duration = Duration::from_secs(2);
+
+// Maximum age of the count of files used by the entry count estimate, before the files are counted again
+const FILE_COUNT_MAX_AGE: Duration = Duration::from_secs(60);
+
+// Number of consecutive failures with a permanent error after which a file is quarantined
+const QUARANTINE_AFTER_FAILURES: u32 = 3;
+
+// Name of the directory where the unreadable files are moved if "quarantine_unreadable" is enabled
+pub(crate) const QUARANTINE_DIRNAME: &str = ".zenoh_quarantine";
+
+// Prefix of the temporary files written before they replace a stored file (or before the "pre_write_hook" is run)
+const WRITE_TMP_PREFIX: &str = ".zenoh_write_";
+
+// Prefix of the temporary files written by a transaction, until it's committed
+const TX_TMP_PREFIX: &str = ".zenoh_tx_";
+
+// Suffix of the encoding of a ".gz" file replied compressed (to a query with "_accept_compressed")
+pub(crate) const GZIP_ENCODING_SUFFIX: &str = ";content-encoding=gzip";
+
+// Period of the check that the background writes in progress are done, while the storage is being quiesced
+const QUIESCE_POLL_PERIOD: Duration = Duration::from_millis(10);
+
+// Period of the check that a quiesced storage was resumed, by a background task waiting to write
+const QUIESCED_WAIT_PERIOD: Duration = Duration::from_millis(500);
+
+// Number of files between 2 yields of get_storage_sizes()
+const SIZES_YIELD_PERIOD: usize = 1000;
+
+// With "encoding_subdirs", the sub-directories of the storage directory where the files are written
+// depending on their encoding (the tombstones being recorded in the default one)
+const JSON_SUBDIR: &str = "json";
+const TEXT_SUBDIR: &str = "text";
+const BINARY_SUBDIR: &str = "binary";
+const ENCODING_SUBDIRS: [&str; 3] = [JSON_SUBDIR, TEXT_SUBDIR, BINARY_SUBDIR];
+const DEFAULT_ENCODING_SUBDIR: &str = BINARY_SUBDIR;
+// The marker of a storage directory whose files are stored in encoding sub-directories
+const ENCODING_SUBDIRS_FILENAME: &str = ".zenoh_encoding_subdirs";
+// The marker of a replacement of a file by a copy in another encoding sub-directory, which might be interrupted
+// (renamed while the copies left by an interrupted replacement are removed)
+const ENCODING_REPLACED_FILENAME: &str = ".zenoh_encoding_replaced";
+const ENCODING_REPAIRING_FILENAME: &str = ".zenoh_encoding_replaced_repairing";
+
 pub(crate) enum OnClosure {
     DeleteAll,
+    // delete the content of the directory, but not the directory itself (e.g. a mount point)
+    DeleteContents,
     DoNothing,
 }
 
+// Policy to resolve the files existing both with and without the CONFLICT_SUFFIX
+pub(crate) enum OnAmbiguousFile {
+    KeepFile,
+    KeepConflictFile,
+    KeepNewest,
+}
+
+// Policy for a key whose data-info is the one of a PUT, but whose file doesn't exist at read time
+// (i.e. it was removed by another process)
+pub(crate) enum OnMissingFile {
+    // the key is absent
+    Absent,
+    // the key is absent, and an error is logged
+    Log,
+    // the key is absent, and its data-info is replaced by a tombstone
+    Tombstone,
+}
+
+// The stored files with a data-info, by directory, watched for their deletion by another process
+// ("watch_external_deletes_ms"). A deletion modifies the directory of the file: only the directories whose
+// modification time changed are listed again.
+#[derive(Default)]
+pub(crate) struct LiveFiles {
+    indexed: bool,
+    // for each directory: its modification time when it was listed (if settled), and its files with their timestamp
+    dirs: HashMap<PathBuf, (Option<SystemTime>, HashMap<PathBuf, Timestamp>)>,
+}
+
+// The access pattern of the files read, advised to the OS ("io_advice")
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum IoAdvice {
+    // no advice: the OS default read-ahead
+    Normal,
+    // the files are read whole, in bulk: more read-ahead, and the pages of the files read for a bulk reply
+    // are dropped from the page cache
+    Sequential,
+    // the files are read by random queries: no read-ahead
+    Random,
+}
+
+// Method used by FilesMgr::diff() to compare the files existing in both storages
+pub(crate) enum DiffMethod {
+    // compare the SHA-256 hashes of the files' content
+    Sha256,
+    // compare the timestamps of the files (from data-info, or from the files' modification time)
+    Mtime,
+}
+
+// Differences between 2 storage directories, with keys relative to the storages' directories
+pub(crate) struct StorageDiff {
+    pub(crate) only_in_self: Vec<OwnedKeyExpr>,
+    pub(crate) only_in_other: Vec<OwnedKeyExpr>,
+    // keys with a different content in both storages, with their timestamp in each storage
+    pub(crate) conflicts: Vec<(OwnedKeyExpr, Timestamp, Timestamp)>,
+}
+
+// A direct child of a key prefix, listed by list_directory()
+pub(crate) struct DirEntry {
+    pub(crate) key: OwnedKeyExpr,
+    // true if the child is a prefix of stored keys, false if it's a stored key
+    pub(crate) is_directory: bool,
+    // number of stored keys under the child (for a directory)
+    pub(crate) entry_count: Option<usize>,
+    // timestamp of the stored key, or the latest timestamp of the keys under the directory
+    pub(crate) last_modified: Option<Timestamp>,
+}
+
+// Checksum of all the files stored in a storage, with their timestamps
+pub(crate) struct StorageChecksum {
+    pub(crate) algorithm: &'static str,
+    pub(crate) value: String,
+    pub(crate) entry_count: u64,
+    pub(crate) computed_at: Timestamp,
+}
+
+// Result of the compaction of a storage's data-info database
+pub(crate) struct CompactReport {
+    // number of data-infos of a key recorded for both its file and its conflict file, only one of them existing
+    pub(crate) duplicates_removed: u64,
+    // number of data-infos of files which no longer exist (i.e. of old deletions)
+    pub(crate) orphans_removed: u64,
+    // number of stale entries of the index by encoding
+    pub(crate) index_entries_removed: u64,
+    pub(crate) records_kept: u64,
+}
+
+// Directories modified by file operations, to be synced for durability if "fsync" is enabled.
+// A bulk operation accumulates the directories modified by all its file operations,
+// and syncs each of them only once before returning.
+#[derive(Default)]
+pub(crate) struct DirtyDirs(HashSet<PathBuf>);
+
+impl DirtyDirs {
+    fn add(&mut self, dir: Option<&Path>) {
+        if let Some(dir) = dir {
+            if !self.0.contains(dir) {
+                self.0.insert(dir.to_path_buf());
+            }
+        }
+    }
+
+    fn merge(&mut self, other: DirtyDirs) {
+        self.0.extend(other.0);
+    }
+}
+
+// An operation staged in a transaction, to be applied when the transaction is committed
+pub(crate) enum StagedOp {
+    // the content is already written in a temporary file, which replaces the file on commit
+    Put {
+        file: PathBuf,
+        tmp: tempfile::NamedTempFile,
+        encoding: Encoding,
+        timestamp: Timestamp,
+        base64: bool,
+        // the size of the value, if it differs from the size of the written content
+        value_size: Option<u64>,
+    },
+    Delete {
+        file: PathBuf,
+        timestamp: Timestamp,
+    },
+}
+
+impl StagedOp {
+    pub(crate) fn file(&self) -> &Path {
+        match self {
+            StagedOp::Put { file, .. } | StagedOp::Delete { file, .. } => file,
+        }
+    }
+
+    pub(crate) fn timestamp(&self) -> &Timestamp {
+        match self {
+            StagedOp::Put { timestamp, .. } | StagedOp::Delete { timestamp, .. } => timestamp,
+        }
+    }
+}
+
 // a structure holding a zenoh path (absolute) and the corresponding file-system path (including the base_dir)
 #[derive(Debug)]
 pub(crate) struct ZFile<'a> {
@@ -44,40 +267,255 @@ pub(crate) struct ZFile<'a> {
     fspath: PathBuf,
 }
 
+// Traversal of the keys tree, for the code working on the hierarchy of the stored keys
+// (the root of the tree is the ZFile with an empty zenoh path, i.e. the storage directory)
+impl ZFile<'_> {
+    // Get the ZFile of the parent key, or None for the root
+    pub(crate) fn parent(&self, files_mgr: &FilesMgr) -> Option<ZFile<'_>> {
+        if self.zpath.is_empty() {
+            return None;
+        }
+        let parent = match self.zpath.rfind('/') {
+            Some(i) => &self.zpath[..i],
+            None => "",
+        };
+        Some(files_mgr.to_zfile(parent))
+    }
+
+    // Get the ZFiles of the direct children keys: the stored keys and the prefixes of stored keys, sorted by key
+    // (without the timestamps of list_directory(), which aren't read)
+    #[allow(dead_code)]
+    pub(crate) async fn children(&self, files_mgr: &FilesMgr) -> ZResult<Vec<ZFile<'static>>> {
+        let mut children: Vec<ZFile<'static>> = Vec::new();
+        for entry in files_mgr.list_directory_entries(&self.zpath, false).await? {
+            // a key listed both as a stored key and as a directory is a single child
+            if children
+                .last()
+                .map_or(true, |last| last.zpath != entry.key.as_str())
+            {
+                let fspath = files_mgr.to_fspath(entry.key.as_str());
+                children.push(ZFile {
+                    zpath: Cow::Owned(entry.key.to_string()),
+                    fspath,
+                });
+            }
+        }
+        Ok(children)
+    }
+}
+
 impl fmt::Display for ZFile<'_> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{:?}", self.fspath)
     }
 }
 
+// A write in progress in a background task, while the storage is not quiesced (see begin_background_write())
+pub(crate) struct BackgroundWrite<'a> {
+    count: &'a AtomicU64,
+}
+
+impl Drop for BackgroundWrite<'_> {
+    fn drop(&mut self) {
+        self.count.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+// A file held open with a shared lock (e.g. while replying to a query), so that it's read through
+// the handle opened when it was found, even if it's deleted or replaced meanwhile. The lock is released on drop.
+// With "mmap_reads", a mapped file is held by its mapping instead, without being opened nor locked.
+pub(crate) struct PinnedFile {
+    path: PathBuf,
+    source: PinnedSource,
+}
+
+enum PinnedSource {
+    Opened(File),
+    Mapped(Arc<Mapping>),
+}
+
+impl Drop for PinnedFile {
+    fn drop(&mut self) {
+        if let PinnedSource::Opened(file) = &self.source {
+            if let Err(e) = file.unlock() {
+                warn!("Failed to unlock file {:?}: {}", self.path, e);
+            }
+        }
+    }
+}
+
 pub(crate) struct FilesMgr {
     base_dir: PathBuf,
     data_info_mgr: DataInfoMgr,
     follow_links: bool,
     keep_mime: bool,
+    default_encoding: Encoding,
     dir_builder: DirBuilder,
     on_closure: OnClosure,
+    shard_depth: usize,
+    fsync: bool,
+    reserved_prefix: String,
+    quarantine_unreadable: bool,
+    // the quarantined files (relative to the quarantine directory), with the reason of their quarantine
+    quarantine: Mutex<HashMap<String, String>>,
+    // the number of consecutive failures to read each file, if "quarantine_unreadable" is enabled
+    read_failures: Mutex<HashMap<PathBuf, u32>>,
+    // the number of stored files, and when they were counted (for the entry count estimate)
+    file_count: Mutex<Option<(Instant, u64)>>,
+    rotation: Option<DirRotation>,
+    path_template: Option<Arc<PathTemplate>>,
+    // if true, a PUT with the same content and encoding than the stored file only updates its timestamp
+    skip_identical_writes: bool,
+    pre_write_hook: Option<Arc<WriteHook>>,
+    // if true, the ".gz" files placed without zenoh are replied decompressed
+    decompress_gz_on_read: bool,
+    // the maximum size of a decompressed ".gz" file, to not exhaust the memory with a gzip bomb
+    max_decompressed_bytes: Option<u64>,
+    // if true, the non-UTF-8 contents are written base64-encoded
+    text_safe_storage: bool,
+    // set if "text_detection" is enabled
+    text_detection: Option<TextDetection>,
+    // the guessing of the mime-types ("mime_source" and "encoding_map")
+    mime_guesser: MimeGuesser,
+    // set if "publish_events" is enabled
+    events: Option<StorageEvents>,
+    // set if "io_deadline_ms" is configured
+    io_watchdog: Option<IoWatchdog>,
+    // the extensions of the files not part of their keys ("strip_extension")
+    strip_extensions: Vec<String>,
+    filename_encoding: FilenameEncoding,
+    // the directory of the temporary files, if not the storage directory ("temp_dir")
+    temp_dir: Option<PathBuf>,
+    // if true, the files matching a key expression are searched with a glob pattern ("glob_mode")
+    glob_mode: bool,
+    // the permissions of the written files and created directories, on Unix ("default_file_mode", "default_dir_mode"),
+    // if configured (otherwise they're created with the default permissions of the process)
+    file_mode: Option<u32>,
+    dir_mode: Option<u32>,
+    on_missing_file: OnMissingFile,
+    io_advice: IoAdvice,
+    // the memory mappings of the files read via "mmap_reads", if enabled
+    mapped_files: Option<Arc<MappedFiles>>,
+    // if true, the files are written in a sub-directory depending on their encoding ("encoding_subdirs")
+    encoding_subdirs: bool,
+    // set while the storage is quiesced ("quiesce" operation)
+    quiesced: AtomicBool,
+    // number of writes in progress in background tasks (see begin_background_write())
+    background_writes: AtomicU64,
+    // per-file locks serializing the writes of the storage and of its background tasks (see lock_key())
+    key_locks: KeyLocks,
+    // set while base_dir is replaced (e.g. its volume was re-mounted), until it's consistent again
+    waiting: AtomicBool,
+    // number of times base_dir was detected as replaced
+    base_dir_transitions: AtomicU64,
+    base_dir_state: async_std::sync::Mutex<BaseDirState>,
+}
+
+// The identity of base_dir as device and inode numbers (only on Unix)
+type DirId = (u64, u64);
+
+struct BaseDirState {
+    // identity of base_dir when the data-info database was opened
+    id: Option<DirId>,
+    // identity of base_dir at the last check while waiting
+    last_seen: Option<DirId>,
 }
 
 impl FilesMgr {
+    #[allow(clippy::too_many_arguments)]
     pub(crate) async fn new(
         base_dir: PathBuf,
         follow_links: bool,
         keep_mime: bool,
+        default_encoding: Encoding,
         on_closure: OnClosure,
+        shard_depth: usize,
+        fsync: bool,
+        reserved_prefix: String,
+        quarantine_unreadable: bool,
+        rotation: Option<DirRotation>,
+        path_template: Option<PathTemplate>,
+        skip_identical_writes: bool,
+        pre_write_hook: Option<WriteHook>,
+        decompress_gz_on_read: bool,
+        max_decompressed_bytes: Option<u64>,
+        text_safe_storage: bool,
+        text_detection: Option<TextDetection>,
+        events: Option<StorageEvents>,
+        io_watchdog: Option<IoWatchdog>,
+        strip_extensions: Vec<String>,
+        filename_encoding: FilenameEncoding,
+        temp_dir: Option<PathBuf>,
+        glob_mode: bool,
+        file_mode: Option<u32>,
+        dir_mode: Option<u32>,
+        on_missing_file: OnMissingFile,
+        mime_guesser: MimeGuesser,
+        io_advice: IoAdvice,
+        mapped_files: Option<MappedFiles>,
+        encoding_subdirs: bool,
     ) -> ZResult<Self> {
+        check_shard_depth(base_dir.as_path(), shard_depth)?;
+        check_encoding_subdirs(base_dir.as_path(), encoding_subdirs)?;
         let data_info_mgr = DataInfoMgr::new(base_dir.as_path()).await?;
+        let quarantine = data_info_mgr.get_quarantine().await?;
 
         let mut dir_builder = DirBuilder::new();
         dir_builder.recursive(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::DirBuilderExt;
+            if let Some(mode) = dir_mode {
+                dir_builder.mode(mode);
+            }
+        }
+        let base_dir_id = dir_id(&base_dir);
 
         Ok(FilesMgr {
             base_dir,
             data_info_mgr,
             follow_links,
             keep_mime,
+            default_encoding,
             dir_builder,
             on_closure,
+            shard_depth,
+            fsync,
+            reserved_prefix,
+            quarantine_unreadable,
+            quarantine: Mutex::new(quarantine),
+            read_failures: Mutex::new(HashMap::new()),
+            file_count: Mutex::new(None),
+            rotation,
+            path_template: path_template.map(Arc::new),
+            skip_identical_writes,
+            pre_write_hook: pre_write_hook.map(Arc::new),
+            decompress_gz_on_read,
+            max_decompressed_bytes,
+            text_safe_storage,
+            text_detection,
+            mime_guesser,
+            events,
+            io_watchdog,
+            strip_extensions,
+            filename_encoding,
+            temp_dir,
+            glob_mode,
+            file_mode,
+            dir_mode,
+            on_missing_file,
+            io_advice,
+            mapped_files: mapped_files.map(Arc::new),
+            encoding_subdirs,
+            quiesced: AtomicBool::new(false),
+            background_writes: AtomicU64::new(0),
+            key_locks: KeyLocks::default(),
+            waiting: AtomicBool::new(false),
+            base_dir_transitions: AtomicU64::new(0),
+            base_dir_state: async_std::sync::Mutex::new(BaseDirState {
+                id: base_dir_id,
+                last_seen: None,
+            }),
         })
     }
 
@@ -85,6 +523,70 @@ impl FilesMgr {
         self.base_dir.as_path()
     }
 
+    pub(crate) fn has_rotation(&self) -> bool {
+        self.rotation.is_some()
+    }
+
+    pub(crate) fn has_encoding_subdirs(&self) -> bool {
+        self.encoding_subdirs
+    }
+
+    // Check if the files are stored in sub-directories of base_dir which are not part of their zenoh path:
+    // the rotation directories, or the "encoding_subdirs"
+    fn has_top_dirs(&self) -> bool {
+        self.rotation.is_some() || self.encoding_subdirs
+    }
+
+    // Quiesce the storage (e.g. before a backup of its directory): the writes are refused, the writes of
+    // the background tasks in progress are waited for, and the data-info database is flushed to its files.
+    // The directory is then at rest, until resume() is called.
+    pub(crate) async fn quiesce(&self) -> ZResult<()> {
+        self.quiesced.store(true, Ordering::SeqCst);
+        self.data_info_mgr.set_paused(true);
+        while self.background_writes.load(Ordering::SeqCst) > 0 {
+            task::sleep(QUIESCE_POLL_PERIOD).await;
+        }
+        self.data_info_mgr.flush().await
+    }
+
+    pub(crate) fn resume(&self) {
+        self.data_info_mgr.set_paused(false);
+        self.quiesced.store(false, Ordering::SeqCst);
+    }
+
+    pub(crate) fn is_quiesced(&self) -> bool {
+        self.quiesced.load(Ordering::SeqCst)
+    }
+
+    // Start a write of a background task (e.g. a batch of a bulk deletion), or return None while the storage
+    // is quiesced. The write is in progress until the returned guard is dropped.
+    pub(crate) fn begin_background_write(&self) -> Option<BackgroundWrite<'_>> {
+        self.background_writes.fetch_add(1, Ordering::SeqCst);
+        if self.is_quiesced() {
+            self.background_writes.fetch_sub(1, Ordering::SeqCst);
+            return None;
+        }
+        Some(BackgroundWrite {
+            count: &self.background_writes,
+        })
+    }
+
+    // Wait until a write of a background task can start, i.e. until the storage is resumed if it's quiesced
+    pub(crate) async fn wait_background_write(&self) -> BackgroundWrite<'_> {
+        loop {
+            if let Some(write) = self.begin_background_write() {
+                return write;
+            }
+            task::sleep(QUIESCED_WAIT_PERIOD).await;
+        }
+    }
+
+    // Wait until the lock of a key's file is acquired, so its timestamp check and its write are not interleaved with
+    // another writer of the same file (a PUT or DELETE, or a background task). It's released when the guard is dropped.
+    pub(crate) async fn lock_key(&self, zfile: &ZFile<'_>) -> KeyLockGuard {
+        self.key_locks.lock(&zfile.fspath).await
+    }
+
     pub(crate) fn to_zfile<'a>(&self, zpath: &'a str) -> ZFile<'a> {
         ZFile {
             zpath: Cow::from(zpath),
@@ -92,9 +594,192 @@ impl FilesMgr {
         }
     }
 
+    // Check if a zenoh path (without the shard directories) corresponds to a path reserved for
+    // the storage's internal use, and thus which cannot be written, deleted or queried via zenoh
+    pub(crate) fn is_reserved_path(&self, zpath: &str) -> bool {
+        is_reserved_path(zpath, &self.reserved_prefix)
+    }
+
+    // Check if a zenoh path can be converted to a file name with the "filename_encoding"
+    pub(crate) fn is_encodable_path(&self, zpath: &str) -> bool {
+        self.filename_encoding.is_encodable(zpath)
+    }
+
+    // Check if a zenoh path can be converted to a file path (i.e. it matches the path template, if any)
+    pub(crate) fn is_mappable_path(&self, zpath: &str) -> bool {
+        match &self.path_template {
+            Some(template) => template.to_path(zpath).is_some(),
+            None => true,
+        }
+    }
+
+    // With "strip_extension", get the stripped extension a zenoh path ends with, if any: its file would be
+    // listed as another key (e.g. the file of "a.json" is listed as "a")
+    pub(crate) fn stripped_extension_of(&self, zpath: &str) -> Option<&str> {
+        stripped_extension(zpath, &self.strip_extensions)
+    }
+
+    // Set the "default_file_mode" permissions of a written file, if configured (on Unix: on the other platforms,
+    // this is a no-op). The files are created with these permissions, but they're also restricted by the umask.
+    fn set_file_mode(&self, file: &Path) -> ZResult<()> {
+        match self.file_mode {
+            Some(mode) => set_mode(file, mode).map_err(|e| {
+                zerror!(
+                    "Failed to set permissions {:o} of file {:?}: {}",
+                    mode,
+                    file,
+                    e
+                )
+                .into()
+            }),
+            None => Ok(()),
+        }
+    }
+
+    // Create a directory and its missing parents, with the "default_dir_mode" permissions if configured (on Unix)
+    fn create_dirs(&self, dir: &Path) -> std::io::Result<()> {
+        let new_dirs: Vec<PathBuf> = dir
+            .ancestors()
+            .take_while(|d| !d.exists())
+            .map(Path::to_path_buf)
+            .collect();
+        self.dir_builder.create(dir)?;
+        // (the mode given at creation is restricted by the umask)
+        if let Some(mode) = self.dir_mode {
+            for new_dir in new_dirs {
+                set_mode(&new_dir, mode)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Create the directory for a zenoh path (e.g. the static part of a "schema"), if the keys are stored
+    // at their path, i.e. without shard directories, path template, dir rotation nor encoding sub-directories.
+    // Return false otherwise.
+    pub(crate) fn create_key_dir(&self, zpath: &str) -> ZResult<bool> {
+        if self.shard_depth > 0 || self.path_template.is_some() || self.has_top_dirs() {
+            return Ok(false);
+        }
+        let dir = self.to_dir_fspath(zpath);
+        self.create_dirs(&dir)
+            .map_err(|e| zerror!("Failed to create directory {:?}: {}", dir, e))?;
+        Ok(true)
+    }
+
+    // Convert a zenoh path to the corresponding file path relative to base_dir (and to the rotation directory)
+    // as a zenoh path: using the path template if any, or else inserting the shard directories.
+    // A zenoh path not matching the path template is kept as is.
+    fn map_zpath<'a>(&self, zpath: &'a str) -> Cow<'a, str> {
+        match &self.path_template {
+            Some(template) => template.to_path(zpath).map_or(Cow::from(zpath), Cow::from),
+            None => shard_zpath(zpath, self.shard_depth),
+        }
+    }
+
+    // Convert a zenoh path to the path of the corresponding file (including the shard directories).
+    // With dir rotation, this is the file in the newest rotation directory containing it,
+    // or else in the current rotation directory. With "encoding_subdirs", this is the file in
+    // the encoding sub-directory containing it, or else in the default one.
     fn to_fspath(&self, zpath: &str) -> PathBuf {
+        let sharded_zpath = self.map_zpath(zpath);
+        match &self.rotation {
+            // (if a replacement was interrupted, the key is stored in several sub-directories: the newest file is used)
+            None if self.encoding_subdirs => ENCODING_SUBDIRS
+                .iter()
+                .map(|dir| self.with_stored_extension(self.to_rotated_fspath(dir, &sharded_zpath)))
+                .filter_map(|file| {
+                    let conflict_file = self.get_conflict_file(file.clone());
+                    let mtime = if file.is_file() {
+                        file_mtime(&file)
+                    } else if conflict_file.is_file() {
+                        file_mtime(&conflict_file)
+                    } else {
+                        return None;
+                    };
+                    Some((mtime, file))
+                })
+                .max_by_key(|(mtime, _)| *mtime)
+                .map(|(_, file)| file)
+                .unwrap_or_else(|| self.to_rotated_fspath(DEFAULT_ENCODING_SUBDIR, &sharded_zpath)),
+            None => self.with_stored_extension(self.to_dir_fspath(&sharded_zpath)),
+            Some(rotation) => self
+                .rotation_dirs()
+                .iter()
+                .map(|dir| self.to_rotated_fspath(dir, &sharded_zpath))
+                .find(|file| file.is_file() || self.get_conflict_file(file.clone()).is_file())
+                .unwrap_or_else(|| {
+                    self.to_rotated_fspath(&rotation.current_dir_name(), &sharded_zpath)
+                }),
+        }
+    }
+
+    // Get the path where a file has to be written: with dir rotation, always in the current rotation directory
+    fn to_write_fspath(&self, zfile: &ZFile<'_>) -> PathBuf {
+        match &self.rotation {
+            None => zfile.fspath.clone(),
+            Some(rotation) => {
+                self.to_rotated_fspath(&rotation.current_dir_name(), &self.map_zpath(&zfile.zpath))
+            }
+        }
+    }
+
+    // With "strip_extension", get the path of the stored file with one of the extensions (or else without extension)
+    fn with_stored_extension(&self, file: PathBuf) -> PathBuf {
+        self.strip_extensions
+            .iter()
+            .map(|ext| with_extension_appended(&file, ext))
+            .find(|f| f.is_file() || self.get_conflict_file(f.clone()).is_file())
+            .unwrap_or(file)
+    }
+
+    // Get the path where a file with this encoding has to be written. With "strip_extension", this is the path
+    // with the extension corresponding to the encoding (or without extension if none corresponds).
+    // With "encoding_subdirs", this is the path in the sub-directory corresponding to the encoding.
+    fn to_encoded_write_fspath(&self, zfile: &ZFile<'_>, encoding: &Encoding) -> PathBuf {
+        if self.strip_extensions.is_empty() && !self.encoding_subdirs {
+            return self.to_write_fspath(zfile);
+        }
+        let encoding = encoding.to_string();
+        let mime = encoding.split(';').next().unwrap_or_default().trim();
+        let sharded_zpath = self.map_zpath(&zfile.zpath);
+        let file = if self.encoding_subdirs {
+            self.to_rotated_fspath(encoding_subdir(mime), &sharded_zpath)
+        } else {
+            self.to_dir_fspath(&sharded_zpath)
+        };
+        match self
+            .strip_extensions
+            .iter()
+            .find(|ext| self.mime_guesser.is_extension_of(ext, mime))
+        {
+            Some(ext) => with_extension_appended(&file, ext),
+            None => file,
+        }
+    }
+
+    // Convert a (sharded) zenoh path to the path of the corresponding file in a rotation directory
+    // (or in an encoding sub-directory)
+    fn to_rotated_fspath(&self, rotation_dir: &str, sharded_zpath: &str) -> PathBuf {
+        let mut fspath = self.base_dir.clone();
+        fspath.push(rotation_dir);
+        fspath.push(&*self.filename_encoding.zpath_to_fspath(sharded_zpath));
+        fspath
+    }
+
+    // List the rotation directories, the newest first
+    fn rotation_dirs(&self) -> Vec<String> {
+        match &self.rotation {
+            Some(rotation) => rotation.list_dirs(&self.base_dir, |name| {
+                is_reserved_path(name, &self.reserved_prefix)
+            }),
+            None => vec![],
+        }
+    }
+
+    // Convert a zenoh path prefix to the path of the corresponding directory
+    fn to_dir_fspath(&self, zpath: &str) -> PathBuf {
         let mut fspath = self.base_dir.clone();
-        fspath.push(zpath_to_fspath(zpath).as_ref());
+        fspath.push(&*self.filename_encoding.zpath_to_fspath(zpath));
         fspath
     }
 
@@ -103,6 +788,20 @@ impl FilesMgr {
     // This leads to having a directory and file with the same name which is forbidden on file systems.
     // We fix this by appending a suffix `.##z` to the conflicting file. This is dealt with internally and not exposed to the user.
 
+    // Recovery guarantees of the write path, if the process crashes at any point (they can be exercised
+    // with the faults injected at the FaultPoints, building with the "fault-injection" feature):
+    //  - a file is written before its data-info is saved. After a crash in between, the file is replied
+    //    with its previous data-info (thus with the previous timestamp, which never goes backwards),
+    //    or with its metadata if it had none.
+    //  - the content is written in a temporary file (in "temp_dir" if set, or else in the file's directory,
+    //    see write_replacing()) which is then renamed over the file, or by "pre_write_hook" in a temporary file
+    //    which replaces the file. Thus a partial content is never replied: after a crash during the write,
+    //    the previous content is replied, and a temporary ".zenoh_write_*" file might be left behind.
+    //  - a file is removed before its tombstone is saved. Once delete_file() returned, the tombstone is
+    //    in the database, and the key is not re-created by an older PUT.
+    //  - surviving a power loss in addition requires "fsync" (files and directories are synced
+    //    before a PUT or DELETE is acknowledged).
+    #[tracing::instrument(level = "debug", skip_all, fields(op = "put", file = %zfile))]
     pub(crate) async fn write_file(
         &self,
         zfile: &ZFile<'_>,
@@ -110,7 +809,63 @@ impl FilesMgr {
         encoding: &Encoding,
         timestamp: &Timestamp,
     ) -> ZResult<()> {
-        let file = &zfile.fspath;
+        let mut dirty_dirs = DirtyDirs::default();
+        let len = content.len() as u64;
+        self.write_file_batched(zfile, content, encoding, timestamp, &mut dirty_dirs)
+            .await?;
+        self.sync_dirs(dirty_dirs)?;
+        if let Some(events) = &self.events {
+            events.put(&zfile.zpath, len).await;
+        }
+        Ok(())
+    }
+
+    // Write a file, adding the directories it modifies to dirty_dirs without syncing them
+    pub(crate) async fn write_file_batched(
+        &self,
+        zfile: &ZFile<'_>,
+        content: ZBuf,
+        encoding: &Encoding,
+        timestamp: &Timestamp,
+        dirty_dirs: &mut DirtyDirs,
+    ) -> ZResult<()> {
+        let file = &self.to_encoded_write_fspath(zfile, encoding);
+        let (content, base64, value_size) = self.to_stored_content(content);
+        // the content is hashed at write time, for the ETag and "skip_identical_writes"
+        let hash = sha256_zbuf(&content);
+        if self.skip_identical_writes {
+            // if the file is unchanged since its content was hashed and has the same content and encoding,
+            // only update its timestamp
+            if let Some((stored_encoding, stored_hash)) =
+                self.data_info_mgr.get_content_hash(file).await?
+            {
+                if stored_hash.hash == hash
+                    && &stored_encoding == encoding
+                    && file_mtime(file) == Some(stored_hash.mtime)
+                {
+                    trace!("Skip write of identical content in file {:?}", file);
+                    return self
+                        .data_info_mgr
+                        .put_data_info_ext(
+                            file,
+                            encoding,
+                            timestamp,
+                            Some(&stored_hash),
+                            base64,
+                            value_size,
+                        )
+                        .await;
+                }
+            }
+        }
+        // with dir rotation, the creation of a new rotation directory triggers the removal of the oldest ones
+        let new_rotation_dir =
+            self.rotation.is_some() && !self.base_dir.join(self.rotation_dirs_root(file)).exists();
+        // with "encoding_subdirs", the replacement of the file stored in another sub-directory is recorded before
+        // the new file is written, so the copy left if it's interrupted is removed at the next start
+        if self.encoding_subdirs && zfile.fspath != *file && zfile.fspath.is_file() {
+            self.mark_encoding_replacement()?;
+        }
 
         // Create parent directories if needed
         let parent = file.parent().unwrap();
@@ -128,8 +883,11 @@ impl FilesMgr {
                     a,
                     conflict_file
                 );
-                rename(a, &conflict_file)
+                self.unmap_file(a);
+                fault_point(FaultPoint::Rename)
+                    .and_then(|_| rename(a, &conflict_file))
                     .map_err(|e| zerror!("Failed to write in file {:?}: {}", conflict_file, e))?;
+                dirty_dirs.add(a.parent());
                 match self.data_info_mgr.rename_key(a, &conflict_file).await {
                     Ok(_) => None,
                     Err(_) => {
@@ -144,9 +902,13 @@ impl FilesMgr {
             }
         }
 
-        self.dir_builder
-            .create(parent)
-            .map_err(|e| zerror!("Failed to create directories for file {:?}: {}", file, e))?;
+        // the parents of the directories to be created are modified
+        for new_dir in parent.ancestors().take_while(|d| !d.exists()) {
+            dirty_dirs.add(new_dir.parent());
+        }
+        self.create_dirs(parent)
+            .map_err(|e| write_error("Failed to create directories for file", file, e))?;
+        dirty_dirs.add(Some(parent));
 
         // Write file
         trace!("Write in file {:?}", file);
@@ -156,377 +918,3376 @@ impl FilesMgr {
             file.to_path_buf()
         };
         trace!("Writing in conflict-free file {:?}", file);
-        let mut f = File::create(&file)
-            .map_err(|e| zerror!("Failed to write in file {:?}: {}", file, e))?;
-        for slice in content.slices() {
-            f.write_all(slice)
-                .map_err(|e| zerror!("Failed to write in file {:?}: {}", file, e))?;
+        if let Some(hook) = &self.pre_write_hook {
+            self.write_file_with_hook(hook.clone(), zfile, &file, content)
+                .await?;
+        } else {
+            // the content is written in a new file which then replaces the stored one, so the stored file is
+            // not truncated while it's read (e.g. held as a PinnedFile by a query)
+            write_replacing(
+                &file,
+                &content,
+                self.file_mode,
+                self.fsync,
+                self.temp_dir.as_deref(),
+            )?;
+        }
+        self.unmap_file(&file);
+        // (the file stored for this key might still be at another path: set the permissions of the written one)
+        self.set_file_mode(&file)?;
+
+        // with "strip_extension", the file stored for this key with another extension is replaced
+        if !self.strip_extensions.is_empty() && zfile.fspath != file && zfile.fspath.is_file() {
+            trace!("Remove {:?}, replaced by {:?}", zfile.fspath, file);
+            self.unmap_file(&zfile.fspath);
+            remove_file(&zfile.fspath)
+                .map_err(|e| zerror!("Failed to delete file {:?}: {}", zfile.fspath, e))?;
+            self.data_info_mgr.del_data_info(&zfile.fspath).await?;
+            dirty_dirs.add(zfile.fspath.parent());
+        }
+        // with "encoding_subdirs", the file stored for this key in another sub-directory is replaced,
+        // and its tombstone in the default sub-directory (if it was deleted) is removed
+        if self.encoding_subdirs && zfile.fspath != file {
+            if zfile.fspath.is_file() {
+                trace!("Remove {:?}, replaced by {:?}", zfile.fspath, file);
+                self.unmap_file(&zfile.fspath);
+                remove_file(&zfile.fspath)
+                    .map_err(|e| zerror!("Failed to delete file {:?}: {}", zfile.fspath, e))?;
+                dirty_dirs.add(zfile.fspath.parent());
+            }
+            self.data_info_mgr.del_data_info(&zfile.fspath).await?;
         }
 
-        // save data-info
+        // save data-info, with the content hash
+        let content_hash = file_mtime(&file).map(|mtime| ContentHash { hash, mtime });
         self.data_info_mgr
-            .put_data_info(file, encoding, timestamp)
-            .await
-    }
+            .put_data_info_ext(
+                file,
+                encoding,
+                timestamp,
+                content_hash.as_ref(),
+                base64,
+                value_size,
+            )
+            .await?;
 
-    fn get_conflict_file(&self, file: PathBuf) -> PathBuf {
-        match file.to_str() {
-            Some(x) => PathBuf::from(get_conflict_resolved_keyexpr(x)),
-            None => file.to_path_buf(),
+        if new_rotation_dir {
+            self.remove_old_rotation_dirs().await?;
         }
+        Ok(())
     }
 
-    pub(crate) async fn delete_file(
-        &self,
-        zfile: &ZFile<'_>,
-        timestamp: &Timestamp,
-    ) -> ZResult<()> {
-        let file = &zfile.fspath;
-
-        let file = if file.exists() && file.is_file() {
-            file.to_path_buf()
-        } else {
-            self.get_conflict_file(file.to_path_buf())
-        };
+    fn mark_encoding_replacement(&self) -> ZResult<()> {
+        let marker = self.base_dir.join(ENCODING_REPLACED_FILENAME);
+        if marker.exists() {
+            return Ok(());
+        }
+        File::create(&marker)
+            .and_then(|f| if self.fsync { f.sync_all() } else { Ok(()) })
+            .map_err(|e| zerror!("Failed to write marker file {:?}: {}", marker, e))?;
+        let mut dirty_dirs = DirtyDirs::default();
+        dirty_dirs.add(Some(&self.base_dir));
+        self.sync_dirs(dirty_dirs)
+    }
 
-        // Delete file
-        trace!("Delete file {:?}", file);
-        if file.exists() {
-            remove_file(&file).map_err(|e| zerror!("Failed to delete file {:?}: {}", file, e))?;
-            // try to delete parent directories if empty
-            let mut f = file.as_path();
-            while let Some(parent) = f.parent() {
-                if parent != self.base_dir() && remove_dir(parent).is_ok() {
-                    trace!("Removed empty dir: {:?}", parent);
-                } else {
-                    break;
+    // With "encoding_subdirs", if a replacement of a file by a copy in another sub-directory might have been
+    // interrupted, remove the copies of each key but the one in use (the newest one)
+    pub(crate) async fn remove_replaced_encoding_copies(&self) -> ZResult<()> {
+        let marker = self.base_dir.join(ENCODING_REPLACED_FILENAME);
+        let repairing = self.base_dir.join(ENCODING_REPAIRING_FILENAME);
+        if !self.encoding_subdirs || !(marker.exists() || repairing.exists()) {
+            return Ok(());
+        }
+        // (a replacement interrupted meanwhile re-creates the marker)
+        if marker.exists() {
+            rename(&marker, &repairing)
+                .map_err(|e| zerror!("Failed to rename {:?}: {}", marker, e))?;
+        }
+        let mut dirty_dirs = DirtyDirs::default();
+        let zpaths: Vec<String> = self
+            .matching_files(unsafe { keyexpr::from_str_unchecked("**") })
+            .map(|zfile| zfile.zpath.to_string())
+            .collect();
+        for zpath in zpaths {
+            let _write = self.wait_background_write().await;
+            let _lock = self.lock_key(&self.to_zfile(&zpath)).await;
+            let in_use = self.to_zfile(&zpath).fspath;
+            let sharded_zpath = self.map_zpath(&zpath);
+            for dir in ENCODING_SUBDIRS.iter() {
+                let copy = self.with_stored_extension(self.to_rotated_fspath(dir, &sharded_zpath));
+                if copy != in_use && copy.is_file() {
+                    warn!(
+                        "Remove {:?}, replaced by {:?} by an interrupted write",
+                        copy, in_use
+                    );
+                    self.unmap_file(&copy);
+                    remove_file(&copy)
+                        .map_err(|e| zerror!("Failed to delete file {:?}: {}", copy, e))?;
+                    self.data_info_mgr.del_data_info(&copy).await?;
+                    dirty_dirs.add(copy.parent());
                 }
-                f = parent;
             }
         }
+        self.sync_dirs(dirty_dirs)?;
+        remove_file(&repairing)
+            .map_err(|e| zerror!("Failed to delete {:?}: {}", repairing, e).into())
+    }
 
-        // save timestamp in data-info (encoding is not used)
-        self.data_info_mgr
-            .put_data_info(file, &KnownEncoding::Empty.into(), timestamp)
-            .await
+    // Get the content as written in a file, if it's base64-encoded, and the size of the content if it differs
+    // from the written one: with "text_safe_storage", the contents which are not UTF-8 text are written base64-encoded
+    fn to_stored_content(&self, content: ZBuf) -> (ZBuf, bool, Option<u64>) {
+        let base64 = self.text_safe_storage && std::str::from_utf8(&content.contiguous()).is_err();
+        if base64 {
+            (
+                ZBuf::from(BASE64.encode(content.contiguous()).into_bytes()),
+                true,
+                Some(content.len() as u64),
+            )
+        } else {
+            (content, false, None)
+        }
     }
 
-    // Read a file and return it's content (as Vec<u8>), encoding and timestamp.
-    // Encoding and timestamp are retrieved from the data_info_mgr if file was put via zenoh.
-    // Otherwise, the encoding is guessed from the file extension, and the timestamp is computed from the file's time.
-    pub(crate) async fn read_file(&self, zfile: &ZFile<'_>) -> ZResult<Option<(Value, Timestamp)>> {
-        let file = &zfile.fspath;
-        match self.perform_read(file).await? {
-            Some(x) => Ok(Some(x)),
-            None => self.perform_read_from_conflict(file.to_path_buf()).await,
+    // Check if the stored file has the same content and encoding, according to the content hash
+    // recorded when it was written (false if there is none, or if the file was modified since)
+    pub(crate) async fn has_identical_content(
+        &self,
+        zfile: &ZFile<'_>,
+        content: &ZBuf,
+        encoding: &Encoding,
+    ) -> ZResult<bool> {
+        let file = self.to_write_fspath(zfile);
+        match self.data_info_mgr.get_content_hash(&file).await? {
+            Some((stored_encoding, stored_hash))
+                if !stored_hash.hash.is_empty()
+                    && &stored_encoding == encoding
+                    && file_mtime(&file) == Some(stored_hash.mtime) =>
+            {
+                let (content, _, _) = self.to_stored_content(content.clone());
+                Ok(sha256_zbuf(&content) == stored_hash.hash)
+            }
+            _ => Ok(false),
         }
     }
 
-    async fn perform_read_from_conflict(
+    // Write the content in a temporary file, run the "pre_write_hook" on it (which may modify it),
+    // and replace the file with it only if the hook succeeded
+    async fn write_file_with_hook(
         &self,
-        file: PathBuf,
-    ) -> ZResult<Option<(Value, Timestamp)>> {
-        let file = self.get_conflict_file(file.to_path_buf());
-        self.perform_read(&file.to_path_buf()).await
+        hook: Arc<WriteHook>,
+        zfile: &ZFile<'_>,
+        file: &Path,
+        content: ZBuf,
+    ) -> ZResult<()> {
+        let parent = file.parent().unwrap();
+        let mut tmp = tempfile::Builder::new()
+            .prefix(WRITE_TMP_PREFIX)
+            .tempfile_in(self.temp_dir.as_deref().unwrap_or(parent))
+            .map_err(|e| write_error("Failed to write in file", file, e))?;
+        for slice in content.slices() {
+            fault_point(FaultPoint::FileWrite)
+                .and_then(|_| tmp.write_all(slice))
+                .map_err(|e| write_error("Failed to write in file", tmp.path(), e))?;
+        }
+        tmp.flush()
+            .map_err(|e| write_error("Failed to write in file", tmp.path(), e))?;
+
+        // the hook is a blocking external command
+        let zpath = zfile.zpath.to_string();
+        let tmp_path = tmp.path().to_path_buf();
+        task::spawn_blocking(move || hook.run(&zpath, &tmp_path)).await?;
+
+        if self.fsync {
+            // re-open the file, as the hook might have replaced it
+            fault_point(FaultPoint::Fsync)
+                .and_then(|_| File::open(tmp.path()))
+                .and_then(|f| f.sync_all())
+                .map_err(|e| zerror!("Failed to sync file {:?}: {}", tmp.path(), e))?;
+        }
+        fault_point(FaultPoint::Rename)
+            .map_err(|e| write_error("Failed to write in file", file, e))?;
+        tmp.persist(file)
+            .map_err(|e| write_error("Failed to write in file", file, e.error))?;
+        Ok(())
     }
 
-    async fn perform_read(&self, file: &Path) -> ZResult<Option<(Value, Timestamp)>> {
-        // consider file only is it exists, it's a file and in case of "follow_links=true" it doesn't contain symlink
-        if file.exists() && file.is_file() && (self.follow_links || !self.contains_symlink(&file)) {
-            match File::open(&file) {
-                Ok(mut f) => {
-                    // TODO: what if file is too big ??
-                    let size = f.metadata().map(|m| m.len()).unwrap_or(256);
-                    if size <= usize::MAX as u64 {
-                        trace!("Read file {:?}", file);
-                        let mut content: Vec<u8> = Vec::with_capacity(size as usize);
-                        if let Err(e) = f.read_to_end(&mut content) {
-                            bail!(r#"Error reading file {:?}: {}"#, file, e)
-                        } else {
-                            let (encoding, timestamp) =
-                                self.get_encoding_and_timestamp(file).await?;
-                            Ok(Some((
-                                Value::new(content.into()).encoding(encoding),
-                                timestamp,
-                            )))
-                        }
-                    } else {
-                        bail!(r#"Error reading file {:?}: too big to fit in memory"#, file)
+    // Get the rotation directory (relative to base_dir) of a file
+    fn rotation_dirs_root(&self, file: &Path) -> PathBuf {
+        file.strip_prefix(&self.base_dir)
+            .ok()
+            .and_then(|relative| relative.components().next())
+            .map(|c| PathBuf::from(c.as_os_str()))
+            .unwrap_or_default()
+    }
+
+    // With dir rotation and "rotate_keep" set, remove the oldest rotation directories.
+    // A tombstone is recorded for each removed key that doesn't exist in a newer rotation directory.
+    pub(crate) async fn remove_old_rotation_dirs(&self) -> ZResult<()> {
+        let rotation = match &self.rotation {
+            Some(rotation) if rotation.keep() > 0 => rotation,
+            _ => return Ok(()),
+        };
+        let dirs = self.rotation_dirs();
+        if dirs.len() <= rotation.keep() {
+            return Ok(());
+        }
+        let current_dir = rotation.current_dir_name();
+        for (i, old_dir) in dirs.iter().enumerate().skip(rotation.keep()) {
+            let old_dir_path = self.base_dir.join(old_dir);
+            debug!("Remove old rotation directory {:?}", old_dir_path);
+            let old_dir_len = old_dir_path.as_os_str().len();
+            let files: Vec<PathBuf> = WalkDir::new(&old_dir_path)
+                .into_iter()
+                .filter_map(Result::ok)
+                .filter(|e| e.file_type().is_file())
+                .map(|e| e.into_path())
+                .collect();
+            for file in files {
+                let sharded_zpath = match self.filename_encoding.fspath_to_zpath(&file, old_dir_len)
+                {
+                    Some(zpath) => get_trimmed_keyexpr(&zpath).to_string(),
+                    None => continue,
+                };
+                let in_newer_dir = dirs[..i].iter().any(|dir| {
+                    let newer_file = self.to_rotated_fspath(dir, &sharded_zpath);
+                    newer_file.is_file() || self.get_conflict_file(newer_file).is_file()
+                });
+                if !in_newer_dir {
+                    // the tombstone is recorded where the key would be written now
+                    let tombstone = self.to_rotated_fspath(&current_dir, &sharded_zpath);
+                    if !tombstone.exists() {
+                        self.data_info_mgr
+                            .put_data_info(
+                                &tombstone,
+                                &KnownEncoding::Empty.into(),
+                                &new_reception_timestamp(),
+                            )
+                            .await?;
                     }
                 }
-                Err(e) => bail!(r#"Error reading file {:?}: {}"#, file, e),
+                self.unmap_file(&file);
+                self.data_info_mgr.del_data_info(&file).await?;
             }
-        } else {
-            Ok(None)
+            remove_dir_all(&old_dir_path).map_err(|e| {
+                zerror!(
+                    "Failed to remove old rotation directory {:?}: {}",
+                    old_dir_path,
+                    e
+                )
+            })?;
         }
+        Ok(())
     }
 
-    // Search for files matching path_expr.
-    pub(crate) fn matching_files<'a>(&self, zpath_expr: &'a keyexpr) -> FilesIterator<'a> {
-        // find the longest segment without '*' to search for files only in the corresponding
-        let star_idx = zpath_expr.find('*').unwrap();
-        let segment = match zpath_expr[..star_idx].rfind('/') {
-            Some(i) => &zpath_expr[..i],
-            None => "",
-        };
-        // Directory to search for matching files is base_dir + segment converted as a file-system path
-        let search_dir = self.to_fspath(segment);
-        let base_dir_len = self.base_dir.as_os_str().len();
+    fn get_conflict_file(&self, file: PathBuf) -> PathBuf {
+        with_extension_appended(&file, CONFLICT_SUFFIX)
+    }
 
-        if !self.follow_links && self.contains_symlink(&search_dir) {
-            debug!(
-                "Don't search for files in {:?} as it's within a symbolic link",
-                search_dir
-            );
-            // return a useless FilesIterator that won't return anything (simpler than to return an Option<FilesIterator>)
-            let walkdir = WalkDir::new("");
-            FilesIterator {
-                walk_iter: walkdir.into_iter(),
-                zpath_expr,
-                base_dir_len,
-            }
+    // Check if writing the file for this ZFile would conflict with a directory: either the file is the directory
+    // of other stored keys, or one of its parent directories is a stored file
+    pub(crate) fn has_key_dir_conflict(&self, zfile: &ZFile<'_>) -> bool {
+        let file = self.to_write_fspath(zfile);
+        file.is_dir()
+            || file
+                .ancestors()
+                .skip(1)
+                .take_while(|a| *a != self.base_dir)
+                .any(|a| a.is_file())
+    }
+
+    // Get the size of the file for this ZFile (possibly with the conflict suffix), if it exists
+    pub(crate) fn file_size(&self, zfile: &ZFile<'_>) -> Option<u64> {
+        metadata(&zfile.fspath)
+            .ok()
+            .filter(|m| m.is_file())
+            .or_else(|| {
+                metadata(self.get_conflict_file(zfile.fspath.clone()))
+                    .ok()
+                    .filter(|m| m.is_file())
+            })
+            .map(|m| m.len())
+    }
+
+    // Get the timestamp of a stored file, and an identifier of its version (ETag) derived from this timestamp
+    // and from the SHA-256 of its content recorded when it was written. The file is never hashed here: if there is
+    // no valid recorded hash (a file placed or modified without zenoh), the ETag is derived from the timestamp,
+    // the modification time and the size of the file.
+    pub(crate) async fn get_etag(&self, zfile: &ZFile<'_>) -> ZResult<Option<(Timestamp, String)>> {
+        let file = if zfile.fspath.is_file() {
+            zfile.fspath.clone()
         } else {
-            debug!(
-                "For path_expr={} search matching files in {:?}",
-                zpath_expr, search_dir
-            );
-            let walkdir = WalkDir::new(search_dir).follow_links(self.follow_links);
-            FilesIterator {
-                walk_iter: walkdir.into_iter(),
-                zpath_expr,
-                base_dir_len,
+            self.get_conflict_file(zfile.fspath.clone())
+        };
+        let metadata = match metadata(&file) {
+            Ok(metadata) if metadata.is_file() => metadata,
+            _ => return Ok(None),
+        };
+        let timestamp = self.get_file_timestamp(&file).await?;
+        let mtime = metadata_mtime(&metadata);
+        let etag = match self.data_info_mgr.get_content_hash(&file).await? {
+            Some((_, stored_hash))
+                if !stored_hash.hash.is_empty() && mtime == Some(stored_hash.mtime) =>
+            {
+                stored_hash.etag(&timestamp)
             }
-        }
+            _ => format!(
+                "{:x}-{:x}-{:x}",
+                timestamp.get_time().as_u64(),
+                mtime.unwrap_or(0),
+                metadata.len()
+            ),
+        };
+        Ok(Some((timestamp, etag)))
     }
 
-    fn generate_metadata(&self, file: &Path, timestamp: &Timestamp) -> (Encoding, Timestamp) {
-        let a_encoding = self.guess_encoding(file);
-        let a_timestamp = match self.get_timestamp_from_metadata(file) {
-            Ok(a_ts) => a_ts,
-            Err(_) => *timestamp,
+    // Get the metadata of the file for this ZFile (possibly with the conflict suffix) from the data-info
+    // and the file's metadata, without reading its content
+    pub(crate) async fn get_stat(&self, zfile: &ZFile<'_>) -> ZResult<Option<FileStat>> {
+        let file = if zfile.fspath.is_file() {
+            zfile.fspath.clone()
+        } else {
+            self.get_conflict_file(zfile.fspath.clone())
         };
-        (a_encoding, a_timestamp)
+        let metadata = match metadata(&file) {
+            Ok(metadata) if metadata.is_file() => metadata,
+            _ => return Ok(None),
+        };
+        let (encoding, timestamp) = self.get_encoding_and_timestamp(&file).await?;
+        let value_size = self.get_value_size(&file, metadata.len()).await?;
+        let mtime = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok());
+        let sha256 = match self.data_info_mgr.get_content_hash(&file).await? {
+            Some((_, stored_hash))
+                if !stored_hash.hash.is_empty()
+                    && mtime.map(|d| d.as_nanos() as u64) == Some(stored_hash.mtime) =>
+            {
+                Some(stored_hash.hash)
+            }
+            _ => None,
+        };
+        Ok(Some(FileStat {
+            size: metadata.len(),
+            value_size,
+            mtime,
+            encoding,
+            timestamp,
+            sha256,
+        }))
     }
 
-    async fn get_encoding_and_timestamp(&self, file: &Path) -> ZResult<(Encoding, Timestamp)> {
-        // try to get Encoding and Timestamp from data_info_mgr
-        match self.data_info_mgr.get_encoding_and_timestamp(&file).await? {
-            Some((encoding, timestamp)) => Ok((encoding, timestamp)),
-            None => {
-                trace!("data-info for {:?} not found; fallback to metadata", file);
-                let encoding = self.guess_encoding(file);
-                // fallback: get timestamp from file's metadata
-                let timestamp = self.get_timestamp_from_metadata(file)?;
-
-                Ok((encoding, timestamp))
+    // Get the size of the value replied for a stored file of `size` bytes: the size recorded in its data-info
+    // if the value was written transformed (e.g. base64-encoded by "text_safe_storage"), or for a ".gz" file
+    // decompressed by "decompress_gz_on_read" the size in its gzip trailer (modulo 2^32, and only of its last
+    // member), or else the file's size
+    async fn get_value_size(&self, file: &Path, size: u64) -> ZResult<u64> {
+        if let Some(value_size) = self
+            .data_info_mgr
+            .get_extras(file)
+            .await?
+            .and_then(|extras| extras.size)
+        {
+            return Ok(value_size);
+        }
+        if size >= 18 && self.external_gz_file_stem(file).await?.is_some() {
+            let mut trailer = [0u8; 4];
+            let read = File::open(file).and_then(|mut f| {
+                f.seek(std::io::SeekFrom::End(-4))?;
+                f.read_exact(&mut trailer)
+            });
+            if read.is_ok() {
+                return Ok(u32::from_le_bytes(trailer) as u64);
             }
         }
+        Ok(size)
     }
 
-    fn guess_encoding(&self, file: &Path) -> Encoding {
-        if self.keep_mime {
-            // fallback: guess mime type from file extension
-            let mime_type = mime_guess::from_path(&file).first_or_octet_stream();
-            Encoding::from(mime_type.essence_str().to_string())
+    // Get the sizes of the files stored for this ZFile (possibly with the conflict suffix): the size of its
+    // value (see get_value_size()) and the size of the file on disk. None if it doesn't exist.
+    pub(crate) async fn get_sizes(&self, zfile: &ZFile<'_>) -> ZResult<Option<(u64, u64)>> {
+        let file = if zfile.fspath.is_file() {
+            zfile.fspath.clone()
         } else {
-            KnownEncoding::AppOctetStream.into()
+            self.get_conflict_file(zfile.fspath.clone())
+        };
+        match metadata(&file) {
+            Ok(metadata) if metadata.is_file() => Ok(Some((
+                self.get_value_size(&file, metadata.len()).await?,
+                metadata.len(),
+            ))),
+            _ => Ok(None),
         }
     }
 
-    pub(crate) async fn get_timestamp(&self, zfile: &ZFile<'_>) -> ZResult<Option<Timestamp>> {
-        let file = &zfile.fspath;
-        // try to get Timestamp from data_info_mgr
-        match self.data_info_mgr.get_timestamp(&file).await? {
-            Some(x) => Ok(Some(x)),
-            None => {
-                // fallback: get timestamp from file's metadata if it exists
-                if file.exists() {
-                    let timestamp = self.get_timestamp_from_metadata(file)?;
-                    Ok(Some(timestamp))
-                } else {
-                    Ok(None)
-                }
+    // Sum the sizes of the stored files (see get_sizes()), returning the number of files, the total size of their
+    // values, their total size on disk, and false if the deadline (if any) was exceeded before all were summed
+    pub(crate) async fn get_storage_sizes(
+        &self,
+        deadline: Option<Instant>,
+    ) -> ZResult<(u64, u64, u64, bool)> {
+        let (mut files, mut value_bytes, mut disk_bytes) = (0, 0, 0);
+        let zfiles = self.matching_files(unsafe { keyexpr::from_str_unchecked("**") });
+        for (i, zfile) in zfiles.enumerate() {
+            if deadline.map_or(false, |deadline| Instant::now() >= deadline) {
+                return Ok((files, value_bytes, disk_bytes, false));
+            }
+            // let the other tasks run regularly during the scan of a large storage
+            if i > 0 && i % SIZES_YIELD_PERIOD == 0 {
+                task::yield_now().await;
+            }
+            let trimmed_zfile = self.to_zfile(get_trimmed_keyexpr(zfile.zpath.as_ref()));
+            if let Some((value_size, disk_size)) = self.get_sizes(&trimmed_zfile).await? {
+                files += 1;
+                value_bytes += value_size;
+                disk_bytes += disk_size;
             }
         }
+        Ok((files, value_bytes, disk_bytes, true))
     }
 
-    fn get_timestamp_from_metadata<P: AsRef<Path>>(&self, file: P) -> ZResult<Timestamp> {
-        let metadata = metadata(&file).map_err(|e| {
-            zerror!(
-                "Failed to get meta-data for file {:?}: {}",
-                file.as_ref(),
-                e
-            )
-        })?;
-        let sys_time = metadata
-            .modified()
-            .or_else(|_| metadata.accessed())
-            .or_else(|_| metadata.created())
-            .unwrap_or_else(|_| SystemTime::now());
-        Ok(Timestamp::new(
-            sys_time.duration_since(UNIX_EPOCH).unwrap().into(),
-            TimestampId::try_from([1]).unwrap(),
-        ))
+    // Check if a file exists for this ZFile (possibly with the conflict suffix)
+    pub(crate) fn file_exists(&self, zfile: &ZFile<'_>) -> bool {
+        zfile.fspath.is_file() || self.get_conflict_file(zfile.fspath.clone()).is_file()
     }
 
-    // Check if a Path contains a segment which is a symbolic link
-    fn contains_symlink<P: AsRef<Path>>(&self, path: P) -> bool {
-        if is_symlink(&path) {
-            return true;
+    #[tracing::instrument(level = "debug", skip_all, fields(op = "delete", file = %zfile))]
+    pub(crate) async fn delete_file(
+        &self,
+        zfile: &ZFile<'_>,
+        timestamp: &Timestamp,
+    ) -> ZResult<()> {
+        let mut dirty_dirs = DirtyDirs::default();
+        self.delete_file_batched(zfile, timestamp, &mut dirty_dirs)
+            .await?;
+        self.sync_dirs(dirty_dirs)?;
+        if let Some(events) = &self.events {
+            events.delete(&zfile.zpath).await;
+        }
+        Ok(())
+    }
+
+    // Run a blocking file system operation on its own thread, with the deadline of "io_deadline_ms" if set
+    async fn run_io<T, F>(&self, op: &'static str, f: F) -> ZResult<T>
+    where
+        F: FnOnce() -> ZResult<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        match &self.io_watchdog {
+            Some(watchdog) => watchdog.run(op, f).await,
+            None => task::spawn_blocking(f).await,
+        }
+    }
+
+    // Run a file system operation of a read: with "io_deadline_ms", on its own thread with this deadline,
+    // otherwise directly
+    async fn run_read<T, F>(&self, op: &'static str, f: F) -> ZResult<T>
+    where
+        F: FnOnce() -> ZResult<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        match &self.io_watchdog {
+            Some(watchdog) => watchdog.run(op, f).await,
+            None => f(),
         }
+    }
 
-        let mut current = path.as_ref();
-        while let Some(parent) = current.parent() {
-            // check only up-to base_dir, and don't mind if it's itself a symbolic link
-            if parent == self.base_dir() {
-                return false;
-            } else if is_symlink(parent) {
-                return true;
+    // Run a write of the storage's files. If "io_deadline_ms" is set, it runs on its own thread
+    // with this deadline, otherwise it's directly awaited.
+    pub(crate) async fn isolated<T, F, Fut>(self: &Arc<Self>, op: &'static str, f: F) -> ZResult<T>
+    where
+        F: FnOnce(Arc<FilesMgr>) -> Fut + Send + 'static,
+        Fut: Future<Output = ZResult<T>>,
+        T: Send + 'static,
+    {
+        match &self.io_watchdog {
+            Some(watchdog) => {
+                let files_mgr = self.clone();
+                watchdog
+                    .run_write(op, move || task::block_on(counted_write(files_mgr, f)))
+                    .await
             }
-            current = parent;
+            None => counted_write(self.clone(), f).await,
         }
-        false
     }
 
-    pub async fn get_deleted_entries(&self) -> Vec<(String, Timestamp)> {
-        let mut result = Vec::new();
-        for (fspath, ts) in self.data_info_mgr.get_deleted_entries().await {
-            // coarse_zpath is the file's absolute path stripped from base_dir and converted as zenoh path
-            let coarse_zpath = fspath_to_zpath(&fspath[self.base_dir.as_os_str().len()..]);
-            // zpath trims away the CONFLICT_SUFFIX if present
-            let zpath = Cow::from(get_trimmed_keyexpr(&coarse_zpath));
-            result.push((zpath.as_ref().to_string(), ts));
+    // Fail if some file system operations exceeded the deadline of "io_deadline_ms" and are still in progress
+    pub(crate) fn check_io_health(&self) -> ZResult<()> {
+        match &self.io_watchdog {
+            Some(watchdog) => watchdog.check(),
+            None => Ok(()),
         }
-        result
     }
-}
 
-impl Drop for FilesMgr {
-    fn drop(&mut self) {
-        debug!("Closing File System Storage on {:?}", self.base_dir);
-        match self.on_closure {
-            OnClosure::DeleteAll => {
-                // Close data_info_mgr at first
-                task::block_on(async move {
-                    self.data_info_mgr
-                        .close()
-                        .await
-                        .unwrap_or_else(|e| warn!("{}", e));
-                    remove_dir_all(&self.base_dir).unwrap_or_else(|err| {
-                        warn!("Failed to cleanup directory {:?}; {}", self.base_dir, err)
-                    });
-                });
+    // Get the file system operations in progress for longer than the deadline of "io_deadline_ms"
+    pub(crate) fn stalled_io(&self) -> Vec<(&'static str, Duration)> {
+        match &self.io_watchdog {
+            Some(watchdog) => watchdog.stalled(),
+            None => vec![],
+        }
+    }
+
+    // Check if a key is one of the keys where the storage events are published ("publish_events")
+    pub(crate) fn is_event_key(&self, key: &keyexpr) -> bool {
+        self.events
+            .as_ref()
+            .map_or(false, |events| events.is_event_key(key))
+    }
+
+    // Delete a file, adding the directories it modifies to dirty_dirs without syncing them
+    pub(crate) async fn delete_file_batched(
+        &self,
+        zfile: &ZFile<'_>,
+        timestamp: &Timestamp,
+        dirty_dirs: &mut DirtyDirs,
+    ) -> ZResult<()> {
+        if self.rotation.is_some() {
+            // remove the copies of the file in the other rotation directories
+            let write_fspath = self.to_write_fspath(zfile);
+            let sharded_zpath = self.map_zpath(&zfile.zpath);
+            for dir in self.rotation_dirs() {
+                let file = self.to_rotated_fspath(&dir, &sharded_zpath);
+                if file != write_fspath {
+                    let file = self.remove_file_or_conflict(&file, dirty_dirs)?;
+                    self.data_info_mgr.del_data_info(&file).await?;
+                }
             }
-            OnClosure::DoNothing => {
-                debug!(
-                    "Close File System Storage, keeping directory {:?} as it is",
-                    self.base_dir
-                );
+            let file = self.remove_file_or_conflict(&write_fspath, dirty_dirs)?;
+            // save timestamp in data-info (encoding is not used)
+            return self
+                .data_info_mgr
+                .put_data_info(file, &KnownEncoding::Empty.into(), timestamp)
+                .await;
+        }
+
+        if self.encoding_subdirs {
+            // the tombstone is recorded in the default sub-directory, where the key is searched once its file is removed
+            let file = self.remove_file_or_conflict(&zfile.fspath, dirty_dirs)?;
+            self.data_info_mgr.del_data_info(&file).await?;
+            let tombstone =
+                self.to_rotated_fspath(DEFAULT_ENCODING_SUBDIR, &self.map_zpath(&zfile.zpath));
+            let tombstone = if file == zfile.fspath {
+                tombstone
+            } else {
+                self.get_conflict_file(tombstone)
+            };
+            return self
+                .data_info_mgr
+                .put_data_info(tombstone, &KnownEncoding::Empty.into(), timestamp)
+                .await;
+        }
+
+        let file = self.remove_file_or_conflict(&zfile.fspath, dirty_dirs)?;
+        // save timestamp in data-info (encoding is not used)
+        self.data_info_mgr
+            .put_data_info(file, &KnownEncoding::Empty.into(), timestamp)
+            .await
+    }
+
+    // Remove a file (or its conflict file if the file doesn't exist) and the directories left empty.
+    // Return the path of the removed file (or of the conflict file).
+    fn remove_file_or_conflict(&self, file: &Path, dirty_dirs: &mut DirtyDirs) -> ZResult<PathBuf> {
+        let file = if file.exists() && file.is_file() {
+            file.to_path_buf()
+        } else {
+            self.get_conflict_file(file.to_path_buf())
+        };
+
+        // Delete file
+        trace!("Delete file {:?}", file);
+        if file.exists() {
+            self.unmap_file(&file);
+            remove_file(&file).map_err(|e| zerror!("Failed to delete file {:?}: {}", file, e))?;
+            // try to delete parent directories if empty
+            let mut f = file.as_path();
+            while let Some(parent) = f.parent() {
+                if parent != self.base_dir() && remove_dir(parent).is_ok() {
+                    trace!("Removed empty dir: {:?}", parent);
+                } else {
+                    dirty_dirs.add(Some(parent));
+                    break;
+                }
+                f = parent;
             }
         }
+        Ok(file)
     }
-}
 
-pub(crate) struct FilesIterator<'a> {
-    walk_iter: IntoIter,
-    zpath_expr: &'a keyexpr,
-    base_dir_len: usize,
-}
+    // Delete the files of a batch of keys (zenoh paths) as DELETE with a same timestamp: the files are removed in
+    // parallel on the threads of `pool`, then their tombstones are put in a single write. The keys modified after
+    // this timestamp, immutable ("retention") or not stored are skipped. Return the deleted keys with the size
+    // of their removed file (None if it was already removed, e.g. by an interrupted bulk deletion), and the keys
+    // whose deletion failed. The files' locks are held from the check of their timestamp until their tombstone is
+    // put, so a PUT written meanwhile is not removed.
+    pub(crate) async fn delete_files_batch(
+        self: &Arc<Self>,
+        mut zpaths: Vec<String>,
+        timestamp: &Timestamp,
+        pool: Arc<rayon::ThreadPool>,
+    ) -> ZResult<(Vec<(String, Option<u64>)>, Vec<String>)> {
+        if self.rotation.is_some() {
+            bail!("Bulk deletion is not supported with rotation directories")
+        }
+        if self.encoding_subdirs {
+            bail!("Bulk deletion is not supported with encoding sub-directories")
+        }
+        let mut to_delete = Vec::with_capacity(zpaths.len());
+        let mut locks = Vec::with_capacity(zpaths.len());
+        // lock the files in the order of their paths (as KeyLocks::lock_all())
+        zpaths.sort_by_cached_key(|zpath| self.to_zfile(zpath).fspath);
+        for zpath in zpaths {
+            let zfile = self.to_zfile(&zpath);
+            let lock = self.lock_key(&zfile).await;
+            if self.get_immutable_until(&zpath).await?.is_some() {
+                continue;
+            }
+            let file_ts = match self.read_file_timestamp(&zfile).await? {
+                Some(ts) => Some(ts),
+                // the file might have been removed by an interrupted bulk deletion, before its tombstone was put
+                None => self.data_info_mgr.get_timestamp(&zfile.fspath).await?,
+            };
+            // (with equal timestamps, a DELETE wins over a PUT)
+            if matches!(file_ts, Some(ts) if ts <= *timestamp) {
+                to_delete.push(zpath);
+                locks.push(lock);
+            }
+        }
 
-impl<'a> Iterator for FilesIterator<'a> {
-    type Item = ZFile<'a>;
-    fn next(&mut self) -> Option<Self::Item> {
-        loop {
-            match self.walk_iter.next() {
-                Some(Ok(e)) => {
-                    if e.file_type().is_dir() {
-                        // skip content of DataInfoMgr::DB_FILENAME directory
-                        if e.file_name().to_str().unwrap_or_default() == DataInfoMgr::DB_FILENAME {
-                            self.walk_iter.skip_current_dir();
-                        }
-                    } else {
-                        let fspath = e.into_path();
-                        if let Some(s) = fspath.to_str() {
-                            // coarse_zpath is the file's absolute path stripped from base_dir and converted as zenoh path
-                            let coarse_zpath = fspath_to_zpath(&s[self.base_dir_len..]);
-                            // zpath trims away the CONFLICT_SUFFIX if present
-                            let zpath = get_trimmed_keyexpr(&coarse_zpath);
-                            let zpath_as_ke = match keyexpr::new(zpath) {
-                                Ok(ke) => ke,
-                                Err(e) => {
-                                    log::error!(
-                                        "Couldn't convert `{}` into a key expression: {}",
-                                        &zpath,
-                                        e
-                                    );
-                                    continue;
-                                }
-                            };
-                            // convert it to zenoh path for matching test with zpath_expr
-                            if self.zpath_expr.intersects(zpath_as_ke) {
-                                // matching file; return a ZFile
-                                let zfile = ZFile {
-                                    zpath: zpath.to_string().into(),
-                                    fspath: fspath.clone(),
-                                };
-                                return Some(zfile);
-                            }
-                        } else {
-                            debug!(
-                                "Looking for files matching {}: ignore {:?} as non UTF-8 filename",
-                                self.zpath_expr, fspath
-                            );
-                        };
-                    }
-                    continue;
+        let files_mgr = self.clone();
+        let removed = task::spawn_blocking(move || {
+            pool.install(|| {
+                to_delete
+                    .into_par_iter()
+                    .map(|zpath| {
+                        let zfile = files_mgr.to_zfile(&zpath);
+                        let size = files_mgr.file_size(&zfile);
+                        let mut dirty_dirs = DirtyDirs::default();
+                        let removed =
+                            files_mgr.remove_file_or_conflict(&zfile.fspath, &mut dirty_dirs);
+                        (zpath.clone(), size, removed, dirty_dirs)
+                    })
+                    .collect::<Vec<_>>()
+            })
+        })
+        .await;
+
+        let mut dirty_dirs = DirtyDirs::default();
+        let (mut deleted, mut files, mut failed) = (Vec::new(), Vec::new(), Vec::new());
+        for (zpath, size, removed, dirs) in removed {
+            dirty_dirs.merge(dirs);
+            match removed {
+                Ok(file) => {
+                    files.push(file);
+                    deleted.push((zpath, size));
                 }
-                None => return None,
-                Some(Err(err)) => {
-                    // Cannot read file or dir... that might be normal (or not...) ignore it
-                    debug!(
-                        "Possible issue looking for files matching {} : {}",
-                        self.zpath_expr, err
-                    );
-                    continue;
+                Err(e) => {
+                    warn!("Bulk deletion of {} failed: {}", zpath, e);
+                    failed.push(zpath);
                 }
-            };
+            }
         }
+        // save the timestamps in data-infos (encoding is not used)
+        let encoding: Encoding = KnownEncoding::Empty.into();
+        let infos: Vec<(&Path, &Encoding, &Timestamp, bool, Option<u64>)> = files
+            .iter()
+            .map(|file| (file.as_path(), &encoding, timestamp, false, None))
+            .collect();
+        self.data_info_mgr.put_data_infos(&infos).await?;
+        drop(locks);
+        self.sync_dirs(dirty_dirs)?;
+        if let Some(events) = &self.events {
+            for (zpath, _) in &deleted {
+                events.delete(zpath).await;
+            }
+        }
+        Ok((deleted, failed))
     }
-}
 
-#[cfg(unix)]
-#[inline(always)]
-pub(crate) fn zpath_to_fspath(zpath: &str) -> Cow<'_, str> {
-    Cow::from(zpath)
-}
+    // Stage the write of a file in a transaction: the content is written in a temporary file
+    // (in the storage directory or "temp_dir", to be renamed on the same file system), which replaces the file on commit
+    pub(crate) fn stage_write(
+        &self,
+        zfile: &ZFile<'_>,
+        content: ZBuf,
+        encoding: &Encoding,
+        timestamp: &Timestamp,
+    ) -> ZResult<StagedOp> {
+        let file = self.to_staged_fspath(zfile)?;
+        let file = if file.is_dir() {
+            self.get_conflict_file(file)
+        } else {
+            file
+        };
+        let (content, base64, value_size) = self.to_stored_content(content);
+        let mut tmp = tempfile::Builder::new()
+            .prefix(TX_TMP_PREFIX)
+            .tempfile_in(self.temp_dir.as_ref().unwrap_or(&self.base_dir))
+            .map_err(|e| write_error("Failed to write in file", &file, e))?;
+        for slice in content.slices() {
+            fault_point(FaultPoint::FileWrite)
+                .and_then(|_| tmp.write_all(slice))
+                .map_err(|e| write_error("Failed to write in file", tmp.path(), e))?;
+        }
+        self.set_file_mode(tmp.path())?;
+        if self.fsync {
+            fault_point(FaultPoint::Fsync)
+                .and_then(|_| tmp.as_file().sync_all())
+                .map_err(|e| zerror!("Failed to sync file {:?}: {}", tmp.path(), e))?;
+        }
+        Ok(StagedOp::Put {
+            file,
+            tmp,
+            encoding: encoding.clone(),
+            timestamp: *timestamp,
+            base64,
+            value_size,
+        })
+    }
 
-#[cfg(windows)]
-pub(crate) fn zpath_to_fspath(zpath: &str) -> Cow<'_, str> {
-    const WIN_SEP: &str = r#"\"#;
-    Cow::from(zpath.replace('/', WIN_SEP))
-}
+    // Stage the deletion of a file in a transaction
+    pub(crate) fn stage_delete(
+        &self,
+        zfile: &ZFile<'_>,
+        timestamp: &Timestamp,
+    ) -> ZResult<StagedOp> {
+        let file = self.to_staged_fspath(zfile)?;
+        let file = if file.is_file() {
+            file
+        } else {
+            self.get_conflict_file(file)
+        };
+        Ok(StagedOp::Delete {
+            file,
+            timestamp: *timestamp,
+        })
+    }
 
-#[cfg(unix)]
-#[inline(always)]
-pub(crate) fn fspath_to_zpath(fspath: &str) -> Cow<'_, str> {
-    Cow::from(fspath)
-}
+    // Get the path of a file written by a transaction, refusing the cases where the commit
+    // would have to modify other files than the ones of the transaction
+    fn to_staged_fspath(&self, zfile: &ZFile<'_>) -> ZResult<PathBuf> {
+        if self.has_top_dirs() || self.pre_write_hook.is_some() || !self.strip_extensions.is_empty()
+        {
+            bail!("Transactions are not supported with dir rotation, encoding sub-directories, a pre-write hook or stripped extensions")
+        }
+        let file = self.to_write_fspath(zfile);
+        if file
+            .ancestors()
+            .skip(1)
+            .take_while(|a| *a != self.base_dir)
+            .any(|a| a.is_file())
+        {
+            bail!(
+                "Writing {} in a transaction would move a stored file to a conflict file",
+                zfile
+            )
+        }
+        Ok(file)
+    }
 
-#[cfg(windows)]
-pub(crate) fn fspath_to_zpath(fspath: &str) -> Cow<'_, str> {
-    const ZENOH_SEP: &str = "/";
-    Cow::from(fspath.replace(std::path::MAIN_SEPARATOR, ZENOH_SEP))
-}
+    // Commit the operations staged in a transaction (at most 1 per file).
+    // The files are locked (in order, so concurrent commits can't deadlock) until committed, and if one of them
+    // was written with a newer timestamp since it was staged, nothing is committed.
+    // All their data-infos are saved at once, thus if this fails no file is modified (the temporary files
+    // are removed when dropped). Only then the files are replaced or removed: a failure at this step
+    // leaves the data-infos of the failed operations ahead of their files.
+    pub(crate) async fn commit(&self, ops: Vec<StagedOp>) -> ZResult<()> {
+        let mut files: Vec<&Path> = ops.iter().map(|op| op.file()).collect();
+        files.sort();
+        // (the files stay locked until committed)
+        let mut locks = Vec::with_capacity(files.len());
+        for file in files {
+            locks.push(self.key_locks.lock(file).await);
+        }
+        for op in &ops {
+            if let Some(stored_ts) = self.data_info_mgr.get_timestamp(op.file()).await? {
+                if stored_ts > *op.timestamp() {
+                    bail!(
+                        "Transaction aborted: {:?} was written with a newer timestamp since it was staged",
+                        op.file()
+                    )
+                }
+            }
+        }
+        let empty: Encoding = KnownEncoding::Empty.into();
+        let infos: Vec<(&Path, &Encoding, &Timestamp, bool, Option<u64>)> = ops
+            .iter()
+            .map(|op| match op {
+                StagedOp::Put {
+                    file,
+                    encoding,
+                    timestamp,
+                    base64,
+                    value_size,
+                    ..
+                } => (file.as_path(), encoding, timestamp, *base64, *value_size),
+                // save timestamp in data-info (encoding is not used)
+                StagedOp::Delete { file, timestamp } => {
+                    (file.as_path(), &empty, timestamp, false, None)
+                }
+            })
+            .collect();
+        self.data_info_mgr.put_data_infos(&infos).await?;
 
-fn is_symlink<P: AsRef<Path>>(path: P) -> bool {
-    match path.as_ref().symlink_metadata() {
-        Ok(metadata) => metadata.file_type().is_symlink(),
-        Err(_) => false,
+        let mut dirty_dirs = DirtyDirs::default();
+        let mut failures = 0;
+        for op in ops {
+            let result = match op {
+                StagedOp::Put { file, tmp, .. } => {
+                    self.commit_staged_write(file, tmp, &mut dirty_dirs)
+                }
+                StagedOp::Delete { file, .. } => self
+                    .remove_file_or_conflict(&file, &mut dirty_dirs)
+                    .map(|_| ()),
+            };
+            if let Err(e) = result {
+                warn!("Failed to commit transaction: {}", e);
+                failures += 1;
+            }
+        }
+        self.sync_dirs(dirty_dirs)?;
+        if failures > 0 {
+            bail!(
+                "Transaction partially committed: {} operations failed (see logs)",
+                failures
+            )
+        }
+        Ok(())
     }
-}
 
-pub(crate) fn get_trimmed_keyexpr(keyexpr: &str) -> &str {
-    let k = if keyexpr.ends_with(CONFLICT_SUFFIX) {
+    fn commit_staged_write(
+        &self,
+        file: PathBuf,
+        tmp: tempfile::NamedTempFile,
+        dirty_dirs: &mut DirtyDirs,
+    ) -> ZResult<()> {
+        let parent = file.parent().unwrap();
+        for new_dir in parent.ancestors().take_while(|d| !d.exists()) {
+            dirty_dirs.add(new_dir.parent());
+        }
+        self.create_dirs(parent)
+            .map_err(|e| write_error("Failed to create directories for file", &file, e))?;
+        dirty_dirs.add(Some(parent));
+        dirty_dirs.add(Some(&self.base_dir));
+        fault_point(FaultPoint::Rename)
+            .map_err(|e| write_error("Failed to write in file", &file, e))?;
+        tmp.persist(&file)
+            .map_err(|e| write_error("Failed to write in file", &file, e.error))?;
+        self.unmap_file(&file);
+        Ok(())
+    }
+
+    // Read a file and return it's content (as Vec<u8>), encoding and timestamp.
+    // Encoding and timestamp are retrieved from the data_info_mgr if file was put via zenoh.
+    // Otherwise, the encoding is guessed from the file extension, and the timestamp is computed from the file's time.
+    #[tracing::instrument(level = "debug", skip_all, fields(op = "get", file = %zfile))]
+    pub(crate) async fn read_file(&self, zfile: &ZFile<'_>) -> ZResult<Option<(Value, Timestamp)>> {
+        let file = &zfile.fspath;
+        match self.perform_read(file).await? {
+            Some(x) => Ok(Some(x)),
+            None => match self.perform_read_from_conflict(file.to_path_buf()).await? {
+                Some(x) => Ok(Some(x)),
+                None => {
+                    self.on_missing_file(file).await;
+                    Ok(None)
+                }
+            },
+        }
+    }
+
+    // Pin and read several files concurrently (each in its own task, possibly through a cache shared by them),
+    // and return their results in the same order, with the files still pinned. A failed read doesn't interrupt
+    // the others.
+    pub(crate) async fn batch_read(
+        self: &Arc<Self>,
+        zfiles: &[ZFile<'_>],
+        cache: Option<Arc<QueryReadCache>>,
+    ) -> Vec<ZResult<Option<(Value, Timestamp, PinnedFile)>>> {
+        let handles: Vec<_> = zfiles
+            .iter()
+            .map(|zfile| {
+                let files_mgr = self.clone();
+                let cache = cache.clone();
+                let zpath = zfile.zpath.to_string();
+                task::spawn(async move {
+                    let zfile = files_mgr.to_zfile(&zpath);
+                    match files_mgr.pin(&zfile).await? {
+                        Some(mut pinned) => {
+                            let (value, timestamp) = files_mgr
+                                .read_pinned(&mut pinned, cache.as_deref(), false)
+                                .await?;
+                            Ok(Some((value, timestamp, pinned)))
+                        }
+                        None => Ok(None),
+                    }
+                })
+            })
+            .collect();
+        let mut results = Vec::with_capacity(handles.len());
+        for handle in handles {
+            results.push(handle.await);
+        }
+        results
+    }
+
+    async fn perform_read_from_conflict(
+        &self,
+        file: PathBuf,
+    ) -> ZResult<Option<(Value, Timestamp)>> {
+        let file = self.get_conflict_file(file.to_path_buf());
+        self.perform_read(&file.to_path_buf()).await
+    }
+
+    // Get the timestamp of a file as read_file() would return it, but without reading its content
+    pub(crate) async fn read_file_timestamp(
+        &self,
+        zfile: &ZFile<'_>,
+    ) -> ZResult<Option<Timestamp>> {
+        let file = &zfile.fspath;
+        match self.perform_read_timestamp(file).await? {
+            Some(x) => Ok(Some(x)),
+            None => {
+                let file = self.get_conflict_file(file.to_path_buf());
+                self.perform_read_timestamp(&file).await
+            }
+        }
+    }
+
+    async fn perform_read_timestamp(&self, file: &Path) -> ZResult<Option<Timestamp>> {
+        if file.exists() && file.is_file() && (self.follow_links || !self.contains_symlink(&file)) {
+            let (_, timestamp) = self.get_encoding_and_timestamp(file).await?;
+            Ok(Some(timestamp))
+        } else {
+            Ok(None)
+        }
+    }
+
+    // Open the file of a ZFile (or its conflict file) and hold it with a shared lock
+    // until the returned PinnedFile is dropped
+    pub(crate) async fn pin(&self, zfile: &ZFile<'_>) -> ZResult<Option<PinnedFile>> {
+        let file = &zfile.fspath;
+        match self.perform_pin(file.to_path_buf()).await? {
+            Some(x) => Ok(Some(x)),
+            None => match self
+                .perform_pin(self.get_conflict_file(file.to_path_buf()))
+                .await?
+            {
+                Some(x) => Ok(Some(x)),
+                None => {
+                    self.on_missing_file(file).await;
+                    Ok(None)
+                }
+            },
+        }
+    }
+
+    // Apply the "on_missing_file" policy if a key not found at read time has the data-info of a PUT
+    // (for its file or its conflict file), i.e. its file was removed by another process
+    async fn on_missing_file(&self, file: &Path) {
+        if let OnMissingFile::Absent = self.on_missing_file {
+            return;
+        }
+        let empty: Encoding = KnownEncoding::Empty.into();
+        for file in [
+            file.to_path_buf(),
+            self.get_conflict_file(file.to_path_buf()),
+        ] {
+            // (an existing file not read is excluded, e.g. a symlink with "follow_links=false")
+            if file.exists() {
+                continue;
+            }
+            let timestamp = match self.data_info_mgr.get_encoding_and_timestamp(&file).await {
+                // the DELETE are recorded as tombstones with an empty encoding
+                Ok(Some((encoding, timestamp))) if encoding != empty => timestamp,
+                Ok(_) => continue,
+                Err(e) => {
+                    warn!("Failed to get data-info for {:?}: {}", file, e);
+                    continue;
+                }
+            };
+            match self.on_missing_file {
+                OnMissingFile::Log => error!(
+                    "File {:?} not found, while its data-info is the one of a PUT with timestamp {}: it was removed by another process",
+                    file, timestamp
+                ),
+                OnMissingFile::Tombstone if self.is_quiesced() => debug!(
+                    "File {:?} not found, while its data-info is the one of a PUT with timestamp {}: not replaced by a tombstone while the storage is quiesced",
+                    file, timestamp
+                ),
+                OnMissingFile::Tombstone => match self
+                    .data_info_mgr
+                    .put_tombstone_if_unchanged(&file, &timestamp, &new_reception_timestamp())
+                    .await
+                {
+                    Ok(true) => warn!(
+                        "File {:?} not found, while its data-info is the one of a PUT with timestamp {}: replaced by a tombstone",
+                        file, timestamp
+                    ),
+                    Ok(false) => (),
+                    Err(e) => warn!("Failed to put tombstone for missing file {:?}: {}", file, e),
+                },
+                OnMissingFile::Absent => (),
+            }
+        }
+    }
+
+    async fn perform_pin(&self, file: PathBuf) -> ZResult<Option<PinnedFile>> {
+        // checking and opening may block on a stalled file system,
+        // and locking is blocking if another process holds an exclusive lock on the file
+        let (path, base_dir, follow_links, mapped_files) = (
+            file.clone(),
+            self.base_dir.clone(),
+            self.follow_links,
+            self.mapped_files.clone(),
+        );
+        let opened = self
+            .run_io("open", move || {
+                // consider file only if it's a file and in case of "follow_links=false" it doesn't contain symlink
+                let metadata = match path.metadata() {
+                    Ok(metadata) if metadata.is_file() => metadata,
+                    _ => return Ok(None),
+                };
+                if !follow_links && contains_symlink(&base_dir, &path) {
+                    return Ok(None);
+                }
+                // a file still mapped with the same identity is read from its mapping, without being opened
+                if let Some(mapping) = mapped_files.as_ref().and_then(|m| m.get(&path, &metadata)) {
+                    return Ok(Some(Ok(PinnedSource::Mapped(mapping))));
+                }
+                match File::open(&path) {
+                    Ok(f) => match f.lock_shared() {
+                        // (once mapped, the file is held by its mapping: it's closed, releasing its lock)
+                        Ok(()) => match mapped_files.as_ref().and_then(|m| m.map(&path, &f)) {
+                            Some(mapping) => Ok(Some(Ok(PinnedSource::Mapped(mapping)))),
+                            None => Ok(Some(Ok(PinnedSource::Opened(f)))),
+                        },
+                        Err(e) => bail!("Failed to lock file {:?}: {}", path, e),
+                    },
+                    Err(e) => Ok(Some(Err(e))),
+                }
+            })
+            .await?;
+        let source = match opened {
+            None => return Ok(None),
+            Some(Ok(source)) => source,
+            Some(Err(e)) => {
+                self.on_unreadable_file(&file, &e).await;
+                bail!(r#"Error reading file {:?}: {}"#, file, e)
+            }
+        };
+        if let PinnedSource::Opened(f) = &source {
+            advise_file(f, self.io_advice);
+        }
+        Ok(Some(PinnedFile { path: file, source }))
+    }
+
+    // Read a pinned file through its held handle, and return it's content, encoding and timestamp.
+    // If a cache is specified, the content is taken from it if a file with the same content was already read.
+    // If keep_compressed is true, a ".gz" file is returned compressed (see read_opened()).
+    // The read is counted in the access count of the file's data-info.
+    pub(crate) async fn read_pinned(
+        &self,
+        pinned: &mut PinnedFile,
+        cache: Option<&QueryReadCache>,
+        keep_compressed: bool,
+    ) -> ZResult<(Value, Timestamp)> {
+        let read = match &pinned.source {
+            PinnedSource::Opened(f) => {
+                self.read_opened(&pinned.path, f, cache, keep_compressed)
+                    .await?
+            }
+            PinnedSource::Mapped(mapping) => {
+                self.read_mapped(&pinned.path, mapping.clone(), keep_compressed)
+                    .await?
+            }
+        };
+        // (a read isn't failed by its count)
+        if let Err(e) = self
+            .data_info_mgr
+            .increment_access_count(&pinned.path)
+            .await
+        {
+            warn!("Failed to count the read of {:?}: {}", pinned.path, e);
+        }
+        Ok(read)
+    }
+
+    // With "io_advice" set to "sequential", drop the pages of a pinned file read for a bulk reply from the page cache
+    pub(crate) fn release_bulk_read(&self, pinned: &PinnedFile) {
+        if let (IoAdvice::Sequential, PinnedSource::Opened(f)) = (self.io_advice, &pinned.source) {
+            drop_page_cache(f);
+        }
+    }
+
+    // Drop the memory mapping of a file written, deleted or renamed via zenoh, if any ("mmap_reads")
+    fn unmap_file(&self, file: &Path) {
+        if let Some(mapped_files) = &self.mapped_files {
+            mapped_files.unmap(file);
+        }
+    }
+
+    async fn perform_read(&self, file: &Path) -> ZResult<Option<(Value, Timestamp)>> {
+        match self.perform_pin(file.to_path_buf()).await? {
+            Some(mut pinned) => self.read_pinned(&mut pinned, None, false).await.map(Some),
+            None => Ok(None),
+        }
+    }
+
+    // Read an opened file, and return it's content, encoding and timestamp.
+    // If keep_compressed is true, a ".gz" file decompressed by "decompress_gz_on_read" is returned as is,
+    // with the GZIP_ENCODING_SUFFIX appended to the encoding of its decompressed content.
+    async fn read_opened(
+        &self,
+        file: &Path,
+        f: &File,
+        cache: Option<&QueryReadCache>,
+        keep_compressed: bool,
+    ) -> ZResult<(Value, Timestamp)> {
+        // (the file system operations run through a handle of the file, as they may block on a stalled file system)
+        let handle = f
+            .try_clone()
+            .map_err(|e| zerror!(r#"Error reading file {:?}: {}"#, file, e))?;
+        let metadata = self
+            .run_read("stat", move || Ok(handle.metadata().ok()))
+            .await?;
+        let size = metadata.as_ref().map(|m| m.len()).unwrap_or(256);
+        if size > usize::MAX as u64 {
+            bail!(r#"Error reading file {:?}: too big to fit in memory"#, file)
+        }
+        let content_ids = match (cache, &metadata) {
+            (Some(_), Some(metadata)) => self.content_ids(file, metadata).await,
+            _ => Vec::new(),
+        };
+        let cached = cache.and_then(|cache| cache.get(&content_ids));
+        let content = if let Some(cached) = &cached {
+            trace!("Read file {:?} from the query's cache", file);
+            Ok(cached.to_vec())
+        } else {
+            trace!("Read file {:?}", file);
+            let mut handle = f
+                .try_clone()
+                .map_err(|e| zerror!(r#"Error reading file {:?}: {}"#, file, e))?;
+            self.run_read("read", move || Ok(read_content(&mut handle, size as usize)))
+                .await?
+        };
+        let content = match content {
+            Ok(content) => {
+                self.on_readable_file(file);
+                content
+            }
+            Err(e) => {
+                self.on_unreadable_file(file, &e).await;
+                bail!(r#"Error reading file {:?}: {}"#, file, e)
+            }
+        };
+        if let (Some(cache), None) = (cache, cached) {
+            cache.insert(content_ids, &content);
+        }
+        self.decode_content(file, content, metadata.as_ref(), keep_compressed)
+            .await
+    }
+
+    // Read a file held by its memory mapping ("mmap_reads"), and return it's content, encoding and timestamp.
+    // The content is copied from the mapping without any lock, as the mapping is held until it's copied.
+    async fn read_mapped(
+        &self,
+        file: &Path,
+        mapping: Arc<Mapping>,
+        keep_compressed: bool,
+    ) -> ZResult<(Value, Timestamp)> {
+        trace!("Read file {:?} via its memory mapping", file);
+        let metadata = mapping.metadata().clone();
+        let content = self
+            .run_read("read", move || Ok(mapping.content().to_vec()))
+            .await?;
+        self.on_readable_file(file);
+        self.decode_content(file, content, Some(&metadata), keep_compressed)
+            .await
+    }
+
+    // Decode the content read from a file (see read_opened()), and return it with its encoding and timestamp
+    async fn decode_content(
+        &self,
+        file: &Path,
+        content: Vec<u8>,
+        metadata: Option<&std::fs::Metadata>,
+        keep_compressed: bool,
+    ) -> ZResult<(Value, Timestamp)> {
+        let (content, encoding, timestamp) = match self.external_gz_file_stem(file).await? {
+            Some(stem) if keep_compressed => {
+                // the content isn't decompressed: its encoding is only guessed from the file's name
+                let timestamp = self.get_opened_file_timestamp(file, metadata)?;
+                let encoding = self
+                    .guess_mime_encoding(&stem, None)
+                    .unwrap_or_else(|| self.default_encoding.clone());
+                let suffix = format!("{}{}", encoding.suffix(), GZIP_ENCODING_SUFFIX);
+                let encoding = Encoding::WithSuffix(*encoding.prefix(), suffix.into());
+                (content, encoding, timestamp)
+            }
+            Some(stem) => {
+                let content = gunzip(&content, self.max_decompressed_bytes)
+                    .map_err(|e| zerror!("Error decompressing file {:?}: {}", file, e))?;
+                let timestamp = self.get_opened_file_timestamp(file, metadata)?;
+                let encoding = self.guess_content_encoding(&stem, &content);
+                (content, encoding, timestamp)
+            }
+            None => match self.data_info_mgr.get_encoding_and_timestamp(file).await? {
+                Some((encoding, timestamp)) => (content, encoding, timestamp),
+                None => {
+                    trace!("data-info for {:?} not found; fallback to metadata", file);
+                    let encoding = self.guess_content_encoding(file, &content);
+                    let timestamp = self.get_opened_file_timestamp(file, metadata)?;
+                    (content, encoding, timestamp)
+                }
+            },
+        };
+        // decode the content written base64-encoded by "text_safe_storage"
+        // (whatever the current configuration, as it might have changed)
+        let content = if self.data_info_mgr.is_base64_encoded(file).await? {
+            BASE64
+                .decode(&content)
+                .map_err(|e| zerror!("Error decoding base64 content of file {:?}: {}", file, e))?
+        } else {
+            content
+        };
+        // the buffer is moved into the ZBuf (no copy): the same bytes are then
+        // moved into the reply Sample by the caller.
+        Ok((
+            Value::new(ZBuf::from(content)).encoding(encoding),
+            timestamp,
+        ))
+    }
+
+    // Get the identities of a file's content known without reading it: its inode,
+    // and the content hash recorded in its data-info if the file was not modified since
+    async fn content_ids(&self, file: &Path, metadata: &std::fs::Metadata) -> Vec<ContentId> {
+        let mut ids: Vec<ContentId> = inode_content_id(metadata).into_iter().collect();
+        if let Ok(Some((_, content_hash))) = self.data_info_mgr.get_content_hash(file).await {
+            if metadata_mtime(metadata) == Some(content_hash.mtime) {
+                ids.push(ContentId::Hash(content_hash.hash));
+            }
+        }
+        ids
+    }
+
+    // If "decompress_gz_on_read" is enabled and the file is a ".gz" file placed without zenoh (i.e. without data-info),
+    // return the file's path without the ".gz" extension (to guess the encoding of the decompressed content)
+    async fn external_gz_file_stem(&self, file: &Path) -> ZResult<Option<PathBuf>> {
+        if !self.decompress_gz_on_read || file.extension().map_or(true, |ext| ext != "gz") {
+            return Ok(None);
+        }
+        if self.data_info_mgr.get_timestamp(file).await?.is_some() {
+            return Ok(None);
+        }
+        Ok(Some(file.with_extension("")))
+    }
+
+    // If "quarantine_unreadable" is enabled, move a persistently unreadable file into the quarantine directory
+    // (with its data-info), so it's no longer part of the storage, and record the reason of its quarantine.
+    // A file is persistently unreadable once it failed QUARANTINE_AFTER_FAILURES consecutive times with a
+    // permanent error (a transient one, such as too many opened files, doesn't count).
+    async fn on_unreadable_file(&self, file: &Path, error: &std::io::Error) {
+        // (the directory must not be modified while the storage is quiesced)
+        if !self.quarantine_unreadable || self.is_quiesced() || !is_permanent_read_error(error) {
+            return;
+        }
+        {
+            let mut read_failures = self.read_failures.lock().unwrap();
+            let failures = read_failures.entry(file.to_path_buf()).or_insert(0);
+            *failures += 1;
+            if *failures < QUARANTINE_AFTER_FAILURES {
+                return;
+            }
+            read_failures.remove(file);
+        }
+        let relative = match file.strip_prefix(&self.base_dir) {
+            Ok(relative) => relative,
+            Err(_) => return,
+        };
+        let mut quarantined = self.base_dir.join(QUARANTINE_DIRNAME);
+        quarantined.push(relative);
+        let result = quarantined
+            .parent()
+            .map_or(Ok(()), |parent| self.create_dirs(parent))
+            .and_then(|()| {
+                self.unmap_file(file);
+                rename(file, &quarantined)
+            });
+        if let Err(e) = result {
+            warn!(
+                "Failed to move unreadable file {:?} to quarantine: {}",
+                file, e
+            );
+            return;
+        }
+        // data-info might not exist if the file was not put via zenoh
+        let _ = self
+            .data_info_mgr
+            .rename_key(file, quarantined.as_path())
+            .await;
+        let relative = relative.to_string_lossy().into_owned();
+        let reason = error.to_string();
+        warn!(
+            "Unreadable file {:?} ({}) moved to quarantine as {:?}",
+            file, reason, quarantined
+        );
+        if let Err(e) = self.data_info_mgr.put_quarantine(&relative, &reason).await {
+            warn!("{}", e);
+        }
+        self.quarantine.lock().unwrap().insert(relative, reason);
+    }
+
+    // Reset the failures count of a file which was read
+    fn on_readable_file(&self, file: &Path) {
+        if self.quarantine_unreadable {
+            let mut read_failures = self.read_failures.lock().unwrap();
+            if !read_failures.is_empty() {
+                read_failures.remove(file);
+            }
+        }
+    }
+
+    // True while base_dir is replaced, until it's consistent again
+    pub(crate) fn is_waiting(&self) -> bool {
+        self.waiting.load(Ordering::Relaxed)
+    }
+
+    // Number of times base_dir was detected as replaced
+    pub(crate) fn base_dir_transitions(&self) -> u64 {
+        self.base_dir_transitions.load(Ordering::Relaxed)
+    }
+
+    // Check if base_dir was replaced underneath the storage (e.g. its volume was unmounted and re-mounted).
+    // If so, the data-info database is closed and the storage is waiting until base_dir exists with the
+    // same identity for 2 consecutive checks. Then the database is re-opened and the storage resumes.
+    pub(crate) async fn check_base_dir(&self) {
+        let mut state = self.base_dir_state.lock().await;
+        let id = dir_id(&self.base_dir);
+        if !self.is_waiting() {
+            if id == state.id {
+                return;
+            }
+            warn!(
+                "Storage directory {:?} was replaced or removed: waiting for it to be consistent again",
+                self.base_dir
+            );
+            self.waiting.store(true, Ordering::Relaxed);
+            self.base_dir_transitions.fetch_add(1, Ordering::Relaxed);
+            state.last_seen = id;
+            self.data_info_mgr.suspend().await;
+            if let Some(mapped_files) = &self.mapped_files {
+                mapped_files.clear();
+            }
+            return;
+        }
+
+        if id.is_none() || id != state.last_seen {
+            state.last_seen = id;
+            return;
+        }
+        match self.data_info_mgr.reopen(&self.base_dir).await {
+            Ok(()) => {
+                match self.data_info_mgr.get_quarantine().await {
+                    Ok(quarantine) => *self.quarantine.lock().unwrap() = quarantine,
+                    Err(e) => warn!("Failed to reload the quarantined files: {}", e),
+                }
+                state.id = id;
+                self.waiting.store(false, Ordering::Relaxed);
+                debug!("Storage directory {:?} is consistent again", self.base_dir);
+            }
+            Err(e) => warn!(
+                "Storage directory {:?} is back, but still waiting: {}",
+                self.base_dir, e
+            ),
+        }
+    }
+
+    // Get the date (in milliseconds since the UNIX epoch) until which a key is immutable, if not passed yet
+    // (a passed date is forgotten)
+    pub(crate) async fn get_immutable_until(&self, zpath: &str) -> ZResult<Option<u64>> {
+        match self.data_info_mgr.get_retention(zpath).await? {
+            Some(until_ms) if until_ms > now_ms() => Ok(Some(until_ms)),
+            Some(_) => {
+                self.data_info_mgr.del_retention(zpath).await?;
+                Ok(None)
+            }
+            None => Ok(None),
+        }
+    }
+
+    // Make a key immutable until a date (in milliseconds since the UNIX epoch), at most `max_duration` from now.
+    // The date can be postponed, but not brought forward.
+    pub(crate) async fn set_immutable_until(
+        &self,
+        zpath: &str,
+        until_ms: u64,
+        max_duration: Duration,
+    ) -> ZResult<()> {
+        let now = now_ms();
+        if until_ms <= now {
+            bail!(
+                "Cannot make key {} immutable until {} ms since the UNIX epoch: this date is passed",
+                zpath,
+                until_ms
+            )
+        }
+        if until_ms - now > max_duration.as_millis() as u64 {
+            bail!(
+                "Cannot make key {} immutable until {} ms since the UNIX epoch: this date is more than {:?} from now",
+                zpath,
+                until_ms,
+                max_duration
+            )
+        }
+        if let Some(current) = self.get_immutable_until(zpath).await? {
+            if until_ms < current {
+                bail!(
+                    "Cannot make key {} immutable until {} ms since the UNIX epoch: it's already immutable until {}",
+                    zpath,
+                    until_ms,
+                    current
+                )
+            }
+        }
+        self.data_info_mgr.put_retention(zpath, until_ms).await
+    }
+
+    // Get the data-info of a key (of its file, or else of its conflict file) with its extras
+    pub(crate) async fn get_data_info(
+        &self,
+        zfile: &ZFile<'_>,
+    ) -> ZResult<Option<(Encoding, Timestamp, DataInfoExtras)>> {
+        for file in [
+            zfile.fspath.to_path_buf(),
+            self.get_conflict_file(zfile.fspath.to_path_buf()),
+        ] {
+            if let Some((encoding, timestamp)) =
+                self.data_info_mgr.get_encoding_and_timestamp(&file).await?
+            {
+                let extras = self
+                    .data_info_mgr
+                    .get_extras(&file)
+                    .await?
+                    .unwrap_or_default();
+                return Ok(Some((encoding, timestamp, extras)));
+            }
+        }
+        Ok(None)
+    }
+
+    // Set a custom tag in the data-info of a key (of its file, or else of its conflict file),
+    // or remove it if value is None
+    pub(crate) async fn set_data_info_tag(
+        &self,
+        zfile: &ZFile<'_>,
+        name: &str,
+        value: Option<&str>,
+    ) -> ZResult<()> {
+        for file in [
+            zfile.fspath.to_path_buf(),
+            self.get_conflict_file(zfile.fspath.to_path_buf()),
+        ] {
+            let updated = self
+                .data_info_mgr
+                .update_extras(&file, |extras| {
+                    extras.tags.retain(|(tag_name, _)| tag_name != name);
+                    if let Some(value) = value {
+                        extras.tags.push((name.to_string(), value.to_string()));
+                    }
+                })
+                .await?;
+            if updated {
+                return Ok(());
+            }
+        }
+        bail!(
+            "Cannot tag key {}: it has no data-info (i.e. it was not put via zenoh)",
+            zfile
+        )
+    }
+
+    // Re-key the data-infos recorded while the storage directory was referred to by another path
+    // (e.g. with or without resolving its symbolic links). Return the number of data-infos re-keyed.
+    pub(crate) async fn rebase_data_info(&self, previous_base_dir: &Path) -> ZResult<u64> {
+        self.data_info_mgr
+            .rebase(previous_base_dir, &self.base_dir)
+            .await
+    }
+
+    // Backup the data-info database to a new directory
+    pub(crate) async fn backup_data_info(&self, dest: &Path) -> ZResult<()> {
+        if dest.exists() {
+            bail!(
+                "Failed to backup data-info database to {:?}: it already exists",
+                dest
+            )
+        }
+        self.data_info_mgr.backup_to(dest).await
+    }
+
+    // Replace the data-info database with a backup created by backup_data_info()
+    pub(crate) async fn restore_data_info(&self, src: &Path) -> ZResult<()> {
+        self.data_info_mgr.restore_from(&self.base_dir, src).await?;
+        // the quarantined files are recorded in the data-info database
+        *self.quarantine.lock().unwrap() = self.data_info_mgr.get_quarantine().await?;
+        Ok(())
+    }
+
+    // Rewrite the data-info database without its duplicate and orphan data-infos, and replace it.
+    // A key has duplicate data-infos if recorded for both its file and its conflict file: the older one is removed,
+    // unless its file exists (if both files exist, they are left to "on_ambiguous_file").
+    pub(crate) async fn compact_storage(&self) -> ZResult<CompactReport> {
+        let timestamps = self.data_info_mgr.get_all_timestamps().await;
+        let mut duplicates: HashSet<PathBuf> = HashSet::new();
+        for (file, timestamp) in &timestamps {
+            let conflict_file = self.get_conflict_file(file.clone());
+            if let Some(conflict_timestamp) = timestamps.get(&conflict_file) {
+                let older = if timestamp < conflict_timestamp {
+                    file
+                } else {
+                    &conflict_file
+                };
+                if !older.is_file() {
+                    trace!("Remove duplicate data-info for {:?}", older);
+                    duplicates.insert(older.clone());
+                }
+            }
+        }
+        let compaction = self
+            .data_info_mgr
+            .compact(&self.base_dir, &duplicates)
+            .await?;
+        Ok(CompactReport {
+            duplicates_removed: duplicates.len() as u64,
+            orphans_removed: compaction.orphans_removed,
+            index_entries_removed: compaction.index_entries_removed,
+            records_kept: compaction.records_kept,
+        })
+    }
+
+    // Record a sample dropped as out-of-date ("conflict_log"), keeping at most `capacity` records
+    pub(crate) async fn log_conflict(&self, record: &[u8], capacity: u64) -> ZResult<()> {
+        self.data_info_mgr.put_conflict(record, capacity).await
+    }
+
+    // Get the most recent records of samples dropped as out-of-date, the newest first
+    pub(crate) async fn get_conflicts(&self, limit: usize) -> ZResult<Vec<Vec<u8>>> {
+        self.data_info_mgr.get_conflicts(limit).await
+    }
+
+    // Get the quarantined files (relative to the quarantine directory), with the reason of their quarantine
+    pub(crate) fn get_quarantine(&self) -> HashMap<String, String> {
+        self.quarantine.lock().unwrap().clone()
+    }
+
+    // Move back a quarantined file (relative to the quarantine directory) to its original place,
+    // if no file was stored with the same key in the meantime.
+    pub(crate) async fn restore_quarantined_file(&self, relative: &str) -> ZResult<()> {
+        if !self.quarantine.lock().unwrap().contains_key(relative) {
+            bail!("No quarantined file {}", relative)
+        }
+        let mut quarantined = self.base_dir.join(QUARANTINE_DIRNAME);
+        quarantined.push(relative);
+        let file = self.base_dir.join(relative);
+        if file.exists() {
+            bail!(
+                "Cannot restore quarantined file {:?}: {:?} already exists",
+                quarantined,
+                file
+            )
+        }
+        if let Some(parent) = file.parent() {
+            self.create_dirs(parent)
+                .map_err(|e| zerror!("Failed to create directories for file {:?}: {}", file, e))?;
+        }
+        rename(&quarantined, &file)
+            .map_err(|e| zerror!("Failed to rename {:?} to {:?}: {}", quarantined, file, e))?;
+        // data-info might not exist if the file was not put via zenoh
+        let _ = self
+            .data_info_mgr
+            .rename_key(quarantined.as_path(), file.as_path())
+            .await;
+        self.data_info_mgr.del_quarantine(relative).await?;
+        self.quarantine.lock().unwrap().remove(relative);
+        debug!("Quarantined file {:?} restored as {:?}", quarantined, file);
+        Ok(())
+    }
+
+    // Get the directory containing all the files possibly matching a key expression with wildcards
+    fn search_dir(&self, zpath_expr: &keyexpr) -> PathBuf {
+        // find the longest segment without '*' to search for files only in the corresponding
+        let star_idx = zpath_expr.find('*').unwrap();
+        let segment = match zpath_expr[..star_idx].rfind('/') {
+            Some(i) => &zpath_expr[..i],
+            None => "",
+        };
+        // Directory to search for matching files is base_dir + segment converted as a file-system path
+        // (with dir rotation, encoding sub-directories or a path template, the whole storage directory is searched)
+        if self.has_top_dirs() || self.path_template.is_some() {
+            self.base_dir.clone()
+        } else {
+            self.to_dir_fspath(segment)
+        }
+    }
+
+    // Search for files matching path_expr.
+    pub(crate) fn matching_files<'a>(&self, zpath_expr: &'a keyexpr) -> FilesIterator<'a> {
+        let search_dir = self.search_dir(zpath_expr);
+        let base_dir_len = self.base_dir.as_os_str().len();
+
+        if !self.follow_links && self.contains_symlink(&search_dir) {
+            debug!(
+                "Don't search for files in {:?} as it's within a symbolic link",
+                search_dir
+            );
+            // return a useless FilesIterator that won't return anything (simpler than to return an Option<FilesIterator>)
+            let walkdir = WalkDir::new("");
+            FilesIterator {
+                source: FilesSource::Walk(walkdir.into_iter()),
+                zpath_expr,
+                base_dir_len,
+                follow_links: self.follow_links,
+                shard_depth: self.shard_depth,
+                reserved_prefix: self.reserved_prefix.clone(),
+                rotated_zpaths: self.has_top_dirs().then(HashSet::new),
+                path_template: self.path_template.clone(),
+                strip_extensions: self.strip_extensions.clone(),
+                filename_encoding: self.filename_encoding,
+            }
+        } else {
+            let source = match self.to_glob_pattern(zpath_expr) {
+                Some(pattern) => {
+                    debug!(
+                        "For path_expr={} search matching files with pattern {}",
+                        zpath_expr, pattern
+                    );
+                    let options = MatchOptions {
+                        case_sensitive: true,
+                        require_literal_separator: true,
+                        // the keys starting with '.' are matched by '*' as the other keys
+                        require_literal_leading_dot: false,
+                    };
+                    match glob_with(&pattern, options) {
+                        Ok(paths) => Some(FilesSource::Glob(paths)),
+                        Err(e) => {
+                            warn!(
+                                "Invalid glob pattern {} for path_expr={}: {}",
+                                pattern, zpath_expr, e
+                            );
+                            None
+                        }
+                    }
+                }
+                None => None,
+            };
+            let source = source.unwrap_or_else(|| {
+                debug!(
+                    "For path_expr={} search matching files in {:?}",
+                    zpath_expr, search_dir
+                );
+                FilesSource::Walk(
+                    WalkDir::new(search_dir)
+                        .follow_links(self.follow_links)
+                        .into_iter(),
+                )
+            });
+            FilesIterator {
+                source,
+                zpath_expr,
+                base_dir_len,
+                follow_links: self.follow_links,
+                shard_depth: self.shard_depth,
+                reserved_prefix: self.reserved_prefix.clone(),
+                rotated_zpaths: self.has_top_dirs().then(HashSet::new),
+                path_template: self.path_template.clone(),
+                strip_extensions: self.strip_extensions.clone(),
+                filename_encoding: self.filename_encoding,
+            }
+        }
+    }
+
+    // Convert a key expression to a glob pattern of the file paths ("glob_mode"): `**` and `*` are kept, and the
+    // `$*` within a chunk are converted to `*`. The last chunk is suffixed with `*` to also match the conflict files
+    // (the returned files being checked against the key expression anyway), and a last `**` is followed by `*`
+    // to match the files and not only the directories. Return None if not in "glob_mode",
+    // or if the storage directory or a chunk can't be converted (e.g. not valid UTF-8), to walk the directories.
+    fn to_glob_pattern(&self, zpath_expr: &keyexpr) -> Option<String> {
+        if !self.glob_mode {
+            return None;
+        }
+        let mut pattern = Pattern::escape(self.base_dir.to_str()?);
+        let chunks: Vec<&str> = zpath_expr.split('/').collect();
+        for (i, chunk) in chunks.iter().enumerate() {
+            pattern.push(std::path::MAIN_SEPARATOR);
+            if *chunk == "**" || *chunk == "*" {
+                pattern.push_str(chunk);
+                continue;
+            }
+            let mut parts = Vec::new();
+            for part in chunk.split("$*") {
+                let fs_part = self.filename_encoding.zpath_to_fspath(part);
+                parts.push(Pattern::escape(fs_part.to_str()?));
+            }
+            pattern.push_str(&parts.join("*"));
+            if i == chunks.len() - 1 && !pattern.ends_with('*') {
+                pattern.push('*');
+            }
+        }
+        if chunks.last() == Some(&"**") {
+            pattern.push(std::path::MAIN_SEPARATOR);
+            pattern.push('*');
+        }
+        Some(pattern)
+    }
+
+    // Search for files existing both with and without the CONFLICT_SUFFIX (e.g. after an unclean shutdown)
+    // and resolve each according to the policy, so the storage doesn't serve contradictory values for the same key.
+    pub(crate) async fn resolve_ambiguous_files(&self, policy: &OnAmbiguousFile) -> ZResult<()> {
+        let mut dirty_dirs = DirtyDirs::default();
+        let conflict_files: Vec<PathBuf> = WalkDir::new(&self.base_dir)
+            .follow_links(self.follow_links)
+            .into_iter()
+            .filter_entry(|e| e.file_name() != DataInfoMgr::DB_FILENAME)
+            .filter_map(Result::ok)
+            .filter(|e| {
+                e.file_type().is_file()
+                    && e.file_name()
+                        .to_str()
+                        .map_or(false, |name| name.ends_with(CONFLICT_SUFFIX))
+            })
+            .map(|e| e.into_path())
+            .collect();
+
+        for conflict_file in conflict_files {
+            let file = match conflict_file
+                .to_str()
+                .and_then(|s| s.strip_suffix(CONFLICT_SUFFIX))
+            {
+                Some(f) => PathBuf::from(f),
+                None => continue,
+            };
+            let _write = self.wait_background_write().await;
+            if !file.is_file() {
+                continue;
+            }
+            let keep_file = match policy {
+                OnAmbiguousFile::KeepFile => true,
+                OnAmbiguousFile::KeepConflictFile => false,
+                OnAmbiguousFile::KeepNewest => {
+                    self.get_file_timestamp(&file).await?
+                        >= self.get_file_timestamp(&conflict_file).await?
+                }
+            };
+            if keep_file {
+                warn!(
+                    "Both {:?} and {:?} exist for the same key: keep {:?}",
+                    file, conflict_file, file
+                );
+                self.unmap_file(&conflict_file);
+                remove_file(&conflict_file)
+                    .map_err(|e| zerror!("Failed to delete file {:?}: {}", conflict_file, e))?;
+                self.data_info_mgr.del_data_info(&conflict_file).await?;
+            } else {
+                warn!(
+                    "Both {:?} and {:?} exist for the same key: keep {:?}",
+                    file, conflict_file, conflict_file
+                );
+                self.unmap_file(&file);
+                self.unmap_file(&conflict_file);
+                remove_file(&file)
+                    .map_err(|e| zerror!("Failed to delete file {:?}: {}", file, e))?;
+                self.data_info_mgr.del_data_info(&file).await?;
+                // as the file is gone, the conflict file can take its place
+                rename(&conflict_file, &file).map_err(|e| {
+                    zerror!("Failed to rename {:?} to {:?}: {}", conflict_file, file, e)
+                })?;
+                // data-info might not exist if the file was not put via zenoh
+                let _ = self.data_info_mgr.rename_key(&conflict_file, &file).await;
+            }
+            dirty_dirs.add(file.parent());
+        }
+        // sync all the modified directories at once
+        self.sync_dirs(dirty_dirs)
+    }
+
+    // Move the stored keys (files and data-info, including the deleted keys) to the zenoh paths returned
+    // by `map`, or keep them unchanged if it returns None. Return the number of moved keys.
+    // Each key is moved while holding the locks of its source and destination, so it can run concurrently with
+    // the PUT and DELETE (a destination written meanwhile is kept).
+    pub(crate) async fn migrate_keys<F>(&self, map: F) -> ZResult<usize>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        if self.rotation.is_some() {
+            bail!("Migration of the stored keys is not supported with rotation directories");
+        }
+        if self.encoding_subdirs {
+            bail!("Migration of the stored keys is not supported with encoding sub-directories");
+        }
+        let mut dirty_dirs = DirtyDirs::default();
+        let mut moved = 0;
+        // collect the files first, as they are moved afterwards
+        let files: Vec<(String, PathBuf)> = self
+            .matching_files(unsafe { keyexpr::from_str_unchecked("**") })
+            .map(|zfile| (zfile.zpath.to_string(), zfile.fspath))
+            .collect();
+        for (zpath, file) in files {
+            let trimmed_zpath = get_trimmed_keyexpr(&zpath);
+            let new_zpath = match map(trimmed_zpath) {
+                Some(new_zpath) if new_zpath != trimmed_zpath => new_zpath,
+                _ => continue,
+            };
+            let new_key_file = self.to_zfile(&new_zpath).fspath;
+            let _write = self.wait_background_write().await;
+            let _locks = self
+                .key_locks
+                .lock_all(&[&self.to_zfile(&zpath).fspath, &new_key_file])
+                .await;
+            // the file might have been deleted since it was listed
+            if !file.exists() {
+                continue;
+            }
+            let new_file = if trimmed_zpath.len() != zpath.len() {
+                self.get_conflict_file(new_key_file)
+            } else {
+                new_key_file
+            };
+            if let Some(parent) = new_file.parent() {
+                DirBuilder::new()
+                    .recursive(true)
+                    .create(parent)
+                    .map_err(|e| zerror!("Failed to create directory {:?}: {}", parent, e))?;
+            }
+            // link then unlink rather than rename, so an existing destination is never replaced
+            match hard_link(&file, &new_file) {
+                Ok(()) => {
+                    self.unmap_file(&file);
+                    remove_file(&file).map_err(|e| zerror!("Failed to remove {:?}: {}", file, e))?
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    warn!(
+                        "Migration of {:?} to {:?} skipped: the destination already exists",
+                        file, new_file
+                    );
+                    continue;
+                }
+                Err(e) => bail!("Failed to move {:?} to {:?}: {}", file, new_file, e),
+            }
+            // data-info might not exist if the file was not put via zenoh
+            let _ = self.data_info_mgr.rename_key(&file, &new_file).await;
+            dirty_dirs.add(new_file.parent());
+            // try to delete the parent directories left empty
+            let mut f = file.as_path();
+            while let Some(parent) = f.parent() {
+                if parent != self.base_dir() && remove_dir(parent).is_ok() {
+                    trace!("Removed empty dir: {:?}", parent);
+                } else {
+                    dirty_dirs.add(Some(parent));
+                    break;
+                }
+                f = parent;
+            }
+            moved += 1;
+        }
+        for (fspath, _) in self.data_info_mgr.get_deleted_entries(None).await {
+            let zpath = match self.fspath_to_stored_zpath(&fspath) {
+                Some(zpath) => zpath,
+                None => continue,
+            };
+            let trimmed_zpath = get_trimmed_keyexpr(&zpath);
+            let new_zpath = match map(trimmed_zpath) {
+                Some(new_zpath) if new_zpath != trimmed_zpath => new_zpath,
+                _ => continue,
+            };
+            let new_key_file = self.to_zfile(&new_zpath).fspath;
+            let _write = self.wait_background_write().await;
+            let _locks = self
+                .key_locks
+                .lock_all(&[&self.to_zfile(&zpath).fspath, &new_key_file])
+                .await;
+            let new_file = if trimmed_zpath.len() != zpath.len() {
+                self.get_conflict_file(new_key_file)
+            } else {
+                new_key_file
+            };
+            // a key put meanwhile is not replaced by the tombstone
+            if self.data_info_mgr.get_timestamp(&new_file).await?.is_some() {
+                continue;
+            }
+            self.data_info_mgr
+                .rename_key(&PathBuf::from(fspath), &new_file)
+                .await?;
+            moved += 1;
+        }
+        self.sync_dirs(dirty_dirs)?;
+        Ok(moved)
+    }
+
+    // Sync the modified directories, if "fsync" is enabled
+    pub(crate) fn sync_dirs(&self, dirty_dirs: DirtyDirs) -> ZResult<()> {
+        if self.fsync {
+            for dir in dirty_dirs.0 {
+                trace!("Sync directory {:?}", dir);
+                fault_point(FaultPoint::Fsync)
+                    .and_then(|_| sync_dir(&dir))
+                    .map_err(|e| zerror!("Failed to sync directory {:?}: {}", dir, e))?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn get_file_timestamp(&self, file: &Path) -> ZResult<Timestamp> {
+        match self.data_info_mgr.get_timestamp(file).await? {
+            Some(timestamp) => Ok(timestamp),
+            None => self.get_timestamp_from_metadata(file),
+        }
+    }
+
+    // Compare the files stored in this storage with the files stored in another storage directory
+    pub(crate) async fn diff(
+        &self,
+        other_base_dir: &Path,
+        method: DiffMethod,
+    ) -> ZResult<StorageDiff> {
+        let other_shard_depth = read_shard_depth(other_base_dir)?.unwrap_or(0);
+        // the other storage directory is assumed to use the same dir rotation (or encoding sub-directories)
+        // and path template
+        let rotated = self.has_top_dirs();
+        let path_template = self.path_template.as_deref();
+        let self_files = list_stored_files(
+            &self.base_dir,
+            self.shard_depth,
+            rotated,
+            path_template,
+            &self.strip_extensions,
+            self.filename_encoding,
+            self.follow_links,
+        );
+        let mut other_files = list_stored_files(
+            other_base_dir,
+            other_shard_depth,
+            rotated,
+            path_template,
+            &self.strip_extensions,
+            self.filename_encoding,
+            self.follow_links,
+        );
+        let self_timestamps = self.data_info_mgr.get_all_timestamps().await;
+        let other_timestamps = read_all_timestamps(other_base_dir)?;
+
+        let mut only_in_self = Vec::new();
+        let mut to_compare = Vec::new();
+        for (zpath, file) in self_files {
+            match other_files.remove(&zpath) {
+                Some(other_file) => {
+                    let timestamp = match self_timestamps.get(&file) {
+                        Some(ts) => *ts,
+                        None => self.get_timestamp_from_metadata(&file)?,
+                    };
+                    let other_timestamp = match other_timestamps.get(&other_file) {
+                        Some(ts) => *ts,
+                        None => self.get_timestamp_from_metadata(&other_file)?,
+                    };
+                    to_compare.push((zpath, file, other_file, timestamp, other_timestamp));
+                }
+                None => only_in_self.push(zpath),
+            }
+        }
+        let only_in_other: Vec<String> = other_files.into_keys().collect();
+
+        let conflicts: Vec<(String, Timestamp, Timestamp)> = match method {
+            DiffMethod::Mtime => to_compare
+                .into_iter()
+                .filter(|(_, _, _, ts, other_ts)| ts != other_ts)
+                .map(|(zpath, _, _, ts, other_ts)| (zpath, ts, other_ts))
+                .collect(),
+            DiffMethod::Sha256 => {
+                // hashing all the files is costly: do it in parallel out of the async executor
+                task::spawn_blocking(move || {
+                    to_compare
+                        .into_par_iter()
+                        .filter(|(zpath, file, other_file, _, _)| {
+                            match (sha256_file(file), sha256_file(other_file)) {
+                                (Ok(hash), Ok(other_hash)) => hash != other_hash,
+                                (Err(e), _) | (_, Err(e)) => {
+                                    warn!("Failed to compare {} in both storages: {}", zpath, e);
+                                    true
+                                }
+                            }
+                        })
+                        .map(|(zpath, _, _, ts, other_ts)| (zpath, ts, other_ts))
+                        .collect()
+                })
+                .await
+            }
+        };
+
+        Ok(StorageDiff {
+            only_in_self: to_sorted_keyexprs(only_in_self),
+            only_in_other: to_sorted_keyexprs(only_in_other),
+            conflicts: {
+                let mut conflicts: Vec<(OwnedKeyExpr, Timestamp, Timestamp)> = conflicts
+                    .into_iter()
+                    .filter_map(|(zpath, ts, other_ts)| {
+                        OwnedKeyExpr::try_from(zpath)
+                            .ok()
+                            .map(|ke| (ke, ts, other_ts))
+                    })
+                    .collect();
+                conflicts.sort_by(|a, b| a.0.as_str().cmp(b.0.as_str()));
+                conflicts
+            },
+        })
+    }
+
+    // Compute a SHA-256 digest over the keys, contents and timestamps of all the stored files, in keys order.
+    // Storages storing the same keys with the same contents and timestamps have the same checksum.
+    pub(crate) async fn compute_checksum(&self) -> ZResult<StorageChecksum> {
+        let computed_at = new_reception_timestamp();
+        let files = list_stored_files(
+            &self.base_dir,
+            self.shard_depth,
+            self.has_top_dirs(),
+            self.path_template.as_deref(),
+            &self.strip_extensions,
+            self.filename_encoding,
+            self.follow_links,
+        );
+        let timestamps = self.data_info_mgr.get_all_timestamps().await;
+        let mut entries = Vec::with_capacity(files.len());
+        for (zpath, file) in files {
+            if self.is_reserved_path(&zpath) {
+                continue;
+            }
+            let timestamp = match timestamps.get(&file) {
+                Some(ts) => *ts,
+                None => self.get_timestamp_from_metadata(&file)?,
+            };
+            entries.push((zpath, file, timestamp));
+        }
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        let entry_count = entries.len() as u64;
+
+        // hashing all the files is costly: do it in parallel out of the async executor,
+        // each file being read by blocks rather than loaded in memory
+        let value = task::spawn_blocking(move || -> std::io::Result<String> {
+            let hashes = entries
+                .par_iter()
+                .map(|(_, file, _)| sha256_file(file))
+                .collect::<std::io::Result<Vec<String>>>()?;
+            let mut hasher = Sha256::new();
+            for ((zpath, _, timestamp), hash) in entries.iter().zip(hashes) {
+                hasher.update(zpath.as_bytes());
+                hasher.update(b"\0");
+                hasher.update(hash.as_bytes());
+                hasher.update(b"\0");
+                hasher.update(timestamp.to_string().as_bytes());
+                hasher.update(b"\n");
+            }
+            Ok(format!("{:x}", hasher.finalize()))
+        })
+        .await
+        .map_err(|e| {
+            zerror!(
+                "Failed to compute checksum of storage {:?}: {}",
+                self.base_dir,
+                e
+            )
+        })?;
+
+        Ok(StorageChecksum {
+            algorithm: "sha256",
+            value,
+            entry_count,
+            computed_at,
+        })
+    }
+
+    fn generate_metadata(&self, file: &Path, timestamp: &Timestamp) -> (Encoding, Timestamp) {
+        let a_encoding = self.guess_encoding(file);
+        let a_timestamp = match self.get_timestamp_from_metadata(file) {
+            Ok(a_ts) => a_ts,
+            Err(_) => *timestamp,
+        };
+        (a_encoding, a_timestamp)
+    }
+
+    async fn get_encoding_and_timestamp(&self, file: &Path) -> ZResult<(Encoding, Timestamp)> {
+        // try to get Encoding and Timestamp from data_info_mgr
+        match self.data_info_mgr.get_encoding_and_timestamp(&file).await? {
+            Some((encoding, timestamp)) => Ok((encoding, timestamp)),
+            None => {
+                trace!("data-info for {:?} not found; fallback to metadata", file);
+                let encoding = self.guess_encoding(file);
+                // fallback: get timestamp from file's metadata
+                let timestamp = self.get_timestamp_from_metadata(file)?;
+
+                Ok((encoding, timestamp))
+            }
+        }
+    }
+
+    fn guess_encoding(&self, file: &Path) -> Encoding {
+        self.guess_mime_encoding(file, None)
+            // last fallback: the configured "default_encoding"
+            .unwrap_or_else(|| self.default_encoding.clone())
+    }
+
+    // Guess the encoding of a file from its name, or else from its content if "text_detection" is enabled
+    fn guess_content_encoding(&self, file: &Path, content: &[u8]) -> Encoding {
+        self.guess_mime_encoding(file, Some(content))
+            .or_else(|| self.text_detection.as_ref()?.detect(content))
+            // last fallback: the configured "default_encoding"
+            .unwrap_or_else(|| self.default_encoding.clone())
+    }
+
+    // Guess the encoding of a file from its extension mapped by "encoding_map", or else from its extension
+    // or its content (read from the file if not known) according to "mime_source"
+    fn guess_mime_encoding(&self, file: &Path, content: Option<&[u8]>) -> Option<Encoding> {
+        if let Some(encoding) = self.mime_guesser.mapped_encoding(file) {
+            return Some(Encoding::from(encoding.to_string()));
+        }
+        if self.keep_mime {
+            // fallback: guess mime type from file extension or content
+            if let Some(mime_type) = self.mime_guesser.guess(file, content) {
+                return Some(Encoding::from(mime_type));
+            }
+        }
+        None
+    }
+
+    pub(crate) async fn get_timestamp(&self, zfile: &ZFile<'_>) -> ZResult<Option<Timestamp>> {
+        let file = &zfile.fspath;
+        // try to get Timestamp from data_info_mgr, for the file or for the conflict file
+        // (the key might have been put or deleted while being a prefix of other keys)
+        let conflict_file = self.get_conflict_file(file.clone());
+        let timestamp = self.data_info_mgr.get_timestamp(&file).await?;
+        let conflict_timestamp = self.data_info_mgr.get_timestamp(&conflict_file).await?;
+        match timestamp.max(conflict_timestamp) {
+            Some(x) => Ok(Some(x)),
+            None => {
+                // fallback: get timestamp from file's metadata if it exists
+                if file.exists() {
+                    let timestamp = self.get_timestamp_from_metadata(file)?;
+                    Ok(Some(timestamp))
+                } else {
+                    Ok(None)
+                }
+            }
+        }
+    }
+
+    // Derive the timestamp of a file not referenced in the data-info database from its modification time.
+    // It only depends on the file's metadata, so it's the same on each read until the file is modified:
+    // the access time (changed by the reads) and the current time are never used.
+    fn get_timestamp_from_metadata<P: AsRef<Path>>(&self, file: P) -> ZResult<Timestamp> {
+        let metadata = metadata(&file).map_err(|e| {
+            zerror!(
+                "Failed to get meta-data for file {:?}: {}",
+                file.as_ref(),
+                e
+            )
+        })?;
+        Ok(timestamp_from_metadata(&metadata))
+    }
+
+    // Same as get_timestamp_from_metadata(), with the metadata of an opened file if got
+    fn get_opened_file_timestamp(
+        &self,
+        file: &Path,
+        metadata: Option<&std::fs::Metadata>,
+    ) -> ZResult<Timestamp> {
+        match metadata {
+            Some(metadata) => Ok(timestamp_from_metadata(metadata)),
+            None => self.get_timestamp_from_metadata(file),
+        }
+    }
+
+    // Check if a Path contains a segment which is a symbolic link
+    fn contains_symlink<P: AsRef<Path>>(&self, path: P) -> bool {
+        contains_symlink(&self.base_dir, path.as_ref())
+    }
+
+    // Find a key matching a key expression (stored or deleted) whose last PUT or DELETE is after a time, if any.
+    // As only the last version of each key is kept, such a key can't be replied as of this time.
+    pub(crate) async fn find_changed_after(
+        &self,
+        ke: &keyexpr,
+        time: &NTP64,
+    ) -> ZResult<Option<(String, Timestamp)>> {
+        if !ke.contains('*') {
+            // (the timestamp of a deleted key is the one of its tombstone)
+            return Ok(self
+                .get_timestamp(&self.to_zfile(ke))
+                .await?
+                .filter(|ts| ts.get_time() > time)
+                .map(|ts| (ke.to_string(), ts)));
+        }
+        for zfile in self.matching_files(ke) {
+            let zpath = get_trimmed_keyexpr(zfile.zpath.as_ref()).to_string();
+            if let Some(ts) = self.get_timestamp(&self.to_zfile(&zpath)).await? {
+                if ts.get_time() > time {
+                    return Ok(Some((zpath, ts)));
+                }
+            }
+        }
+        // (only the tombstones under the searched directory and after the time are checked)
+        let matches = |fspath: &Path| {
+            self.fspath_to_stored_zpath(fspath).map_or(false, |zpath| {
+                keyexpr::new(zpath.as_str()).map_or(false, |key| ke.intersects(key))
+            })
+        };
+        Ok(self
+            .data_info_mgr
+            .find_deleted_after(&self.search_dir(ke), time, matches)
+            .await
+            .and_then(|(fspath, ts)| Some((self.fspath_to_stored_zpath(&fspath)?, ts))))
+    }
+
+    // Get the zenoh paths of the tombstones, pausing between the chunks of their scan if specified
+    pub async fn get_deleted_entries(&self, pause: Option<Duration>) -> Vec<(String, Timestamp)> {
+        let mut result = Vec::new();
+        for (fspath, ts) in self.data_info_mgr.get_deleted_entries(pause).await {
+            if let Some(zpath) = self.fspath_to_stored_zpath(&fspath) {
+                result.push((zpath, ts));
+            }
+        }
+        result
+    }
+
+    // Record the stored files deleted by another process since the previous call ("watch_external_deletes_ms"):
+    // the files which existed with a data-info (in `live`, updated by each call) and no longer exist, while their
+    // data-info is unchanged. A tombstone is recorded for each of them (unless the file was recreated meanwhile),
+    // with the earliest timestamp after the one of the deleted file. Return their zenoh paths.
+    // (the DELETE published for a file is timestamped later: this storage stores it as the other storages do,
+    // replacing the tombstone, so all the storages end up with the same timestamp)
+    pub(crate) async fn record_external_deletions(&self, live: &mut LiveFiles) -> Vec<String> {
+        let start = SystemTime::now();
+        if !live.indexed {
+            // first call: index the existing files by directory
+            let mut files: HashMap<PathBuf, HashMap<PathBuf, Timestamp>> = HashMap::new();
+            for (fspath, ts) in self.data_info_mgr.get_all_timestamps().await {
+                if let Some(dir) = fspath.parent() {
+                    if fspath.is_file() {
+                        files
+                            .entry(dir.to_path_buf())
+                            .or_default()
+                            .insert(fspath, ts);
+                    }
+                }
+            }
+            for dir in list_live_dirs(&self.base_dir) {
+                let mtime = settled_mtime(&dir, start);
+                let dir_files = files.remove(&dir).unwrap_or_default();
+                live.dirs.insert(dir, (mtime, dir_files));
+            }
+            live.indexed = true;
+            return Vec::new();
+        }
+
+        // only the directories modified since the previous call are listed again
+        let modified: Vec<PathBuf> = live
+            .dirs
+            .iter()
+            .filter(|(dir, (mtime, _))| mtime.is_none() || settled_mtime(dir, start) != *mtime)
+            .map(|(dir, _)| dir.clone())
+            .collect();
+        let mut deleted = Vec::new();
+        for dir in modified {
+            let (_, files) = live.dirs.remove(&dir).unwrap_or_default();
+            for (fspath, ts) in files {
+                if !fspath.exists() {
+                    if let Some(zpath) = self.record_external_deletion(&fspath, &ts).await {
+                        deleted.push(zpath);
+                    }
+                }
+            }
+            // (a removed directory is removed from the index, as its sub-directories when they're checked)
+            if !dir.is_dir() {
+                continue;
+            }
+            // list it again, with its new sub-directories
+            let mut to_list = vec![dir];
+            while let Some(dir) = to_list.pop() {
+                let mtime = settled_mtime(&dir, start);
+                let mut dir_files = HashMap::new();
+                for entry in dir.read_dir().into_iter().flatten().filter_map(Result::ok) {
+                    let path = entry.path();
+                    match entry.file_type() {
+                        Ok(t) if t.is_file() => {
+                            if let Ok(Some(ts)) = self.data_info_mgr.get_timestamp(&path).await {
+                                dir_files.insert(path, ts);
+                            }
+                        }
+                        Ok(t) if t.is_dir() => {
+                            if !live.dirs.contains_key(&path)
+                                && !entry
+                                    .file_name()
+                                    .to_string_lossy()
+                                    .starts_with(INTERNAL_PREFIX)
+                            {
+                                to_list.push(path);
+                            }
+                        }
+                        _ => (),
+                    }
+                }
+                live.dirs.insert(dir, (mtime, dir_files));
+            }
+        }
+        deleted
+    }
+
+    // Record the deletion of a file deleted by another process, if its data-info is unchanged.
+    // Return its zenoh path if recorded.
+    async fn record_external_deletion(&self, fspath: &Path, ts: &Timestamp) -> Option<String> {
+        let zpath = self.fspath_to_stored_zpath(fspath)?;
+        let tombstone = Timestamp::new(NTP64(ts.get_time().as_u64() + 1), *ts.get_id());
+        match self
+            .data_info_mgr
+            .put_tombstone_if_unchanged(fspath, ts, &tombstone)
+            .await
+        {
+            Ok(true) => {
+                debug!("File {:?} deleted externally: tombstone recorded", fspath);
+                Some(zpath)
+            }
+            Ok(false) => {
+                trace!("File {:?} changed meanwhile: no tombstone", fspath);
+                None
+            }
+            Err(e) => {
+                warn!(
+                    "Failed to record deletion of file {:?} deleted externally: {}",
+                    fspath, e
+                );
+                None
+            }
+        }
+    }
+
+    // Get an estimate of the number of entries (stored files and deleted keys): the number of files in base_dir,
+    // plus the number of data-infos estimated by RocksDB in excess of it (i.e. those of the deleted keys, assuming
+    // the stored files have one). The files are counted again at most every FILE_COUNT_MAX_AGE.
+    pub(crate) fn get_entry_count(&self) -> ZResult<u64> {
+        let data_infos = self.data_info_mgr.estimate_count()?;
+        let mut file_count = self.file_count.lock().unwrap();
+        let files = match *file_count {
+            Some((counted_at, files)) if counted_at.elapsed() < FILE_COUNT_MAX_AGE => files,
+            _ => {
+                let files = self
+                    .matching_files(unsafe { keyexpr::from_str_unchecked("**") })
+                    .count() as u64;
+                *file_count = Some((Instant::now(), files));
+                files
+            }
+        };
+        Ok(files.max(data_infos))
+    }
+
+    // Count the entries (stored files and deleted keys) as listed by get_all_entries(), without collecting them
+    pub(crate) async fn get_exact_entry_count(&self) -> ZResult<u64> {
+        let stored = self
+            .matching_files(unsafe { keyexpr::from_str_unchecked("**") })
+            .count() as u64;
+        let deleted = self
+            .data_info_mgr
+            .count_deleted_entries(|fspath| self.fspath_to_stored_zpath(fspath).is_some())
+            .await;
+        Ok(stored + deleted)
+    }
+
+    // List the direct children of a key prefix (an empty prefix for the storage's root): the stored keys,
+    // and the prefixes of stored keys (directories), sorted by key. A key can be listed both as a stored key
+    // and as a directory, if it's also a prefix of other stored keys.
+    pub(crate) async fn list_directory(&self, prefix: &str) -> ZResult<Vec<DirEntry>> {
+        self.list_directory_entries(prefix, true).await
+    }
+
+    // List the direct children of a key prefix as list_directory() does, reading their timestamps only if requested
+    // (otherwise the entries have no last_modified)
+    async fn list_directory_entries(
+        &self,
+        prefix: &str,
+        with_timestamps: bool,
+    ) -> ZResult<Vec<DirEntry>> {
+        let expr = if prefix.is_empty() {
+            OwnedKeyExpr::try_from("**")?
+        } else {
+            OwnedKeyExpr::try_from(format!("{}/**", prefix))?
+        };
+        let zfiles: Vec<ZFile> = self.matching_files(&expr).collect();
+        // for each child: (is_directory, entry_count, last_modified)
+        let mut children: BTreeMap<(String, bool), (usize, Option<Timestamp>)> = BTreeMap::new();
+        for zfile in zfiles {
+            let relative = if prefix.is_empty() {
+                zfile.zpath.as_ref()
+            } else {
+                match zfile
+                    .zpath
+                    .strip_prefix(prefix)
+                    .and_then(|r| r.strip_prefix('/'))
+                {
+                    Some(relative) => relative,
+                    None => continue, // the prefix itself
+                }
+            };
+            let (child, is_directory) = match relative.find('/') {
+                Some(i) => (&relative[..i], true),
+                None => (relative, false),
+            };
+            let key = if prefix.is_empty() {
+                child.to_string()
+            } else {
+                format!("{}/{}", prefix, child)
+            };
+            let timestamp = if with_timestamps {
+                self.get_timestamp(&zfile).await?
+            } else {
+                None
+            };
+            let entry = children.entry((key, is_directory)).or_insert((0, None));
+            entry.0 += 1;
+            entry.1 = entry.1.max(timestamp);
+        }
+        Ok(children
+            .into_iter()
+            .filter_map(|((key, is_directory), (count, last_modified))| {
+                Some(DirEntry {
+                    key: OwnedKeyExpr::try_from(key).ok()?,
+                    is_directory,
+                    entry_count: if is_directory { Some(count) } else { None },
+                    last_modified,
+                })
+            })
+            .collect())
+    }
+
+    // Get the keys of all the files stored with an encoding
+    pub(crate) async fn get_by_encoding(&self, encoding: &Encoding) -> ZResult<Vec<OwnedKeyExpr>> {
+        let zpaths = self
+            .data_info_mgr
+            .get_by_encoding(encoding)
+            .await?
+            .iter()
+            .filter_map(|fspath| self.fspath_to_stored_zpath(fspath))
+            .collect();
+        Ok(to_sorted_keyexprs(zpaths))
+    }
+
+    // Convert the absolute path of a stored file to its zenoh path,
+    // or None if it's not the path of a stored file (e.g. a reserved path)
+    fn fspath_to_stored_zpath(&self, fspath: &Path) -> Option<String> {
+        // coarse_zpath is the file's absolute path stripped from base_dir and converted as zenoh path
+        let coarse_zpath = self
+            .filename_encoding
+            .fspath_to_zpath(fspath, self.base_dir.as_os_str().len())?;
+        let zpath = unmap_coarse_zpath(
+            &coarse_zpath,
+            self.has_top_dirs(),
+            self.shard_depth,
+            self.path_template.as_deref(),
+            &self.strip_extensions,
+        )?;
+        if self.is_reserved_path(&zpath) {
+            None
+        } else {
+            Some(zpath)
+        }
+    }
+}
+
+impl Drop for FilesMgr {
+    fn drop(&mut self) {
+        debug!("Closing File System Storage on {:?}", self.base_dir);
+        match self.on_closure {
+            OnClosure::DeleteAll => {
+                // Close data_info_mgr at first
+                task::block_on(async move {
+                    self.data_info_mgr
+                        .close()
+                        .await
+                        .unwrap_or_else(|e| warn!("{}", e));
+                    remove_dir_all(&self.base_dir).unwrap_or_else(|err| {
+                        warn!("Failed to cleanup directory {:?}; {}", self.base_dir, err)
+                    });
+                });
+            }
+            OnClosure::DeleteContents => {
+                // Close data_info_mgr at first (its database is removed with the other contents)
+                task::block_on(async move {
+                    self.data_info_mgr
+                        .close()
+                        .await
+                        .unwrap_or_else(|e| warn!("{}", e));
+                    remove_dir_contents(&self.base_dir).unwrap_or_else(|err| {
+                        warn!("Failed to cleanup directory {:?}; {}", self.base_dir, err)
+                    });
+                });
+            }
+            OnClosure::DoNothing => {
+                debug!(
+                    "Close File System Storage, keeping directory {:?} as it is",
+                    self.base_dir
+                );
+            }
+        }
+    }
+}
+
+// The file paths iterated by a FilesIterator, to be converted to zenoh paths
+enum FilesSource {
+    // all the files in the searched directory
+    Walk(IntoIter),
+    // the paths matching a glob pattern ("glob_mode"), possibly directories
+    Glob(Paths),
+}
+
+pub(crate) struct FilesIterator<'a> {
+    source: FilesSource,
+    zpath_expr: &'a keyexpr,
+    base_dir_len: usize,
+    follow_links: bool,
+    shard_depth: usize,
+    reserved_prefix: String,
+    // with dir rotation (or encoding sub-directories), the zenoh paths already returned
+    // (a key can be stored in several rotation directories)
+    rotated_zpaths: Option<HashSet<String>>,
+    path_template: Option<Arc<PathTemplate>>,
+    strip_extensions: Vec<String>,
+    filename_encoding: FilenameEncoding,
+}
+
+impl<'a> FilesIterator<'a> {
+    // Get the path of the next file to be converted to a zenoh path
+    fn next_fspath(&mut self) -> Option<PathBuf> {
+        let base_dir_len = self.base_dir_len;
+        loop {
+            match &mut self.source {
+                FilesSource::Walk(walk_iter) => match walk_iter.next() {
+                    Some(Ok(e)) => {
+                        if e.file_type().is_dir() {
+                            // skip content of reserved directories (e.g. DataInfoMgr::DB_FILENAME)
+                            if is_reserved_chunk(
+                                e.file_name().to_str().unwrap_or_default(),
+                                &self.reserved_prefix,
+                            ) {
+                                walk_iter.skip_current_dir();
+                            }
+                            continue;
+                        }
+                        return Some(e.into_path());
+                    }
+                    None => return None,
+                    Some(Err(err)) => {
+                        // Cannot read file or dir... that might be normal (or not...) ignore it
+                        debug!(
+                            "Possible issue looking for files matching {} : {}",
+                            self.zpath_expr, err
+                        );
+                    }
+                },
+                FilesSource::Glob(paths) => match paths.next() {
+                    Some(Ok(fspath)) => {
+                        // as with the directory walk, without "follow_links" a symbolic link to a directory
+                        // is returned as a file, and the files within it are skipped
+                        if fspath.is_dir() && (self.follow_links || !is_symlink(&fspath)) {
+                            continue;
+                        }
+                        if !self.follow_links
+                            && fspath
+                                .ancestors()
+                                .skip(1)
+                                .take_while(|dir| dir.as_os_str().len() > base_dir_len)
+                                .any(is_symlink)
+                        {
+                            continue;
+                        }
+                        return Some(fspath);
+                    }
+                    None => return None,
+                    Some(Err(err)) => {
+                        debug!(
+                            "Possible issue looking for files matching {} : {}",
+                            self.zpath_expr, err
+                        );
+                    }
+                },
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for FilesIterator<'a> {
+    type Item = ZFile<'a>;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let fspath = self.next_fspath()?;
+            // coarse_zpath is the file's absolute path stripped from base_dir and converted as zenoh path
+            if let Some(coarse_zpath) = self
+                .filename_encoding
+                .fspath_to_zpath(&fspath, self.base_dir_len)
+            {
+                // files not corresponding to a key (e.g. files not deep enough in shard directories,
+                // such as SHARD_DEPTH_FILENAME) are not part of the storage
+                let zpath = match unmap_coarse_zpath(
+                    &coarse_zpath,
+                    self.rotated_zpaths.is_some(),
+                    self.shard_depth,
+                    self.path_template.as_deref(),
+                    &self.strip_extensions,
+                ) {
+                    Some(zpath) => zpath,
+                    None => continue,
+                };
+                let zpath = zpath.as_str();
+                if is_reserved_path(zpath, &self.reserved_prefix) {
+                    continue;
+                }
+                if let Some(rotated_zpaths) = &mut self.rotated_zpaths {
+                    if !rotated_zpaths.insert(zpath.to_string()) {
+                        continue;
+                    }
+                }
+                let zpath_as_ke = match keyexpr::new(zpath) {
+                    Ok(ke) => ke,
+                    Err(e) => {
+                        tracing::error!(
+                            "Couldn't convert `{}` into a key expression: {}",
+                            &zpath,
+                            e
+                        );
+                        continue;
+                    }
+                };
+                // convert it to zenoh path for matching test with zpath_expr
+                if self.zpath_expr.intersects(zpath_as_ke) {
+                    // matching file; return a ZFile
+                    let zfile = ZFile {
+                        zpath: zpath.to_string().into(),
+                        fspath: fspath.clone(),
+                    };
+                    return Some(zfile);
+                }
+            } else {
+                debug!(
+                    "Looking for files matching {}: ignore {:?} as non UTF-8 filename (see `filename_encoding`)",
+                    self.zpath_expr, fspath
+                );
+            };
+        }
+    }
+}
+
+#[cfg(unix)]
+#[inline(always)]
+pub(crate) fn zpath_to_fspath(zpath: &str) -> Cow<'_, str> {
+    Cow::from(zpath)
+}
+
+#[cfg(windows)]
+pub(crate) fn zpath_to_fspath(zpath: &str) -> Cow<'_, str> {
+    const WIN_SEP: &str = r#"\"#;
+    Cow::from(zpath.replace('/', WIN_SEP))
+}
+
+#[cfg(unix)]
+#[inline(always)]
+pub(crate) fn fspath_to_zpath(fspath: &str) -> Cow<'_, str> {
+    Cow::from(fspath)
+}
+
+#[cfg(windows)]
+pub(crate) fn fspath_to_zpath(fspath: &str) -> Cow<'_, str> {
+    const ZENOH_SEP: &str = "/";
+    Cow::from(fspath.replace(std::path::MAIN_SEPARATOR, ZENOH_SEP))
+}
+
+// Check the shard depth is the one used to store the existing files, and record it in a marker file.
+// Changing the shard depth once files are stored would make them unreachable.
+fn check_shard_depth(base_dir: &Path, shard_depth: usize) -> ZResult<()> {
+    let mut marker = base_dir.to_path_buf();
+    marker.push(SHARD_DEPTH_FILENAME);
+    match read_shard_depth(base_dir)? {
+        Some(depth) if depth != shard_depth => bail!(
+            "Cannot use shard_depth={} on {:?}: its files are stored with shard_depth={}",
+            shard_depth,
+            base_dir,
+            depth
+        ),
+        Some(_) => Ok(()),
+        None if shard_depth == 0 => Ok(()),
+        None => {
+            let has_files = base_dir
+                .read_dir()
+                .map_err(|e| zerror!("Failed to read directory {:?}: {}", base_dir, e))?
+                .filter_map(Result::ok)
+                .any(|e| e.file_name() != DataInfoMgr::DB_FILENAME);
+            if has_files {
+                bail!(
+                    "Cannot use shard_depth={} on {:?}: it already contains files stored without sharding",
+                    shard_depth,
+                    base_dir
+                )
+            }
+            std::fs::write(&marker, shard_depth.to_string())
+                .map_err(|e| zerror!("Failed to write shard depth in {:?}: {}", marker, e).into())
+        }
+    }
+}
+
+// Check that the files of a storage directory are stored in encoding sub-directories if "encoding_subdirs" is
+// enabled, and not otherwise (as recorded by a marker file, created when enabled on an empty directory)
+fn check_encoding_subdirs(base_dir: &Path, encoding_subdirs: bool) -> ZResult<()> {
+    let marker = base_dir.join(ENCODING_SUBDIRS_FILENAME);
+    match (marker.exists(), encoding_subdirs) {
+        (true, true) | (false, false) => Ok(()),
+        (true, false) => bail!(
+            "Cannot use {:?} without encoding_subdirs: its files are stored in encoding sub-directories",
+            base_dir
+        ),
+        (false, true) => {
+            // (a directory with only encoding sub-directories was used with encoding_subdirs before the marker)
+            let has_files = base_dir
+                .read_dir()
+                .map_err(|e| zerror!("Failed to read directory {:?}: {}", base_dir, e))?
+                .filter_map(Result::ok)
+                .any(|e| {
+                    let name = e.file_name();
+                    let name = name.to_string_lossy();
+                    !name.starts_with(INTERNAL_PREFIX)
+                        && !(ENCODING_SUBDIRS.contains(&name.as_ref())
+                            && e.file_type().map_or(false, |t| t.is_dir()))
+                });
+            if has_files {
+                bail!(
+                    "Cannot use encoding_subdirs on {:?}: it already contains files stored without encoding sub-directories",
+                    base_dir
+                )
+            }
+            File::create(&marker)
+                .map(|_| ())
+                .map_err(|e| zerror!("Failed to write marker file {:?}: {}", marker, e).into())
+        }
+    }
+}
+
+// Read the shard depth recorded in a storage directory, if any
+fn read_shard_depth(base_dir: &Path) -> ZResult<Option<usize>> {
+    let mut marker = base_dir.to_path_buf();
+    marker.push(SHARD_DEPTH_FILENAME);
+    match std::fs::read_to_string(&marker) {
+        Ok(s) => Ok(Some(s.trim().parse::<usize>().map_err(|e| {
+            zerror!("Invalid shard depth in {:?}: {}", marker, e)
+        })?)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => bail!("Failed to read shard depth from {:?}: {}", marker, e),
+    }
+}
+
+// List the files stored in a storage directory, indexed by their zenoh path
+fn list_stored_files(
+    base_dir: &Path,
+    shard_depth: usize,
+    rotated: bool,
+    path_template: Option<&PathTemplate>,
+    strip_extensions: &[String],
+    filename_encoding: FilenameEncoding,
+    follow_links: bool,
+) -> HashMap<String, PathBuf> {
+    let base_dir_len = base_dir.as_os_str().len();
+    WalkDir::new(base_dir)
+        .follow_links(follow_links)
+        .into_iter()
+        .filter_entry(|e| e.file_name() != DataInfoMgr::DB_FILENAME)
+        .filter_map(Result::ok)
+        .filter(|e| e.file_type().is_file())
+        .filter_map(|e| {
+            let fspath = e.into_path();
+            let zpath = {
+                let coarse_zpath = filename_encoding.fspath_to_zpath(&fspath, base_dir_len)?;
+                unmap_coarse_zpath(
+                    &coarse_zpath,
+                    rotated,
+                    shard_depth,
+                    path_template,
+                    strip_extensions,
+                )?
+            };
+            Some((zpath, fspath))
+        })
+        .collect()
+}
+
+// Convert a coarse zenoh path (i.e. the path of a file relative to base_dir, converted as zenoh path)
+// to the zenoh path of the stored key: removing the rotation directory and the CONFLICT_SUFFIX,
+// reversing the path template or removing the shard directories, and removing the stripped extension if any.
+// Return None if the file doesn't correspond to a stored key.
+fn unmap_coarse_zpath(
+    coarse_zpath: &str,
+    rotated: bool,
+    shard_depth: usize,
+    path_template: Option<&PathTemplate>,
+    strip_extensions: &[String],
+) -> Option<String> {
+    let coarse_zpath = strip_rotation_dir(coarse_zpath, rotated)?;
+    let coarse_zpath = get_trimmed_keyexpr(coarse_zpath);
+    let mut zpath = match path_template {
+        Some(template) => template.to_zpath(coarse_zpath)?,
+        None => unshard_zpath(coarse_zpath, shard_depth)?.into_owned(),
+    };
+    if let Some(ext) = stripped_extension(&zpath, strip_extensions) {
+        zpath.truncate(zpath.len() - ext.len());
+    }
+    Some(zpath)
+}
+
+// Get the extension of strip_extensions a zenoh path ends with, if any (a chunk made of an extension only,
+// such as ".json", is not an extension)
+fn stripped_extension<'a>(zpath: &str, strip_extensions: &'a [String]) -> Option<&'a str> {
+    strip_extensions
+        .iter()
+        .find(|ext| {
+            zpath.len() > ext.len()
+                && zpath.ends_with(ext.as_str())
+                && !zpath[..zpath.len() - ext.len()].ends_with('/')
+        })
+        .map(String::as_str)
+}
+
+// Get the sub-directory where the files of a mime-type are written with "encoding_subdirs"
+fn encoding_subdir(mime: &str) -> &'static str {
+    if mime.ends_with("/json") || mime.ends_with("+json") {
+        JSON_SUBDIR
+    } else if mime.starts_with("text/") {
+        TEXT_SUBDIR
+    } else {
+        BINARY_SUBDIR
+    }
+}
+
+// Append an extension to a file path (e.g. "a/b.c" + ".json" = "a/b.c.json")
+fn with_extension_appended(file: &Path, ext: &str) -> PathBuf {
+    let mut file = file.as_os_str().to_owned();
+    file.push(ext);
+    PathBuf::from(file)
+}
+
+// With dir rotation (or encoding sub-directories), strip the rotation directory (i.e. the 1st chunk)
+// from a coarse zenoh path. Return None if the path is not within a rotation directory.
+fn strip_rotation_dir(coarse_zpath: &str, rotated: bool) -> Option<&str> {
+    if !rotated {
+        return Some(coarse_zpath);
+    }
+    let zpath = coarse_zpath.strip_prefix('/').unwrap_or(coarse_zpath);
+    zpath.find('/').map(|i| &zpath[i..])
+}
+
+// Check if a zenoh path has a chunk reserved for the storage's internal use
+pub(crate) fn is_reserved_path(zpath: &str, reserved_prefix: &str) -> bool {
+    zpath
+        .split('/')
+        .any(|chunk| is_reserved_chunk(chunk, reserved_prefix) || chunk.ends_with(CONFLICT_SUFFIX))
+}
+
+// Check if a file name (or a zenoh path chunk) is reserved for the storage's internal use
+fn is_reserved_chunk(chunk: &str, reserved_prefix: &str) -> bool {
+    chunk.starts_with(INTERNAL_PREFIX)
+        || (!reserved_prefix.is_empty() && chunk.starts_with(reserved_prefix))
+}
+
+fn to_sorted_keyexprs(zpaths: Vec<String>) -> Vec<OwnedKeyExpr> {
+    let mut result: Vec<OwnedKeyExpr> = zpaths
+        .into_iter()
+        .filter_map(|zpath| OwnedKeyExpr::try_from(zpath).ok())
+        .collect();
+    result.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+    result
+}
+
+// Get the identity of a directory, or None if it doesn't exist
+#[cfg(unix)]
+fn dir_id(dir: &Path) -> Option<DirId> {
+    use std::os::unix::fs::MetadataExt;
+    metadata(dir)
+        .ok()
+        .filter(|m| m.is_dir())
+        .map(|m| (m.dev(), m.ino()))
+}
+
+// The replacement of base_dir is not detected on non-Unix platforms
+#[cfg(not(unix))]
+fn dir_id(_dir: &Path) -> Option<DirId> {
+    None
+}
+
+// Get the identity of a file's inode, with its size and modification time (in nanoseconds since the epoch)
+#[cfg(unix)]
+pub(crate) fn inode_content_id(metadata: &std::fs::Metadata) -> Option<ContentId> {
+    use std::os::unix::fs::MetadataExt;
+    Some(ContentId::Inode {
+        dev: metadata.dev(),
+        ino: metadata.ino(),
+        len: metadata.len(),
+        mtime: metadata
+            .modified()
+            .ok()?
+            .duration_since(UNIX_EPOCH)
+            .ok()?
+            .as_nanos() as u64,
+    })
+}
+
+// The inodes are not identified on non-Unix platforms
+#[cfg(not(unix))]
+pub(crate) fn inode_content_id(_metadata: &std::fs::Metadata) -> Option<ContentId> {
+    None
+}
+
+// Convert an IO error on a file write to a zenoh error, signaling to retry later if the disk is full
+fn write_error(msg: &str, file: &Path, e: std::io::Error) -> zenoh_core::Error {
+    if is_disk_full(&e) {
+        RetryLater::new(format!("{} {:?}: {}", msg, file, e)).into()
+    } else {
+        zerror!("{} {:?}: {}", msg, file, e).into()
+    }
+}
+
+// Remove all the content of a directory, but not the directory itself
+fn remove_dir_contents(dir: &Path) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            remove_dir_all(entry.path())?;
+        } else {
+            remove_file(entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+// Get the modification time of a file, in nanoseconds since the epoch
+fn file_mtime(file: &Path) -> Option<u64> {
+    metadata_mtime(&metadata(file).ok()?)
+}
+
+fn metadata_mtime(metadata: &std::fs::Metadata) -> Option<u64> {
+    metadata
+        .modified()
+        .ok()?
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|d| d.as_nanos() as u64)
+}
+
+// Get the timestamp of a file put without zenoh, from its modification time
+fn timestamp_from_metadata(metadata: &std::fs::Metadata) -> Timestamp {
+    // without modification nor creation time, the file is as old as possible (any PUT replaces it)
+    let time = metadata
+        .modified()
+        .or_else(|_| metadata.created())
+        .ok()
+        .and_then(|sys_time| sys_time.duration_since(UNIX_EPOCH).ok())
+        .unwrap_or_default();
+    Timestamp::new(time.into(), TimestampId::try_from([1]).unwrap())
+}
+
+// The metadata of a stored file, obtained without reading its content
+pub(crate) struct FileStat {
+    pub(crate) size: u64,
+    // the size of the value replied for the file (see get_value_size())
+    pub(crate) value_size: u64,
+    // the modification time, since the UNIX epoch
+    pub(crate) mtime: Option<Duration>,
+    pub(crate) encoding: Encoding,
+    pub(crate) timestamp: Timestamp,
+    // the SHA-256 of the content recorded when it was written, if still valid
+    pub(crate) sha256: Option<String>,
+}
+
+// Compute the SHA-256 hash of a content, as an hexadecimal string
+fn sha256_zbuf(content: &ZBuf) -> String {
+    let mut hasher = Sha256::new();
+    for slice in content.slices() {
+        hasher.update(slice);
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+// Compute the SHA-256 hash of a file's content, as an hexadecimal string
+pub(crate) fn sha256_file<P: AsRef<Path>>(file: P) -> std::io::Result<String> {
+    let mut f = File::open(file)?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut f, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+// Insert the shard directories before the last chunk of a zenoh path.
+// Each shard directory is 2 hexadecimal characters derived from a hash of the last chunk.
+pub(crate) fn shard_zpath(zpath: &str, shard_depth: usize) -> Cow<'_, str> {
+    if shard_depth == 0 || zpath.is_empty() {
+        return Cow::from(zpath);
+    }
+    let (parent, leaf) = match zpath.rfind('/') {
+        Some(i) => (&zpath[..=i], &zpath[i + 1..]),
+        None => ("", zpath),
+    };
+    let hash = fnv1a_hash(leaf.as_bytes()).to_be_bytes();
+    let mut result = String::with_capacity(zpath.len() + 3 * shard_depth);
+    result.push_str(parent);
+    for b in hash.iter().take(shard_depth) {
+        result.push_str(&format!("{:02x}/", b));
+    }
+    result.push_str(leaf);
+    Cow::from(result)
+}
+
+// Remove the shard directories from a zenoh path built with shard_zpath().
+// Returns None if the path is not deep enough to contain shard directories.
+pub(crate) fn unshard_zpath(zpath: &str, shard_depth: usize) -> Option<Cow<'_, str>> {
+    if shard_depth == 0 {
+        return Some(Cow::from(zpath));
+    }
+    let mut chunks: Vec<&str> = zpath.split('/').collect();
+    let leaf_idx = chunks.len() - 1;
+    if leaf_idx < shard_depth {
+        return None;
+    }
+    chunks.drain(leaf_idx - shard_depth..leaf_idx);
+    Some(Cow::from(chunks.join("/")))
+}
+
+// FNV-1a hash: simple and stable across processes and platforms, as required for on-disk layout
+fn fnv1a_hash(bytes: &[u8]) -> u64 {
+    const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const FNV_PRIME: u64 = 0x0100_0000_01b3;
+    bytes.iter().fold(FNV_OFFSET_BASIS, |hash, b| {
+        (hash ^ u64::from(*b)).wrapping_mul(FNV_PRIME)
+    })
+}
+
+// Decompress a gzip content (possibly with several members), failing if it exceeds max_size
+fn gunzip(content: &[u8], max_size: Option<u64>) -> std::io::Result<Vec<u8>> {
+    let max_size = max_size.unwrap_or(u64::MAX);
+    let capacity = (content.len() as u64 * 4).min(max_size);
+    let mut result = Vec::with_capacity(capacity as usize);
+    MultiGzDecoder::new(content)
+        .take(max_size.saturating_add(1))
+        .read_to_end(&mut result)?;
+    if result.len() as u64 > max_size {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("decompressed content exceeds {} bytes", max_size),
+        ));
+    }
+    Ok(result)
+}
+
+// Read the whole content of a file into a single buffer allocated once with the expected size.
+// If the file grew since its size was checked, the remaining bytes are appended.
+fn read_content(f: &mut File, expected_size: usize) -> std::io::Result<Vec<u8>> {
+    let mut content = vec![0u8; expected_size];
+    let mut read = 0;
+    while read < expected_size {
+        match f.read(&mut content[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    if read < expected_size {
+        // file was truncated since its size was checked
+        content.truncate(read);
+    } else {
+        f.read_to_end(&mut content)?;
+    }
+    Ok(content)
+}
+
+// True if an error reading a file is expected to happen again (e.g. a permission or an I/O error),
+// rather than to be due to the current state of the system (e.g. too many opened files)
+fn is_permanent_read_error(error: &std::io::Error) -> bool {
+    #[cfg(unix)]
+    {
+        if error.raw_os_error() == Some(libc::EIO) {
+            return true;
+        }
+    }
+    matches!(
+        error.kind(),
+        std::io::ErrorKind::PermissionDenied | std::io::ErrorKind::InvalidData
+    )
+}
+
+// List a directory and its sub-directories, except the internal ones
+fn list_live_dirs(dir: &Path) -> Vec<PathBuf> {
+    WalkDir::new(dir)
+        .into_iter()
+        .filter_entry(|e| {
+            e.depth() == 0 || !e.file_name().to_string_lossy().starts_with(INTERNAL_PREFIX)
+        })
+        .filter_map(Result::ok)
+        .filter(|e| e.file_type().is_dir())
+        .map(walkdir::DirEntry::into_path)
+        .collect()
+}
+
+// The modification time of a directory, if it's older than DIR_MTIME_SETTLE before `now`
+// (otherwise, the directory might still be modified without its modification time changing)
+fn settled_mtime(dir: &Path, now: SystemTime) -> Option<SystemTime> {
+    let mtime = metadata(dir).and_then(|m| m.modified()).ok()?;
+    match now.duration_since(mtime) {
+        Ok(age) if age >= DIR_MTIME_SETTLE => Some(mtime),
+        _ => None,
+    }
+}
+
+// Advise the OS of the access pattern of an opened file (on Linux only: on the other platforms, this is a no-op).
+// A failure only loses the hint, so it's ignored.
+#[cfg(target_os = "linux")]
+fn advise_file(f: &File, advice: IoAdvice) {
+    use std::os::unix::io::AsRawFd;
+
+    let advice = match advice {
+        IoAdvice::Normal => return,
+        IoAdvice::Sequential => libc::POSIX_FADV_SEQUENTIAL,
+        IoAdvice::Random => libc::POSIX_FADV_RANDOM,
+    };
+    unsafe { libc::posix_fadvise(f.as_raw_fd(), 0, 0, advice) };
+}
+
+#[cfg(not(target_os = "linux"))]
+fn advise_file(_f: &File, _advice: IoAdvice) {}
+
+// Drop the (clean) pages of a file from the page cache (on Linux only)
+#[cfg(target_os = "linux")]
+fn drop_page_cache(f: &File) {
+    use std::os::unix::io::AsRawFd;
+
+    unsafe { libc::posix_fadvise(f.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED) };
+}
+
+#[cfg(not(target_os = "linux"))]
+fn drop_page_cache(_f: &File) {}
+
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> std::io::Result<()> {
+    File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> std::io::Result<()> {
+    // directories cannot be opened as files to be synced on this platform
+    Ok(())
+}
+
+// Check if a Path within base_dir contains a segment which is a symbolic link
+fn contains_symlink(base_dir: &Path, path: &Path) -> bool {
+    if is_symlink(path) {
+        return true;
+    }
+
+    let mut current = path;
+    while let Some(parent) = current.parent() {
+        // check only up-to base_dir, and don't mind if it's itself a symbolic link
+        if parent == base_dir {
+            return false;
+        } else if is_symlink(parent) {
+            return true;
+        }
+        current = parent;
+    }
+    false
+}
+
+fn is_symlink<P: AsRef<Path>>(path: P) -> bool {
+    match path.as_ref().symlink_metadata() {
+        Ok(metadata) => metadata.file_type().is_symlink(),
+        Err(_) => false,
+    }
+}
+
+// Run a write of the storage's files, counted as a background write until it completes (even after the deadline
+// of "io_deadline_ms") so quiesce() waits for it. It's refused if the storage is quiesced meanwhile.
+async fn counted_write<T, F, Fut>(files_mgr: Arc<FilesMgr>, f: F) -> ZResult<T>
+where
+    F: FnOnce(Arc<FilesMgr>) -> Fut,
+    Fut: Future<Output = ZResult<T>>,
+{
+    let _write = match files_mgr.begin_background_write() {
+        Some(write) => write,
+        None => {
+            return Err(RetryLater::new(
+                "write refused: the storage is quiesced for maintenance".to_string(),
+            )
+            .into())
+        }
+    };
+    f(files_mgr.clone()).await
+}
+
+// Write a content in a new file of "temp_dir" if specified, or else of the same directory, which then replaces
+// a file. If the new file can't be renamed from "temp_dir" as it's on another file system than the replaced file
+// (e.g. a sub-directory of the storage is a mount point), it's written again in the same directory.
+// The new file has the given permissions if any (on Unix), or else the ones of the replaced file.
+fn write_replacing(
+    file: &Path,
+    content: &ZBuf,
+    mode: Option<u32>,
+    fsync: bool,
+    temp_dir: Option<&Path>,
+) -> ZResult<()> {
+    if let Some(temp_dir) = temp_dir {
+        if write_then_rename(temp_dir, file, content, mode, fsync)? {
+            return Ok(());
+        }
+        debug!(
+            "{:?} is not on the same file system as {:?}: write it from its own directory",
+            file, temp_dir
+        );
+    }
+    let dir = file.parent().unwrap_or_else(|| Path::new("."));
+    if !write_then_rename(dir, file, content, mode, fsync)? {
+        bail!(
+            "Failed to write in file {:?}: cannot rename a file of its directory over it",
+            file
+        )
+    }
+    Ok(())
+}
+
+// Write a content in a new file of a directory, which then replaces a file (see write_replacing()).
+// Return false if the rename failed as the directory and the file are on different file systems.
+fn write_then_rename(
+    dir: &Path,
+    file: &Path,
+    content: &ZBuf,
+    mode: Option<u32>,
+    fsync: bool,
+) -> ZResult<bool> {
+    static NEXT_TMP_ID: AtomicU64 = AtomicU64::new(0);
+    let tmp = dir.join(format!(
+        "{}{}_{}",
+        WRITE_TMP_PREFIX,
+        std::process::id(),
+        NEXT_TMP_ID.fetch_add(1, Ordering::Relaxed)
+    ));
+    let result = create_new_file(&tmp, mode)
+        .map_err(|e| write_error("Failed to write in file", &tmp, e))
+        .and_then(|mut f| {
+            for slice in content.slices() {
+                fault_point(FaultPoint::FileWrite)
+                    .and_then(|_| f.write_all(slice))
+                    .map_err(|e| write_error("Failed to write in file", file, e))?;
+            }
+            if mode.is_none() {
+                if let Ok(replaced) = metadata(file) {
+                    f.set_permissions(replaced.permissions())
+                        .map_err(|e| write_error("Failed to write in file", file, e))?;
+                }
+            }
+            if fsync {
+                fault_point(FaultPoint::Fsync)
+                    .and_then(|_| f.sync_all())
+                    .map_err(|e| zerror!("Failed to sync file {:?}: {}", file, e))?;
+            }
+            match fault_point(FaultPoint::Rename).and_then(|_| rename(&tmp, file)) {
+                Ok(()) => Ok(true),
+                Err(e) if is_cross_device(&e) => Ok(false),
+                Err(e) => Err(write_error("Failed to write in file", file, e)),
+            }
+        });
+    if !matches!(result, Ok(true)) {
+        let _ = remove_file(&tmp);
+    }
+    result
+}
+
+// Check if an IO error is due to a rename across file systems
+#[cfg(unix)]
+fn is_cross_device(error: &std::io::Error) -> bool {
+    error.raw_os_error() == Some(libc::EXDEV)
+}
+
+#[cfg(not(unix))]
+fn is_cross_device(_error: &std::io::Error) -> bool {
+    false
+}
+
+// Create a new file to write it, with the given permissions if any (on Unix)
+fn create_new_file(file: &Path, mode: Option<u32>) -> std::io::Result<File> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        if let Some(mode) = mode {
+            options.mode(mode);
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = mode;
+    options.open(file)
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+}
+
+#[cfg(windows)]
+fn set_mode(_path: &Path, _mode: u32) -> std::io::Result<()> {
+    Ok(())
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+// Get the canonical form of a key (without the strip_prefix) to store a sample ("canonicalize_keys"):
+// its last chunks with a wildcard (e.g. "/**" or "/*/", with a trailing '/') and its "." chunks are removed,
+// and each ".." chunk removes the previous chunk. E.g. "a/./b/../c/**" is "a/c".
+pub(crate) fn canonicalize_key(raw: &str) -> ZResult<String> {
+    let mut chunks: Vec<&str> = raw.split('/').collect();
+    while chunks
+        .last()
+        .map_or(false, |chunk| chunk.is_empty() || chunk.contains('*'))
+    {
+        chunks.pop();
+    }
+    let mut canonical: Vec<&str> = Vec::with_capacity(chunks.len());
+    for chunk in chunks {
+        match chunk {
+            "." => (),
+            ".." => {
+                if canonical.pop().is_none() {
+                    bail!(
+                        "Key {:?} can't be canonicalized: a '..' is above the storage's root",
+                        raw
+                    )
+                }
+            }
+            _ if chunk.contains('*') => bail!(
+                "Key {:?} can't be canonicalized: a wildcard is followed by chunks without wildcard",
+                raw
+            ),
+            _ => canonical.push(chunk),
+        }
+    }
+    if canonical.is_empty() {
+        bail!(
+            "Key {:?} can't be canonicalized: it has no chunk without wildcard",
+            raw
+        )
+    }
+    Ok(canonical.join("/"))
+}
+
+pub(crate) fn get_trimmed_keyexpr(keyexpr: &str) -> &str {
+    let k = if keyexpr.ends_with(CONFLICT_SUFFIX) {
         keyexpr.strip_suffix(CONFLICT_SUFFIX).unwrap_or(keyexpr)
     } else {
         keyexpr
@@ -534,6 +4295,1629 @@ pub(crate) fn get_trimmed_keyexpr(keyexpr: &str) -> &str {
     k.strip_prefix('/').unwrap_or(k)
 }
 
-pub(crate) fn get_conflict_resolved_keyexpr(keyexpr: &str) -> String {
-    format!("{}{}", keyexpr, CONFLICT_SUFFIX)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage_meta::StorageMeta;
+
+    // A FilesMgr with the default configuration
+    pub(crate) async fn test_files_mgr(base_dir: &Path) -> FilesMgr {
+        FilesMgr::new(
+            base_dir.to_path_buf(),
+            false,
+            true,
+            Encoding::APP_OCTET_STREAM,
+            OnClosure::DoNothing,
+            0,
+            false,
+            "@".to_string(),
+            false,
+            None,
+            None,
+            false,
+            None,
+            false,
+            None,
+            false,
+            None,
+            None,
+            None,
+            Vec::new(),
+            FilenameEncoding::Utf8,
+            None,
+            false,
+            None,
+            None,
+            OnMissingFile::Absent,
+            MimeGuesser::new(MimeSource::Extension, HashMap::new(), None).unwrap(),
+            IoAdvice::Normal,
+            None,
+            false,
+        )
+        .await
+        .unwrap()
+    }
+
+    #[test]
+    fn files_are_quarantined_after_repeated_permanent_errors() {
+        task::block_on(async {
+            let dir = tempfile::tempdir().unwrap();
+            let mut files_mgr = test_files_mgr(dir.path()).await;
+            files_mgr.quarantine_unreadable = true;
+            let file = dir.path().join("a");
+            std::fs::write(&file, "a").unwrap();
+            let denied = std::io::Error::from(std::io::ErrorKind::PermissionDenied);
+            let interrupted = std::io::Error::from(std::io::ErrorKind::Interrupted);
+
+            // the transient errors don't count, and a successful read resets the count
+            for _ in 0..QUARANTINE_AFTER_FAILURES {
+                files_mgr.on_unreadable_file(&file, &interrupted).await;
+            }
+            files_mgr.on_unreadable_file(&file, &denied).await;
+            files_mgr.on_readable_file(&file);
+            for _ in 1..QUARANTINE_AFTER_FAILURES {
+                files_mgr.on_unreadable_file(&file, &denied).await;
+            }
+            assert!(file.exists());
+            assert!(files_mgr.get_quarantine().is_empty());
+
+            files_mgr.on_unreadable_file(&file, &denied).await;
+            assert!(!file.exists());
+            assert!(dir.path().join(QUARANTINE_DIRNAME).join("a").exists());
+            assert!(files_mgr.get_quarantine().contains_key("a"));
+        })
+    }
+
+    #[test]
+    fn external_deletions_are_recorded_after_the_deleted_timestamp() {
+        task::block_on(async {
+            let dir = tempfile::tempdir().unwrap();
+            let files_mgr = test_files_mgr(dir.path()).await;
+            let ts = new_reception_timestamp();
+            for file in &["a/x", "b/y"] {
+                let path = dir.path().join(file);
+                std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+                std::fs::write(&path, "v").unwrap();
+                files_mgr
+                    .data_info_mgr
+                    .put_data_info(&path, &Encoding::TEXT_PLAIN, &ts)
+                    .await
+                    .unwrap();
+            }
+            let mut live = LiveFiles::default();
+            assert!(files_mgr
+                .record_external_deletions(&mut live)
+                .await
+                .is_empty());
+
+            std::fs::remove_file(dir.path().join("a/x")).unwrap();
+            assert_eq!(
+                files_mgr.record_external_deletions(&mut live).await,
+                vec!["a/x".to_string()]
+            );
+            let tombstone = files_mgr
+                .data_info_mgr
+                .get_timestamp(dir.path().join("a/x"))
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(tombstone.get_time().as_u64(), ts.get_time().as_u64() + 1);
+            assert!(files_mgr
+                .record_external_deletions(&mut live)
+                .await
+                .is_empty());
+
+            // a file put in a new directory is watched
+            let path = dir.path().join("c/d/z");
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, "v").unwrap();
+            files_mgr
+                .data_info_mgr
+                .put_data_info(&path, &Encoding::TEXT_PLAIN, &ts)
+                .await
+                .unwrap();
+            assert!(files_mgr
+                .record_external_deletions(&mut live)
+                .await
+                .is_empty());
+            std::fs::remove_dir_all(dir.path().join("c")).unwrap();
+            assert_eq!(
+                files_mgr.record_external_deletions(&mut live).await,
+                vec!["c/d/z".to_string()]
+            );
+        })
+    }
+
+    #[test]
+    fn entry_count_is_estimated_within_5_percent() {
+        task::block_on(async {
+            // (files put via zenoh, of which deleted since, and files placed without zenoh)
+            for (put, deleted, external) in &[(900, 0, 100), (1000, 100, 0), (0, 0, 1000)] {
+                let dir = tempfile::tempdir().unwrap();
+                let files_mgr = test_files_mgr(dir.path()).await;
+                let ts = new_reception_timestamp();
+                for i in 0..(put + external) {
+                    let path = dir.path().join(format!("{}/{}", i % 10, i));
+                    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+                    std::fs::write(&path, "v").unwrap();
+                    if i < *put {
+                        files_mgr
+                            .data_info_mgr
+                            .put_data_info(&path, &Encoding::TEXT_PLAIN, &ts)
+                            .await
+                            .unwrap();
+                    }
+                    if i < *deleted {
+                        std::fs::remove_file(&path).unwrap();
+                    }
+                }
+                assert_eq!(files_mgr.get_exact_entry_count().await.unwrap(), 1000);
+                let estimate = files_mgr.get_entry_count().unwrap();
+                assert!((950..=1050).contains(&estimate), "estimate: {}", estimate);
+            }
+        })
+    }
+
+    #[test]
+    fn batch_reads_keep_the_order_and_fail_independently() {
+        task::block_on(async {
+            let dir = tempfile::tempdir().unwrap();
+            let files_mgr = Arc::new(test_files_mgr(dir.path()).await);
+            let ts = new_reception_timestamp();
+            let mut zpaths = Vec::new();
+            for i in 0..100 {
+                let zpath = format!("k/{}", i);
+                let path = dir.path().join(&zpath);
+                std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+                std::fs::write(&path, format!("v{}", i)).unwrap();
+                // (every 10th file can't be decoded as base64, and every 25th doesn't exist)
+                files_mgr
+                    .data_info_mgr
+                    .put_data_info_ext(&path, &Encoding::TEXT_PLAIN, &ts, None, i % 10 == 0, None)
+                    .await
+                    .unwrap();
+                if i % 25 == 1 {
+                    std::fs::remove_file(&path).unwrap();
+                }
+                zpaths.push(zpath);
+            }
+            let zfiles: Vec<ZFile> = zpaths.iter().map(|z| files_mgr.to_zfile(z)).collect();
+            let cache = Arc::new(QueryReadCache::new(1 << 20));
+            let reads = files_mgr.batch_read(&zfiles, Some(cache)).await;
+            assert_eq!(reads.len(), 100);
+            for (i, read) in reads.into_iter().enumerate() {
+                match read {
+                    Err(_) => assert_eq!(i % 10, 0),
+                    Ok(None) => assert_eq!(i % 25, 1),
+                    Ok(Some((value, _, pinned))) => {
+                        assert!(i % 10 != 0 && i % 25 != 1);
+                        assert_eq!(
+                            value.payload.contiguous().as_ref(),
+                            format!("v{}", i).as_bytes()
+                        );
+                        assert_eq!(pinned.path, dir.path().join(&zpaths[i]));
+                    }
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn changes_after_a_snapshot_time_are_found() {
+        task::block_on(async {
+            let dir = tempfile::tempdir().unwrap();
+            let files_mgr = test_files_mgr(dir.path()).await;
+            let all = keyexpr::new("a/**").unwrap();
+            let (base_dir, files_mgr_ref) = (dir.path(), &files_mgr);
+            let put = move |zpath: &'static str, ts: Timestamp| {
+                let files_mgr = files_mgr_ref;
+                async move {
+                    let path = base_dir.join(zpath);
+                    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+                    std::fs::write(&path, "v").unwrap();
+                    files_mgr
+                        .data_info_mgr
+                        .put_data_info(&path, &Encoding::TEXT_PLAIN, &ts)
+                        .await
+                        .unwrap();
+                }
+            };
+            put("a/x", new_reception_timestamp()).await;
+            put("a/y", new_reception_timestamp()).await;
+            put("b/z", new_reception_timestamp()).await;
+            let snapshot = *new_reception_timestamp().get_time();
+            assert!(files_mgr
+                .find_changed_after(all, &snapshot)
+                .await
+                .unwrap()
+                .is_none());
+
+            // a key of another directory modified after the snapshot doesn't change it
+            put("b/z", new_reception_timestamp()).await;
+            assert!(files_mgr
+                .find_changed_after(all, &snapshot)
+                .await
+                .unwrap()
+                .is_none());
+
+            // a matching key deleted after the snapshot changes it
+            let path = dir.path().join("a/y");
+            std::fs::remove_file(&path).unwrap();
+            let deleted = new_reception_timestamp();
+            files_mgr
+                .data_info_mgr
+                .put_data_info(&path, &Encoding::TEXT_PLAIN, &deleted)
+                .await
+                .unwrap();
+            assert_eq!(
+                files_mgr.find_changed_after(all, &snapshot).await.unwrap(),
+                Some(("a/y".to_string(), deleted))
+            );
+            // (but not a snapshot taken after the deletion)
+            let later = *new_reception_timestamp().get_time();
+            assert!(files_mgr
+                .find_changed_after(all, &later)
+                .await
+                .unwrap()
+                .is_none());
+
+            // a matching key put after the snapshot changes it
+            let modified = new_reception_timestamp();
+            put("a/x", modified).await;
+            assert_eq!(
+                files_mgr
+                    .find_changed_after(keyexpr::new("a/x").unwrap(), &later)
+                    .await
+                    .unwrap(),
+                Some(("a/x".to_string(), modified))
+            );
+        })
+    }
+
+    #[test]
+    fn keys_are_canonicalized() {
+        for (raw, canonical) in &[
+            ("a/b", Some("a/b")),
+            ("a/b/", Some("a/b")),
+            ("a/**", Some("a")),
+            ("a/*/", Some("a")),
+            ("a/*/**", Some("a")),
+            ("a/b*", Some("a")),
+            ("a/./b/../c/**", Some("a/c")),
+            ("./a/b/..", Some("a")),
+            ("a/b/../../c", Some("c")),
+            ("..", None),
+            ("../a", None),
+            ("a/../../b", None),
+            ("a/..", None),
+            ("**", None),
+            ("*/**", None),
+            ("a/*/b", None),
+        ] {
+            assert_eq!(
+                canonicalize_key(raw).ok().as_deref(),
+                *canonical,
+                "canonicalize_key({:?})",
+                raw
+            );
+        }
+    }
+
+    #[test]
+    fn parents_lead_to_the_root() {
+        task::block_on(async {
+            let dir = tempfile::tempdir().unwrap();
+            let files_mgr = test_files_mgr(dir.path()).await;
+            let mut zpath = "a/b/c".to_string();
+            let mut ancestors = Vec::new();
+            loop {
+                let parent = match files_mgr.to_zfile(&zpath).parent(&files_mgr) {
+                    Some(parent) => parent.zpath.to_string(),
+                    None => break,
+                };
+                ancestors.push(parent.clone());
+                zpath = parent;
+            }
+            assert_eq!(ancestors, vec!["a/b", "a", ""]);
+            assert_eq!(
+                files_mgr.to_zfile("a/b").parent(&files_mgr).unwrap().fspath,
+                dir.path().join("a")
+            );
+        })
+    }
+
+    #[test]
+    fn permanent_read_errors() {
+        use std::io::{Error, ErrorKind};
+        assert!(is_permanent_read_error(&Error::from(
+            ErrorKind::PermissionDenied
+        )));
+        assert!(is_permanent_read_error(&Error::from(
+            ErrorKind::InvalidData
+        )));
+        assert!(!is_permanent_read_error(&Error::from(
+            ErrorKind::Interrupted
+        )));
+        assert!(!is_permanent_read_error(&Error::from(ErrorKind::NotFound)));
+        #[cfg(unix)]
+        {
+            assert!(is_permanent_read_error(&Error::from_raw_os_error(
+                libc::EIO
+            )));
+            assert!(!is_permanent_read_error(&Error::from_raw_os_error(
+                libc::EMFILE
+            )));
+            assert!(!is_permanent_read_error(&Error::from_raw_os_error(
+                libc::EINTR
+            )));
+        }
+    }
+
+    #[test]
+    fn keys_ending_with_a_stripped_extension() {
+        let exts = vec![".json".to_string(), ".txt".to_string()];
+        assert_eq!(stripped_extension("a/b.json", &exts), Some(".json"));
+        assert_eq!(stripped_extension("a/b.txt", &exts), Some(".txt"));
+        assert_eq!(stripped_extension("a/b.jsonl", &exts), None);
+        assert_eq!(stripped_extension("a/b", &exts), None);
+        assert_eq!(stripped_extension("a/.json", &exts), None);
+        assert_eq!(stripped_extension(".json", &exts), None);
+        // the file written for a key with an extension is listed with this key
+        assert_eq!(
+            unmap_coarse_zpath("/a/b.json", false, 0, None, &exts).as_deref(),
+            Some("a/b")
+        );
+        assert_eq!(
+            unmap_coarse_zpath("/a/.json", false, 0, None, &exts).as_deref(),
+            Some("a/.json")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn files_are_created_with_the_configured_mode() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("a");
+        let mode = |file: &Path| metadata(file).unwrap().permissions().mode() & 0o7777;
+        let content = ZBuf::from(b"content".to_vec());
+        write_replacing(&file, &content, Some(0o600), false, None).unwrap();
+        assert_eq!(mode(&file), 0o600);
+        // without configured permissions, the ones of the replaced file are kept
+        set_mode(&file, 0o640).unwrap();
+        write_replacing(&file, &content, None, false, None).unwrap();
+        assert_eq!(mode(&file), 0o640);
+    }
+
+    #[test]
+    fn written_files_are_not_truncated_while_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("a");
+        write_replacing(&file, &ZBuf::from(b"old".to_vec()), None, false, None).unwrap();
+        let mut reader = File::open(&file).unwrap();
+        write_replacing(
+            &file,
+            &ZBuf::from(b"new content".to_vec()),
+            None,
+            false,
+            None,
+        )
+        .unwrap();
+        let mut content = String::new();
+        reader.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "old");
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "new content");
+        // no temporary file is left
+        assert_eq!(dir.path().read_dir().unwrap().count(), 1);
+    }
+
+    #[test]
+    fn gz_files_are_read_decompressed_up_to_the_max_size() {
+        task::block_on(async {
+            let dir = tempfile::tempdir().unwrap();
+            let mut files_mgr = test_files_mgr(dir.path()).await;
+            files_mgr.decompress_gz_on_read = true;
+            let content = br#"{"a": 1}"#.repeat(1000);
+            let mut encoder =
+                flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(&content).unwrap();
+            std::fs::write(dir.path().join("a.json.gz"), encoder.finish().unwrap()).unwrap();
+
+            let (value, _) = files_mgr
+                .read_file(&files_mgr.to_zfile("a.json.gz"))
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(value.payload.contiguous().as_ref(), content.as_slice());
+            assert_eq!(value.encoding.to_string(), "application/json");
+
+            files_mgr.max_decompressed_bytes = Some(content.len() as u64);
+            assert!(files_mgr
+                .read_file(&files_mgr.to_zfile("a.json.gz"))
+                .await
+                .is_ok());
+            files_mgr.max_decompressed_bytes = Some(content.len() as u64 - 1);
+            assert!(files_mgr
+                .read_file(&files_mgr.to_zfile("a.json.gz"))
+                .await
+                .is_err());
+        })
+    }
+
+    #[test]
+    fn etags_are_derived_from_the_hash_recorded_at_write_time() {
+        task::block_on(async {
+            let dir = tempfile::tempdir().unwrap();
+            let files_mgr = test_files_mgr(dir.path()).await;
+            let ts = new_reception_timestamp();
+            let time = ts.get_time().as_u64();
+            files_mgr
+                .write_file(
+                    &files_mgr.to_zfile("a"),
+                    ZBuf::from(b"test".to_vec()),
+                    &Encoding::TEXT_PLAIN,
+                    &ts,
+                )
+                .await
+                .unwrap();
+            let (etag_ts, etag) = files_mgr
+                .get_etag(&files_mgr.to_zfile("a"))
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(etag_ts, ts);
+            // the SHA-256 of "test" starts with 9f86d081884c7d65
+            assert_eq!(etag, format!("{:x}-9f86d081884c7d65", time));
+
+            // a file modified without zenoh isn't hashed
+            std::thread::sleep(Duration::from_millis(10));
+            std::fs::write(dir.path().join("a"), "modified").unwrap();
+            let mtime = file_mtime(&dir.path().join("a")).unwrap();
+            let (_, etag) = files_mgr
+                .get_etag(&files_mgr.to_zfile("a"))
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(etag, format!("{:x}-{:x}-8", time, mtime));
+
+            assert!(files_mgr
+                .get_etag(&files_mgr.to_zfile("b"))
+                .await
+                .unwrap()
+                .is_none());
+        })
+    }
+
+    #[test]
+    fn diff_reports_the_entry_modified_in_one_storage() {
+        task::block_on(async {
+            let (dir, other_dir) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+            let files_mgr = test_files_mgr(dir.path()).await;
+            let other_files_mgr = test_files_mgr(other_dir.path()).await;
+            let ts = new_reception_timestamp();
+            for mgr in [&files_mgr, &other_files_mgr] {
+                for zpath in ["a", "b/c", "d"] {
+                    mgr.write_file(
+                        &mgr.to_zfile(zpath),
+                        ZBuf::from(zpath.as_bytes().to_vec()),
+                        &Encoding::TEXT_PLAIN,
+                        &ts,
+                    )
+                    .await
+                    .unwrap();
+                }
+            }
+            let diff = files_mgr
+                .diff(other_dir.path(), DiffMethod::Sha256)
+                .await
+                .unwrap();
+            assert!(diff.only_in_self.is_empty() && diff.only_in_other.is_empty());
+            assert!(diff.conflicts.is_empty());
+
+            let modified = new_reception_timestamp();
+            other_files_mgr
+                .write_file(
+                    &other_files_mgr.to_zfile("b/c"),
+                    ZBuf::from(b"modified".to_vec()),
+                    &Encoding::TEXT_PLAIN,
+                    &modified,
+                )
+                .await
+                .unwrap();
+            for method in [DiffMethod::Sha256, DiffMethod::Mtime] {
+                let diff = files_mgr.diff(other_dir.path(), method).await.unwrap();
+                assert!(diff.only_in_self.is_empty() && diff.only_in_other.is_empty());
+                assert_eq!(diff.conflicts.len(), 1);
+                assert_eq!(diff.conflicts[0].0.as_str(), "b/c");
+                assert_eq!((diff.conflicts[0].1, diff.conflicts[0].2), (ts, modified));
+            }
+        })
+    }
+
+    // A tracing subscriber recording the name and the fields of the spans created
+    #[derive(Default)]
+    struct SpansRecorder(Mutex<Vec<(String, Vec<(String, String)>)>>);
+
+    struct FieldsRecorder<'a>(&'a mut Vec<(String, String)>);
+
+    impl tracing::field::Visit for FieldsRecorder<'_> {
+        fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+            self.0.push((field.name().to_string(), value.to_string()));
+        }
+
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn fmt::Debug) {
+            self.0
+                .push((field.name().to_string(), format!("{:?}", value)));
+        }
+    }
+
+    impl tracing::Subscriber for SpansRecorder {
+        fn enabled(&self, _: &tracing::Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+            let mut fields = Vec::new();
+            span.record(&mut FieldsRecorder(&mut fields));
+            let mut spans = self.0.lock().unwrap();
+            spans.push((span.metadata().name().to_string(), fields));
+            tracing::span::Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, _: &tracing::span::Id, _: &tracing::span::Record<'_>) {}
+
+        fn record_follows_from(&self, _: &tracing::span::Id, _: &tracing::span::Id) {}
+
+        fn event(&self, _: &tracing::Event<'_>) {}
+
+        fn enter(&self, _: &tracing::span::Id) {}
+
+        fn exit(&self, _: &tracing::span::Id) {}
+    }
+
+    #[test]
+    fn file_operations_are_traced_in_spans() {
+        let recorder = Arc::new(SpansRecorder::default());
+        let dir = tempfile::tempdir().unwrap();
+        tracing::subscriber::with_default(recorder.clone(), || {
+            task::block_on(async {
+                let files_mgr = test_files_mgr(dir.path()).await;
+                let zfile = files_mgr.to_zfile("a");
+                files_mgr
+                    .write_file(
+                        &zfile,
+                        ZBuf::from(b"a".to_vec()),
+                        &Encoding::TEXT_PLAIN,
+                        &new_reception_timestamp(),
+                    )
+                    .await
+                    .unwrap();
+                files_mgr.read_file(&zfile).await.unwrap().unwrap();
+                files_mgr
+                    .delete_file(&zfile, &new_reception_timestamp())
+                    .await
+                    .unwrap();
+            })
+        });
+        let spans = recorder.0.lock().unwrap();
+        let file = format!("{:?}", dir.path().join("a"));
+        for (name, op) in [
+            ("write_file", "put"),
+            ("read_file", "get"),
+            ("delete_file", "delete"),
+        ] {
+            let (_, fields) = spans
+                .iter()
+                .find(|(span, _)| span == name)
+                .unwrap_or_else(|| panic!("no {} span", name));
+            assert!(fields.contains(&("op".to_string(), op.to_string())));
+            assert!(fields.contains(&("file".to_string(), file.clone())));
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn identical_writes_leave_the_file_untouched() {
+        use std::os::unix::fs::MetadataExt;
+        task::block_on(async {
+            let dir = tempfile::tempdir().unwrap();
+            let mut files_mgr = test_files_mgr(dir.path()).await;
+            files_mgr.skip_identical_writes = true;
+            let zfile = files_mgr.to_zfile("a");
+            let write = |content: &'static [u8], ts: Timestamp| {
+                let (files_mgr, zfile) = (&files_mgr, &zfile);
+                async move {
+                    files_mgr
+                        .write_file(
+                            zfile,
+                            ZBuf::from(content.to_vec()),
+                            &Encoding::TEXT_PLAIN,
+                            &ts,
+                        )
+                        .await
+                        .unwrap();
+                    let metadata = metadata(&zfile.fspath).unwrap();
+                    (metadata.ino(), metadata_mtime(&metadata))
+                }
+            };
+            let written = write(b"a", new_reception_timestamp()).await;
+            std::thread::sleep(Duration::from_millis(10));
+            let ts = new_reception_timestamp();
+            assert_eq!(write(b"a", ts).await, written);
+            // only the timestamp is updated
+            assert_eq!(files_mgr.get_timestamp(&zfile).await.unwrap(), Some(ts));
+
+            // a different content is written
+            assert_ne!(write(b"b", new_reception_timestamp()).await, written);
+            assert_eq!(std::fs::read(&zfile.fspath).unwrap(), b"b");
+        })
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn the_content_modified_by_the_pre_write_hook_is_stored() {
+        use std::os::unix::fs::PermissionsExt;
+        task::block_on(async {
+            let (dir, hook_dir) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+            let hook = hook_dir.path().join("hook.sh");
+            std::fs::write(
+                &hook,
+                "#!/bin/sh\ncase \"$1\" in\n  bad) echo \"invalid $1\" >&2; exit 1;;\n  *) printf \"validated $1\" > \"$2\";;\nesac\n",
+            )
+            .unwrap();
+            std::fs::set_permissions(&hook, std::fs::Permissions::from_mode(0o755)).unwrap();
+            let mut files_mgr = test_files_mgr(dir.path()).await;
+            files_mgr.pre_write_hook = Some(Arc::new(
+                WriteHook::parse(&format!("{} %k %f", hook.display()), 10000).unwrap(),
+            ));
+
+            let write = |zpath: &'static str| {
+                let files_mgr = &files_mgr;
+                async move {
+                    files_mgr
+                        .write_file(
+                            &files_mgr.to_zfile(zpath),
+                            ZBuf::from(b"content".to_vec()),
+                            &Encoding::TEXT_PLAIN,
+                            &new_reception_timestamp(),
+                        )
+                        .await
+                }
+            };
+            write("good").await.unwrap();
+            assert_eq!(
+                std::fs::read_to_string(dir.path().join("good")).unwrap(),
+                "validated good"
+            );
+            let e = write("bad").await.unwrap_err();
+            assert!(e.to_string().contains("invalid bad"));
+            assert!(!dir.path().join("bad").exists());
+        })
+    }
+
+    #[test]
+    fn directories_are_listed_level_by_level() {
+        task::block_on(async {
+            let dir = tempfile::tempdir().unwrap();
+            let files_mgr = test_files_mgr(dir.path()).await;
+            let id = *new_reception_timestamp().get_id();
+            let ts = |secs: u64| Timestamp::new(NTP64(secs << 32), id);
+            for (i, zpath) in ["a/b/c", "a/b/d", "a/e/f", "a/g", "h"].iter().enumerate() {
+                files_mgr
+                    .write_file(
+                        &files_mgr.to_zfile(zpath),
+                        ZBuf::from(zpath.as_bytes().to_vec()),
+                        &Encoding::TEXT_PLAIN,
+                        &ts(i as u64 + 1),
+                    )
+                    .await
+                    .unwrap();
+            }
+            let list = |prefix: &'static str| {
+                let files_mgr = &files_mgr;
+                async move {
+                    files_mgr
+                        .list_directory(prefix)
+                        .await
+                        .unwrap()
+                        .into_iter()
+                        .map(|e| {
+                            (
+                                e.key.to_string(),
+                                e.is_directory,
+                                e.entry_count,
+                                e.last_modified,
+                            )
+                        })
+                        .collect::<Vec<_>>()
+                }
+            };
+            assert_eq!(
+                list("").await,
+                vec![
+                    ("a".to_string(), true, Some(4), Some(ts(4))),
+                    ("h".to_string(), false, None, Some(ts(5))),
+                ]
+            );
+            assert_eq!(
+                list("a").await,
+                vec![
+                    ("a/b".to_string(), true, Some(2), Some(ts(2))),
+                    ("a/e".to_string(), true, Some(1), Some(ts(3))),
+                    ("a/g".to_string(), false, None, Some(ts(4))),
+                ]
+            );
+            assert_eq!(
+                list("a/b").await,
+                vec![
+                    ("a/b/c".to_string(), false, None, Some(ts(1))),
+                    ("a/b/d".to_string(), false, None, Some(ts(2))),
+                ]
+            );
+            assert!(list("a/b/c").await.is_empty());
+            assert!(list("x").await.is_empty());
+        })
+    }
+
+    #[test]
+    fn stored_keys_and_tombstones_are_migrated() {
+        task::block_on(async {
+            let dir = tempfile::tempdir().unwrap();
+            let files_mgr = test_files_mgr(dir.path()).await;
+            let write = |zpath: &'static str| {
+                let files_mgr = &files_mgr;
+                async move {
+                    files_mgr
+                        .write_file(
+                            &files_mgr.to_zfile(zpath),
+                            ZBuf::from(zpath.as_bytes().to_vec()),
+                            &Encoding::TEXT_PLAIN,
+                            &new_reception_timestamp(),
+                        )
+                        .await
+                        .unwrap()
+                }
+            };
+            write("old/x/y").await;
+            write("old/z").await;
+            // written for the new key_expr before the migration: kept
+            write("new/z").await;
+            files_mgr
+                .delete_file(&files_mgr.to_zfile("old/w"), &new_reception_timestamp())
+                .await
+                .unwrap();
+
+            let previous = StorageMeta {
+                key_expr: "old/**".to_string(),
+                strip_prefix: None,
+            };
+            let current = StorageMeta {
+                key_expr: "new/**".to_string(),
+                strip_prefix: None,
+            };
+            let moved = files_mgr
+                .migrate_keys(|zpath| previous.migrate_zpath(&current, zpath))
+                .await
+                .unwrap();
+            // old/x/y and the tombstone of old/w
+            assert_eq!(moved, 2);
+            let read = |zpath: &str| std::fs::read_to_string(files_mgr.to_zfile(zpath).fspath).ok();
+            assert_eq!(read("new/x/y").as_deref(), Some("old/x/y"));
+            assert_eq!(read("old/x/y"), None);
+            assert!(!dir.path().join("old/x").exists());
+            assert_eq!(read("new/z").as_deref(), Some("new/z"));
+            assert_eq!(read("old/z").as_deref(), Some("old/z"));
+            let deleted: Vec<String> = files_mgr
+                .data_info_mgr
+                .get_deleted_entries(None)
+                .await
+                .iter()
+                .filter_map(|(fspath, _)| files_mgr.fspath_to_stored_zpath(fspath))
+                .collect();
+            assert_eq!(deleted, vec!["new/w".to_string()]);
+        })
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn identical_contents_are_read_once_per_query() {
+        task::block_on(async {
+            let dir = tempfile::tempdir().unwrap();
+            let files_mgr = test_files_mgr(dir.path()).await;
+            for (zpath, content) in [("a", "same"), ("b", "same"), ("c", "other")] {
+                files_mgr
+                    .write_file(
+                        &files_mgr.to_zfile(zpath),
+                        ZBuf::from(content.as_bytes().to_vec()),
+                        &Encoding::TEXT_PLAIN,
+                        &new_reception_timestamp(),
+                    )
+                    .await
+                    .unwrap();
+            }
+            // a hard link placed without zenoh
+            std::fs::hard_link(dir.path().join("a"), dir.path().join("d")).unwrap();
+
+            let cache = QueryReadCache::new(1 << 20);
+            let read = |zpath: &'static str| {
+                let (files_mgr, cache) = (&files_mgr, &cache);
+                async move {
+                    let mut pinned = files_mgr
+                        .pin(&files_mgr.to_zfile(zpath))
+                        .await
+                        .unwrap()
+                        .unwrap();
+                    let (value, _) = files_mgr
+                        .read_pinned(&mut pinned, Some(cache), false)
+                        .await
+                        .unwrap();
+                    String::from_utf8(value.payload.contiguous().to_vec()).unwrap()
+                }
+            };
+            let is_cached = |zpath: &str| {
+                let (files_mgr, cache) = (&files_mgr, &cache);
+                let file = dir.path().join(zpath);
+                async move {
+                    let metadata = std::fs::metadata(&file).unwrap();
+                    cache
+                        .get(&files_mgr.content_ids(&file, &metadata).await)
+                        .is_some()
+                }
+            };
+            assert!(!is_cached("a").await);
+            assert_eq!(read("a").await, "same");
+            // b by its recorded hash, d by its inode
+            assert!(is_cached("b").await && is_cached("d").await);
+            assert!(!is_cached("c").await);
+            assert_eq!(read("b").await, "same");
+            assert_eq!(read("d").await, "same");
+            assert_eq!(read("c").await, "other");
+            assert!(is_cached("c").await);
+        })
+    }
+
+    #[test]
+    fn events_are_published_on_write_and_delete() {
+        use zenoh::prelude::r#async::AsyncResolve;
+        task::block_on(async {
+            // a peer session without any connection: the events are delivered to its own subscriber
+            let config = zenoh::config::Config::from_deserializer(serde_json::json!({
+                "mode": "peer",
+                "scouting": { "multicast": { "enabled": false } },
+                "listen": { "endpoints": [] },
+            }))
+            .map_err(|_| "invalid configuration")
+            .unwrap();
+            let session = Arc::new(zenoh::open(config).res().await.unwrap());
+            let subscriber = session
+                .declare_subscriber("demo/example/@events/*")
+                .res()
+                .await
+                .unwrap();
+
+            let dir = tempfile::tempdir().unwrap();
+            let mut files_mgr = test_files_mgr(dir.path()).await;
+            let events = StorageEvents::new(
+                Arc::downgrade(&session),
+                keyexpr::new("demo/example/**").unwrap(),
+                Some(OwnedKeyExpr::try_from("demo/example").unwrap()),
+            )
+            .unwrap();
+            files_mgr.events = Some(events);
+            assert!(files_mgr.is_event_key(keyexpr::new("demo/example/@events/put").unwrap()));
+            assert!(!files_mgr.is_event_key(keyexpr::new("demo/example/a/b").unwrap()));
+
+            let zfile = files_mgr.to_zfile("a/b");
+            files_mgr
+                .write_file(
+                    &zfile,
+                    ZBuf::from(b"value".to_vec()),
+                    &Encoding::TEXT_PLAIN,
+                    &new_reception_timestamp(),
+                )
+                .await
+                .unwrap();
+            files_mgr
+                .delete_file(&zfile, &new_reception_timestamp())
+                .await
+                .unwrap();
+            let mut received = Vec::new();
+            for _ in 0..2 {
+                let sample = subscriber.recv_async().await.unwrap();
+                let event: serde_json::Value =
+                    serde_json::from_slice(&sample.value.payload.contiguous()).unwrap();
+                received.push((sample.key_expr.to_string(), event));
+            }
+            assert_eq!(
+                received,
+                vec![
+                    (
+                        "demo/example/@events/put".to_string(),
+                        serde_json::json!({"op": "put", "key": "demo/example/a/b", "bytes": 5})
+                    ),
+                    (
+                        "demo/example/@events/delete".to_string(),
+                        serde_json::json!({"op": "delete", "key": "demo/example/a/b"})
+                    ),
+                ]
+            );
+
+            // once the session is closed, the operations succeed without publishing
+            drop(subscriber);
+            drop(session);
+            files_mgr
+                .write_file(
+                    &zfile,
+                    ZBuf::from(b"value".to_vec()),
+                    &Encoding::TEXT_PLAIN,
+                    &new_reception_timestamp(),
+                )
+                .await
+                .unwrap();
+        })
+    }
+
+    #[test]
+    fn keys_round_trip_through_files_with_a_stripped_extension() {
+        task::block_on(async {
+            let dir = tempfile::tempdir().unwrap();
+            let mut files_mgr = test_files_mgr(dir.path()).await;
+            files_mgr.strip_extensions = vec![".json".to_string(), ".txt".to_string()];
+            let write = |zpath: &'static str, content: &'static str, encoding: Encoding| {
+                let files_mgr = &files_mgr;
+                async move {
+                    files_mgr
+                        .write_file(
+                            &files_mgr.to_zfile(zpath),
+                            ZBuf::from(content.as_bytes().to_vec()),
+                            &encoding,
+                            &new_reception_timestamp(),
+                        )
+                        .await
+                        .unwrap()
+                }
+            };
+            let files = || {
+                let mut files: Vec<String> = WalkDir::new(dir.path().join("a"))
+                    .into_iter()
+                    .filter_map(|e| e.ok())
+                    .filter(|e| e.file_type().is_file())
+                    .map(|e| e.file_name().to_string_lossy().to_string())
+                    .collect();
+                files.sort();
+                files
+            };
+            let keys = || {
+                let mut keys: Vec<String> = files_mgr
+                    .matching_files(keyexpr::new("a/**").unwrap())
+                    .map(|zfile| zfile.zpath.to_string())
+                    .collect();
+                keys.sort();
+                keys
+            };
+
+            write("a/b", r#"{"x":1}"#, Encoding::APP_JSON).await;
+            write("a/c", "c", Encoding::APP_OCTET_STREAM).await;
+            assert_eq!(files(), vec!["b.json", "c"]);
+            assert_eq!(keys(), vec!["a/b", "a/c"]);
+            let (value, _) = files_mgr
+                .read_file(&files_mgr.to_zfile("a/b"))
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(value.payload.contiguous().as_ref(), br#"{"x":1}"#);
+            assert_eq!(value.encoding, Encoding::APP_JSON);
+
+            // a PUT with another encoding replaces the file with another extension
+            write("a/b", "text", Encoding::TEXT_PLAIN).await;
+            assert_eq!(files(), vec!["b.txt", "c"]);
+            assert_eq!(keys(), vec!["a/b", "a/c"]);
+            let (value, _) = files_mgr
+                .read_file(&files_mgr.to_zfile("a/b"))
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(value.payload.contiguous().as_ref(), b"text");
+            assert_eq!(value.encoding, Encoding::TEXT_PLAIN);
+
+            files_mgr
+                .delete_file(&files_mgr.to_zfile("a/b"), &new_reception_timestamp())
+                .await
+                .unwrap();
+            assert_eq!(files(), vec!["c"]);
+            assert_eq!(keys(), vec!["a/c"]);
+        })
+    }
+
+    #[test]
+    fn gz_files_are_replied_compressed_or_decompressed_with_the_same_content() {
+        task::block_on(async {
+            let dir = tempfile::tempdir().unwrap();
+            let mut files_mgr = test_files_mgr(dir.path()).await;
+            files_mgr.decompress_gz_on_read = true;
+            let content = br#"{"a": 1}"#.repeat(100);
+            let mut encoder =
+                flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(&content).unwrap();
+            let compressed = encoder.finish().unwrap();
+            std::fs::write(dir.path().join("a.json.gz"), &compressed).unwrap();
+
+            let read = |keep_compressed: bool| {
+                let files_mgr = &files_mgr;
+                async move {
+                    let mut pinned = files_mgr
+                        .pin(&files_mgr.to_zfile("a.json.gz"))
+                        .await
+                        .unwrap()
+                        .unwrap();
+                    files_mgr
+                        .read_pinned(&mut pinned, None, keep_compressed)
+                        .await
+                        .unwrap()
+                        .0
+                }
+            };
+            let decompressed = read(false).await;
+            assert_eq!(
+                decompressed.payload.contiguous().as_ref(),
+                content.as_slice()
+            );
+            assert_eq!(decompressed.encoding.to_string(), "application/json");
+            let kept = read(true).await;
+            assert_eq!(kept.payload.contiguous().as_ref(), compressed.as_slice());
+            assert_eq!(
+                kept.encoding.to_string(),
+                format!("application/json{}", GZIP_ENCODING_SUFFIX)
+            );
+            let mut unzipped = Vec::new();
+            flate2::read::GzDecoder::new(kept.payload.contiguous().as_ref())
+                .read_to_end(&mut unzipped)
+                .unwrap();
+            assert_eq!(unzipped, content);
+
+            // a ".gz" key put via zenoh is replied as put, in both cases
+            files_mgr
+                .write_file(
+                    &files_mgr.to_zfile("a.json.gz"),
+                    ZBuf::from(compressed.clone()),
+                    &Encoding::APP_OCTET_STREAM,
+                    &new_reception_timestamp(),
+                )
+                .await
+                .unwrap();
+            for keep_compressed in [false, true] {
+                let value = read(keep_compressed).await;
+                assert_eq!(value.payload.contiguous().as_ref(), compressed.as_slice());
+                assert_eq!(value.encoding, Encoding::APP_OCTET_STREAM);
+            }
+        })
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_file_names_are_stored_and_listed_with_os_string() {
+        use std::ffi::OsString;
+        use std::os::unix::ffi::{OsStrExt, OsStringExt};
+        task::block_on(async {
+            let dir = tempfile::tempdir().unwrap();
+            let mut files_mgr = test_files_mgr(dir.path()).await;
+            files_mgr.filename_encoding = FilenameEncoding::OsString;
+            files_mgr
+                .write_file(
+                    &files_mgr.to_zfile("a/caf%E9"),
+                    ZBuf::from(b"put".to_vec()),
+                    &Encoding::TEXT_PLAIN,
+                    &new_reception_timestamp(),
+                )
+                .await
+                .unwrap();
+            let file = dir
+                .path()
+                .join("a")
+                .join(OsString::from_vec(b"caf\xE9".to_vec()));
+            assert_eq!(std::fs::read(&file).unwrap(), b"put");
+            // a file placed without zenoh
+            let placed = dir
+                .path()
+                .join("a")
+                .join(OsString::from_vec(b"\xFF\xFE\xF0\x9F\x98\x80".to_vec()));
+            std::fs::write(&placed, "placed").unwrap();
+
+            let mut keys: Vec<String> = files_mgr
+                .matching_files(keyexpr::new("a/**").unwrap())
+                .map(|zfile| zfile.zpath.to_string())
+                .collect();
+            keys.sort();
+            assert_eq!(keys, vec!["a/%FF%FE\u{1F600}", "a/caf%E9"]);
+            let zfile = files_mgr.to_zfile("a/%FF%FE\u{1F600}");
+            assert_eq!(
+                zfile.fspath.as_os_str().as_bytes(),
+                placed.as_os_str().as_bytes()
+            );
+            let (value, _) = files_mgr.read_file(&zfile).await.unwrap().unwrap();
+            assert_eq!(value.payload.contiguous().as_ref(), b"placed");
+        })
+    }
+
+    #[test]
+    fn compaction_removes_the_duplicate_and_orphan_data_infos() {
+        task::block_on(async {
+            let dir = tempfile::tempdir().unwrap();
+            let files_mgr = test_files_mgr(dir.path()).await;
+            let id = *new_reception_timestamp().get_id();
+            let old_ts = Timestamp::new(NTP64(1 << 32), id);
+            let zfile = files_mgr.to_zfile("a");
+            files_mgr
+                .write_file(
+                    &zfile,
+                    ZBuf::from(b"a".to_vec()),
+                    &Encoding::TEXT_PLAIN,
+                    &new_reception_timestamp(),
+                )
+                .await
+                .unwrap();
+            // an older record of the key's conflict file, which doesn't exist
+            let duplicate = files_mgr.get_conflict_file(zfile.fspath.clone());
+            // a record of a file removed long ago
+            let orphan = dir.path().join("gone");
+            for file in [&duplicate, &orphan] {
+                files_mgr
+                    .data_info_mgr
+                    .put_data_info(file, &Encoding::TEXT_PLAIN, &old_ts)
+                    .await
+                    .unwrap();
+            }
+            // a recent tombstone is kept
+            let deleted = files_mgr.to_zfile("b");
+            files_mgr
+                .delete_file(&deleted, &new_reception_timestamp())
+                .await
+                .unwrap();
+
+            let report = files_mgr.compact_storage().await.unwrap();
+            assert_eq!(report.duplicates_removed, 1);
+            assert_eq!(report.orphans_removed, 1);
+            assert_eq!(report.index_entries_removed, 2);
+            let data_info_mgr = &files_mgr.data_info_mgr;
+            assert!(data_info_mgr
+                .get_timestamp(&zfile.fspath)
+                .await
+                .unwrap()
+                .is_some());
+            assert!(data_info_mgr
+                .get_timestamp(&deleted.fspath)
+                .await
+                .unwrap()
+                .is_some());
+            assert_eq!(data_info_mgr.get_timestamp(&duplicate).await.unwrap(), None);
+            assert_eq!(data_info_mgr.get_timestamp(&orphan).await.unwrap(), None);
+            assert_eq!(
+                data_info_mgr
+                    .get_by_encoding(&Encoding::TEXT_PLAIN)
+                    .await
+                    .unwrap(),
+                vec![zfile.fspath.clone()]
+            );
+            // the database is still usable once replaced
+            files_mgr
+                .write_file(
+                    &files_mgr.to_zfile("c"),
+                    ZBuf::from(b"c".to_vec()),
+                    &Encoding::TEXT_PLAIN,
+                    &new_reception_timestamp(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(
+                files_mgr
+                    .get_by_encoding(&Encoding::TEXT_PLAIN)
+                    .await
+                    .unwrap()
+                    .len(),
+                2
+            );
+        })
+    }
+
+    #[test]
+    fn glob_mode_finds_the_same_files_as_the_walk() {
+        task::block_on(async {
+            let dir = tempfile::tempdir().unwrap();
+            let mut files_mgr = test_files_mgr(dir.path()).await;
+            // "a" is stored as a conflict file, as "a/b" is a directory
+            for zpath in [
+                "a/b/c", "a/b", "a", "a/x/c", "ab/c", "abc", "w[1]", "w1", "s p/x",
+            ] {
+                files_mgr
+                    .write_file(
+                        &files_mgr.to_zfile(zpath),
+                        ZBuf::from(zpath.as_bytes().to_vec()),
+                        &Encoding::TEXT_PLAIN,
+                        &new_reception_timestamp(),
+                    )
+                    .await
+                    .unwrap();
+            }
+            let matching = |files_mgr: &FilesMgr, expr: &str| {
+                let mut zpaths: Vec<String> = files_mgr
+                    .matching_files(keyexpr::new(expr).unwrap())
+                    .map(|zfile| zfile.zpath.to_string())
+                    .collect();
+                zpaths.sort();
+                zpaths
+            };
+            let exprs = [
+                "**", "a", "a/**", "a/*", "a/*/c", "*/b/**", "*/c", "a$*", "a$*/**", "w[1]",
+                "s p/*", "nope/**",
+            ];
+            let walked: Vec<Vec<String>> = exprs.iter().map(|e| matching(&files_mgr, e)).collect();
+            files_mgr.glob_mode = true;
+            for (expr, walked) in exprs.iter().zip(walked) {
+                assert_eq!(matching(&files_mgr, expr), walked, "{}", expr);
+            }
+            assert_eq!(matching(&files_mgr, "a/*/c"), vec!["a/b/c", "a/x/c"]);
+            assert_eq!(matching(&files_mgr, "*/c"), vec!["ab/c"]);
+            assert_eq!(matching(&files_mgr, "w[1]"), vec!["w[1]"]);
+            assert_eq!(matching(&files_mgr, "s p/*"), vec!["s p/x"]);
+        })
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn written_files_and_created_dirs_get_the_configured_modes() {
+        use std::os::unix::fs::PermissionsExt;
+        task::block_on(async {
+            let dir = tempfile::tempdir().unwrap();
+            let mut files_mgr = test_files_mgr(dir.path()).await;
+            files_mgr.file_mode = Some(0o600);
+            files_mgr.dir_mode = Some(0o750);
+            files_mgr.strip_extensions = vec![".json".to_string()];
+            let mode = |path: &Path| metadata(path).unwrap().permissions().mode() & 0o7777;
+            for (content, encoding) in [("text", Encoding::TEXT_PLAIN), ("{}", Encoding::APP_JSON)]
+            {
+                files_mgr
+                    .write_file(
+                        &files_mgr.to_zfile("d/e/f"),
+                        ZBuf::from(content.as_bytes().to_vec()),
+                        &encoding,
+                        &new_reception_timestamp(),
+                    )
+                    .await
+                    .unwrap();
+            }
+            // the file written with the extension of its encoding, replacing the one without extension
+            let file = dir.path().join("d/e/f.json");
+            assert!(!dir.path().join("d/e/f").exists());
+            assert_eq!(mode(&file), 0o600);
+            assert_eq!(mode(&dir.path().join("d")), 0o750);
+            assert_eq!(mode(&dir.path().join("d/e")), 0o750);
+        })
+    }
+
+    #[test]
+    fn missing_files_and_files_without_data_info_are_handled_deterministically() {
+        task::block_on(async {
+            for policy in [
+                OnMissingFile::Absent,
+                OnMissingFile::Log,
+                OnMissingFile::Tombstone,
+            ] {
+                let dir = tempfile::tempdir().unwrap();
+                let mut files_mgr = test_files_mgr(dir.path()).await;
+                files_mgr.on_missing_file = policy;
+                // a data-info without file: the file was removed by another process
+                let zfile = files_mgr.to_zfile("a");
+                let put_ts = new_reception_timestamp();
+                files_mgr
+                    .write_file(
+                        &zfile,
+                        ZBuf::from(b"a".to_vec()),
+                        &Encoding::TEXT_PLAIN,
+                        &put_ts,
+                    )
+                    .await
+                    .unwrap();
+                std::fs::remove_file(&zfile.fspath).unwrap();
+                assert!(files_mgr.read_file(&zfile).await.unwrap().is_none());
+                let data_info = files_mgr
+                    .data_info_mgr
+                    .get_encoding_and_timestamp(&zfile.fspath)
+                    .await
+                    .unwrap()
+                    .unwrap();
+                if let OnMissingFile::Tombstone = files_mgr.on_missing_file {
+                    assert_eq!(data_info.0, Encoding::from(KnownEncoding::Empty));
+                    assert!(data_info.1 > put_ts);
+                    // the key is now a deleted key, and is still absent
+                    assert!(files_mgr.read_file(&zfile).await.unwrap().is_none());
+                } else {
+                    assert_eq!(data_info, (Encoding::TEXT_PLAIN, put_ts));
+                }
+
+                // a file without data-info: placed by another process
+                let placed = dir.path().join("b");
+                std::fs::write(&placed, "b").unwrap();
+                let (value, timestamp) = files_mgr
+                    .read_file(&files_mgr.to_zfile("b"))
+                    .await
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(value.payload.contiguous().as_ref(), b"b");
+                assert_eq!(
+                    timestamp,
+                    timestamp_from_metadata(&metadata(&placed).unwrap())
+                );
+                assert_eq!(
+                    files_mgr
+                        .data_info_mgr
+                        .get_timestamp(&placed)
+                        .await
+                        .unwrap(),
+                    None
+                );
+            }
+        })
+    }
+
+    #[test]
+    fn files_are_read_whatever_the_io_advice() {
+        task::block_on(async {
+            let dir = tempfile::tempdir().unwrap();
+            let mut files_mgr = test_files_mgr(dir.path()).await;
+            let content = b"0123456789".repeat(100_000);
+            let zfile = files_mgr.to_zfile("a");
+            files_mgr
+                .write_file(
+                    &zfile,
+                    ZBuf::from(content.clone()),
+                    &Encoding::APP_OCTET_STREAM,
+                    &new_reception_timestamp(),
+                )
+                .await
+                .unwrap();
+            for advice in [IoAdvice::Normal, IoAdvice::Sequential, IoAdvice::Random] {
+                files_mgr.io_advice = advice;
+                let (value, _) = files_mgr.read_file(&zfile).await.unwrap().unwrap();
+                assert_eq!(value.payload.contiguous().as_ref(), content.as_slice());
+                // the bulk read path, releasing the pages once read
+                let mut pinned = files_mgr.pin(&zfile).await.unwrap().unwrap();
+                let (value, _) = files_mgr
+                    .read_pinned(&mut pinned, None, false)
+                    .await
+                    .unwrap();
+                files_mgr.release_bulk_read(&pinned);
+                assert_eq!(value.payload.contiguous().as_ref(), content.as_slice());
+                // the file can still be read once its pages are released
+                let (value, _) = files_mgr.read_file(&zfile).await.unwrap().unwrap();
+                assert_eq!(value.payload.contiguous().as_ref(), content.as_slice());
+            }
+        })
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    #[ignore = "mounts a tmpfs, which requires CAP_SYS_ADMIN (e.g. running as root): run with --ignored"]
+    fn writes_on_a_full_disk_fail_to_be_retried_and_keep_the_stored_files() {
+        use crate::tmpfs::{Tmpfs, DEFAULT_TMPFS_SIZE};
+        task::block_on(async {
+            let tmpfs = Tmpfs::mount(DEFAULT_TMPFS_SIZE);
+            let files_mgr = test_files_mgr(tmpfs.path()).await;
+            let zfile = files_mgr.to_zfile("a");
+            let write = |content: Vec<u8>| {
+                let (files_mgr, zfile) = (&files_mgr, &zfile);
+                async move {
+                    files_mgr
+                        .write_file(
+                            zfile,
+                            ZBuf::from(content),
+                            &Encoding::APP_OCTET_STREAM,
+                            &new_reception_timestamp(),
+                        )
+                        .await
+                }
+            };
+            write(b"stored".to_vec()).await.unwrap();
+            let (_, stored_ts) = files_mgr.read_file(&zfile).await.unwrap().unwrap();
+
+            let too_big = vec![0u8; 2 * DEFAULT_TMPFS_SIZE as usize];
+            let error = write(too_big).await.err().unwrap();
+            assert!(is_retry_later(&error), "{}", error);
+            // the stored file and its data-info are unchanged, and no temporary file is left
+            let (value, ts) = files_mgr.read_file(&zfile).await.unwrap().unwrap();
+            assert_eq!(value.payload.contiguous().as_ref(), b"stored");
+            assert_eq!(ts, stored_ts);
+            assert!(!std::fs::read_dir(tmpfs.path()).unwrap().any(|entry| entry
+                .unwrap()
+                .file_name()
+                .to_string_lossy()
+                .starts_with(WRITE_TMP_PREFIX)));
+
+            // the space of the failed write is released
+            write(vec![1u8; DEFAULT_TMPFS_SIZE as usize / 2])
+                .await
+                .unwrap();
+            let (value, _) = files_mgr.read_file(&zfile).await.unwrap().unwrap();
+            assert_eq!(
+                value.payload.contiguous().len(),
+                DEFAULT_TMPFS_SIZE as usize / 2
+            );
+        })
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn mapped_files_are_remapped_when_rewritten_or_renamed_over() {
+        task::block_on(async {
+            let dir = tempfile::tempdir().unwrap();
+            let mut files_mgr = test_files_mgr(dir.path()).await;
+            files_mgr.mapped_files = Some(Arc::new(MappedFiles::new(4)));
+            let mapped_files = files_mgr.mapped_files.clone().unwrap();
+            let zfile = files_mgr.to_zfile("a");
+            async fn read(files_mgr: &FilesMgr) -> Vec<u8> {
+                let zfile = files_mgr.to_zfile("a");
+                let (value, _) = files_mgr.read_file(&zfile).await.unwrap().unwrap();
+                value.payload.contiguous().to_vec()
+            }
+            files_mgr
+                .write_file(
+                    &zfile,
+                    ZBuf::from(b"first".to_vec()),
+                    &Encoding::APP_OCTET_STREAM,
+                    &new_reception_timestamp(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(read(&files_mgr).await, b"first");
+            assert_eq!(mapped_files.len(), 1);
+            // a file held by its mapping (e.g. by a query) is still read as it was, once rewritten via zenoh
+            let mut pinned = files_mgr.pin(&zfile).await.unwrap().unwrap();
+            files_mgr
+                .write_file(
+                    &zfile,
+                    ZBuf::from(b"second".to_vec()),
+                    &Encoding::APP_OCTET_STREAM,
+                    &new_reception_timestamp(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(mapped_files.len(), 0);
+            let (value, _) = files_mgr
+                .read_pinned(&mut pinned, None, false)
+                .await
+                .unwrap();
+            assert_eq!(value.payload.contiguous().as_ref(), b"first");
+            drop(pinned);
+            assert_eq!(read(&files_mgr).await, b"second");
+            // a file renamed over outside of zenoh is re-mapped
+            let other = dir.path().join("other");
+            std::fs::write(&other, b"third").unwrap();
+            std::fs::rename(&other, &zfile.fspath).unwrap();
+            assert_eq!(read(&files_mgr).await, b"third");
+            assert_eq!(mapped_files.len(), 1);
+            // the files smaller than the minimum size are not mapped
+            let small = files_mgr.to_zfile("b");
+            files_mgr
+                .write_file(
+                    &small,
+                    ZBuf::from(b"b".to_vec()),
+                    &Encoding::APP_OCTET_STREAM,
+                    &new_reception_timestamp(),
+                )
+                .await
+                .unwrap();
+            let (value, _) = files_mgr.read_file(&small).await.unwrap().unwrap();
+            assert_eq!(value.payload.contiguous().as_ref(), b"b");
+            assert_eq!(mapped_files.len(), 1);
+            // a deleted file is unmapped
+            files_mgr
+                .delete_file(&zfile, &new_reception_timestamp())
+                .await
+                .unwrap();
+            assert_eq!(mapped_files.len(), 0);
+            assert!(files_mgr.read_file(&zfile).await.unwrap().is_none());
+        })
+    }
+
+    #[test]
+    fn the_keys_tree_is_traversed_from_the_root_to_the_leaves() {
+        task::block_on(async {
+            let dir = tempfile::tempdir().unwrap();
+            let files_mgr = test_files_mgr(dir.path()).await;
+            // "a/b" is both a stored key and a prefix of stored keys
+            let leaves = ["a/b", "a/b/c", "a/b/d/e", "a/f", "g"];
+            for zpath in leaves {
+                files_mgr
+                    .write_file(
+                        &files_mgr.to_zfile(zpath),
+                        ZBuf::from(zpath.as_bytes().to_vec()),
+                        &Encoding::APP_OCTET_STREAM,
+                        &new_reception_timestamp(),
+                    )
+                    .await
+                    .unwrap();
+            }
+            let root = files_mgr.to_zfile("");
+            assert!(root.parent(&files_mgr).is_none());
+            let mut found = Vec::new();
+            let mut visited = Vec::new();
+            let mut to_visit = vec![root];
+            while let Some(zfile) = to_visit.pop() {
+                let children = zfile.children(&files_mgr).await.unwrap();
+                // (a stored key which is also a prefix of other keys is stored in its conflict file)
+                if !zfile.zpath.is_empty() && files_mgr.read_file(&zfile).await.unwrap().is_some() {
+                    found.push(zfile.zpath.to_string());
+                }
+                for child in &children {
+                    assert_eq!(child.parent(&files_mgr).unwrap().zpath, zfile.zpath);
+                }
+                visited.push(zfile.zpath.to_string());
+                to_visit.extend(children);
+            }
+            found.sort();
+            assert_eq!(found, leaves);
+            // each key is visited once
+            visited.sort();
+            assert_eq!(
+                visited,
+                vec!["", "a", "a/b", "a/b/c", "a/b/d", "a/b/d/e", "a/f", "g"]
+            );
+            assert!(files_mgr
+                .to_zfile("g")
+                .children(&files_mgr)
+                .await
+                .unwrap()
+                .is_empty());
+        })
+    }
+
+    #[test]
+    fn writes_create_their_temporary_file_in_the_temp_dir() {
+        task::block_on(async {
+            let dir = tempfile::tempdir().unwrap();
+            let temp_dir = tempfile::tempdir().unwrap();
+            let mut files_mgr = test_files_mgr(dir.path()).await;
+            files_mgr.temp_dir = Some(temp_dir.path().to_path_buf());
+            let zfile = files_mgr.to_zfile("a");
+            async fn write(files_mgr: &FilesMgr, content: &[u8]) -> ZResult<()> {
+                files_mgr
+                    .write_file(
+                        &files_mgr.to_zfile("a"),
+                        ZBuf::from(content.to_vec()),
+                        &Encoding::APP_OCTET_STREAM,
+                        &new_reception_timestamp(),
+                    )
+                    .await
+            }
+            // the temporary file is created then renamed from temp_dir, thus modifying it
+            let modified = || metadata(temp_dir.path()).unwrap().modified().unwrap();
+            let before = modified();
+            std::thread::sleep(Duration::from_millis(50));
+            write(&files_mgr, b"first").await.unwrap();
+            assert!(modified() > before);
+            assert_eq!(std::fs::read(&zfile.fspath).unwrap(), b"first");
+            assert_eq!(temp_dir.path().read_dir().unwrap().count(), 0);
+            // a missing temp_dir makes the write fail, on the temporary file in temp_dir
+            let missing = temp_dir.path().join("missing");
+            files_mgr.temp_dir = Some(missing.clone());
+            let error = write(&files_mgr, b"second").await.unwrap_err().to_string();
+            assert!(error.contains(missing.to_str().unwrap()), "{}", error);
+            assert_eq!(std::fs::read(&zfile.fspath).unwrap(), b"first");
+        })
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    #[ignore = "mounts a tmpfs, which requires CAP_SYS_ADMIN (e.g. running as root): run with --ignored"]
+    fn files_on_another_file_system_than_the_temp_dir_are_written_from_their_directory() {
+        use crate::tmpfs::Tmpfs;
+        let temp_dir = Tmpfs::mount(1024 * 1024);
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("a");
+        let content = ZBuf::from(b"content".to_vec());
+        write_replacing(&file, &content, None, false, Some(temp_dir.path())).unwrap();
+        assert_eq!(std::fs::read(&file).unwrap(), b"content");
+        // no temporary file is left
+        assert_eq!(temp_dir.path().read_dir().unwrap().count(), 0);
+        assert_eq!(dir.path().read_dir().unwrap().count(), 1);
+    }
 }
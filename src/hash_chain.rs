@@ -0,0 +1,406 @@
+//
+// Copyright (c) 2022 ZettaScale Technology
+//
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+// Contributors:
+//   ZettaScale Zenoh Team, <zenoh@zettascale.tech>
+//
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{read_dir, rename, File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::warn;
+use zenoh::buffers::ZBuf;
+use zenoh::time::Timestamp;
+use zenoh::Result as ZResult;
+use zenoh_core::{bail, zerror};
+
+// Name of the file where the hash chain is logged, in the storage directory
+pub(crate) const HASH_CHAIN_FILENAME: &str = ".zenoh_hash_chain";
+
+// Size of the log above which it's archived (as "<HASH_CHAIN_FILENAME>.<index of its first entry>")
+// and the chain continues in a new log
+const HASH_CHAIN_SEGMENT_BYTES: u64 = 64 * 1024 * 1024;
+
+// The head of an empty chain
+const GENESIS_HEAD: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+// A rolling SHA-256 hash chain of the writes of a storage ("hash_chain"), for tamper-evidence.
+// Each PUT or DELETE appends an entry to the chain log, whose hash covers the previous head, the key,
+// the timestamp and the SHA-256 of the written content (empty for a DELETE).
+// The last head is the root of the whole history: altering any entry changes all the following heads.
+pub(crate) struct HashChain {
+    file: PathBuf,
+    segment_bytes: u64,
+    state: Mutex<ChainState>,
+}
+
+struct ChainState {
+    length: u64,
+    head: String,
+    // index of the first entry of the log (the previous ones are in the archived logs)
+    first_index: u64,
+    // size of the log
+    size: u64,
+    // offset in the log of the last write on each key
+    last_writes: HashMap<String, u64>,
+}
+
+impl HashChain {
+    // Open (or create) the chain log of a storage directory, verifying the existing entries
+    pub(crate) fn open(base_dir: &Path) -> ZResult<Self> {
+        Self::open_with_segment_bytes(base_dir, HASH_CHAIN_SEGMENT_BYTES)
+    }
+
+    fn open_with_segment_bytes(base_dir: &Path, segment_bytes: u64) -> ZResult<Self> {
+        let file = base_dir.join(HASH_CHAIN_FILENAME);
+        let mut state = ChainState {
+            length: 0,
+            head: GENESIS_HEAD.to_string(),
+            first_index: 0,
+            size: 0,
+            last_writes: HashMap::new(),
+        };
+        if file.exists() {
+            let f = File::open(&file)
+                .map_err(|e| zerror!("Failed to open hash chain {:?}: {}", file, e))?;
+            let mut reader = BufReader::new(f);
+            let mut line = Vec::new();
+            loop {
+                line.clear();
+                let read = reader
+                    .read_until(b'\n', &mut line)
+                    .map_err(|e| zerror!("Failed to read hash chain {:?}: {}", file, e))?;
+                if read == 0 {
+                    break;
+                }
+                let entry = match Some(&line).filter(|l| l.ends_with(b"\n")).and_then(|l| parse_entry(l)) {
+                    Some(entry) => entry,
+                    None if reader.fill_buf().map(|b| b.is_empty()).unwrap_or(false) => {
+                        // the last line was torn by a crash while it was appended: truncate it
+                        warn!(
+                            "Hash chain {:?}: truncating its last entry, which is incomplete",
+                            file
+                        );
+                        OpenOptions::new()
+                            .write(true)
+                            .open(&file)
+                            .and_then(|f| f.set_len(state.size))
+                            .map_err(|e| zerror!("Failed to truncate hash chain {:?}: {}", file, e))?;
+                        break;
+                    }
+                    None => bail!(
+                        "Invalid entry in hash chain {:?} at entry {}: it was modified outside of the storage",
+                        file,
+                        state.length
+                    ),
+                };
+                if state.size == 0 && entry.index > 0 {
+                    // the log continues the archived ones
+                    state.length = entry.index;
+                    state.head = entry.previous.clone();
+                    state.first_index = entry.index;
+                }
+                let head = chain_head(&state.head, &entry.key, &entry.timestamp, &entry.content);
+                if entry.index != state.length || entry.previous != state.head || entry.head != head
+                {
+                    bail!(
+                        "Hash chain {:?} is broken at entry {}: it was modified outside of the storage",
+                        file,
+                        state.length
+                    );
+                }
+                state.last_writes.insert(entry.key, state.size);
+                state.length += 1;
+                state.head = head;
+                state.size += read as u64;
+            }
+        }
+        if state.size == 0 {
+            // the log might have just been archived
+            if let Some(entry) = last_archived_entry(base_dir)? {
+                state.length = entry.index + 1;
+                state.head = entry.head;
+                state.first_index = state.length;
+            }
+        }
+        Ok(HashChain {
+            file,
+            segment_bytes,
+            state: Mutex::new(state),
+        })
+    }
+
+    // Append a write to the chain, with the SHA-256 of the written content (None for a DELETE)
+    pub(crate) fn append(
+        &self,
+        key: &str,
+        timestamp: &Timestamp,
+        content_sha256: Option<String>,
+    ) -> ZResult<()> {
+        self.append_entry(
+            key,
+            timestamp.to_string(),
+            content_sha256.unwrap_or_default(),
+        )
+    }
+
+    fn append_entry(&self, key: &str, timestamp: String, content: String) -> ZResult<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.size >= self.segment_bytes {
+            // archive the log, not to re-read it at each start
+            let archive = self
+                .file
+                .with_file_name(format!("{}.{}", HASH_CHAIN_FILENAME, state.first_index));
+            match rename(&self.file, &archive) {
+                Ok(()) => {
+                    state.first_index = state.length;
+                    state.size = 0;
+                    state.last_writes.clear();
+                }
+                Err(e) => warn!("Failed to archive hash chain {:?}: {}", self.file, e),
+            }
+        }
+        let head = chain_head(&state.head, key, &timestamp, &content);
+        let entry = ChainEntry {
+            index: state.length,
+            key: key.to_string(),
+            timestamp,
+            content,
+            previous: state.head.clone(),
+            head: head.clone(),
+        };
+        let mut line = entry.to_json().to_string();
+        line.push('\n');
+        let mut f = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.file)
+            .map_err(|e| zerror!("Failed to append to hash chain {:?}: {}", self.file, e))?;
+        if let Err(e) = f.write_all(line.as_bytes()) {
+            // don't leave a partial entry, which the following ones would be appended to
+            let _ = f.set_len(state.size);
+            bail!("Failed to append to hash chain {:?}: {}", self.file, e);
+        }
+        let offset = state.size;
+        state.last_writes.insert(entry.key, offset);
+        state.length += 1;
+        state.head = head;
+        state.size += line.len() as u64;
+        Ok(())
+    }
+
+    pub(crate) fn status(&self) -> serde_json::Value {
+        let state = self.state.lock().unwrap();
+        serde_json::json!({
+            "algorithm": "sha256",
+            "length": state.length,
+            "root": state.head,
+        })
+    }
+
+    // Return the proof of the last write on a key: the chain entries from this write up to the current root,
+    // allowing to recompute the root from the entry. None if the key wasn't written since the log was archived.
+    pub(crate) fn proof(&self, key: &str) -> ZResult<Option<serde_json::Value>> {
+        // hold the lock, not to read an entry being appended
+        let state = self.state.lock().unwrap();
+        let offset = match state.last_writes.get(key) {
+            Some(offset) => *offset,
+            None => return Ok(None),
+        };
+        let mut f = File::open(&self.file)
+            .map_err(|e| zerror!("Failed to open hash chain {:?}: {}", self.file, e))?;
+        f.seek(SeekFrom::Start(offset))
+            .map_err(|e| zerror!("Failed to read hash chain {:?}: {}", self.file, e))?;
+        let mut entries = Vec::new();
+        for line in BufReader::new(f).take(state.size - offset).split(b'\n') {
+            let line =
+                line.map_err(|e| zerror!("Failed to read hash chain {:?}: {}", self.file, e))?;
+            match parse_entry(&line) {
+                Some(entry) => entries.push(entry.to_json()),
+                None => bail!(
+                    "Invalid entry in hash chain {:?}: {}",
+                    self.file,
+                    String::from_utf8_lossy(&line)
+                ),
+            }
+        }
+        Ok(Some(serde_json::json!({
+            "algorithm": "sha256",
+            "root": state.head,
+            "entries": entries,
+        })))
+    }
+}
+
+struct ChainEntry {
+    index: u64,
+    key: String,
+    timestamp: String,
+    content: String,
+    previous: String,
+    head: String,
+}
+
+impl ChainEntry {
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "index": self.index,
+            "key": self.key,
+            "timestamp": self.timestamp,
+            "content_sha256": self.content,
+            "previous": self.previous,
+            "head": self.head,
+        })
+    }
+
+    fn from_json(value: &serde_json::Value) -> Option<Self> {
+        let field = |name: &str| value.get(name)?.as_str().map(str::to_string);
+        Some(ChainEntry {
+            index: value.get("index")?.as_u64()?,
+            key: field("key")?,
+            timestamp: field("timestamp")?,
+            content: field("content_sha256")?,
+            previous: field("previous")?,
+            head: field("head")?,
+        })
+    }
+}
+
+// The last entry of the most recent archived log, if any
+fn last_archived_entry(base_dir: &Path) -> ZResult<Option<ChainEntry>> {
+    let prefix = format!("{}.", HASH_CHAIN_FILENAME);
+    let archive = read_dir(base_dir)
+        .map_err(|e| zerror!("Failed to list directory {:?}: {}", base_dir, e))?
+        .filter_map(|entry| {
+            let name = entry.ok()?.file_name().into_string().ok()?;
+            name.strip_prefix(&prefix)?.parse::<u64>().ok()
+        })
+        .max();
+    let file = match archive {
+        Some(first_index) => base_dir.join(format!("{}{}", prefix, first_index)),
+        None => return Ok(None),
+    };
+    let f =
+        File::open(&file).map_err(|e| zerror!("Failed to open hash chain {:?}: {}", file, e))?;
+    let mut last = None;
+    for line in BufReader::new(f).split(b'\n') {
+        let line = line.map_err(|e| zerror!("Failed to read hash chain {:?}: {}", file, e))?;
+        last = Some(line);
+    }
+    match last.as_deref().and_then(parse_entry) {
+        Some(entry) => Ok(Some(entry)),
+        None => bail!("Invalid last entry in archived hash chain {:?}", file),
+    }
+}
+
+fn parse_entry(line: &[u8]) -> Option<ChainEntry> {
+    serde_json::from_slice(line)
+        .ok()
+        .as_ref()
+        .and_then(ChainEntry::from_json)
+}
+
+pub(crate) fn content_sha256(payload: &ZBuf) -> String {
+    let mut hasher = Sha256::new();
+    for slice in payload.slices() {
+        hasher.update(slice);
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+// The head following `previous` after a write
+fn chain_head(previous: &str, key: &str, timestamp: &str, content: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(previous.as_bytes());
+    hasher.update(b"\n");
+    hasher.update(key.as_bytes());
+    hasher.update(b"\0");
+    hasher.update(timestamp.as_bytes());
+    hasher.update(b"\0");
+    hasher.update(content.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(chain: &HashChain, key: &str, i: u64) {
+        chain
+            .append_entry(key, format!("ts{}", i), format!("sha{}", i))
+            .unwrap();
+    }
+
+    fn root(chain: &HashChain) -> serde_json::Value {
+        chain.status()["root"].clone()
+    }
+
+    #[test]
+    fn a_torn_last_entry_is_truncated() {
+        let dir = tempfile::tempdir().unwrap();
+        let chain = HashChain::open(dir.path()).unwrap();
+        write(&chain, "a", 0);
+        write(&chain, "b", 1);
+        let file = dir.path().join(HASH_CHAIN_FILENAME);
+        let size = std::fs::metadata(&file).unwrap().len();
+        let mut f = OpenOptions::new().append(true).open(&file).unwrap();
+        f.write_all(br#"{"index":2,"key":"c","#).unwrap();
+
+        let reopened = HashChain::open(dir.path()).unwrap();
+        assert_eq!(std::fs::metadata(&file).unwrap().len(), size);
+        assert_eq!(root(&reopened), root(&chain));
+        write(&reopened, "c", 2);
+        assert_eq!(HashChain::open(dir.path()).unwrap().status()["length"], 3);
+    }
+
+    #[test]
+    fn a_modified_entry_is_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let chain = HashChain::open(dir.path()).unwrap();
+        write(&chain, "a", 0);
+        write(&chain, "b", 1);
+        let file = dir.path().join(HASH_CHAIN_FILENAME);
+        let log = std::fs::read_to_string(&file).unwrap();
+        std::fs::write(&file, log.replacen("sha0", "sha9", 1)).unwrap();
+        assert!(HashChain::open(dir.path()).is_err());
+        // an invalid entry which is not the last one is not truncated
+        std::fs::write(&file, log.replacen("{", "#", 1)).unwrap();
+        assert!(HashChain::open(dir.path()).is_err());
+    }
+
+    #[test]
+    fn the_log_is_archived_when_full() {
+        let dir = tempfile::tempdir().unwrap();
+        let chain = HashChain::open_with_segment_bytes(dir.path(), 1000).unwrap();
+        for i in 0..20 {
+            write(&chain, if i == 0 { "a" } else { "b" }, i);
+        }
+        assert!(dir
+            .path()
+            .join(format!("{}.0", HASH_CHAIN_FILENAME))
+            .exists());
+        let proof = chain.proof("b").unwrap().unwrap();
+        assert_eq!(proof["entries"].as_array().unwrap().len(), 1);
+        assert_eq!(proof["entries"][0]["head"], proof["root"]);
+        // the write on "a" is in an archived log
+        assert!(chain.proof("a").unwrap().is_none());
+
+        // the chain continues from the archived logs
+        let reopened = HashChain::open_with_segment_bytes(dir.path(), 1000).unwrap();
+        assert_eq!(reopened.status(), chain.status());
+        // (as if it stopped right after archiving its log)
+        let first_index = chain.state.lock().unwrap().first_index;
+        std::fs::remove_file(dir.path().join(HASH_CHAIN_FILENAME)).unwrap();
+        let reopened = HashChain::open_with_segment_bytes(dir.path(), 1000).unwrap();
+        assert_eq!(reopened.status()["length"], first_index);
+    }
+}
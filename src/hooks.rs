@@ -0,0 +1,164 @@
+//
+// Copyright (c) 2022 ZettaScale Technology
+//
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+// Contributors:
+//   ZettaScale Zenoh Team, <zenoh@zettascale.tech>
+//
+use async_trait::async_trait;
+use zenoh::prelude::*;
+use zenoh::Result as ZResult;
+
+/// Hooks called by the File System Storages, for the users embedding the backend as a library
+/// (see [`create_volume_with_hooks`](crate::create_volume_with_hooks)).
+/// Each hook can modify the value, or veto the operation by returning an error.
+/// The default implementations accept everything unchanged.
+#[async_trait]
+pub trait StorageHooks: Send + Sync {
+    /// Called before a PUT is stored, with its key and value. The returned value is stored.
+    async fn before_put(&self, _key: &keyexpr, value: Value) -> ZResult<Value> {
+        Ok(value)
+    }
+
+    /// Called before a DELETE is applied, with its key.
+    async fn before_delete(&self, _key: &keyexpr) -> ZResult<()> {
+        Ok(())
+    }
+
+    /// Called before a stored value is replied to a query, with its key. The returned value is replied.
+    /// If an error is returned, the value is not replied. Also called with the JSON metadata replied for a key
+    /// (e.g. for a `_meta` query), and with an empty value for a deleted key replied to a `_deleted` query.
+    async fn before_reply(&self, _key: &keyexpr, value: Value) -> ZResult<Value> {
+        Ok(value)
+    }
+}
+
+// Pass a value to reply on a key to the "before_reply" hook, if any: the value to reply, or an error if vetoed
+pub(crate) async fn before_reply(
+    hooks: Option<&dyn StorageHooks>,
+    key: &keyexpr,
+    value: Value,
+) -> ZResult<Value> {
+    match hooks {
+        Some(hooks) => hooks.before_reply(key, value).await,
+        None => Ok(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zenoh_core::bail;
+
+    // Veto the replies on the "secret/**" keys, and redact the "user/*/password" values
+    struct Redaction;
+
+    #[async_trait]
+    impl StorageHooks for Redaction {
+        async fn before_reply(&self, key: &keyexpr, value: Value) -> ZResult<Value> {
+            if key.as_str().starts_with("secret/") {
+                bail!("redacted");
+            }
+            if key.as_str().ends_with("/password") {
+                return Ok(Value::new(b"***".to_vec().into()).encoding(value.encoding));
+            }
+            Ok(value)
+        }
+    }
+
+    fn meta(key: &str) -> Value {
+        let meta = serde_json::json!({"key": key, "size": 42, "etag": "0-0"});
+        Value::new(meta.to_string().into_bytes().into()).encoding(KnownEncoding::AppJson.into())
+    }
+
+    #[test]
+    fn replies_are_redacted() {
+        async_std::task::block_on(async {
+            let hooks = Redaction;
+            let key = |k: &'static str| keyexpr::new(k).unwrap();
+            // a metadata reply on a vetoed key is not replied
+            assert!(
+                before_reply(Some(&hooks), key("secret/a"), meta("secret/a"))
+                    .await
+                    .is_err()
+            );
+            // a metadata reply is redacted as a value reply
+            let redacted = before_reply(
+                Some(&hooks),
+                key("user/a/password"),
+                meta("user/a/password"),
+            )
+            .await
+            .unwrap();
+            assert_eq!(redacted.payload.contiguous().as_ref(), b"***");
+            let replied = before_reply(Some(&hooks), key("user/a/name"), meta("user/a/name"))
+                .await
+                .unwrap();
+            assert_eq!(
+                replied.payload.contiguous(),
+                meta("user/a/name").payload.contiguous()
+            );
+            // without hooks, everything is replied
+            assert!(before_reply(None, key("secret/a"), meta("secret/a"))
+                .await
+                .is_ok());
+        })
+    }
+
+    // Mask every occurrence of a byte pattern in the replied payloads
+    struct PatternRedaction(&'static [u8]);
+
+    #[async_trait]
+    impl StorageHooks for PatternRedaction {
+        async fn before_reply(&self, _key: &keyexpr, value: Value) -> ZResult<Value> {
+            let mut payload = value.payload.contiguous().to_vec();
+            let len = self.0.len();
+            let mut i = 0;
+            while i + len <= payload.len() {
+                if &payload[i..i + len] == self.0 {
+                    payload[i..i + len].iter_mut().for_each(|b| *b = b'*');
+                    i += len;
+                } else {
+                    i += 1;
+                }
+            }
+            Ok(Value::new(payload.into()).encoding(value.encoding))
+        }
+    }
+
+    #[test]
+    fn byte_patterns_are_redacted_from_the_replied_payloads() {
+        async_std::task::block_on(async {
+            let hooks = PatternRedaction(b"s3cr3t");
+            let key = keyexpr::new("a/b").unwrap();
+            let value = |payload: &[u8]| {
+                Value::new(payload.to_vec().into()).encoding(KnownEncoding::AppOctetStream.into())
+            };
+            let redacted = before_reply(Some(&hooks), key, value(b"\x00s3cr3t\xffs3cr3ts3cr"))
+                .await
+                .unwrap();
+            assert_eq!(
+                redacted.payload.contiguous().as_ref(),
+                b"\x00******\xff******s3cr"
+            );
+            assert_eq!(
+                redacted.encoding,
+                Encoding::from(KnownEncoding::AppOctetStream)
+            );
+            // payloads without the pattern are replied unchanged
+            let replied = before_reply(Some(&hooks), key, value(b"public"))
+                .await
+                .unwrap();
+            assert_eq!(replied.payload.contiguous().as_ref(), b"public");
+            // without hooks, the pattern is replied
+            let replied = before_reply(None, key, value(b"s3cr3t")).await.unwrap();
+            assert_eq!(replied.payload.contiguous().as_ref(), b"s3cr3t");
+        })
+    }
+}
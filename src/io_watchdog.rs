@@ -0,0 +1,244 @@
+//
+// Copyright (c) 2022 ZettaScale Technology
+//
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+// Contributors:
+//   ZettaScale Zenoh Team, <zenoh@zettascale.tech>
+//
+use async_std::future::timeout;
+use async_std::task;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::warn;
+use zenoh::Result as ZResult;
+use zenoh_core::bail;
+
+use crate::backpressure::RetryLater;
+
+// Maximum number of file system operations of a storage in progress at once: beyond, an operation is refused
+// rather than occupying one more thread of the blocking pool shared with the other storages
+const MAX_IN_FLIGHT: usize = 64;
+
+// A watchdog of the file system operations of a storage ("io_deadline_ms").
+// Each operation runs on its own thread (from the blocking pool), thus a file system stall (e.g. a hung NFS mount)
+// blocks this thread only, and not the executor shared with the other storages. If an operation exceeds the deadline,
+// its caller gets an error and the storage is unhealthy: its next operations fail immediately (rather than stalling
+// more threads) until all the stalled operations completed.
+pub(crate) struct IoWatchdog {
+    deadline: Duration,
+    // the operations in progress, with their start time
+    in_flight: Arc<Mutex<HashMap<u64, (&'static str, Instant)>>>,
+    next_id: AtomicU64,
+}
+
+impl IoWatchdog {
+    pub(crate) fn new(deadline: Duration) -> Self {
+        IoWatchdog {
+            deadline,
+            in_flight: Arc::new(Mutex::new(HashMap::new())),
+            next_id: AtomicU64::new(0),
+        }
+    }
+
+    // Get the operations in progress for longer than the deadline, with their duration so far
+    pub(crate) fn stalled(&self) -> Vec<(&'static str, Duration)> {
+        self.in_flight
+            .lock()
+            .unwrap()
+            .values()
+            .map(|(op, start)| (*op, start.elapsed()))
+            .filter(|(_, elapsed)| *elapsed > self.deadline)
+            .collect()
+    }
+
+    // Fail if some operations are stalled
+    pub(crate) fn check(&self) -> ZResult<()> {
+        let stalled = self.stalled();
+        if let Some((op, elapsed)) = stalled.first() {
+            return Err(RetryLater::new(format!(
+                "the storage is unhealthy: {} file system operation(s) stalled (`{}` since {:?})",
+                stalled.len(),
+                op,
+                elapsed
+            ))
+            .into());
+        }
+        Ok(())
+    }
+
+    // Run a blocking operation on its own thread, failing with RetryLater if it exceeds the deadline
+    // (it keeps on running, and the storage is unhealthy until it completes)
+    pub(crate) async fn run<T, F>(&self, op: &'static str, f: F) -> ZResult<T>
+    where
+        F: FnOnce() -> ZResult<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        match self.run_with_deadline(op, f).await? {
+            Some(result) => result,
+            None => Err(RetryLater::new(format!(
+                "file system operation `{}` exceeded the deadline of {:?}",
+                op, self.deadline
+            ))
+            .into()),
+        }
+    }
+
+    // Run a write as run(), but if it exceeds the deadline it's not to be retried: it keeps on running
+    // and is applied once it completes
+    pub(crate) async fn run_write<T, F>(&self, op: &'static str, f: F) -> ZResult<T>
+    where
+        F: FnOnce() -> ZResult<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        match self.run_with_deadline(op, f).await? {
+            Some(result) => result,
+            None => bail!(
+                "file system operation `{}` exceeded the deadline of {:?}: it's still in progress, and is applied once it completes",
+                op,
+                self.deadline
+            ),
+        }
+    }
+
+    // Run a blocking operation on its own thread, returning None if it exceeds the deadline.
+    // It's refused if too many operations are already in progress.
+    async fn run_with_deadline<T, F>(&self, op: &'static str, f: F) -> ZResult<Option<ZResult<T>>>
+    where
+        F: FnOnce() -> ZResult<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.check()?;
+        let guard = {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            if in_flight.len() >= MAX_IN_FLIGHT {
+                return Err(RetryLater::new(format!(
+                    "the storage is overloaded: {} file system operations in progress",
+                    in_flight.len()
+                ))
+                .into());
+            }
+            let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+            in_flight.insert(id, (op, Instant::now()));
+            InFlight {
+                in_flight: self.in_flight.clone(),
+                id,
+            }
+        };
+        let handle = task::spawn_blocking(move || {
+            // (the operation is removed from the operations in progress even if it panics)
+            let _guard = guard;
+            f()
+        });
+        match timeout(self.deadline, handle).await {
+            Ok(result) => Ok(Some(result)),
+            Err(_) => {
+                warn!(
+                    "File system operation `{}` exceeded the deadline of {:?}: the storage is unhealthy until it completes",
+                    op, self.deadline
+                );
+                Ok(None)
+            }
+        }
+    }
+}
+
+// An operation in progress, until dropped
+struct InFlight {
+    in_flight: Arc<Mutex<HashMap<u64, (&'static str, Instant)>>>,
+    id: u64,
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.in_flight.lock().unwrap().remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stalled_operations_make_the_storage_unhealthy() {
+        task::block_on(async {
+            let watchdog = IoWatchdog::new(Duration::from_millis(50));
+            assert_eq!(watchdog.run("read", || Ok(1)).await.unwrap(), 1);
+            let (tx, rx) = std::sync::mpsc::channel::<()>();
+            let result = watchdog
+                .run_write("write", move || {
+                    rx.recv().ok();
+                    Ok(())
+                })
+                .await;
+            // a timed-out write is not to be retried, and the next operations fail fast until it completes
+            assert!(result.unwrap_err().downcast_ref::<RetryLater>().is_none());
+            assert_eq!(watchdog.stalled().len(), 1);
+            assert!(watchdog.run("read", || Ok(())).await.is_err());
+            tx.send(()).unwrap();
+            while !watchdog.in_flight.lock().unwrap().is_empty() {
+                task::sleep(Duration::from_millis(5)).await;
+            }
+            assert!(watchdog.run("read", || Ok(())).await.is_ok());
+        });
+    }
+
+    #[test]
+    fn panicking_operations_are_not_left_in_progress() {
+        let in_flight = Arc::new(Mutex::new(HashMap::new()));
+        in_flight
+            .lock()
+            .unwrap()
+            .insert(0, ("read", Instant::now()));
+        let guard = InFlight {
+            in_flight: in_flight.clone(),
+            id: 0,
+        };
+        let result = std::thread::spawn(move || {
+            let _guard = guard;
+            panic!("read failed");
+        })
+        .join();
+        assert!(result.is_err());
+        assert!(in_flight.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn operations_in_progress_are_bounded() {
+        task::block_on(async {
+            let watchdog = Arc::new(IoWatchdog::new(Duration::from_secs(5)));
+            let (tx, rx) = std::sync::mpsc::channel::<()>();
+            let rx = Arc::new(Mutex::new(rx));
+            let ops: Vec<_> = (0..MAX_IN_FLIGHT)
+                .map(|_| {
+                    let (watchdog, rx) = (watchdog.clone(), rx.clone());
+                    task::spawn(async move {
+                        watchdog
+                            .run("read", move || {
+                                rx.lock().unwrap().recv().ok();
+                                Ok(())
+                            })
+                            .await
+                    })
+                })
+                .collect();
+            while watchdog.in_flight.lock().unwrap().len() < MAX_IN_FLIGHT {
+                task::sleep(Duration::from_millis(5)).await;
+            }
+            assert!(watchdog.run("read", || Ok(())).await.is_err());
+            for _ in 0..MAX_IN_FLIGHT {
+                tx.send(()).unwrap();
+            }
+            for op in ops {
+                op.await.unwrap();
+            }
+        });
+    }
+}
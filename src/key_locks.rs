@@ -0,0 +1,124 @@
+//
+// Copyright (c) 2022 ZettaScale Technology
+//
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+// Contributors:
+//   ZettaScale Zenoh Team, <zenoh@zettascale.tech>
+//
+use async_std::sync::{Mutex as AsyncMutex, MutexGuardArc};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+// Per-file locks of a storage, serializing the writes on a same file by its background tasks (e.g. a bulk deletion)
+// and by its PUT and DELETE in "pessimistic" locking mode. A lock is removed once released by all its writers.
+#[derive(Clone, Default)]
+pub(crate) struct KeyLocks {
+    locks: Arc<Mutex<HashMap<PathBuf, Arc<AsyncMutex<()>>>>>,
+}
+
+impl KeyLocks {
+    // Wait until the lock on this file is acquired. It's released when the returned guard is dropped.
+    pub(crate) async fn lock(&self, file: &Path) -> KeyLockGuard {
+        let lock = self
+            .locks
+            .lock()
+            .unwrap()
+            .entry(file.to_path_buf())
+            .or_default()
+            .clone();
+        let guard = lock.lock_arc().await;
+        KeyLockGuard {
+            locks: self.locks.clone(),
+            file: file.to_path_buf(),
+            guard: Some(guard),
+        }
+    }
+
+    // Wait until the locks on several files are acquired, in the order of their paths so that 2 writers locking
+    // several files can't deadlock. They are released when the returned guards are dropped.
+    pub(crate) async fn lock_all(&self, files: &[&Path]) -> Vec<KeyLockGuard> {
+        let mut files = files.to_vec();
+        files.sort();
+        files.dedup();
+        let mut guards = Vec::with_capacity(files.len());
+        for file in files {
+            guards.push(self.lock(file).await);
+        }
+        guards
+    }
+}
+
+pub(crate) struct KeyLockGuard {
+    locks: Arc<Mutex<HashMap<PathBuf, Arc<AsyncMutex<()>>>>>,
+    file: PathBuf,
+    guard: Option<MutexGuardArc<()>>,
+}
+
+impl Drop for KeyLockGuard {
+    fn drop(&mut self) {
+        // release the lock before checking if it's still used
+        drop(self.guard.take());
+        let mut locks = self.locks.lock().unwrap();
+        // remove the lock if no other writer holds or waits for it (the map owns the only reference)
+        if let Some(lock) = locks.get(&self.file) {
+            if Arc::strong_count(lock) == 1 {
+                locks.remove(&self.file);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_std::task;
+
+    // 100 writers checking then updating a same value, yielding in between (as a PUT between its timestamp
+    // check and its write): with the lock, none of them interleaves
+    #[test]
+    fn concurrent_writers_are_serialized() {
+        task::block_on(async {
+            let locks = KeyLocks::default();
+            let stored = Arc::new(Mutex::new((0u64, 0u64)));
+            let writers: Vec<_> = (0..100u64)
+                .map(|i| {
+                    let (locks, stored) = (locks.clone(), stored.clone());
+                    task::spawn(async move {
+                        let _guard = locks.lock(Path::new("a/b")).await;
+                        let (timestamp, count) = *stored.lock().unwrap();
+                        task::yield_now().await;
+                        // the writers' timestamps are not in the order of their spawning
+                        let ts = (i * 37) % 100 + 1;
+                        *stored.lock().unwrap() = (timestamp.max(ts), count + 1);
+                    })
+                })
+                .collect();
+            for writer in writers {
+                writer.await;
+            }
+            // a single final value: the one with the highest timestamp, after all the writes
+            assert_eq!(*stored.lock().unwrap(), (100, 100));
+        });
+    }
+
+    #[test]
+    fn released_locks_are_removed() {
+        task::block_on(async {
+            let locks = KeyLocks::default();
+            let guard = locks.lock(Path::new("a")).await;
+            let other = locks.lock(Path::new("b")).await;
+            assert_eq!(locks.locks.lock().unwrap().len(), 2);
+            drop(guard);
+            assert_eq!(locks.locks.lock().unwrap().len(), 1);
+            drop(other);
+            assert!(locks.locks.lock().unwrap().is_empty());
+        });
+    }
+}
@@ -0,0 +1,299 @@
+//
+// Copyright (c) 2022 ZettaScale Technology
+//
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+// Contributors:
+//   ZettaScale Zenoh Team, <zenoh@zettascale.tech>
+//
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use zenoh::prelude::*;
+use zenoh::Result as ZResult;
+use zenoh_core::{bail, zerror};
+
+use crate::backpressure::RetryLater;
+use crate::files_mgt::FilesMgr;
+use crate::{extract_bool, extract_u64};
+
+// The options of a branch
+const BRANCH_READ_ONLY: &str = "read_only";
+const BRANCH_QUOTA_BYTES: &str = "quota_bytes";
+
+// A branch of a key schema: the keys matching a key expression, with their options and their usage
+pub(crate) struct SchemaBranch {
+    // the key expression as declared (possibly with "{a,b}" alternatives)
+    declared: String,
+    // the key expressions of the alternatives
+    key_exprs: Vec<OwnedKeyExpr>,
+    // if true, the PUT and DELETE on the branch's keys are refused
+    pub(crate) read_only: bool,
+    // maximum total size of the files stored for the branch's keys
+    pub(crate) quota_bytes: Option<u64>,
+    // number of files stored for the branch's keys, and their total size
+    keys: AtomicU64,
+    bytes: AtomicU64,
+}
+
+impl SchemaBranch {
+    fn includes(&self, key: &keyexpr) -> bool {
+        self.key_exprs.iter().any(|ke| ke.includes(key))
+    }
+
+    // Check if a file of new_size bytes replacing a file of old_size bytes (if any) fits in the quota
+    pub(crate) fn check_quota(
+        &self,
+        usage_ready: bool,
+        old_size: Option<u64>,
+        new_size: u64,
+    ) -> ZResult<()> {
+        let quota_bytes = match self.quota_bytes {
+            Some(quota_bytes) => quota_bytes,
+            None => return Ok(()),
+        };
+        if !usage_ready {
+            return Err(RetryLater::new(format!(
+                "the usage of the schema branch `{}` is not computed yet (the storage is warming up)",
+                self.declared
+            ))
+            .into());
+        }
+        let used = self.bytes.load(Ordering::Relaxed);
+        if used.saturating_sub(old_size.unwrap_or(0)) + new_size > quota_bytes {
+            bail!(
+                "the schema branch `{}` would exceed its quota of {} bytes ({} bytes used)",
+                self.declared,
+                quota_bytes,
+                used
+            );
+        }
+        Ok(())
+    }
+
+    // Record a file of new_size bytes written, replacing a file of old_size bytes (if any)
+    pub(crate) fn record_put(&self, old_size: Option<u64>, new_size: u64) {
+        match old_size {
+            Some(old_size) => self.sub_bytes(old_size),
+            None => {
+                self.keys.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.bytes.fetch_add(new_size, Ordering::Relaxed);
+    }
+
+    // Record a file of old_size bytes (if it existed) deleted
+    pub(crate) fn record_delete(&self, old_size: Option<u64>) {
+        if let Some(old_size) = old_size {
+            let _ = self
+                .keys
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1));
+            self.sub_bytes(old_size);
+        }
+    }
+
+    // (the usage being an estimate, it's kept positive if the files were modified by something else)
+    fn sub_bytes(&self, size: u64) {
+        let _ = self
+            .bytes
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| {
+                Some(n.saturating_sub(size))
+            });
+    }
+}
+
+// The key layout declared for a storage ("schema"): the keys (without the strip_prefix) in branches,
+// each with its own options, e.g. `{ "sites/*/devices/*/{config,state}": { "quota_bytes": 1000000 } }`.
+// The branches can't intersect, so each key belongs to one branch at most.
+pub(crate) struct KeySchema {
+    branches: Vec<SchemaBranch>,
+    // if true, the PUT on keys not in any branch are refused ("enforce_schema")
+    pub(crate) enforced: bool,
+    // set once the usage of the branches is computed (at the end of the warm-up)
+    usage_ready: AtomicBool,
+}
+
+impl KeySchema {
+    pub(crate) fn parse(
+        config: &serde_json::Map<String, serde_json::Value>,
+        enforced: bool,
+    ) -> ZResult<Self> {
+        let mut branches: Vec<SchemaBranch> = Vec::with_capacity(config.len());
+        for (declared, options) in config {
+            let options = match options {
+                serde_json::Value::Object(options) => options,
+                _ => bail!(
+                    "Invalid schema branch `{}`: its options must be an object (possibly empty)",
+                    declared
+                ),
+            };
+            if let Some(option) = options
+                .keys()
+                .find(|k| *k != BRANCH_READ_ONLY && *k != BRANCH_QUOTA_BYTES)
+            {
+                bail!(
+                    "Invalid schema branch `{}`: unknown option `{}` (must be `{}` or `{}`)",
+                    declared,
+                    option,
+                    BRANCH_READ_ONLY,
+                    BRANCH_QUOTA_BYTES
+                );
+            }
+            let key_exprs = expand_alternatives(declared)?
+                .into_iter()
+                .map(|ke| {
+                    OwnedKeyExpr::try_from(ke.as_str()).map_err(|e| {
+                        zerror!("Invalid schema branch `{}`: `{}` {}", declared, ke, e)
+                    })
+                })
+                .collect::<Result<Vec<OwnedKeyExpr>, _>>()?;
+            let branch = SchemaBranch {
+                declared: declared.clone(),
+                key_exprs,
+                read_only: extract_bool(options, BRANCH_READ_ONLY, false)
+                    .map_err(|e| zerror!("Invalid schema branch `{}`: {}", declared, e))?,
+                quota_bytes: match options.get(BRANCH_QUOTA_BYTES) {
+                    Some(_) => Some(
+                        extract_u64(options, BRANCH_QUOTA_BYTES, 0)
+                            .map_err(|e| zerror!("Invalid schema branch `{}`: {}", declared, e))?,
+                    ),
+                    None => None,
+                },
+                keys: AtomicU64::new(0),
+                bytes: AtomicU64::new(0),
+            };
+            if let Some(other) = branches.iter().find(|other| {
+                other
+                    .key_exprs
+                    .iter()
+                    .any(|ke1| branch.key_exprs.iter().any(|ke2| ke1.intersects(ke2)))
+            }) {
+                bail!(
+                    "Invalid schema: the branches `{}` and `{}` intersect",
+                    other.declared,
+                    branch.declared
+                );
+            }
+            branches.push(branch);
+        }
+        if branches.is_empty() {
+            bail!("Invalid schema: it must declare at least 1 branch");
+        }
+        Ok(KeySchema {
+            branches,
+            enforced,
+            usage_ready: AtomicBool::new(false),
+        })
+    }
+
+    // Get the branch including a zenoh path (without the strip_prefix), if any
+    pub(crate) fn find(&self, zpath: &str) -> Option<&SchemaBranch> {
+        let key = keyexpr::new(zpath).ok()?;
+        self.branches.iter().find(|branch| branch.includes(key))
+    }
+
+    pub(crate) fn has_quotas(&self) -> bool {
+        self.branches
+            .iter()
+            .any(|branch| branch.quota_bytes.is_some())
+    }
+
+    pub(crate) fn is_usage_ready(&self) -> bool {
+        self.usage_ready.load(Ordering::Relaxed)
+    }
+
+    // Get the directories (as zenoh paths) which exist whatever the keys stored: the chunks of each key
+    // expression before its first wildcard, and before its last chunk (e.g. "sites" for "sites/*/devices/*/config")
+    pub(crate) fn static_dirs(&self) -> Vec<String> {
+        let mut dirs: Vec<String> = Vec::new();
+        for ke in self.branches.iter().flat_map(|branch| &branch.key_exprs) {
+            let chunks: Vec<&str> = ke.split('/').collect();
+            let nb_static = chunks
+                .iter()
+                .position(|chunk| chunk.contains('*') || chunk.contains('$'))
+                .unwrap_or(chunks.len() - 1);
+            if nb_static > 0 {
+                let dir = chunks[..nb_static].join("/");
+                if !dirs.contains(&dir) {
+                    dirs.push(dir);
+                }
+            }
+        }
+        dirs
+    }
+
+    // Compute the usage of each branch from the stored files
+    pub(crate) fn compute_usage(&self, files_mgr: &FilesMgr) {
+        for branch in &self.branches {
+            // the alternatives of a branch may intersect: count each file once
+            let mut sizes: HashMap<String, u64> = HashMap::new();
+            for ke in &branch.key_exprs {
+                if ke.contains('*') {
+                    for zfile in files_mgr.matching_files(ke) {
+                        if let Some(size) = files_mgr.file_size(&zfile) {
+                            sizes.insert(zfile.zpath.to_string(), size);
+                        }
+                    }
+                } else if let Some(size) = files_mgr.file_size(&files_mgr.to_zfile(ke.as_str())) {
+                    sizes.insert(ke.to_string(), size);
+                }
+            }
+            branch.keys.store(sizes.len() as u64, Ordering::Relaxed);
+            branch.bytes.store(sizes.values().sum(), Ordering::Relaxed);
+        }
+        self.usage_ready.store(true, Ordering::Relaxed);
+    }
+
+    // The branches with their options and usage, for the admin status
+    pub(crate) fn status(&self) -> serde_json::Value {
+        let usage_ready = self.is_usage_ready();
+        self.branches
+            .iter()
+            .map(|branch| {
+                let mut status = serde_json::json!({
+                    "key_expr": branch.declared,
+                    BRANCH_READ_ONLY: branch.read_only,
+                });
+                if let Some(quota_bytes) = branch.quota_bytes {
+                    status[BRANCH_QUOTA_BYTES] = quota_bytes.into();
+                }
+                if usage_ready {
+                    status["keys"] = branch.keys.load(Ordering::Relaxed).into();
+                    status["bytes"] = branch.bytes.load(Ordering::Relaxed).into();
+                }
+                status
+            })
+            .collect()
+    }
+}
+
+// Expand the "{a,b}" alternatives of a declared key expression (e.g. "x/{a,b}" into "x/a" and "x/b")
+fn expand_alternatives(declared: &str) -> ZResult<Vec<String>> {
+    let start = match declared.find('{') {
+        Some(start) => start,
+        None => return Ok(vec![declared.to_string()]),
+    };
+    let end = match declared[start..].find('}') {
+        Some(end) => start + end,
+        None => bail!("Invalid schema branch `{}`: unclosed '{{'", declared),
+    };
+    let alternatives = &declared[start + 1..end];
+    if alternatives.contains('{') {
+        bail!(
+            "Invalid schema branch `{}`: nested '{{' are not supported",
+            declared
+        );
+    }
+    let mut expanded = Vec::new();
+    for rest in expand_alternatives(&declared[end + 1..])? {
+        for alternative in alternatives.split(',') {
+            expanded.push(format!("{}{}{}", &declared[..start], alternative, rest));
+        }
+    }
+    Ok(expanded)
+}
@@ -12,17 +12,25 @@
 //   ZettaScale Zenoh Team, <zenoh@zettascale.tech>
 //
 
+use async_std::task;
 use async_trait::async_trait;
-use log::{debug, warn};
-use std::convert::TryInto;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::{TryFrom, TryInto};
+use std::future::Future;
 use std::io::prelude::*;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Weak;
+use std::time::{Duration, Instant};
 use std::{fs::DirBuilder, sync::Arc};
 use tempfile::tempfile_in;
+use tracing::{debug, error, warn};
 use zenoh::prelude::r#async::AsyncResolve;
 use zenoh::prelude::*;
-use zenoh::time::new_reception_timestamp;
+use zenoh::time::{new_reception_timestamp, Timestamp, NTP64};
 use zenoh::Result as ZResult;
+use zenoh::Session;
 use zenoh_backend_traits::{
     config::StorageConfig, config::VolumeConfig, CreateVolume, Query, Storage,
     StorageInsertionResult, Volume,
@@ -30,9 +38,51 @@ use zenoh_backend_traits::{
 use zenoh_core::{bail, zerror};
 use zenoh_util::zenoh_home;
 
+mod backpressure;
+mod bulk_delete;
 mod data_info_mgt;
+mod fault_injection;
+mod filename_encoding;
 mod files_mgt;
+mod hash_chain;
+mod hooks;
+mod io_watchdog;
+mod key_locks;
+mod key_schema;
+mod mapped_files;
+mod mime_guessing;
+mod path_template;
+mod read_cache;
+mod rotation;
+mod storage_events;
+mod storage_meta;
+mod tail_source;
+mod text_detection;
+mod throttle;
+#[cfg(all(test, target_os = "linux"))]
+mod tmpfs;
+mod transcoding;
+mod write_hook;
+pub use backpressure::{is_retry_later, RetryLater, RETRY_LATER_HINT};
+use bulk_delete::*;
+use filename_encoding::*;
 use files_mgt::*;
+use hash_chain::*;
+pub use hooks::StorageHooks;
+use io_watchdog::*;
+use key_schema::*;
+use mapped_files::*;
+use mime_guessing::*;
+use path_template::*;
+use read_cache::*;
+use rotation::*;
+use storage_events::*;
+use storage_meta::*;
+use tail_source::*;
+use text_detection::*;
+use throttle::*;
+use transcoding::*;
+use write_hook::*;
 
 /// The environement variable used to configure the root of all storages managed by this FileSystemBackend.
 pub const SCOPE_ENV_VAR: &str = "ZBACKEND_FS_ROOT";
@@ -41,7 +91,7 @@ pub const SCOPE_ENV_VAR: &str = "ZBACKEND_FS_ROOT";
 pub const DEFAULT_ROOT_DIR: &str = "zbackend_fs";
 
 // Properies used by the Backend
-//  - None
+pub const PROP_VOLUME_SESSION_CONFIG: &str = "session_config";
 
 // Properies used by the Storage
 pub const PROP_STORAGE_READ_ONLY: &str = "read_only";
@@ -49,6 +99,140 @@ pub const PROP_STORAGE_DIR: &str = "dir";
 pub const PROP_STORAGE_ON_CLOSURE: &str = "on_closure";
 pub const PROP_STORAGE_FOLLOW_LINK: &str = "follow_links";
 pub const PROP_STORAGE_KEEP_MIME: &str = "keep_mime_types";
+pub const PROP_STORAGE_SNAPSHOT_QUERIES: &str = "snapshot_queries";
+pub const PROP_STORAGE_SHARD_DEPTH: &str = "shard_depth";
+pub const PROP_STORAGE_ON_AMBIGUOUS_FILE: &str = "on_ambiguous_file";
+pub const PROP_STORAGE_PUBLISH_ON_START: &str = "publish_on_start";
+pub const PROP_STORAGE_PUBLISH_ON_START_RATE: &str = "publish_on_start_rate";
+pub const PROP_STORAGE_FSYNC: &str = "fsync";
+pub const PROP_STORAGE_REPLY_FRAGMENT_BYTES: &str = "reply_fragment_bytes";
+pub const PROP_STORAGE_REPLY_MAX_BYTES_PER_SEC: &str = "reply_max_bytes_per_sec";
+pub const PROP_STORAGE_QUERY_READ_CACHE_BYTES: &str = "query_read_cache_bytes";
+pub const PROP_STORAGE_RESERVED_PREFIX: &str = "reserved_prefix";
+pub const PROP_STORAGE_MISSING_TIMESTAMP: &str = "missing_timestamp";
+pub const PROP_STORAGE_DELETE_MISSING: &str = "delete_missing";
+pub const PROP_STORAGE_SKIP_IDENTICAL_WRITES: &str = "skip_identical_writes";
+pub const PROP_STORAGE_SKIP_IDENTICAL: &str = "skip_identical";
+pub const PROP_STORAGE_KEY_DIR_CONFLICT: &str = "key_dir_conflict";
+pub const PROP_STORAGE_PRE_WRITE_HOOK: &str = "pre_write_hook";
+pub const PROP_STORAGE_HOOK_TIMEOUT_MS: &str = "hook_timeout_ms";
+pub const PROP_STORAGE_ON_OVERLAPPING_DIR: &str = "on_overlapping_dir";
+pub const PROP_STORAGE_DECOMPRESS_GZ_ON_READ: &str = "decompress_gz_on_read";
+pub const PROP_STORAGE_MAX_DECOMPRESSED_BYTES: &str = "max_decompressed_bytes";
+pub const PROP_STORAGE_TEXT_SAFE_STORAGE: &str = "text_safe_storage";
+pub const PROP_STORAGE_TEXT_DETECTION: &str = "text_detection";
+pub const PROP_STORAGE_TEXT_DETECTION_SHEBANGS: &str = "text_detection_shebangs";
+pub const PROP_STORAGE_READ_ENCODING_OVERRIDE: &str = "read_encoding_override";
+pub const PROP_STORAGE_PREWARM: &str = "prewarm";
+pub const PROP_STORAGE_QUARANTINE_UNREADABLE: &str = "quarantine_unreadable";
+pub const PROP_STORAGE_ROTATE_KEEP: &str = "rotate_keep";
+pub const PROP_STORAGE_PATH_TEMPLATE: &str = "path_template";
+pub const PROP_STORAGE_DEFAULT_ENCODING: &str = "default_encoding";
+pub const PROP_STORAGE_LOCKING_MODE: &str = "locking_mode";
+pub const PROP_STORAGE_ADMIN_QUERY_PARAM: &str = "admin_query_param";
+pub const PROP_STORAGE_ADMIN_WRITE_OPS: &str = "admin_write_ops";
+pub const PROP_STORAGE_MAX_RETENTION_SECS: &str = "max_retention_secs";
+pub const PROP_STORAGE_HASH_CHAIN: &str = "hash_chain";
+pub const PROP_STORAGE_AUTO_MIGRATE_ON_KEY_CHANGE: &str = "auto_migrate_on_key_change";
+pub const PROP_STORAGE_TAIL_SOURCE: &str = "tail_source";
+pub const PROP_STORAGE_CHANGE_NOTIFY_KEY: &str = "change_notify_key";
+pub const PROP_STORAGE_CONFLICT_LOG: &str = "conflict_log";
+pub const PROP_STORAGE_PUBLISH_EVENTS: &str = "publish_events";
+pub const PROP_STORAGE_IO_DEADLINE_MS: &str = "io_deadline_ms";
+pub const PROP_STORAGE_STRIP_EXTENSION: &str = "strip_extension";
+pub const PROP_STORAGE_FILENAME_ENCODING: &str = "filename_encoding";
+pub const PROP_STORAGE_WARMUP_TIMEOUT_SECS: &str = "warmup_timeout_secs";
+pub const PROP_STORAGE_TEMP_DIR: &str = "temp_dir";
+pub const PROP_STORAGE_SCHEMA: &str = "schema";
+pub const PROP_STORAGE_ENFORCE_SCHEMA: &str = "enforce_schema";
+pub const PROP_STORAGE_DIR_SYMLINKS: &str = "dir_symlinks";
+pub const PROP_STORAGE_ALLOW_SYMLINKED_BASE: &str = "allow_symlinked_base";
+pub const PROP_STORAGE_QUERY_BUDGET_MS: &str = "query_budget_ms";
+pub const PROP_STORAGE_MAX_REPLIES_PER_QUERY: &str = "max_replies_per_query";
+pub const PROP_STORAGE_MAX_ARCHIVE_BYTES: &str = "max_archive_bytes";
+pub const PROP_STORAGE_WATCH_EXTERNAL_DELETES_MS: &str = "watch_external_deletes_ms";
+pub const PROP_STORAGE_GLOB_MODE: &str = "glob_mode";
+pub const PROP_STORAGE_DEFAULT_FILE_MODE: &str = "default_file_mode";
+pub const PROP_STORAGE_DEFAULT_DIR_MODE: &str = "default_dir_mode";
+pub const PROP_STORAGE_MIN_WRITE_INTERVAL_MS: &str = "min_write_interval_ms";
+pub const PROP_STORAGE_CATCHUP_THROTTLE_US: &str = "catchup_throttle_us";
+pub const PROP_STORAGE_ON_MISSING_FILE: &str = "on_missing_file";
+pub const PROP_STORAGE_WRITABILITY_CHECK: &str = "writability_check";
+pub const PROP_STORAGE_BULK_DELETE_BATCH_SIZE: &str = "bulk_delete_batch_size";
+pub const PROP_STORAGE_BULK_DELETE_CONCURRENCY: &str = "bulk_delete_concurrency";
+pub const PROP_STORAGE_MIME_SOURCE: &str = "mime_source";
+pub const PROP_STORAGE_ENCODING_MAP: &str = "encoding_map";
+pub const PROP_STORAGE_COMPOUND_EXTENSIONS: &str = "compound_extensions";
+pub const PROP_STORAGE_MMAP_READS: &str = "mmap_reads";
+pub const PROP_STORAGE_IO_ADVICE: &str = "io_advice";
+pub const PROP_STORAGE_CANONICALIZE_KEYS: &str = "canonicalize_keys";
+pub const PROP_STORAGE_STRICT_KEYEXPR: &str = "strict_keyexpr";
+pub const PROP_STORAGE_ENCODING_SUBDIRS: &str = "encoding_subdirs";
+
+// Default maximum size of the content of a ".gz" file decompressed by "decompress_gz_on_read"
+const DEFAULT_MAX_DECOMPRESSED_BYTES: u64 = 256 * 1024 * 1024;
+
+// Default maximum size of an archive replied to a "_as=tar" query
+const DEFAULT_MAX_ARCHIVE_BYTES: u64 = 1024 * 1024 * 1024;
+
+// Size of the parts of an archive replied as soon as they're built (if "reply_fragment_bytes" is not set)
+const ARCHIVE_PART_BYTES: usize = 1024 * 1024;
+
+// Default maximum duration a key can be made immutable for by the "retention" reserved operation (10 years)
+const DEFAULT_MAX_RETENTION_SECS: u64 = 10 * 365 * 24 * 3600;
+
+// Default number of records returned by the "conflicts" reserved operation
+const DEFAULT_CONFLICTS_LIMIT: usize = 100;
+
+// Default timeout of the "pre_write_hook" command
+const DEFAULT_HOOK_TIMEOUT_MS: u64 = 10000;
+
+// Default maximum number of publications per second for "publish_on_start"
+const DEFAULT_PUBLISH_ON_START_RATE: u64 = 1000;
+
+// Period of the checks that the storage directory was not replaced (e.g. by a volume re-mount)
+const BASE_DIR_CHECK_PERIOD: Duration = Duration::from_secs(5);
+
+// Period of the check that a quiesced storage was resumed, by a bulk deletion paused between 2 batches
+const BULK_DELETE_QUIESCED_PERIOD: Duration = Duration::from_millis(500);
+
+// Prefix of the metrics names replied by the "metrics" reserved operation
+const METRICS_PREFIX: &str = "zenoh_backend_fs";
+
+// Query parameters used to reply the number of matching keys (optionally grouped by prefix), instead of their values
+const COUNT_QUERY_PARAM: &str = "_count";
+const COUNT_BY_QUERY_PARAM: &str = "_count_by";
+
+// Query parameter used to reply all the matching values at once in an archive (the only supported format is "tar")
+const AS_QUERY_PARAM: &str = "_as";
+const TAR_ENCODING: &str = "application/x-tar";
+
+// Query parameter used to reply the metadata of the matching keys (including their ETag), instead of their values
+const META_QUERY_PARAM: &str = "_meta";
+
+// Query parameter used to reply the metadata of the matching keys read without their content, instead of their values
+const STAT_QUERY_PARAM: &str = "_stat";
+
+// Query parameter used to reply the deleted keys (with their deletion timestamp), instead of the stored values
+const DELETED_QUERY_PARAM: &str = "_deleted";
+
+// Query parameter used to reply a "not modified" value instead of a key's value if its ETag is unchanged
+const IF_NONE_MATCH_QUERY_PARAM: &str = "_if_none_match";
+
+// Query parameter used to reply the ".gz" files decompressed by "decompress_gz_on_read" as is, without decompressing them
+const ACCEPT_COMPRESSED_QUERY_PARAM: &str = "_accept_compressed";
+
+// Query parameter used to reply the values of a key's siblings (the keys with the same parent) along with its value
+const WITH_SIBLINGS_QUERY_PARAM: &str = "_with_siblings";
+
+// Query parameter used to reply the values as of a time (a timestamp, or its time part), instead of the current values
+const SNAPSHOT_QUERY_PARAM: &str = "_snapshot";
+
+// Query parameter used to list the direct children of the selector's key, instead of replying the values
+const LIST_DIR_QUERY_PARAM: &str = "_list_dir";
+
+// Default query parameter used to trigger a reserved operation on a Storage, instead of a regular GET
+pub const ADMIN_QUERY_PARAM: &str = "_admin";
 
 const GIT_VERSION: &str = git_version::git_version!(prefix = "v", cargo_prefix = "v");
 lazy_static::lazy_static!(
@@ -60,9 +244,25 @@ lazy_static::lazy_static!(
 const CREATE_VOLUME_TYPECHECK: CreateVolume = create_volume;
 
 #[no_mangle]
-pub fn create_volume(_unused: VolumeConfig) -> ZResult<Box<dyn Volume>> {
+pub fn create_volume(config: VolumeConfig) -> ZResult<Box<dyn Volume>> {
+    Ok(Box::new(new_backend(&config, None)?))
+}
+
+/// Create the backend with some hooks called by all its storages, for the users embedding it as a library
+pub fn create_volume_with_hooks(
+    config: VolumeConfig,
+    hooks: Arc<dyn StorageHooks>,
+) -> ZResult<Box<dyn Volume>> {
+    Ok(Box::new(new_backend(&config, Some(hooks))?))
+}
+
+fn new_backend(
+    config: &VolumeConfig,
+    hooks: Option<Arc<dyn StorageHooks>>,
+) -> ZResult<FileSystemBackend> {
     // For some reasons env_logger is sometime not active in a loaded library.
     // Try to activate it here, ignoring failures.
+    // Note: without tracing subscriber, the tracing events are forwarded as log records to env_logger.
     let _ = env_logger::try_init();
     debug!("FileSystem backend {}", LONG_VERSION.as_str());
 
@@ -92,6 +292,25 @@ pub fn create_volume(_unused: VolumeConfig) -> ZResult<Box<dyn Volume>> {
     };
     debug!("Using root dir: {}", root.display());
 
+    let session_config = match config.rest.get(PROP_VOLUME_SESSION_CONFIG) {
+        Some(serde_json::Value::String(file)) => Some(zenoh::config::Config::from_file(file)?),
+        Some(value @ serde_json::Value::Object(_)) => Some(
+            zenoh::config::Config::from_deserializer(value.clone()).map_err(|e| {
+                zerror!(
+                    "Invalid value for `{}` property of the volume: {}",
+                    PROP_VOLUME_SESSION_CONFIG,
+                    e.map_or_else(|e| e.to_string(), |_| "invalid configuration".to_string())
+                )
+            })?,
+        ),
+        None => None,
+        Some(v) => bail!(
+            "Unsupported value {:?} for `{}` property of the volume: must be a zenoh configuration (as an object, or the path of its file)",
+            v,
+            PROP_VOLUME_SESSION_CONFIG
+        ),
+    };
+
     let mut properties = zenoh::properties::Properties::default();
     properties.insert("root".into(), root.to_string_lossy().into());
     properties.insert("version".into(), LONG_VERSION.clone());
@@ -101,12 +320,61 @@ pub fn create_volume(_unused: VolumeConfig) -> ZResult<Box<dyn Volume>> {
         .into_iter()
         .map(|(k, v)| (k, serde_json::Value::String(v)))
         .collect();
-    Ok(Box::new(FileSystemBackend { admin_status, root }))
+    Ok(FileSystemBackend {
+        admin_status,
+        root,
+        session_config,
+        session: None,
+        active_dirs: Arc::new(std::sync::Mutex::new(Vec::new())),
+        hooks,
+    })
 }
 
 pub struct FileSystemBackend {
     admin_status: serde_json::Value,
     root: PathBuf,
+    // configuration of the zenoh Session used by the storages to publish ("session_config")
+    session_config: Option<zenoh::config::Config>,
+    // zenoh Session used by the storages to publish, opened only when required
+    session: Option<Arc<Session>>,
+    // directories of the storages currently running (one entry per storage)
+    active_dirs: Arc<std::sync::Mutex<Vec<PathBuf>>>,
+    // hooks set by the users embedding the backend as a library
+    hooks: Option<Arc<dyn StorageHooks>>,
+}
+
+impl FileSystemBackend {
+    // Get the zenoh Session used to publish, required by a storage property
+    async fn get_session(&mut self, property: &str) -> ZResult<Arc<Session>> {
+        match (&self.session, &self.session_config) {
+            (Some(session), _) => Ok(session.clone()),
+            (None, None) => bail!(
+                "`{}` property requires the `{}` property of the volume: the configuration of the zenoh session used to publish",
+                property,
+                PROP_VOLUME_SESSION_CONFIG
+            ),
+            (None, Some(config)) => {
+                debug!("Open a zenoh session for the File System Storages publications");
+                let session = Arc::new(zenoh::open(config.clone()).res().await?);
+                self.session = Some(session.clone());
+                Ok(session)
+            }
+        }
+    }
+}
+
+// What to publish for each stored file at storage startup
+enum PublishOnStart {
+    Keys,
+    Full,
+}
+
+// Progress of the publication of the stored files at storage startup
+#[derive(Default)]
+struct PublishProgress {
+    published: AtomicU64,
+    total: AtomicU64,
+    done: AtomicBool,
 }
 
 fn extract_bool(
@@ -124,13 +392,239 @@ fn extract_bool(
     }
 }
 
+fn extract_u64(
+    from: &serde_json::Map<String, serde_json::Value>,
+    key: &str,
+    default: u64,
+) -> ZResult<u64> {
+    match from.get(key) {
+        Some(serde_json::Value::Number(n)) if n.is_u64() => Ok(n.as_u64().unwrap()),
+        None => Ok(default),
+        _ => bail!(
+            r#"Invalid value for File System Storage configuration: `{}` must be a positive integer"#,
+            key
+        ),
+    }
+}
+
+// Extract Unix permissions, as a string of octal digits (e.g. "0640")
+fn extract_mode(
+    from: &serde_json::Map<String, serde_json::Value>,
+    key: &str,
+) -> ZResult<Option<u32>> {
+    match from.get(key) {
+        Some(serde_json::Value::String(s)) => match u32::from_str_radix(s, 8) {
+            Ok(mode) if mode <= 0o7777 => Ok(Some(mode)),
+            _ => bail!(
+                r#"Invalid value for File System Storage configuration: `{}` must be Unix permissions in octal (e.g. "0640"), not {:?}"#,
+                key,
+                s
+            ),
+        },
+        None => Ok(None),
+        _ => bail!(
+            r#"Invalid value for File System Storage configuration: `{}` must be a string of octal digits (e.g. "0640")"#,
+            key
+        ),
+    }
+}
+
+// Check with the permissions of a directory (and its ACLs, and its file system being mounted read-only),
+// without creating any file, if the files can be created in it
+#[cfg(unix)]
+fn check_writable_dir(dir: &Path) -> std::io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(dir.as_os_str().as_bytes())
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    if unsafe { libc::access(path.as_ptr(), libc::W_OK | libc::X_OK) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+// On Windows, only the read-only attribute of the directory is checked (not its ACLs)
+#[cfg(windows)]
+fn check_writable_dir(dir: &Path) -> std::io::Result<()> {
+    if dir.metadata()?.permissions().readonly() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            "the directory is read-only",
+        ));
+    }
+    Ok(())
+}
+
+// Number of files listed by get_all_entries() between 2 yields to the other tasks
+const GET_ALL_ENTRIES_YIELD_PERIOD: usize = 1000;
+
+// Name of the file renamed from "temp_dir" into the storage directory at startup, to check it's possible
+const TEMP_DIR_PROBE_FILENAME: &str = ".zenoh_temp_dir_probe";
+
+// Check that a "temp_dir" can be used for the temporary files of a storage: it must be outside of the storage
+// directory, and on the same file system so the temporary files can be atomically renamed into the storage directory
+fn check_temp_dir(temp_dir: &Path, base_dir: &Path) -> ZResult<()> {
+    DirBuilder::new()
+        .recursive(true)
+        .create(temp_dir)
+        .map_err(|e| {
+            zerror!(
+                r#"Invalid property "{}": cannot create {:?}: {}"#,
+                PROP_STORAGE_TEMP_DIR,
+                temp_dir,
+                e
+            )
+        })?;
+    let canonical = |dir: &Path| dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+    let (canonical_temp_dir, canonical_base_dir) = (canonical(temp_dir), canonical(base_dir));
+    if canonical_temp_dir.starts_with(&canonical_base_dir)
+        || canonical_base_dir.starts_with(&canonical_temp_dir)
+    {
+        bail!(
+            r#"Invalid property "{}": {:?} must not overlap the storage directory {:?}"#,
+            PROP_STORAGE_TEMP_DIR,
+            temp_dir,
+            base_dir
+        )
+    }
+    check_same_file_system(temp_dir, base_dir)?;
+    // a rename can fail even within a same file system (e.g. across bind mounts): try it
+    let target = base_dir.join(TEMP_DIR_PROBE_FILENAME);
+    tempfile::Builder::new()
+        .prefix(TEMP_DIR_PROBE_FILENAME)
+        .tempfile_in(temp_dir)
+        .map_err(|e| e.to_string())
+        .and_then(|probe| probe.persist(&target).map_err(|e| e.error.to_string()))
+        .map_err(|e| {
+            zerror!(
+                r#"Invalid property "{}": a file of {:?} can't be renamed into the storage directory {:?}: {}"#,
+                PROP_STORAGE_TEMP_DIR,
+                temp_dir,
+                base_dir,
+                e
+            )
+        })?;
+    let _ = std::fs::remove_file(&target);
+    Ok(())
+}
+
+#[cfg(unix)]
+fn check_same_file_system(temp_dir: &Path, base_dir: &Path) -> ZResult<()> {
+    use std::os::unix::fs::MetadataExt;
+    let device = |dir: &Path| {
+        std::fs::metadata(dir)
+            .map(|m| m.dev())
+            .map_err(|e| zerror!("Failed to get metadata of {:?}: {}", dir, e))
+    };
+    if device(temp_dir)? != device(base_dir)? {
+        bail!(
+            r#"Invalid property "{}": {:?} is not on the same file system as the storage directory {:?}, so its files can't be atomically renamed into it"#,
+            PROP_STORAGE_TEMP_DIR,
+            temp_dir,
+            base_dir
+        )
+    }
+    Ok(())
+}
+
+// The device ids are not available on non-Unix platforms: only the rename probe checks the file system
+#[cfg(not(unix))]
+fn check_same_file_system(_temp_dir: &Path, _base_dir: &Path) -> ZResult<()> {
+    Ok(())
+}
+
+// Resolve a directory specified in configuration or in a query as a path relative to root
+fn resolve_dir(root: &Path, property: &str, dir: &str) -> ZResult<PathBuf> {
+    let dir_path = PathBuf::from(dir);
+    if dir_path.is_absolute() {
+        bail!(
+            r#"Invalid property "{}"="{}": the path must be relative"#,
+            property,
+            dir
+        );
+    }
+    if dir_path
+        .components()
+        .any(|c| c == std::path::Component::ParentDir)
+    {
+        bail!(
+            r#"Invalid property "{}"="{}": the path must not contain any '..'"#,
+            property,
+            dir
+        );
+    }
+    // prepend dir with root
+    let mut result = root.to_path_buf();
+    result.push(dir_path);
+    Ok(result)
+}
+
+// Find a symbolic link in the path of a directory, from the root (excluded) to the directory itself (included)
+fn find_symlink<'a>(root: &Path, dir: &'a Path) -> Option<&'a Path> {
+    dir.ancestors()
+        .take_while(|ancestor| *ancestor != root)
+        .find(|ancestor| {
+            ancestor
+                .symlink_metadata()
+                .map_or(false, |metadata| metadata.file_type().is_symlink())
+        })
+}
+
+// Get the parameters of a Selector as a map
+fn get_query_params(selector: &Selector) -> HashMap<String, String> {
+    selector
+        .parameters()
+        .split('&')
+        .filter(|p| !p.is_empty())
+        .map(|p| match p.split_once('=') {
+            Some((k, v)) => (k.to_string(), v.to_string()),
+            None => (p.to_string(), String::new()),
+        })
+        .collect()
+}
+
+// Check if a reserved operation (with its parameters) modifies the storage, and thus is refused while it's quiesced
+fn is_admin_write(operation: &str, params: &HashMap<String, String>) -> bool {
+    match operation {
+        "restore" | "restore_backup" | "compact" | "delete_matching" => true,
+        "retention" => params.contains_key("until_ms"),
+        "data_info" => params.contains_key("tag"),
+        _ => false,
+    }
+}
+
+// Check if a reserved operation (with its parameters) is refused unless "admin_write_ops" is enabled,
+// as it can alter the storage or access the files out of its directory
+fn requires_admin_write_ops(operation: &str, params: &HashMap<String, String>) -> bool {
+    match operation {
+        "backup" | "restore_backup" | "compact" | "diff" | "delete_matching" => true,
+        "retention" => params.contains_key("until_ms"),
+        _ => false,
+    }
+}
+
 #[async_trait]
 impl Volume for FileSystemBackend {
     fn get_admin_status(&self) -> serde_json::Value {
         self.admin_status.clone()
     }
 
-    async fn create_storage(&mut self, mut config: StorageConfig) -> ZResult<Box<dyn Storage>> {
+    async fn create_storage(&mut self, config: StorageConfig) -> ZResult<Box<dyn Storage>> {
+        Ok(Box::new(self.new_storage(config).await?))
+    }
+
+    fn incoming_data_interceptor(&self) -> Option<Arc<dyn Fn(Sample) -> Sample + Sync + Send>> {
+        None
+    }
+
+    fn outgoing_data_interceptor(&self) -> Option<Arc<dyn Fn(Sample) -> Sample + Sync + Send>> {
+        None
+    }
+}
+
+impl FileSystemBackend {
+    // Create a storage of this backend (see create_storage())
+    async fn new_storage(&mut self, mut config: StorageConfig) -> ZResult<FileSystemStorage> {
         let volume_cfg = match config.volume_cfg.as_object() {
             Some(v) => v,
             None => bail!("fs backed volumes require volume-specific configuration"),
@@ -139,60 +633,669 @@ impl Volume for FileSystemBackend {
         let read_only = extract_bool(volume_cfg, PROP_STORAGE_READ_ONLY, false)?;
         let follow_links = extract_bool(volume_cfg, PROP_STORAGE_FOLLOW_LINK, false)?;
         let keep_mime = extract_bool(volume_cfg, PROP_STORAGE_KEEP_MIME, true)?;
+        let snapshot_queries = extract_bool(volume_cfg, PROP_STORAGE_SNAPSHOT_QUERIES, false)?;
+        let fsync = extract_bool(volume_cfg, PROP_STORAGE_FSYNC, false)?;
+        let skip_identical_writes =
+            extract_bool(volume_cfg, PROP_STORAGE_SKIP_IDENTICAL_WRITES, false)?;
+        let skip_identical = extract_bool(volume_cfg, PROP_STORAGE_SKIP_IDENTICAL, false)?;
+        let decompress_gz_on_read =
+            extract_bool(volume_cfg, PROP_STORAGE_DECOMPRESS_GZ_ON_READ, false)?;
+        let max_decompressed_bytes = match extract_u64(
+            volume_cfg,
+            PROP_STORAGE_MAX_DECOMPRESSED_BYTES,
+            DEFAULT_MAX_DECOMPRESSED_BYTES,
+        )? {
+            0 => None,
+            max => Some(max),
+        };
+        let text_safe_storage = extract_bool(volume_cfg, PROP_STORAGE_TEXT_SAFE_STORAGE, false)?;
+        let text_detection = if extract_bool(volume_cfg, PROP_STORAGE_TEXT_DETECTION, false)? {
+            let shebang_encodings = match volume_cfg.get(PROP_STORAGE_TEXT_DETECTION_SHEBANGS) {
+                Some(serde_json::Value::Object(map)) => map
+                    .iter()
+                    .map(|(interpreter, encoding)| match encoding {
+                        serde_json::Value::String(encoding) => {
+                            Ok((interpreter.clone(), encoding.clone()))
+                        }
+                        _ => bail!(
+                            r#"Invalid value for `{}` property: the encoding of "{}" must be a string"#,
+                            PROP_STORAGE_TEXT_DETECTION_SHEBANGS,
+                            interpreter
+                        ),
+                    })
+                    .collect::<ZResult<HashMap<String, String>>>()?,
+                None => HashMap::new(),
+                Some(_) => bail!(
+                    r#"Invalid value for `{}` property: must be an object mapping interpreters to encodings"#,
+                    PROP_STORAGE_TEXT_DETECTION_SHEBANGS
+                ),
+            };
+            Some(TextDetection::new(shebang_encodings))
+        } else {
+            None
+        };
+        let mime_source = match volume_cfg.get(PROP_STORAGE_MIME_SOURCE) {
+            Some(serde_json::Value::String(s)) if s == "extension" => MimeSource::Extension,
+            Some(serde_json::Value::String(s)) if s == "content" => MimeSource::Content,
+            None => MimeSource::Extension,
+            Some(s) => {
+                bail!(
+                    r#"Unsupported value {:?} for `mime_source` property: must be either "extension" or "content""#,
+                    s
+                )
+            }
+        };
+        let encoding_map = match volume_cfg.get(PROP_STORAGE_ENCODING_MAP) {
+            Some(serde_json::Value::Object(map)) => map
+                .iter()
+                .map(|(ext, encoding)| match encoding {
+                    serde_json::Value::String(encoding) if !ext.trim_start_matches('.').is_empty() => {
+                        Ok((ext.clone(), encoding.clone()))
+                    }
+                    _ => bail!(
+                        r#"Invalid value for `{}` property: the encoding of "{}" must be a string, for a non-empty extension"#,
+                        PROP_STORAGE_ENCODING_MAP,
+                        ext
+                    ),
+                })
+                .collect::<ZResult<HashMap<String, String>>>()?,
+            None => HashMap::new(),
+            Some(_) => bail!(
+                r#"Invalid value for `{}` property: must be an object mapping file extensions to encodings"#,
+                PROP_STORAGE_ENCODING_MAP
+            ),
+        };
+        let compound_extensions = match volume_cfg.get(PROP_STORAGE_COMPOUND_EXTENSIONS) {
+            None | Some(serde_json::Value::Bool(true)) => None,
+            Some(serde_json::Value::Bool(false)) => Some(vec![]),
+            Some(serde_json::Value::Array(exts)) => Some(
+                exts.iter()
+                    .map(|ext| match ext {
+                        serde_json::Value::String(s) => Ok(s.clone()),
+                        _ => bail!(
+                            r#"Invalid value {:?} in `{}` property: must be a compound file extension (e.g. ".tar.gz")"#,
+                            ext,
+                            PROP_STORAGE_COMPOUND_EXTENSIONS
+                        ),
+                    })
+                    .collect::<ZResult<Vec<String>>>()?,
+            ),
+            Some(v) => bail!(
+                r#"Unsupported value {:?} for `{}` property: must be a boolean or a list of compound file extensions"#,
+                v,
+                PROP_STORAGE_COMPOUND_EXTENSIONS
+            ),
+        };
+        let mime_guesser = MimeGuesser::new(mime_source, encoding_map, compound_extensions)
+            .map_err(|e| {
+                zerror!(
+                    "Invalid `{}` property: {}",
+                    PROP_STORAGE_COMPOUND_EXTENSIONS,
+                    e
+                )
+            })?;
+        let hash_chain = extract_bool(volume_cfg, PROP_STORAGE_HASH_CHAIN, false)?;
+        let admin_write_ops = extract_bool(volume_cfg, PROP_STORAGE_ADMIN_WRITE_OPS, false)?;
+        let max_retention = Duration::from_secs(extract_u64(
+            volume_cfg,
+            PROP_STORAGE_MAX_RETENTION_SECS,
+            DEFAULT_MAX_RETENTION_SECS,
+        )?);
+        let publish_events = extract_bool(volume_cfg, PROP_STORAGE_PUBLISH_EVENTS, false)?;
+        let auto_migrate_on_key_change =
+            extract_bool(volume_cfg, PROP_STORAGE_AUTO_MIGRATE_ON_KEY_CHANGE, false)?;
+        let quarantine_unreadable =
+            extract_bool(volume_cfg, PROP_STORAGE_QUARANTINE_UNREADABLE, false)?;
+        let reply_fragment_bytes = extract_u64(volume_cfg, PROP_STORAGE_REPLY_FRAGMENT_BYTES, 0)?;
+        let query_budget = match extract_u64(volume_cfg, PROP_STORAGE_QUERY_BUDGET_MS, 0)? {
+            0 => None,
+            ms => Some(Duration::from_millis(ms)),
+        };
+        let max_replies_per_query =
+            match extract_u64(volume_cfg, PROP_STORAGE_MAX_REPLIES_PER_QUERY, 0)? {
+                0 => None,
+                max => Some(max),
+            };
+        let max_archive_bytes = match extract_u64(
+            volume_cfg,
+            PROP_STORAGE_MAX_ARCHIVE_BYTES,
+            DEFAULT_MAX_ARCHIVE_BYTES,
+        )? {
+            0 => None,
+            max => Some(max),
+        };
+        let conflict_log = extract_u64(volume_cfg, PROP_STORAGE_CONFLICT_LOG, 0)?;
+        // if not set, the storage creation waits for the end of the warm-up
+        let warmup_timeout = match volume_cfg.get(PROP_STORAGE_WARMUP_TIMEOUT_SECS) {
+            Some(_) => Some(Duration::from_secs(extract_u64(
+                volume_cfg,
+                PROP_STORAGE_WARMUP_TIMEOUT_SECS,
+                0,
+            )?)),
+            None => None,
+        };
+        let io_watchdog = match extract_u64(volume_cfg, PROP_STORAGE_IO_DEADLINE_MS, 0)? {
+            0 => None,
+            ms => Some(IoWatchdog::new(Duration::from_millis(ms))),
+        };
+        let query_read_cache_bytes =
+            extract_u64(volume_cfg, PROP_STORAGE_QUERY_READ_CACHE_BYTES, 0)? as usize;
+        let reply_throttle = match extract_u64(volume_cfg, PROP_STORAGE_REPLY_MAX_BYTES_PER_SEC, 0)?
+        {
+            0 => None,
+            rate => Some(Arc::new(ReplyThrottle::new(rate))),
+        };
+        let write_throttle = match extract_u64(volume_cfg, PROP_STORAGE_MIN_WRITE_INTERVAL_MS, 0)? {
+            0 => None,
+            ms => Some(WriteThrottle::new(Duration::from_millis(ms))),
+        };
+        let catchup_throttle = match extract_u64(volume_cfg, PROP_STORAGE_CATCHUP_THROTTLE_US, 0)? {
+            0 => None,
+            us => Some(Duration::from_micros(us)),
+        };
+        let bulk_delete_batch_size =
+            extract_u64(volume_cfg, PROP_STORAGE_BULK_DELETE_BATCH_SIZE, 1000)?.max(1) as usize;
+        let bulk_delete_concurrency =
+            extract_u64(volume_cfg, PROP_STORAGE_BULK_DELETE_CONCURRENCY, 4)?.max(1) as usize;
+        let io_advice = match volume_cfg.get(PROP_STORAGE_IO_ADVICE) {
+            Some(serde_json::Value::String(s)) if s == "normal" => IoAdvice::Normal,
+            Some(serde_json::Value::String(s)) if s == "sequential" => IoAdvice::Sequential,
+            Some(serde_json::Value::String(s)) if s == "random" => IoAdvice::Random,
+            None => IoAdvice::Normal,
+            Some(s) => {
+                bail!(
+                    r#"Unsupported value {:?} for `io_advice` property: must be either "normal", "sequential" or "random""#,
+                    s
+                )
+            }
+        };
+        let mapped_files = match extract_u64(volume_cfg, PROP_STORAGE_MMAP_READS, 0)? {
+            0 => None,
+            min_size => Some(MappedFiles::new(min_size)),
+        };
+        let shard_depth = extract_u64(volume_cfg, PROP_STORAGE_SHARD_DEPTH, 0)? as usize;
+        if shard_depth > MAX_SHARD_DEPTH {
+            bail!(
+                r#"Invalid property "{}"={}: the maximum supported depth is {}"#,
+                PROP_STORAGE_SHARD_DEPTH,
+                shard_depth,
+                MAX_SHARD_DEPTH
+            );
+        }
+        let default_encoding = match config.volume_cfg.get(PROP_STORAGE_DEFAULT_ENCODING) {
+            Some(serde_json::Value::String(s)) if !s.is_empty() => Encoding::from(s.clone()),
+            None => Encoding::APP_OCTET_STREAM,
+            Some(s) => {
+                bail!(
+                    r#"Unsupported value {:?} for `{}` property: must be a non-empty encoding string (e.g. "text/plain"). Default is "{}""#,
+                    s,
+                    PROP_STORAGE_DEFAULT_ENCODING,
+                    Encoding::APP_OCTET_STREAM
+                )
+            }
+        };
+        let reserved_prefix = match config.volume_cfg.get(PROP_STORAGE_RESERVED_PREFIX) {
+            Some(serde_json::Value::String(s)) if !s.is_empty() && !s.contains('/') => s.clone(),
+            None => DEFAULT_RESERVED_PREFIX.to_string(),
+            Some(s) => {
+                bail!(
+                    r#"Unsupported value {:?} for `{}` property: must be a non-empty string without '/'. Default is "{}""#,
+                    s,
+                    PROP_STORAGE_RESERVED_PREFIX,
+                    DEFAULT_RESERVED_PREFIX
+                )
+            }
+        };
+        // the parameter name can't contain the characters separating the selector's parameters
+        let admin_query_param = match config.volume_cfg.get(PROP_STORAGE_ADMIN_QUERY_PARAM) {
+            Some(serde_json::Value::String(s))
+                if !s.is_empty() && !s.contains(|c| matches!(c, '&' | ';' | '=' | '?')) =>
+            {
+                s.clone()
+            }
+            None => ADMIN_QUERY_PARAM.to_string(),
+            Some(s) => {
+                bail!(
+                    r#"Unsupported value {:?} for `{}` property: must be a non-empty string without '&', ';', '=' or '?'. Default is "{}""#,
+                    s,
+                    PROP_STORAGE_ADMIN_QUERY_PARAM,
+                    ADMIN_QUERY_PARAM
+                )
+            }
+        };
+        let missing_timestamp = match config.volume_cfg.get(PROP_STORAGE_MISSING_TIMESTAMP) {
+            Some(serde_json::Value::String(s)) if s == "reception" => MissingTimestamp::Reception,
+            Some(serde_json::Value::String(s)) if s == "reject" => MissingTimestamp::Reject,
+            Some(serde_json::Value::String(s)) if s == "epoch" => MissingTimestamp::Epoch,
+            None => MissingTimestamp::Reception,
+            Some(s) => {
+                bail!(
+                    r#"Unsupported value {:?} for `missing_timestamp` property: must be either "reception", "reject" or "epoch". Default is "reception""#,
+                    s
+                )
+            }
+        };
+        let delete_missing = match config.volume_cfg.get(PROP_STORAGE_DELETE_MISSING) {
+            Some(serde_json::Value::String(s)) if s == "tombstone" => DeleteMissing::Tombstone,
+            Some(serde_json::Value::String(s)) if s == "ignore" => DeleteMissing::Ignore,
+            None => DeleteMissing::Tombstone,
+            Some(s) => {
+                bail!(
+                    r#"Unsupported value {:?} for `{}` property: must be either "tombstone" or "ignore". Default is "tombstone""#,
+                    s,
+                    PROP_STORAGE_DELETE_MISSING
+                )
+            }
+        };
+        let hook_timeout_ms = extract_u64(
+            volume_cfg,
+            PROP_STORAGE_HOOK_TIMEOUT_MS,
+            DEFAULT_HOOK_TIMEOUT_MS,
+        )?;
+        let pre_write_hook = match config.volume_cfg.get(PROP_STORAGE_PRE_WRITE_HOOK) {
+            Some(serde_json::Value::String(s)) => Some(WriteHook::parse(s, hook_timeout_ms)?),
+            None => None,
+            Some(s) => {
+                bail!(
+                    r#"Unsupported value {:?} for `{}` property: must be a command line string (e.g. "/usr/local/bin/validate %k %f")"#,
+                    s,
+                    PROP_STORAGE_PRE_WRITE_HOOK
+                )
+            }
+        };
+        let overlapping_dir_error = match config.volume_cfg.get(PROP_STORAGE_ON_OVERLAPPING_DIR) {
+            Some(serde_json::Value::String(s)) if s == "error" => true,
+            Some(serde_json::Value::String(s)) if s == "warn" => false,
+            None => true,
+            Some(s) => {
+                bail!(
+                    r#"Unsupported value {:?} for `{}` property: must be either "error" or "warn". Default is "error""#,
+                    s,
+                    PROP_STORAGE_ON_OVERLAPPING_DIR
+                )
+            }
+        };
+        let canonicalize_keys = extract_bool(volume_cfg, PROP_STORAGE_CANONICALIZE_KEYS, false)?;
+        let strict_keyexpr = extract_bool(volume_cfg, PROP_STORAGE_STRICT_KEYEXPR, true)?;
+        let key_dir_conflict_error = match config.volume_cfg.get(PROP_STORAGE_KEY_DIR_CONFLICT) {
+            Some(serde_json::Value::String(s)) if s == "suffix" => false,
+            Some(serde_json::Value::String(s)) if s == "error" => true,
+            None => false,
+            Some(s) => {
+                bail!(
+                    r#"Unsupported value {:?} for `{}` property: must be either "suffix" or "error". Default is "suffix""#,
+                    s,
+                    PROP_STORAGE_KEY_DIR_CONFLICT
+                )
+            }
+        };
+        let pessimistic_locking = match config.volume_cfg.get(PROP_STORAGE_LOCKING_MODE) {
+            Some(serde_json::Value::String(s)) if s == "optimistic" => false,
+            Some(serde_json::Value::String(s)) if s == "pessimistic" => true,
+            None => false,
+            Some(s) => {
+                bail!(
+                    r#"Unsupported value {:?} for `{}` property: must be either "optimistic" or "pessimistic". Default is "optimistic""#,
+                    s,
+                    PROP_STORAGE_LOCKING_MODE
+                )
+            }
+        };
+        let filename_encoding = match config.volume_cfg.get(PROP_STORAGE_FILENAME_ENCODING) {
+            Some(serde_json::Value::String(s)) if s == "utf8" => FilenameEncoding::Utf8,
+            Some(serde_json::Value::String(s)) if s == "os_string" && cfg!(unix) => {
+                FilenameEncoding::OsString
+            }
+            Some(serde_json::Value::String(s)) if s == "os_string" => bail!(
+                r#"Unsupported value "os_string" for `{}` property: only supported on Unix"#,
+                PROP_STORAGE_FILENAME_ENCODING
+            ),
+            None => FilenameEncoding::Utf8,
+            Some(s) => {
+                bail!(
+                    r#"Unsupported value {:?} for `{}` property: must be either "utf8" or "os_string". Default is "utf8""#,
+                    s,
+                    PROP_STORAGE_FILENAME_ENCODING
+                )
+            }
+        };
+        let read_encoding_override =
+            match config.volume_cfg.get(PROP_STORAGE_READ_ENCODING_OVERRIDE) {
+                Some(serde_json::Value::String(s)) => match TargetEncoding::from_mime(s) {
+                    Some(target) => Some(target),
+                    None => bail!(
+                        r#"Unsupported value {:?} for `{}` property: must be either "{}" or "{}""#,
+                        s,
+                        PROP_STORAGE_READ_ENCODING_OVERRIDE,
+                        MIME_APP_CBOR,
+                        MIME_APP_OCTET_STREAM
+                    ),
+                },
+                None => None,
+                Some(s) => bail!(
+                    r#"Unsupported value {:?} for `{}` property: must be a string"#,
+                    s,
+                    PROP_STORAGE_READ_ENCODING_OVERRIDE
+                ),
+            };
+        let prewarm = match config.volume_cfg.get(PROP_STORAGE_PREWARM) {
+            Some(serde_json::Value::Array(exprs)) => exprs
+                .iter()
+                .map(|expr| match expr {
+                    serde_json::Value::String(s) => {
+                        OwnedKeyExpr::try_from(s.clone()).map_err(|e| {
+                            zerror!(
+                                r#"Invalid key expression {:?} in `{}` property: {}"#,
+                                s,
+                                PROP_STORAGE_PREWARM,
+                                e
+                            )
+                            .into()
+                        })
+                    }
+                    _ => bail!(
+                        r#"Invalid value {:?} in `{}` property: must be a key expression"#,
+                        expr,
+                        PROP_STORAGE_PREWARM
+                    ),
+                })
+                .collect::<ZResult<Vec<OwnedKeyExpr>>>()?,
+            None => vec![],
+            Some(v) => bail!(
+                r#"Unsupported value {:?} for `{}` property: must be a list of key expressions"#,
+                v,
+                PROP_STORAGE_PREWARM
+            ),
+        };
+        let strip_extensions = match config.volume_cfg.get(PROP_STORAGE_STRIP_EXTENSION) {
+            Some(serde_json::Value::Array(exts)) => exts
+                .iter()
+                .map(|ext| match ext {
+                    serde_json::Value::String(s)
+                        if s.len() > 1 && s.starts_with('.') && !s.contains('/') =>
+                    {
+                        Ok(s.clone())
+                    }
+                    _ => bail!(
+                        r#"Invalid value {:?} in `{}` property: must be a file extension starting with '.' (e.g. ".json")"#,
+                        ext,
+                        PROP_STORAGE_STRIP_EXTENSION
+                    ),
+                })
+                .collect::<ZResult<Vec<String>>>()?,
+            None => vec![],
+            Some(v) => bail!(
+                r#"Unsupported value {:?} for `{}` property: must be a list of file extensions"#,
+                v,
+                PROP_STORAGE_STRIP_EXTENSION
+            ),
+        };
+        let enforce_schema = extract_bool(volume_cfg, PROP_STORAGE_ENFORCE_SCHEMA, false)?;
+        let schema = match config.volume_cfg.get(PROP_STORAGE_SCHEMA) {
+            Some(serde_json::Value::Object(branches)) => {
+                Some(Arc::new(KeySchema::parse(branches, enforce_schema)?))
+            }
+            None if enforce_schema => bail!(
+                r#"Invalid property "{}": it requires a "{}""#,
+                PROP_STORAGE_ENFORCE_SCHEMA,
+                PROP_STORAGE_SCHEMA
+            ),
+            None => None,
+            Some(v) => bail!(
+                r#"Unsupported value {:?} for `{}` property: must be an object mapping key expressions to their options"#,
+                v,
+                PROP_STORAGE_SCHEMA
+            ),
+        };
+        let path_template = match config.volume_cfg.get(PROP_STORAGE_PATH_TEMPLATE) {
+            Some(serde_json::Value::String(s)) => Some(PathTemplate::parse(s).map_err(|e| {
+                zerror!(
+                    r#"Invalid property "{}": {}"#,
+                    PROP_STORAGE_PATH_TEMPLATE,
+                    e
+                )
+            })?),
+            None => None,
+            Some(s) => bail!(
+                r#"Unsupported value {:?} for `{}` property: must be a string"#,
+                s,
+                PROP_STORAGE_PATH_TEMPLATE
+            ),
+        };
+        if path_template.is_some() && shard_depth > 0 {
+            bail!(
+                r#"Properties "{}" and "{}" cannot be used together"#,
+                PROP_STORAGE_PATH_TEMPLATE,
+                PROP_STORAGE_SHARD_DEPTH
+            );
+        }
         let on_closure = match config.volume_cfg.get(PROP_STORAGE_ON_CLOSURE) {
             Some(serde_json::Value::String(s)) if s == "delete_all" => OnClosure::DeleteAll,
+            Some(serde_json::Value::String(s)) if s == "delete_contents" => {
+                OnClosure::DeleteContents
+            }
             Some(serde_json::Value::String(s)) if s == "do_nothing" => OnClosure::DoNothing,
             None => OnClosure::DoNothing,
             Some(s) => {
                 bail!(
-                    r#"Unsupported value {:?} for `on_closure` property: must be either "delete_all" or "do_nothing". Default is "do_nothing""#,
+                    r#"Unsupported value {:?} for `on_closure` property: must be either "delete_all", "delete_contents" or "do_nothing". Default is "do_nothing""#,
                     s
                 )
             }
         };
 
-        let base_dir =
-            if let Some(serde_json::Value::String(dir)) = config.volume_cfg.get(PROP_STORAGE_DIR) {
-                let dir_path = PathBuf::from(dir.as_str());
-                if dir_path.is_absolute() {
-                    bail!(
-                        r#"Invalid property "{}"="{}": the path must be relative"#,
-                        PROP_STORAGE_DIR,
-                        dir
-                    );
-                }
-                if dir_path
-                    .components()
-                    .any(|c| c == std::path::Component::ParentDir)
-                {
-                    bail!(
-                        r#"Invalid property "{}"="{}": the path must not contain any '..'"#,
-                        PROP_STORAGE_DIR,
-                        dir
-                    );
-                }
-
-                // prepend base_dir with self.root
-                let mut base_dir = self.root.clone();
-                base_dir.push(dir_path);
-                base_dir
-            } else {
+        let on_ambiguous_file = match config.volume_cfg.get(PROP_STORAGE_ON_AMBIGUOUS_FILE) {
+            Some(serde_json::Value::String(s)) if s == "keep_file" => {
+                Some(OnAmbiguousFile::KeepFile)
+            }
+            Some(serde_json::Value::String(s)) if s == "keep_conflict_file" => {
+                Some(OnAmbiguousFile::KeepConflictFile)
+            }
+            Some(serde_json::Value::String(s)) if s == "keep_newest" => {
+                Some(OnAmbiguousFile::KeepNewest)
+            }
+            None => None,
+            Some(s) => {
                 bail!(
-                    r#"Missing required property for File System Storage: "{}""#,
-                    PROP_STORAGE_DIR
+                    r#"Unsupported value {:?} for `on_ambiguous_file` property: must be either "keep_file", "keep_conflict_file" or "keep_newest""#,
+                    s
                 )
-            };
+            }
+        };
 
-        // check if base_dir exists and is readable (and writeable if not "read_only" mode)
-        let mut dir_builder = DirBuilder::new();
-        dir_builder.recursive(true);
-        let base_dir_path = PathBuf::from(&base_dir);
-        if !base_dir_path.exists() {
-            if let Err(err) = dir_builder.create(&base_dir) {
+        let on_missing_file = match config.volume_cfg.get(PROP_STORAGE_ON_MISSING_FILE) {
+            Some(serde_json::Value::String(s)) if s == "absent" => OnMissingFile::Absent,
+            Some(serde_json::Value::String(s)) if s == "log" => OnMissingFile::Log,
+            Some(serde_json::Value::String(s)) if s == "tombstone" => {
+                if read_only {
+                    bail!(
+                        r#"Invalid property "{}": "tombstone" is not supported for a read-only storage"#,
+                        PROP_STORAGE_ON_MISSING_FILE
+                    )
+                }
+                OnMissingFile::Tombstone
+            }
+            None => OnMissingFile::Absent,
+            Some(s) => {
                 bail!(
-                    r#"Cannot create File System Storage on "dir"={:?} : {}"#,
-                    base_dir,
-                    err
+                    r#"Unsupported value {:?} for `on_missing_file` property: must be either "absent", "log" or "tombstone""#,
+                    s
+                )
+            }
+        };
+
+        let publish_on_start = match config.volume_cfg.get(PROP_STORAGE_PUBLISH_ON_START) {
+            Some(serde_json::Value::String(s)) if s == "none" => None,
+            Some(serde_json::Value::String(s)) if s == "keys" => Some(PublishOnStart::Keys),
+            Some(serde_json::Value::String(s)) if s == "full" => Some(PublishOnStart::Full),
+            None => None,
+            Some(s) => {
+                bail!(
+                    r#"Unsupported value {:?} for `publish_on_start` property: must be either "none", "keys" or "full". Default is "none""#,
+                    s
+                )
+            }
+        };
+        let tail_source = match config.volume_cfg.get(PROP_STORAGE_TAIL_SOURCE) {
+            Some(_) if read_only => bail!(
+                r#"Invalid property "{}": not supported for a read-only storage"#,
+                PROP_STORAGE_TAIL_SOURCE
+            ),
+            Some(value) => Some(TailSource::parse(
+                PROP_STORAGE_TAIL_SOURCE,
+                value,
+                &config.key_expr,
+                config.strip_prefix.as_deref(),
+            )?),
+            None => None,
+        };
+        // the notifications can't be received by the storage itself (each would be notified in turn)
+        let change_notify_key = match config.volume_cfg.get(PROP_STORAGE_CHANGE_NOTIFY_KEY) {
+            Some(serde_json::Value::String(s)) => match OwnedKeyExpr::try_from(s.as_str()) {
+                Ok(ke) if !ke.contains('*') && !ke.intersects(&config.key_expr) => Some(ke),
+                _ => bail!(
+                    r#"Unsupported value {:?} for `{}` property: must be a key without wildcard, not matching the storage's key expression"#,
+                    s,
+                    PROP_STORAGE_CHANGE_NOTIFY_KEY
+                ),
+            },
+            None => None,
+            Some(v) => bail!(
+                r#"Unsupported value {:?} for `{}` property: must be a string"#,
+                v,
+                PROP_STORAGE_CHANGE_NOTIFY_KEY
+            ),
+        };
+        let publish_on_start_rate = extract_u64(
+            volume_cfg,
+            PROP_STORAGE_PUBLISH_ON_START_RATE,
+            DEFAULT_PUBLISH_ON_START_RATE,
+        )?;
+        let watch_external_deletes =
+            match extract_u64(volume_cfg, PROP_STORAGE_WATCH_EXTERNAL_DELETES_MS, 0)? {
+                0 => None,
+                _ if read_only => bail!(
+                    r#"Invalid property "{}": not supported for a read-only storage"#,
+                    PROP_STORAGE_WATCH_EXTERNAL_DELETES_MS
+                ),
+                ms => Some(Duration::from_millis(ms)),
+            };
+
+        let (base_dir, rotation_format) =
+            if let Some(serde_json::Value::String(dir)) = config.volume_cfg.get(PROP_STORAGE_DIR) {
+                // the files are stored in the static part of "dir", if it ends with a date template
+                let (static_dir, rotation_format) = DirRotation::parse_dir_template(dir)?;
+                (
+                    resolve_dir(&self.root, PROP_STORAGE_DIR, &static_dir)?,
+                    rotation_format,
+                )
+            } else {
+                bail!(
+                    r#"Missing required property for File System Storage: "{}""#,
+                    PROP_STORAGE_DIR
+                )
+            };
+        let resolve_dir_symlinks = match config.volume_cfg.get(PROP_STORAGE_DIR_SYMLINKS) {
+            Some(serde_json::Value::String(s)) if s == "resolve" => true,
+            Some(serde_json::Value::String(s)) if s == "keep" => false,
+            None => true,
+            Some(v) => bail!(
+                r#"Unsupported value {:?} for `{}` property: must be "resolve" or "keep". Default is "resolve""#,
+                v,
+                PROP_STORAGE_DIR_SYMLINKS
+            ),
+        };
+        let allow_symlinked_base =
+            extract_bool(volume_cfg, PROP_STORAGE_ALLOW_SYMLINKED_BASE, false)?;
+        let rotate_keep = extract_u64(volume_cfg, PROP_STORAGE_ROTATE_KEEP, 0)? as usize;
+        let rotation = match rotation_format {
+            Some(format) => Some(DirRotation::new(format, rotate_keep)),
+            None if rotate_keep > 0 => bail!(
+                r#"Invalid property "{}": it requires a "{}" ending with a "{{date:<format>}}" template"#,
+                PROP_STORAGE_ROTATE_KEEP,
+                PROP_STORAGE_DIR
+            ),
+            None => None,
+        };
+        let encoding_subdirs = extract_bool(volume_cfg, PROP_STORAGE_ENCODING_SUBDIRS, false)?;
+        if rotation.is_some() && encoding_subdirs {
+            bail!(
+                r#"Invalid property "{}": it's not supported with a "{}" ending with a "{{date:<format>}}" template"#,
+                PROP_STORAGE_ENCODING_SUBDIRS,
+                PROP_STORAGE_DIR
+            )
+        }
+        if rotation.is_some() && !strip_extensions.is_empty() {
+            bail!(
+                r#"Invalid property "{}": it's not supported with a "{}" ending with a "{{date:<format>}}" template"#,
+                PROP_STORAGE_STRIP_EXTENSION,
+                PROP_STORAGE_DIR
+            )
+        }
+        // the glob patterns are converted from the keys as stored without sharding, template or extension
+        let glob_mode = extract_bool(volume_cfg, PROP_STORAGE_GLOB_MODE, false)?;
+        let file_mode = extract_mode(volume_cfg, PROP_STORAGE_DEFAULT_FILE_MODE)?;
+        let dir_mode = extract_mode(volume_cfg, PROP_STORAGE_DEFAULT_DIR_MODE)?;
+        if glob_mode {
+            if rotation.is_some() {
+                bail!(
+                    r#"Invalid property "{}": it's not supported with a "{}" ending with a "{{date:<format>}}" template"#,
+                    PROP_STORAGE_GLOB_MODE,
+                    PROP_STORAGE_DIR
+                )
+            }
+            for (prop, is_set) in [
+                (PROP_STORAGE_ENCODING_SUBDIRS, encoding_subdirs),
+                (PROP_STORAGE_SHARD_DEPTH, shard_depth > 0),
+                (PROP_STORAGE_PATH_TEMPLATE, path_template.is_some()),
+                (PROP_STORAGE_STRIP_EXTENSION, !strip_extensions.is_empty()),
+            ] {
+                if is_set {
+                    bail!(
+                        r#"Invalid property "{}": it's not supported with "{}""#,
+                        PROP_STORAGE_GLOB_MODE,
+                        prop
+                    )
+                }
+            }
+        }
+
+        // unless allowed, refuse a "dir" with a symbolic link, which might lead the files out of the intended volume
+        // (checked before creating base_dir, which would create the directories through the link)
+        if !allow_symlinked_base {
+            if let Some(link) = find_symlink(&self.root, &base_dir) {
+                bail!(
+                    r#"Cannot create File System Storage on "dir"={:?} : {:?} is a symbolic link (see `{}`)"#,
+                    base_dir,
+                    link,
+                    PROP_STORAGE_ALLOW_SYMLINKED_BASE
+                )
+            }
+        }
+
+        let writability_check = match config.volume_cfg.get(PROP_STORAGE_WRITABILITY_CHECK) {
+            Some(serde_json::Value::String(s)) if s == "tempfile" => WritabilityCheck::Tempfile,
+            Some(serde_json::Value::String(s)) if s == "metadata" => WritabilityCheck::Metadata,
+            Some(serde_json::Value::String(s)) if s == "none" => WritabilityCheck::None,
+            None => WritabilityCheck::Tempfile,
+            Some(s) => {
+                bail!(
+                    r#"Unsupported value {:?} for `writability_check` property: must be either "tempfile", "metadata" or "none""#,
+                    s
+                )
+            }
+        };
+
+        // check if base_dir exists and is readable (and writeable if not "read_only" mode)
+        let mut dir_builder = DirBuilder::new();
+        dir_builder.recursive(true);
+        let base_dir_path = PathBuf::from(&base_dir);
+        if !base_dir_path.exists() {
+            if let Err(err) = dir_builder.create(&base_dir) {
+                bail!(
+                    r#"Cannot create File System Storage on "dir"={:?} : {}"#,
+                    base_dir,
+                    err
                 )
             }
         } else if !base_dir_path.is_dir() {
@@ -207,135 +1310,2797 @@ impl Volume for FileSystemBackend {
                 err
             )
         } else if !read_only {
-            // try to write a random file
-            let _ = tempfile_in(&base_dir)
-                .map(|mut f| writeln!(f, "test"))
-                .map_err(|err| {
-                    zerror!(
-                        r#"Cannot create writeable File System Storage on "dir"={:?} : {}"#,
-                        base_dir,
-                        err
-                    )
-                })?;
+            match writability_check {
+                WritabilityCheck::Tempfile => {
+                    // try to write a random file (without name, thus removed by the OS when dropped here,
+                    // whatever happens next)
+                    let _ = tempfile_in(&base_dir)
+                        .map(|mut f| writeln!(f, "test"))
+                        .map_err(|err| {
+                            zerror!(
+                                r#"Cannot create writeable File System Storage on "dir"={:?} : {}"#,
+                                base_dir,
+                                err
+                            )
+                        })?;
+                }
+                WritabilityCheck::Metadata => {
+                    check_writable_dir(&base_dir_path).map_err(|err| {
+                        zerror!(
+                            r#"Cannot create writeable File System Storage on "dir"={:?} : {}"#,
+                            base_dir,
+                            err
+                        )
+                    })?
+                }
+                WritabilityCheck::None => (),
+            }
         }
 
+        // with "dir_symlinks" = "resolve", all the file paths are relative to the canonical path of the storage
+        // directory, whatever the symbolic links to it. With "keep", they're relative to the configured path,
+        // so replacing a symbolic link in this path is seen as a replacement of the storage directory.
+        let canonical_base_dir = dunce::canonicalize(&base_dir).map_err(|err| {
+            zerror!(
+                r#"Cannot create File System Storage on "dir"={:?} : {}"#,
+                base_dir,
+                err
+            )
+        })?;
+        let (base_dir, other_base_dir) = if resolve_dir_symlinks {
+            (canonical_base_dir, base_dir)
+        } else {
+            (base_dir, canonical_base_dir)
+        };
+
+        let temp_dir = match config.volume_cfg.get(PROP_STORAGE_TEMP_DIR) {
+            Some(serde_json::Value::String(_)) if read_only => {
+                warn!(
+                    "Storage on {} is read-only: `{}` is ignored",
+                    config.key_expr, PROP_STORAGE_TEMP_DIR
+                );
+                None
+            }
+            Some(serde_json::Value::String(dir)) => {
+                let temp_dir = resolve_dir(&self.root, PROP_STORAGE_TEMP_DIR, dir)?;
+                check_temp_dir(&temp_dir, &base_dir)?;
+                Some(temp_dir)
+            }
+            None => None,
+            Some(v) => bail!(
+                r#"Invalid value for File System Storage configuration: `{}` must be a string: {:?}"#,
+                PROP_STORAGE_TEMP_DIR,
+                v
+            ),
+        };
+
         config
             .volume_cfg
             .as_object_mut()
             .unwrap()
             .insert("dir_full_path".into(), base_dir.to_string_lossy().into());
 
-        log::debug!(
+        tracing::debug!(
             "Storage on {} will store files in {}",
             config.key_expr,
             base_dir.display()
         );
 
-        let files_mgr = FilesMgr::new(base_dir, follow_links, keep_mime, on_closure).await?;
-        Ok(Box::new(FileSystemStorage {
+        // check that no running storage uses the same directory, or a parent or a sub-directory of it
+        let dir_registration =
+            ActiveDirRegistration::register(&self.active_dirs, &base_dir, overlapping_dir_error)?;
+
+        let events = if publish_events {
+            let session = self.get_session(PROP_STORAGE_PUBLISH_EVENTS).await?;
+            Some(StorageEvents::new(
+                Arc::downgrade(&session),
+                &config.key_expr,
+                config.strip_prefix.clone(),
+            )?)
+        } else {
+            None
+        };
+
+        let files_mgr = Arc::new(
+            FilesMgr::new(
+                base_dir,
+                follow_links,
+                keep_mime,
+                default_encoding,
+                on_closure,
+                shard_depth,
+                fsync,
+                reserved_prefix,
+                quarantine_unreadable,
+                rotation,
+                path_template,
+                skip_identical_writes,
+                pre_write_hook,
+                decompress_gz_on_read,
+                max_decompressed_bytes,
+                text_safe_storage,
+                text_detection,
+                events,
+                io_watchdog,
+                strip_extensions,
+                filename_encoding,
+                temp_dir,
+                glob_mode,
+                file_mode,
+                dir_mode,
+                on_missing_file,
+                mime_guesser,
+                io_advice,
+                mapped_files,
+                encoding_subdirs,
+            )
+            .await?,
+        );
+        // the data-infos might have been recorded with the other path of the storage directory
+        // (i.e. before "dir_symlinks" was changed)
+        if base_dir != other_base_dir && !read_only {
+            let rebased = files_mgr.rebase_data_info(&other_base_dir).await?;
+            if rebased > 0 {
+                tracing::info!(
+                    "Storage on {}: {} data-infos recorded for {:?} re-keyed for {:?}",
+                    config.key_expr,
+                    rebased,
+                    other_base_dir,
+                    files_mgr.base_dir()
+                );
+            }
+        }
+        // create the directories existing whatever the keys stored in the schema's branches
+        if let (Some(schema), false) = (&schema, read_only) {
+            for dir in schema.static_dirs() {
+                if files_mgr.is_reserved_path(&dir) {
+                    bail!(
+                        r#"Invalid property "{}": "{}" is reserved for the storage's internal use"#,
+                        PROP_STORAGE_SCHEMA,
+                        dir
+                    );
+                }
+                if !files_mgr.create_key_dir(&dir)? {
+                    debug!(
+                        "Storage on {}: the directories of the `{}` are not created, as the keys are not stored at their path",
+                        config.key_expr, PROP_STORAGE_SCHEMA
+                    );
+                    break;
+                }
+            }
+        }
+        // record the storage's key expression, to migrate the stored keys if it changed since the last run
+        let meta = StorageMeta {
+            key_expr: config.key_expr.to_string(),
+            strip_prefix: config.strip_prefix.as_ref().map(|p| p.to_string()),
+        };
+        let hash_chain = if hash_chain {
+            Some(HashChain::open(files_mgr.base_dir())?)
+        } else {
+            None
+        };
+        let warmup = warm_up_storage(
+            files_mgr.clone(),
+            config.key_expr.clone(),
+            meta,
+            auto_migrate_on_key_change,
+            read_only,
+            on_ambiguous_file,
+            schema.clone(),
+        );
+        let warmup_progress = match warmup_timeout {
+            None => {
+                warmup.await?;
+                None
+            }
+            Some(warmup_timeout) => {
+                // wait for the warm-up at most warmup_timeout, then let it complete in background
+                let progress = Arc::new(WarmupProgress::default());
+                let handle = task::spawn(run_warmup(warmup, progress.clone()));
+                if let Ok(result) = async_std::future::timeout(warmup_timeout, handle).await {
+                    result?;
+                } else {
+                    tracing::info!(
+                        "Storage on {}: warm-up not completed after {:?}, it continues in background",
+                        config.key_expr,
+                        warmup_timeout
+                    );
+                }
+                Some(progress)
+            }
+        };
+
+        if !prewarm.is_empty() {
+            // if strip_prefix is set, strip it from the key expressions (as done for queries)
+            let sub_keyexprs: Vec<OwnedKeyExpr> = prewarm
+                .iter()
+                .flat_map(|ke| match &config.strip_prefix {
+                    Some(prefix) => ke
+                        .strip_prefix(prefix)
+                        .into_iter()
+                        .map(|sub_ke| sub_ke.to_owned())
+                        .collect(),
+                    None => vec![ke.clone()],
+                })
+                .collect();
+            task::spawn(prewarm_files(Arc::downgrade(&files_mgr), sub_keyexprs));
+        }
+
+        task::spawn(watch_base_dir(Arc::downgrade(&files_mgr)));
+        if let Some(tail_source) = tail_source {
+            task::spawn(tail_file(
+                self.get_session(PROP_STORAGE_TAIL_SOURCE).await?,
+                Arc::downgrade(&files_mgr),
+                tail_source,
+            ));
+        }
+
+        if let Some(period) = watch_external_deletes {
+            task::spawn(watch_external_deletes(
+                self.get_session(PROP_STORAGE_WATCH_EXTERNAL_DELETES_MS)
+                    .await?,
+                Arc::downgrade(&files_mgr),
+                config.strip_prefix.clone(),
+                period,
+            ));
+        }
+
+        // resume the bulk deletion interrupted in the storage directory, if any
+        let bulk_delete = Arc::new(BulkDeleteProgress::default());
+        if !read_only {
+            if let Some(journal) = BulkDeleteJournal::open(files_mgr.base_dir())? {
+                warn!(
+                    "Resume bulk deletion of {} keys in {:?}, interrupted after {} keys",
+                    journal.zpaths.len(),
+                    files_mgr.base_dir(),
+                    journal.processed
+                );
+                bulk_delete.start(&journal);
+                task::spawn(run_bulk_delete(
+                    Arc::downgrade(&files_mgr),
+                    journal,
+                    bulk_delete_batch_size,
+                    bulk_delete_concurrency,
+                    schema.clone(),
+                    bulk_delete.clone(),
+                ));
+            }
+        }
+
+        let change_notifier = match change_notify_key {
+            Some(key_expr) => Some(ChangeNotifier {
+                session: self.get_session(PROP_STORAGE_CHANGE_NOTIFY_KEY).await?,
+                key_expr,
+            }),
+            None => None,
+        };
+
+        let publish_progress = match publish_on_start {
+            Some(mode) => {
+                let session = self.get_session(PROP_STORAGE_PUBLISH_ON_START).await?;
+                let progress = Arc::new(PublishProgress::default());
+                task::spawn(publish_stored_files(
+                    session,
+                    Arc::downgrade(&files_mgr),
+                    config.strip_prefix.clone(),
+                    mode,
+                    publish_on_start_rate,
+                    progress.clone(),
+                ));
+                Some(progress)
+            }
+            None => None,
+        };
+
+        Ok(FileSystemStorage {
             config,
+            root: self.root.clone(),
             files_mgr,
             read_only,
-        }))
+            snapshot_queries,
+            reply_fragment_bytes,
+            query_budget,
+            max_replies_per_query,
+            max_archive_bytes,
+            reply_throttle,
+            write_throttle,
+            catchup_throttle,
+            query_read_cache_bytes,
+            admin_query_param,
+            admin_write_ops,
+            max_retention,
+            hash_chain,
+            change_notifier,
+            conflict_log,
+            skip_identical,
+            missing_timestamp,
+            delete_missing,
+            key_dir_conflict_error,
+            canonicalize_keys,
+            strict_keyexpr,
+            read_encoding_override,
+            publish_progress,
+            warmup_progress,
+            schema,
+            pessimistic_locking,
+            _dir_registration: Arc::new(dir_registration),
+            hooks: self.hooks.clone(),
+            bulk_delete,
+            bulk_delete_batch_size,
+            bulk_delete_concurrency,
+            puts: 0,
+            deletes: 0,
+            bytes_written: 0,
+            puts_outdated: 0,
+            puts_identical: 0,
+            puts_throttled: 0,
+            deletes_outdated: 0,
+            sample_errors: 0,
+            samples_foreign: 0,
+            writability_unverified: !read_only
+                && matches!(writability_check, WritabilityCheck::None),
+            queries_overflowed: 0,
+            transaction: None,
+            snapshot_view_ts: None,
+        })
     }
+}
 
-    fn incoming_data_interceptor(&self) -> Option<Arc<dyn Fn(Sample) -> Sample + Sync + Send>> {
+// Publish all the stored keys (and their values in "full" mode), at most `rate` publications per second.
+// The FilesMgr is only weakly referenced, so the publication stops if the storage is closed meanwhile.
+async fn publish_stored_files(
+    session: Arc<Session>,
+    files_mgr: Weak<FilesMgr>,
+    strip_prefix: Option<OwnedKeyExpr>,
+    mode: PublishOnStart,
+    rate: u64,
+    progress: Arc<PublishProgress>,
+) {
+    let zpaths: Vec<String> = match files_mgr.upgrade() {
+        Some(files_mgr) => files_mgr
+            .matching_files(unsafe { keyexpr::from_str_unchecked("**") })
+            .map(|zfile| get_trimmed_keyexpr(zfile.zpath.as_ref()).to_string())
+            .collect(),
+        None => return,
+    };
+    progress.total.store(zpaths.len() as u64, Ordering::Relaxed);
+    let period = if rate > 0 {
+        Some(Duration::from_secs_f64(1.0 / rate as f64))
+    } else {
         None
+    };
+
+    for zpath in zpaths {
+        let value = match files_mgr.upgrade() {
+            Some(files_mgr) => match mode {
+                PublishOnStart::Keys => Value::new(Vec::<u8>::new().into()),
+                PublishOnStart::Full => {
+                    match files_mgr.read_file(&files_mgr.to_zfile(&zpath)).await {
+                        Ok(Some((value, _))) => value,
+                        Ok(None) => continue, // file removed in the meantime
+                        Err(e) => {
+                            warn!("Publishing stored file {} failed: {}", zpath, e);
+                            continue;
+                        }
+                    }
+                }
+            },
+            None => {
+                debug!("Storage closed: stop publishing its stored files");
+                return;
+            }
+        };
+        // if strip_prefix is set, prefix it back to the zenoh path of this file
+        let key_expr = match &strip_prefix {
+            Some(prefix) => prefix.join(&zpath),
+            None => OwnedKeyExpr::try_from(zpath.clone()),
+        };
+        match key_expr {
+            Ok(key_expr) => {
+                if let Err(e) = session.put(key_expr, value).res().await {
+                    warn!("Publishing stored file {} failed: {}", zpath, e);
+                }
+            }
+            Err(e) => warn!("Publishing stored file {} failed: {}", zpath, e),
+        }
+        progress.published.fetch_add(1, Ordering::Relaxed);
+        if let Some(period) = period {
+            task::sleep(period).await;
+        }
     }
+    progress.done.store(true, Ordering::Relaxed);
+    debug!(
+        "Published {} stored files",
+        progress.published.load(Ordering::Relaxed)
+    );
+}
 
-    fn outgoing_data_interceptor(&self) -> Option<Arc<dyn Fn(Sample) -> Sample + Sync + Send>> {
-        None
+// Publication of a notification on each change of a storage ("change_notify_key")
+struct ChangeNotifier {
+    session: Arc<Session>,
+    key_expr: OwnedKeyExpr,
+}
+
+impl ChangeNotifier {
+    // Publish the changed key, the kind of change and its timestamp, as a JSON value.
+    // A failure is only logged, as the change itself succeeded.
+    async fn notify(&self, key: &keyexpr, kind: SampleKind, timestamp: &Timestamp) {
+        let notification = serde_json::json!({
+            "key": key.as_str(),
+            "kind": kind.to_string(),
+            "timestamp": timestamp.to_string(),
+        });
+        let value = Value::new(notification.to_string().into_bytes().into())
+            .encoding(KnownEncoding::AppJson.into());
+        if let Err(e) = self.session.put(self.key_expr.clone(), value).res().await {
+            warn!(
+                "Failed to publish change notification of {} on {}: {}",
+                key, self.key_expr, e
+            );
+        }
     }
 }
 
-struct FileSystemStorage {
-    config: StorageConfig,
-    files_mgr: FilesMgr,
-    read_only: bool,
+// The PUT and DELETE received during a transaction, staged to be applied all at once on commit
+#[derive(Default)]
+struct FileSystemTransaction {
+    ops: Vec<(StagedOp, StagedSample)>,
 }
 
-impl FileSystemStorage {
-    async fn reply_with_matching_files(&self, query: &Query, path_expr: &str) {
-        match path_expr.try_into() {
-            Ok(ke) => {
-                for zfile in self.files_mgr.matching_files(ke) {
-                    let trimmed_zpath = get_trimmed_keyexpr(zfile.zpath.as_ref());
-                    let trimmed_zfile = self.files_mgr.to_zfile(trimmed_zpath);
-                    self.reply_with_file(query, &trimmed_zfile).await;
+// What's left to do for a staged PUT or DELETE once committed
+struct StagedSample {
+    key_expr: OwnedKeyExpr,
+    kind: SampleKind,
+    timestamp: Timestamp,
+    // the SHA-256 of the value for the "hash_chain"
+    content_sha256: Option<String>,
+    len: u64,
+}
+
+impl FileSystemTransaction {
+    // Stage an operation, replacing the one staged on the same file if older
+    // (with the same rule than for stored files: in case of equal timestamps, a DELETE wins over a PUT).
+    // Return false if the operation is dropped as out-of-date.
+    fn stage(&mut self, op: StagedOp, sample: StagedSample) -> bool {
+        match self
+            .ops
+            .iter()
+            .position(|(staged, _)| staged.file() == op.file())
+        {
+            Some(i) => {
+                let staged_ts = self.ops[i].0.timestamp();
+                if op.timestamp() < staged_ts
+                    || (op.timestamp() == staged_ts && matches!(sample.kind, SampleKind::Put))
+                {
+                    return false;
                 }
+                self.ops[i] = (op, sample);
             }
-            Err(e) => log::error!("Couldn't convert `{}` to key expression: {}", path_expr, e),
+            None => self.ops.push((op, sample)),
         }
+        true
     }
+}
 
-    async fn reply_with_file(&self, query: &Query, zfile: &ZFile<'_>) {
-        match self.files_mgr.read_file(zfile).await {
-            Ok(Some((value, timestamp))) => {
-                debug!(
-                    "Replying to query on {} with file {:?}",
-                    query.selector(),
-                    zfile,
+// Registration of a storage's directory in the backend's active directories, removed on drop
+struct ActiveDirRegistration {
+    active_dirs: Arc<std::sync::Mutex<Vec<PathBuf>>>,
+    dir: PathBuf,
+}
+
+impl ActiveDirRegistration {
+    // Register a storage's directory, failing (or warning) if it overlaps the directory of a running storage
+    fn register(
+        active_dirs: &Arc<std::sync::Mutex<Vec<PathBuf>>>,
+        dir: &Path,
+        overlap_error: bool,
+    ) -> ZResult<Self> {
+        // compare the canonical paths, as symbolic links could hide an overlap
+        let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+        let mut dirs = active_dirs.lock().unwrap();
+        if let Some(other) = dirs
+            .iter()
+            .find(|other| dir.starts_with(other) || other.starts_with(&dir))
+        {
+            if overlap_error {
+                bail!(
+                    r#"Cannot create File System Storage on "dir"={:?} : it overlaps the directory {:?} of another storage"#,
+                    dir,
+                    other
+                )
+            }
+            warn!(
+                "File System Storage on {:?} overlaps the directory {:?} of another storage: they might corrupt each other",
+                dir, other
+            );
+        }
+        dirs.push(dir.clone());
+        Ok(ActiveDirRegistration {
+            active_dirs: active_dirs.clone(),
+            dir,
+        })
+    }
+}
+
+impl Drop for ActiveDirRegistration {
+    fn drop(&mut self) {
+        let mut dirs = self.active_dirs.lock().unwrap();
+        if let Some(i) = dirs.iter().position(|d| *d == self.dir) {
+            dirs.remove(i);
+        }
+    }
+}
+
+// Progress of the warm-up of a storage, when it can continue in background ("warmup_timeout_secs")
+#[derive(Default)]
+struct WarmupProgress {
+    done: AtomicBool,
+    error: std::sync::Mutex<Option<String>>,
+}
+
+// The scans of the existing files at storage creation, which can be long for a big storage directory:
+// the migration of the stored keys if the storage's key expression changed, the removal of the old rotation
+// directories and the resolution of the ambiguous files. If it continues in background ("warmup_timeout_secs"),
+// the storage accepts PUT and DELETE meanwhile (a key written meanwhile is not overwritten by the migration).
+async fn warm_up_storage(
+    files_mgr: Arc<FilesMgr>,
+    key_expr: OwnedKeyExpr,
+    meta: StorageMeta,
+    auto_migrate_on_key_change: bool,
+    read_only: bool,
+    on_ambiguous_file: Option<OnAmbiguousFile>,
+    schema: Option<Arc<KeySchema>>,
+) -> ZResult<()> {
+    // migrate the stored keys if the storage's key expression changed since the last run
+    // (the new key expression is only recorded once they're migrated, so they can still be migrated later)
+    let mut up_to_date = true;
+    match StorageMeta::load(files_mgr.base_dir())? {
+        Some(previous) if previous != meta => {
+            if auto_migrate_on_key_change && !read_only {
+                let moved = files_mgr
+                    .migrate_keys(|zpath| previous.migrate_zpath(&meta, zpath))
+                    .await?;
+                tracing::info!(
+                    "Storage on {}: migrated {} keys stored for key_expr {} (strip_prefix: {:?})",
+                    key_expr,
+                    moved,
+                    previous.key_expr,
+                    previous.strip_prefix
                 );
-                // if strip_prefix is set, prefix it back to the zenoh path of this ZFile
-                let zpath = match &self.config.strip_prefix {
-                    Some(prefix) => prefix.join(zfile.zpath.as_ref()).unwrap(),
-                    None => zfile.zpath.as_ref().try_into().unwrap(),
-                };
-                if let Err(e) = query
-                    .reply(Sample::new(zpath, value).with_timestamp(timestamp))
-                    .res()
-                    .await
-                {
-                    log::error!(
-                        "Error replying to query on {} with file {}: {}",
-                        query.selector(),
-                        zfile,
-                        e
+            } else {
+                up_to_date = false;
+                warn!(
+                    "Storage on {}: the storage directory was used for key_expr {} (strip_prefix: {:?}), and its keys are not migrated (see `{}`)",
+                    key_expr,
+                    previous.key_expr,
+                    previous.strip_prefix,
+                    PROP_STORAGE_AUTO_MIGRATE_ON_KEY_CHANGE
+                );
+            }
+        }
+        _ => (),
+    }
+    if !read_only {
+        if up_to_date {
+            meta.save(files_mgr.base_dir())?;
+        }
+        let write = files_mgr.wait_background_write().await;
+        files_mgr.remove_old_rotation_dirs().await?;
+        drop(write);
+        files_mgr.remove_replaced_encoding_copies().await?;
+    }
+    if let Some(policy) = on_ambiguous_file {
+        if read_only {
+            warn!(
+                "Storage on {} is read-only: `{}` is ignored",
+                key_expr, PROP_STORAGE_ON_AMBIGUOUS_FILE
+            );
+        } else {
+            files_mgr.resolve_ambiguous_files(&policy).await?;
+        }
+    }
+    if let Some(schema) = schema {
+        schema.compute_usage(&files_mgr);
+    }
+
+    Ok(())
+}
+
+// Run a warm-up in background, recording its progress
+async fn run_warmup(
+    warmup: impl Future<Output = ZResult<()>>,
+    progress: Arc<WarmupProgress>,
+) -> ZResult<()> {
+    let result = warmup.await;
+    if let Err(e) = &result {
+        tracing::error!("Storage warm-up failed: {}", e);
+        *progress.error.lock().unwrap() = Some(e.to_string());
+    }
+    progress.done.store(true, Ordering::Relaxed);
+    result
+}
+
+// Periodically check that the storage directory was not replaced, until the storage is dropped
+async fn watch_base_dir(files_mgr: Weak<FilesMgr>) {
+    loop {
+        task::sleep(BASE_DIR_CHECK_PERIOD).await;
+        match files_mgr.upgrade() {
+            Some(files_mgr) => files_mgr.check_base_dir().await,
+            None => return,
+        }
+    }
+}
+
+// Periodically detect the stored files deleted by another process, until the storage is dropped: a tombstone is
+// recorded for each of them, and a DELETE is published for its key so the other storages (e.g. replicas) delete it.
+async fn watch_external_deletes(
+    session: Arc<Session>,
+    files_mgr: Weak<FilesMgr>,
+    strip_prefix: Option<OwnedKeyExpr>,
+    period: Duration,
+) {
+    // the files existing with a data-info at the previous check, with their timestamp
+    let mut live = LiveFiles::default();
+    loop {
+        let deleted = match files_mgr.upgrade() {
+            Some(files_mgr) => {
+                // (while the storage is quiesced, the deletions are detected once it's resumed)
+                let write = files_mgr.begin_background_write();
+                if write.is_some() {
+                    files_mgr.record_external_deletions(&mut live).await
+                } else {
+                    Vec::new()
+                }
+            }
+            None => return,
+        };
+        for zpath in deleted {
+            // if strip_prefix is set, prefix it back to the zenoh path of this file
+            let key_expr = match &strip_prefix {
+                Some(prefix) => prefix.join(&zpath),
+                None => OwnedKeyExpr::try_from(zpath.clone()),
+            };
+            match key_expr {
+                Ok(key_expr) => {
+                    if let Err(e) = session.delete(key_expr).res().await {
+                        warn!("Publishing deletion of {} failed: {}", zpath, e);
+                    }
+                }
+                Err(e) => warn!("Publishing deletion of {} failed: {}", zpath, e),
+            }
+        }
+        task::sleep(period).await;
+    }
+}
+
+// Read all the files matching the key expressions, so they are in the OS page cache for the first queries.
+// The FilesMgr is only weakly referenced, so the reads stop if the storage is closed meanwhile.
+async fn prewarm_files(files_mgr: Weak<FilesMgr>, keyexprs: Vec<OwnedKeyExpr>) {
+    let mut zpaths: Vec<String> = Vec::new();
+    match files_mgr.upgrade() {
+        Some(files_mgr) => {
+            for ke in &keyexprs {
+                if ke.contains('*') {
+                    zpaths.extend(
+                        files_mgr
+                            .matching_files(ke)
+                            .map(|zfile| get_trimmed_keyexpr(zfile.zpath.as_ref()).to_string()),
                     );
+                } else if !files_mgr.is_reserved_path(ke) {
+                    zpaths.push(ke.to_string());
                 }
-                debug!("Reply sent !!!!!");
             }
-            Ok(None) => (), // file not found, do nothing
-            Err(e) => warn!(
-                "Replying to query on {} : failed to read file {} : {}",
-                query.selector(),
-                zfile,
-                e
-            ),
+        }
+        None => return,
+    };
+
+    let mut count = 0;
+    for zpath in zpaths {
+        match files_mgr.upgrade() {
+            Some(files_mgr) => match files_mgr.read_file(&files_mgr.to_zfile(&zpath)).await {
+                Ok(Some(_)) => count += 1,
+                Ok(None) => (), // file not found, do nothing
+                Err(e) => warn!("Prewarming stored file {} failed: {}", zpath, e),
+            },
+            None => {
+                debug!("Storage closed: stop prewarming its stored files");
+                return;
+            }
         }
     }
+    debug!("Prewarmed {} stored files", count);
 }
 
-#[async_trait]
-impl Storage for FileSystemStorage {
-    fn get_admin_status(&self) -> serde_json::Value {
-        self.config.to_json_value()
+// Reply a JSON value to a query, on the query's key expression
+async fn reply_json(query: &Query, value: &serde_json::Value) -> ZResult<()> {
+    let key_expr: OwnedKeyExpr = query.selector().key_expr.as_keyexpr().to_owned();
+    let value =
+        Value::new(value.to_string().into_bytes().into()).encoding(KnownEncoding::AppJson.into());
+    query.reply(Sample::new(key_expr, value)).res().await
+}
+
+// Process a bulk deletion by batches of keys, in the background. Between the batches, the storage's other
+// operations can run. It stops if the storage is closed or on an error, to be resumed from its journal
+// when the storage is created again.
+async fn run_bulk_delete(
+    files_mgr: Weak<FilesMgr>,
+    journal: BulkDeleteJournal,
+    batch_size: usize,
+    concurrency: usize,
+    schema: Option<Arc<KeySchema>>,
+    progress: Arc<BulkDeleteProgress>,
+) {
+    if let Err(e) = process_bulk_delete(
+        files_mgr,
+        journal,
+        batch_size,
+        concurrency,
+        schema,
+        &progress,
+    )
+    .await
+    {
+        warn!("Bulk deletion interrupted: {}", e);
+        *progress.error.lock().unwrap() = Some(e.to_string());
+    }
+    progress.running.store(false, Ordering::Release);
+}
+
+async fn process_bulk_delete(
+    files_mgr: Weak<FilesMgr>,
+    mut journal: BulkDeleteJournal,
+    batch_size: usize,
+    concurrency: usize,
+    schema: Option<Arc<KeySchema>>,
+    progress: &BulkDeleteProgress,
+) -> ZResult<()> {
+    // the files are removed in parallel, by at most `concurrency` threads
+    let pool = Arc::new(
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(concurrency)
+            .build()
+            .map_err(|e| zerror!("Failed to create bulk deletion threads: {}", e))?,
+    );
+    while journal.processed < journal.zpaths.len() {
+        let files_mgr = match files_mgr.upgrade() {
+            Some(files_mgr) => files_mgr,
+            None => {
+                debug!("Storage closed: stop bulk deletion (resumed when the storage is created again)");
+                return Ok(());
+            }
+        };
+        // the bulk deletion is paused between 2 batches while the storage is quiesced
+        let write = files_mgr.begin_background_write();
+        if write.is_none() {
+            drop(write);
+            drop(files_mgr);
+            task::sleep(BULK_DELETE_QUIESCED_PERIOD).await;
+            continue;
+        }
+        let end = (journal.processed + batch_size).min(journal.zpaths.len());
+        let batch = journal.zpaths[journal.processed..end].to_vec();
+        let count = batch.len() as u64;
+        let (deleted, failed) = files_mgr
+            .delete_files_batch(batch, &journal.timestamp, pool.clone())
+            .await?;
+        if let Some(schema) = &schema {
+            for (zpath, size) in &deleted {
+                if let Some(branch) = schema.find(zpath) {
+                    branch.record_delete(*size);
+                }
+            }
+        }
+        progress
+            .deleted
+            .fetch_add(deleted.len() as u64, Ordering::Relaxed);
+        progress
+            .failed
+            .fetch_add(failed.len() as u64, Ordering::Relaxed);
+        progress.skipped.fetch_add(
+            count - deleted.len() as u64 - failed.len() as u64,
+            Ordering::Relaxed,
+        );
+        journal.record_processed(end)?;
+        drop(write);
+        drop(files_mgr);
+        task::yield_now().await;
+    }
+    debug!("Bulk deletion of {} keys completed", journal.zpaths.len());
+    journal.complete()
+}
+
+// A SHA-256 digest over some (key, timestamp) entries, in the order of update
+#[derive(Default)]
+struct EntriesDigest {
+    hasher: Sha256,
+    count: usize,
+}
+
+impl EntriesDigest {
+    fn update(&mut self, key: &keyexpr, timestamp: &Timestamp) {
+        self.hasher.update(key.as_str().as_bytes());
+        self.hasher.update(b"\0");
+        self.hasher.update(timestamp.to_string().as_bytes());
+        self.hasher.update(b"\n");
+        self.count += 1;
+    }
+
+    fn value(&self) -> String {
+        format!("{:x}", self.hasher.clone().finalize())
+    }
+}
+
+// How to timestamp the samples received without timestamp
+enum MissingTimestamp {
+    // use the reception time
+    Reception,
+    // refuse the sample
+    Reject,
+    // use the epoch time (i.e. the sample loses any conflict)
+    Epoch,
+}
+
+// What to do on a DELETE for a key that is unknown (no file and no data-info)
+enum DeleteMissing {
+    // record a tombstone with the DELETE's timestamp
+    Tombstone,
+    // drop the DELETE
+    Ignore,
+}
+
+// How the storage directory is checked as writeable at storage creation ("writability_check")
+enum WritabilityCheck {
+    // write a temporary file
+    Tempfile,
+    // check the directory's permissions, without creating any file
+    Metadata,
+    // no check: a storage directory not writeable is reported by the failed PUT and DELETE
+    None,
+}
+
+// The bounds of a GET query replying values: its deadline for "query_budget_ms" and its number of replies
+// for "max_replies_per_query" (if configured)
+struct QueryBounds {
+    deadline: Option<Instant>,
+    // the number of files which can still be replied
+    remaining: Option<u64>,
+    // the number of matching files found not replied, once the maximum number of replies is reached
+    // (the matching files aren't searched any further, so it's a lower bound)
+    omitted: u64,
+    // the zenoh paths already replied, if the query has several sub-keyexprs (which might overlap)
+    replied: Option<HashSet<String>>,
+}
+
+impl QueryBounds {
+    // Check if the query exceeded its deadline
+    fn is_over_budget(&self) -> bool {
+        self.deadline
+            .map_or(false, |deadline| Instant::now() >= deadline)
+    }
+
+    // Record a matching zenoh path: return false if it was already replied for another sub-keyexpr
+    fn is_first_match(&mut self, zpath: &str) -> bool {
+        match &mut self.replied {
+            Some(replied) => replied.insert(zpath.to_string()),
+            None => true,
+        }
+    }
+
+    // Check if the maximum number of replies was reached, and a matching file omitted
+    fn is_capped(&self) -> bool {
+        self.omitted > 0
+    }
+
+    // Count a matching file: return true if it can be replied, or false if it's omitted
+    fn take_reply(&mut self) -> bool {
+        match &mut self.remaining {
+            Some(0) => {
+                self.omitted += 1;
+                false
+            }
+            Some(remaining) => {
+                *remaining -= 1;
+                true
+            }
+            None => true,
+        }
+    }
+}
+
+// A tar archive replied to a "_as=tar" query, built incrementally and replied by parts of part_size bytes.
+// An archive replied in a single part has the "application/x-tar" encoding. Otherwise, the encoding of each part
+// has a ";part=<index>" suffix, except the last one whose suffix is ";part=<index>/<count>".
+struct ArchiveReply {
+    builder: tar::Builder<Vec<u8>>,
+    part_size: usize,
+    // number of parts already built
+    parts: usize,
+    // total size of the values appended
+    size: u64,
+}
+
+impl ArchiveReply {
+    fn new(part_size: usize) -> Self {
+        ArchiveReply {
+            builder: tar::Builder::new(Vec::new()),
+            part_size,
+            parts: 0,
+            size: 0,
+        }
+    }
+
+    // Append a value as an entry of the archive. On error, the archive is left unchanged.
+    fn append(&mut self, path: &str, content: &[u8], timestamp: &Timestamp) -> std::io::Result<()> {
+        let len = self.builder.get_ref().len();
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(timestamp.get_time().as_secs() as u64);
+        if let Err(e) = self.builder.append_data(&mut header, path, content) {
+            // (an entry might be partially written, e.g. after a long path extension)
+            self.builder.get_mut().truncate(len);
+            return Err(e);
+        }
+        self.size += content.len() as u64;
+        Ok(())
+    }
+
+    // Take the next part of the archive if it's built, leaving at least one byte for the last part
+    fn next_part(&mut self) -> Option<Value> {
+        let buffer = self.builder.get_mut();
+        if buffer.len() <= self.part_size {
+            return None;
+        }
+        let rest = buffer.split_off(self.part_size);
+        let part = std::mem::replace(buffer, rest);
+        let value = Value::new(part.into()).encoding(Encoding::from(format!(
+            "{};part={}",
+            TAR_ENCODING, self.parts
+        )));
+        self.parts += 1;
+        Some(value)
+    }
+
+    // Finish the archive, and return its remaining parts
+    fn finish(mut self) -> ZResult<Vec<Value>> {
+        self.builder
+            .finish()
+            .map_err(|e| zerror!("Failed to build tar archive: {}", e))?;
+        let mut parts = Vec::new();
+        while let Some(part) = self.next_part() {
+            parts.push(part);
+        }
+        let last = std::mem::take(self.builder.get_mut());
+        let encoding = if self.parts == 0 {
+            TAR_ENCODING.to_string()
+        } else {
+            format!("{};part={}/{}", TAR_ENCODING, self.parts, self.parts + 1)
+        };
+        parts.push(Value::new(last.into()).encoding(Encoding::from(encoding)));
+        Ok(parts)
+    }
+}
+
+// Split a Value in fragments of at most fragment_size bytes. Each fragment's encoding
+// is the Value's encoding with a ";part=<index>/<count>" suffix, for the querier to re-assemble them.
+fn fragment_value(value: &Value, fragment_size: usize) -> Vec<Value> {
+    let payload = value.payload.contiguous();
+    let count = (payload.len() + fragment_size - 1) / fragment_size;
+    payload
+        .chunks(fragment_size)
+        .enumerate()
+        .map(|(index, chunk)| {
+            let suffix = format!("{};part={}/{}", value.encoding.suffix(), index, count);
+            Value::new(chunk.to_vec().into()).encoding(Encoding::WithSuffix(
+                *value.encoding.prefix(),
+                suffix.into(),
+            ))
+        })
+        .collect()
+}
+
+struct FileSystemStorage {
+    config: StorageConfig,
+    root: PathBuf,
+    files_mgr: Arc<FilesMgr>,
+    read_only: bool,
+    snapshot_queries: bool,
+    reply_fragment_bytes: u64,
+    // set if "query_budget_ms" is configured
+    query_budget: Option<Duration>,
+    // set if "max_replies_per_query" is configured
+    max_replies_per_query: Option<u64>,
+    // unless "max_archive_bytes" is 0
+    max_archive_bytes: Option<u64>,
+    // set if "reply_max_bytes_per_sec" is configured
+    reply_throttle: Option<Arc<ReplyThrottle>>,
+    // set if "min_write_interval_ms" is configured
+    write_throttle: Option<WriteThrottle>,
+    // the pause between the chunks of the listing of all the entries, if "catchup_throttle_us" is configured
+    catchup_throttle: Option<Duration>,
+    // memory budget of the cache of file contents for a query matching several files (0 to disable it)
+    query_read_cache_bytes: usize,
+    // the query parameter triggering a reserved operation
+    admin_query_param: String,
+    // if false, the reserved operations checked by requires_admin_write_ops() are refused
+    admin_write_ops: bool,
+    // the maximum duration a key can be made immutable for ("max_retention_secs")
+    max_retention: Duration,
+    // set if "hash_chain" is enabled
+    hash_chain: Option<HashChain>,
+    // set if "change_notify_key" is configured
+    change_notifier: Option<ChangeNotifier>,
+    // maximum number of samples dropped as out-of-date recorded by "conflict_log" (0 if disabled)
+    conflict_log: u64,
+    // if true, a PUT with the same content and encoding than the stored file is dropped
+    skip_identical: bool,
+    missing_timestamp: MissingTimestamp,
+    delete_missing: DeleteMissing,
+    // if true, a PUT on a key which is also a prefix of stored keys (or the reverse) is refused
+    key_dir_conflict_error: bool,
+    // if true, the keys of the samples are canonicalized (see canonicalize_key()) before being stored
+    canonicalize_keys: bool,
+    // if true, the samples whose key doesn't intersect the storage's key expression are refused
+    strict_keyexpr: bool,
+    read_encoding_override: Option<TargetEncoding>,
+    publish_progress: Option<Arc<PublishProgress>>,
+    // set if "warmup_timeout_secs" is configured
+    warmup_progress: Option<Arc<WarmupProgress>>,
+    // set if "schema" is configured
+    schema: Option<Arc<KeySchema>>,
+    // if true, a PUT or DELETE holds its file's lock from its timestamp check until its write, otherwise it takes
+    // no lock ("locking_mode")
+    pessimistic_locking: bool,
+    // unregisters the storage's directory from the backend when the storage (and its snapshot views) are dropped
+    _dir_registration: Arc<ActiveDirRegistration>,
+    hooks: Option<Arc<dyn StorageHooks>>,
+    // the bulk deletion in progress (or the last one), and its options
+    bulk_delete: Arc<BulkDeleteProgress>,
+    bulk_delete_batch_size: usize,
+    bulk_delete_concurrency: usize,
+    // number of PUT and DELETE stored, and of bytes written by the PUT
+    puts: u64,
+    deletes: u64,
+    bytes_written: u64,
+    // number of PUT and DELETE dropped as out-of-date
+    puts_outdated: u64,
+    deletes_outdated: u64,
+    // number of PUT dropped by "skip_identical"
+    puts_identical: u64,
+    // number of PUT dropped by "min_write_interval_ms"
+    puts_throttled: u64,
+    // number of PUT and DELETE which failed
+    sample_errors: u64,
+    // number of PUT and DELETE refused by "strict_keyexpr"
+    samples_foreign: u64,
+    // set with "writability_check" = "none", until a PUT or DELETE succeeds
+    writability_unverified: bool,
+    // number of GET whose matching files exceeded "max_replies_per_query"
+    queries_overflowed: u64,
+    // set between begin_transaction() and commit() or rollback()
+    transaction: Option<FileSystemTransaction>,
+    // set on a point-in-time view created by with_snapshot_ts()
+    snapshot_view_ts: Option<Timestamp>,
+}
+
+// Point-in-time views, used by the queries with a "_snapshot" parameter
+impl FileSystemStorage {
+    // Create a read-only view of this storage, backed by the same FilesMgr, only replying the entries
+    // whose timestamp is not after `ts`. As the storage keeps only the last version of each key, an entry
+    // modified or deleted after `ts` is not replied at all (rather than at its version as of `ts`).
+    fn with_snapshot_ts(&self, ts: Timestamp) -> FileSystemStorage {
+        FileSystemStorage {
+            config: self.config.clone(),
+            root: self.root.clone(),
+            files_mgr: self.files_mgr.clone(),
+            read_only: true,
+            snapshot_queries: self.snapshot_queries,
+            reply_fragment_bytes: self.reply_fragment_bytes,
+            query_budget: self.query_budget,
+            max_replies_per_query: self.max_replies_per_query,
+            max_archive_bytes: self.max_archive_bytes,
+            reply_throttle: self.reply_throttle.clone(),
+            write_throttle: None,
+            catchup_throttle: self.catchup_throttle,
+            query_read_cache_bytes: self.query_read_cache_bytes,
+            admin_query_param: self.admin_query_param.clone(),
+            admin_write_ops: self.admin_write_ops,
+            max_retention: self.max_retention,
+            // the view doesn't write: no history nor notification of its (refused) changes
+            hash_chain: None,
+            change_notifier: None,
+            conflict_log: 0,
+            skip_identical: false,
+            missing_timestamp: MissingTimestamp::Reject,
+            delete_missing: DeleteMissing::Ignore,
+            key_dir_conflict_error: self.key_dir_conflict_error,
+            canonicalize_keys: self.canonicalize_keys,
+            strict_keyexpr: self.strict_keyexpr,
+            read_encoding_override: self.read_encoding_override.clone(),
+            publish_progress: None,
+            warmup_progress: None,
+            schema: self.schema.clone(),
+            pessimistic_locking: false,
+            _dir_registration: self._dir_registration.clone(),
+            hooks: self.hooks.clone(),
+            bulk_delete: self.bulk_delete.clone(),
+            bulk_delete_batch_size: self.bulk_delete_batch_size,
+            bulk_delete_concurrency: self.bulk_delete_concurrency,
+            puts: 0,
+            deletes: 0,
+            bytes_written: 0,
+            puts_outdated: 0,
+            puts_identical: 0,
+            puts_throttled: 0,
+            deletes_outdated: 0,
+            sample_errors: 0,
+            samples_foreign: 0,
+            writability_unverified: false,
+            queries_overflowed: 0,
+            transaction: None,
+            snapshot_view_ts: Some(ts),
+        }
+    }
+
+    // Check if an entry with this timestamp is visible (i.e. not in a snapshot view, or not after its time)
+    fn is_visible(&self, timestamp: &Timestamp) -> bool {
+        self.snapshot_view_ts
+            .as_ref()
+            .map_or(true, |view_ts| timestamp.get_time() <= view_ts.get_time())
+    }
+
+    // Reply a query as of the time of its "_snapshot" parameter, via a snapshot view. As the storage keeps only
+    // the last version of each key, the query is refused if a matching key was put or deleted after this time.
+    async fn reply_snapshot(&self, query: Query, snapshot: &str) -> ZResult<()> {
+        let time = snapshot
+            .split('/')
+            .next()
+            .unwrap_or_default()
+            .parse::<u64>()
+            .map(NTP64)
+            .map_err(|e| {
+                zerror!(
+                    "Invalid `{}` query parameter {:?}: {}",
+                    SNAPSHOT_QUERY_PARAM,
+                    snapshot,
+                    e
+                )
+            })?;
+        let selector = query.selector();
+        let sub_keyexprs = match &self.config.strip_prefix {
+            Some(prefix) => selector.key_expr.strip_prefix(prefix),
+            None => vec![selector.key_expr.as_keyexpr()],
+        };
+        for ke in sub_keyexprs {
+            if let Some((zpath, ts)) = self.files_mgr.find_changed_after(ke, &time).await? {
+                bail!(
+                    "Query on {} refused: {} was modified at {}, after the snapshot time {}, and the storage keeps no previous versions",
+                    selector,
+                    zpath,
+                    ts,
+                    time
+                );
+            }
+        }
+        let view_ts = Timestamp::new(time, *new_reception_timestamp().get_id());
+        let mut view = self.with_snapshot_ts(view_ts);
+        view.on_query(query).await
+    }
+}
+
+// Transactions, until the StorageTransaction trait is available in zenoh_backend_traits
+#[allow(dead_code)]
+impl FileSystemStorage {
+    // Start a transaction: the next PUT and DELETE are staged (their values written in temporary files),
+    // and applied only on commit()
+    fn begin_transaction(&mut self) -> ZResult<()> {
+        if self.read_only {
+            bail!("Cannot start a transaction on a read-only File System Storage")
+        }
+        if self.transaction.is_some() {
+            bail!("A transaction is already in progress")
+        }
+        if self.schema.is_some() {
+            bail!(
+                "Cannot start a transaction on a File System Storage with a `{}`: the usage of its branches is not tracked in transactions",
+                PROP_STORAGE_SCHEMA
+            )
+        }
+        self.transaction = Some(FileSystemTransaction::default());
+        Ok(())
+    }
+
+    // Apply all the PUT and DELETE staged in the transaction. If one of them fails before the files
+    // are replaced, none of them is applied.
+    async fn commit(&mut self) -> ZResult<()> {
+        let transaction = match self.transaction.take() {
+            Some(transaction) => transaction,
+            None => bail!("No transaction in progress"),
+        };
+        let (ops, samples): (Vec<StagedOp>, Vec<StagedSample>) =
+            transaction.ops.into_iter().unzip();
+        if let Err(e) = self.files_mgr.commit(ops).await {
+            // the failure might be due to the storage directory being replaced: check it now
+            self.files_mgr.check_base_dir().await;
+            self.sample_errors += samples.len() as u64;
+            return Err(e);
+        }
+        for sample in samples {
+            if let Some(hash_chain) = &self.hash_chain {
+                hash_chain.append(
+                    sample.key_expr.as_str(),
+                    &sample.timestamp,
+                    sample.content_sha256,
+                )?;
+            }
+            if let Some(notifier) = &self.change_notifier {
+                notifier
+                    .notify(&sample.key_expr, sample.kind, &sample.timestamp)
+                    .await;
+            }
+            match sample.kind {
+                SampleKind::Put => {
+                    self.puts += 1;
+                    self.bytes_written += sample.len;
+                }
+                SampleKind::Delete => self.deletes += 1,
+            }
+        }
+        Ok(())
+    }
+
+    // Discard all the PUT and DELETE staged in the transaction (their temporary files are removed)
+    fn rollback(&mut self) {
+        if let Some(transaction) = self.transaction.take() {
+            debug!(
+                "Transaction rolled back: {} operations discarded",
+                transaction.ops.len()
+            );
+        }
+    }
+}
+
+impl FileSystemStorage {
+    // Perform a reserved operation and reply its result as a JSON value
+    async fn on_admin_query(
+        &self,
+        query: &Query,
+        operation: &str,
+        params: &HashMap<String, String>,
+    ) -> ZResult<()> {
+        if operation == "metrics" {
+            // replied as text, in Prometheus exposition format
+            let key_expr: OwnedKeyExpr = query.selector().key_expr.as_keyexpr().to_owned();
+            let value = Value::new(self.admin_metrics().await.into_bytes().into())
+                .encoding(KnownEncoding::TextPlain.into());
+            return query.reply(Sample::new(key_expr, value)).res().await;
+        }
+        if self.files_mgr.is_quiesced() && is_admin_write(operation, params) {
+            return Err(RetryLater::new(format!(
+                "Operation `{}` refused: the storage is quiesced for maintenance",
+                operation
+            ))
+            .into());
+        }
+        if !self.admin_write_ops && requires_admin_write_ops(operation, params) {
+            bail!(
+                "Operation `{}` refused: it requires the `{}` property to be enabled",
+                operation,
+                PROP_STORAGE_ADMIN_WRITE_OPS
+            )
+        }
+        let result = match operation {
+            "diff" => self.admin_diff(params).await?,
+            "entries" => self.admin_entries(params).await?,
+            "restore" => self.admin_restore(params).await?,
+            "backup" => self.admin_backup(params, false).await?,
+            "restore_backup" => self.admin_backup(params, true).await?,
+            "checksum" => self.admin_checksum().await?,
+            "proof" => self.admin_proof(params)?,
+            "digest" => self.admin_digest(params).await?,
+            "conflicts" => self.admin_conflicts(params).await?,
+            "compact" => self.admin_compact().await?,
+            "retention" => self.admin_retention(params).await?,
+            "data_info" => self.admin_data_info(params).await?,
+            "delete_matching" => self.admin_delete_matching(params).await?,
+            "quiesce" => {
+                self.files_mgr.quiesce().await?;
+                serde_json::json!({ "state": "quiesced" })
+            }
+            "resume" => {
+                self.files_mgr.resume();
+                serde_json::json!({ "state": "ready" })
+            }
+            "entry_count" => serde_json::json!({
+                "count": self.files_mgr.get_exact_entry_count().await?,
+                "estimate": self.files_mgr.get_entry_count()?,
+            }),
+            "sizes" => {
+                let deadline = self.query_budget.map(|budget| Instant::now() + budget);
+                let (files, logical_bytes, disk_bytes, complete) =
+                    self.files_mgr.get_storage_sizes(deadline).await?;
+                serde_json::json!({
+                    "files": files,
+                    "logical_bytes": logical_bytes,
+                    "disk_bytes": disk_bytes,
+                    // (null if no bytes are stored)
+                    "compression_ratio": (disk_bytes > 0)
+                        .then(|| logical_bytes as f64 / disk_bytes as f64),
+                    // (true if not all the files were summed within "query_budget_ms")
+                    "truncated": !complete,
+                })
+            }
+            _ => bail!(
+                "Unsupported operation for `{}` query parameter: {}",
+                self.admin_query_param,
+                operation
+            ),
+        };
+        reply_json(query, &result).await
+    }
+
+    // List the keys of the files stored with the encoding specified by the "encoding" parameter
+    async fn admin_entries(&self, params: &HashMap<String, String>) -> ZResult<serde_json::Value> {
+        let encoding = match params.get("encoding") {
+            Some(encoding) => Encoding::from(encoding.as_str()),
+            None => bail!(r#"Missing "encoding" parameter for `entries` operation"#),
+        };
+        let keys: Vec<String> = self
+            .files_mgr
+            .get_by_encoding(&encoding)
+            .await?
+            .iter()
+            .map(|zpath| match &self.config.strip_prefix {
+                Some(prefix) => prefix.join(zpath.as_str()).unwrap().to_string(),
+                None => zpath.to_string(),
+            })
+            .collect();
+        Ok(serde_json::json!(keys))
+    }
+
+    // Format the storage's metrics in Prometheus text exposition format
+    async fn admin_metrics(&self) -> String {
+        let mut stored_files = 0u64;
+        let mut disk_usage = 0u64;
+        for zfile in self
+            .files_mgr
+            .matching_files(unsafe { keyexpr::from_str_unchecked("**") })
+        {
+            if let Some(size) = self.files_mgr.file_size(&zfile) {
+                stored_files += 1;
+                disk_usage += size;
+            }
+        }
+        let tombstones = self.files_mgr.get_deleted_entries(None).await.len() as u64;
+
+        // escape the label value as required by the exposition format
+        let labels = format!(
+            r#"{{key_expr="{}"}}"#,
+            self.config
+                .key_expr
+                .as_str()
+                .replace('\\', r"\\")
+                .replace('"', r#"\""#)
+        );
+        let metrics = [
+            ("puts_total", "counter", "PUT stored", self.puts),
+            ("deletes_total", "counter", "DELETE stored", self.deletes),
+            (
+                "written_bytes_total",
+                "counter",
+                "Bytes written by the PUT",
+                self.bytes_written,
+            ),
+            (
+                "puts_outdated_total",
+                "counter",
+                "PUT dropped as out-of-date",
+                self.puts_outdated,
+            ),
+            (
+                "deletes_outdated_total",
+                "counter",
+                "DELETE dropped as out-of-date",
+                self.deletes_outdated,
+            ),
+            (
+                "puts_identical_total",
+                "counter",
+                "PUT dropped as identical to the stored value",
+                self.puts_identical,
+            ),
+            (
+                "errors_total",
+                "counter",
+                "PUT and DELETE which failed",
+                self.sample_errors,
+            ),
+            (
+                "stored_files",
+                "gauge",
+                "Files currently stored",
+                stored_files,
+            ),
+            (
+                "tombstones",
+                "gauge",
+                "Deleted keys currently recorded",
+                tombstones,
+            ),
+            (
+                "disk_usage_bytes",
+                "gauge",
+                "Size of the files currently stored",
+                disk_usage,
+            ),
+        ];
+        let mut result = String::new();
+        for (name, kind, help, value) in metrics {
+            result.push_str(&format!(
+                "# HELP {prefix}_{name} {help}\n# TYPE {prefix}_{name} {kind}\n{prefix}_{name}{labels} {value}\n",
+                prefix = METRICS_PREFIX,
+                name = name,
+                help = help,
+                kind = kind,
+                labels = labels,
+                value = value
+            ));
+        }
+        result
+    }
+
+    // Compute the checksum of all the stored files
+    async fn admin_checksum(&self) -> ZResult<serde_json::Value> {
+        let checksum = self.files_mgr.compute_checksum().await?;
+        Ok(serde_json::json!({
+            "algorithm": checksum.algorithm,
+            "value": checksum.value,
+            "entry_count": checksum.entry_count,
+            "computed_at": checksum.computed_at.to_string(),
+        }))
+    }
+
+    // Rewrite the data-info database without its duplicate and orphan data-infos
+    async fn admin_compact(&self) -> ZResult<serde_json::Value> {
+        if self.read_only {
+            bail!("Operation `compact` refused on a read-only storage")
+        }
+        let report = self.files_mgr.compact_storage().await?;
+        Ok(serde_json::json!({
+            "duplicates_removed": report.duplicates_removed,
+            "orphans_removed": report.orphans_removed,
+            "index_entries_removed": report.index_entries_removed,
+            "records_kept": report.records_kept,
+        }))
+    }
+
+    // Compute a digest of all the entries (stored files and deleted keys) with their timestamps,
+    // and a sub-digest per prefix of the depth specified by the "depth" parameter (1 by default)
+    async fn admin_digest(&self, params: &HashMap<String, String>) -> ZResult<serde_json::Value> {
+        let depth = match params.get("depth") {
+            Some(depth) => depth.parse::<usize>().map_err(|e| {
+                zerror!(
+                    r#"Invalid "depth" parameter for `digest` operation {:?}: {}"#,
+                    depth,
+                    e
+                )
+            })?,
+            None => 1,
+        };
+        let entries = self.get_all_entries().await?;
+        // the digests are computed incrementally, not to keep per-prefix copies of the entries
+        let mut digest = EntriesDigest::default();
+        let mut by_prefix: BTreeMap<&str, EntriesDigest> = BTreeMap::new();
+        for (key, timestamp) in &entries {
+            let prefix_len = key
+                .as_str()
+                .split('/')
+                .take(depth)
+                .map(|chunk| chunk.len() + 1)
+                .sum::<usize>()
+                .saturating_sub(1);
+            digest.update(key, timestamp);
+            by_prefix
+                .entry(&key.as_str()[..prefix_len])
+                .or_default()
+                .update(key, timestamp);
+        }
+        let prefixes: serde_json::Map<String, serde_json::Value> = by_prefix
+            .into_iter()
+            .map(|(prefix, digest)| {
+                (
+                    prefix.to_string(),
+                    serde_json::json!({ "value": digest.value(), "entry_count": digest.count }),
+                )
+            })
+            .collect();
+        Ok(serde_json::json!({
+            "algorithm": "sha256",
+            "value": digest.value(),
+            "entry_count": digest.count,
+            "prefixes": prefixes,
+        }))
+    }
+
+    // Get the most recent samples dropped as out-of-date, at most "limit" of them
+    async fn admin_conflicts(
+        &self,
+        params: &HashMap<String, String>,
+    ) -> ZResult<serde_json::Value> {
+        if self.conflict_log == 0 {
+            bail!(
+                "`conflicts` operation requires the `{}` property to be set",
+                PROP_STORAGE_CONFLICT_LOG
+            )
+        }
+        let limit = match params.get("limit") {
+            Some(limit) => limit
+                .parse::<usize>()
+                .map_err(|e| zerror!(r#"Invalid "limit" parameter {:?}: {}"#, limit, e))?,
+            None => DEFAULT_CONFLICTS_LIMIT,
+        };
+        let records = self
+            .files_mgr
+            .get_conflicts(limit)
+            .await?
+            .iter()
+            .filter_map(|record| serde_json::from_slice(record).ok())
+            .collect();
+        Ok(serde_json::Value::Array(records))
+    }
+
+    // Record a sample dropped as out-of-date, if "conflict_log" is enabled.
+    // A failure is only logged, as the sample is dropped anyway.
+    async fn log_conflict(&self, sample: &Sample, sample_ts: &Timestamp, stored_ts: &Timestamp) {
+        if self.conflict_log == 0 {
+            return;
+        }
+        let payload_sha256 = match sample.kind {
+            SampleKind::Put => Some(content_sha256(&sample.value.payload)),
+            SampleKind::Delete => None,
+        };
+        let record = serde_json::json!({
+            "key": sample.key_expr.as_str(),
+            "kind": sample.kind.to_string(),
+            "incoming_timestamp": sample_ts.to_string(),
+            "stored_timestamp": stored_ts.to_string(),
+            "payload_sha256": payload_sha256,
+        });
+        if let Err(e) = self
+            .files_mgr
+            .log_conflict(record.to_string().as_bytes(), self.conflict_log)
+            .await
+        {
+            warn!(
+                "Failed to record the {} on {} dropped as out-of-date: {}",
+                sample.kind, sample.key_expr, e
+            );
+        }
+    }
+
+    // Return the hash chain proof of the last write on the key specified by the "key" parameter
+    fn admin_proof(&self, params: &HashMap<String, String>) -> ZResult<serde_json::Value> {
+        let hash_chain = match &self.hash_chain {
+            Some(hash_chain) => hash_chain,
+            None => bail!(
+                "`proof` operation requires the `{}` property to be enabled",
+                PROP_STORAGE_HASH_CHAIN
+            ),
+        };
+        let key = match params.get("key") {
+            Some(key) => key,
+            None => bail!(r#"Missing "key" parameter for `proof` operation"#),
+        };
+        match hash_chain.proof(key)? {
+            Some(proof) => Ok(proof),
+            None => bail!("No write of key {} in the hash chain", key),
+        }
+    }
+
+    // Get the date until which the key specified by the "key" parameter is immutable,
+    // or make it immutable until the date specified by the "until_ms" parameter
+    async fn admin_retention(
+        &self,
+        params: &HashMap<String, String>,
+    ) -> ZResult<serde_json::Value> {
+        let key = match params.get("key") {
+            Some(key) => OwnedKeyExpr::try_from(key.as_str())?,
+            None => bail!(r#"Missing "key" parameter for `retention` operation"#),
+        };
+        // if strip_prefix is set, strip it from the key
+        let zpath = match &self.config.strip_prefix {
+            Some(prefix) => match key.strip_prefix(prefix).as_slice() {
+                [ke] => ke.to_string(),
+                _ => bail!(
+                    "Key {} for `retention` operation doesn't start with strip_prefix '{}'",
+                    key,
+                    prefix
+                ),
+            },
+            None => key.to_string(),
+        };
+        if let Some(until_ms) = params.get("until_ms") {
+            if self.read_only {
+                bail!("Operation `retention` refused on a read-only storage")
+            }
+            let until_ms = until_ms.parse::<u64>().map_err(|e| {
+                zerror!(
+                    r#"Invalid "until_ms" parameter for `retention` operation {:?}: {}"#,
+                    until_ms,
+                    e
+                )
+            })?;
+            self.files_mgr
+                .set_immutable_until(&zpath, until_ms, self.max_retention)
+                .await?;
+        }
+        Ok(serde_json::json!({
+            "key": key.as_str(),
+            "immutable_until_ms": self.files_mgr.get_immutable_until(&zpath).await?,
+        }))
+    }
+
+    // Get the data-info of the key specified by the "key" parameter, with its extras. If a "tag" parameter
+    // is specified, set this custom tag to the "value" parameter before (or remove it if no "value").
+    async fn admin_data_info(
+        &self,
+        params: &HashMap<String, String>,
+    ) -> ZResult<serde_json::Value> {
+        let key = match params.get("key") {
+            Some(key) => OwnedKeyExpr::try_from(key.as_str())?,
+            None => bail!(r#"Missing "key" parameter for `data_info` operation"#),
+        };
+        // if strip_prefix is set, strip it from the key
+        let zfile = match &self.config.strip_prefix {
+            Some(prefix) => match key.strip_prefix(prefix).as_slice() {
+                [ke] => self.files_mgr.to_zfile(ke.as_str()),
+                _ => bail!(
+                    "Key {} for `data_info` operation doesn't start with strip_prefix '{}'",
+                    key,
+                    prefix
+                ),
+            },
+            None => self.files_mgr.to_zfile(key.as_str()),
+        };
+        if let Some(name) = params.get("tag") {
+            if self.read_only {
+                bail!("Operation `data_info` with a \"tag\" refused on a read-only storage")
+            }
+            self.files_mgr
+                .set_data_info_tag(&zfile, name, params.get("value").map(String::as_str))
+                .await?;
+        }
+        let mut result = serde_json::json!({
+            "key": key.as_str(),
+            "schema_version": data_info_mgt::SCHEMA_VERSION,
+        });
+        if let Some((encoding, timestamp, extras)) = self.files_mgr.get_data_info(&zfile).await? {
+            result["timestamp"] = timestamp.to_string().into();
+            result["encoding"] = encoding.to_string().into();
+            if let Some(size) = extras.size {
+                result["size"] = size.into();
+            }
+            if let Some(etag) = extras.etag {
+                result["etag"] = etag.into();
+            }
+            if let Some(access_count) = extras.access_count {
+                result["access_count"] = access_count.into();
+            }
+            result["tags"] = extras
+                .tags
+                .into_iter()
+                .map(|(name, value)| (name, value.into()))
+                .collect::<serde_json::Map<String, serde_json::Value>>()
+                .into();
+        }
+        Ok(result)
+    }
+
+    // Delete all the stored keys matching the key expression specified by the "key" parameter, as DELETE with
+    // a same timestamp, in a bulk deletion running in the background (its progress is in the admin status)
+    async fn admin_delete_matching(
+        &self,
+        params: &HashMap<String, String>,
+    ) -> ZResult<serde_json::Value> {
+        if self.read_only {
+            bail!("Operation `delete_matching` refused on a read-only storage")
+        }
+        // the bulk deletion doesn't record its DELETE in the history, nor notify them
+        if self.hash_chain.is_some() || self.change_notifier.is_some() {
+            bail!(
+                "Operation `delete_matching` is not supported with `{}` or `{}`",
+                PROP_STORAGE_HASH_CHAIN,
+                PROP_STORAGE_CHANGE_NOTIFY_KEY
+            )
+        }
+        if self.files_mgr.has_rotation() {
+            bail!("Operation `delete_matching` is not supported with rotation directories")
+        }
+        if self.files_mgr.has_encoding_subdirs() {
+            bail!(
+                "Operation `delete_matching` is not supported with `{}`",
+                PROP_STORAGE_ENCODING_SUBDIRS
+            )
+        }
+        let key = match params.get("key") {
+            Some(key) => OwnedKeyExpr::try_from(key.as_str())?,
+            None => bail!(r#"Missing "key" parameter for `delete_matching` operation"#),
+        };
+        // if strip_prefix is set, strip it from the key expression
+        let kes = match &self.config.strip_prefix {
+            Some(prefix) => {
+                let kes = key.strip_prefix(prefix);
+                if kes.is_empty() {
+                    bail!(
+                        "Key expression {} for `delete_matching` operation doesn't intersect strip_prefix '{}'",
+                        key,
+                        prefix
+                    )
+                }
+                kes
+            }
+            None => vec![&*key],
+        };
+        let mut zpaths: Vec<String> = Vec::new();
+        for ke in kes {
+            if ke.contains('*') {
+                for zfile in self.files_mgr.matching_files(ke) {
+                    if !self.files_mgr.is_reserved_path(&zfile.zpath) {
+                        zpaths.push(zfile.zpath.to_string());
+                    }
+                }
+            } else if !self.files_mgr.is_reserved_path(ke.as_str())
+                && self
+                    .files_mgr
+                    .file_exists(&self.files_mgr.to_zfile(ke.as_str()))
+            {
+                zpaths.push(ke.to_string());
+            }
+        }
+        zpaths.sort();
+        zpaths.dedup();
+        if self.bulk_delete.running.load(Ordering::Acquire) {
+            bail!("Operation `delete_matching` refused: a bulk deletion is already in progress")
+        }
+        let timestamp = new_reception_timestamp();
+        let journal = BulkDeleteJournal::create(self.files_mgr.base_dir(), timestamp, zpaths)?;
+        let total = journal.zpaths.len();
+        if !self.bulk_delete.start(&journal) {
+            bail!("Operation `delete_matching` refused: a bulk deletion is already in progress")
+        }
+        task::spawn(run_bulk_delete(
+            Arc::downgrade(&self.files_mgr),
+            journal,
+            self.bulk_delete_batch_size,
+            self.bulk_delete_concurrency,
+            self.schema.clone(),
+            self.bulk_delete.clone(),
+        ));
+        Ok(serde_json::json!({
+            "key": key.as_str(),
+            "total": total,
+            "timestamp": timestamp.to_string(),
+        }))
+    }
+
+    // Restore the quarantined file specified by the "file" parameter
+    async fn admin_restore(&self, params: &HashMap<String, String>) -> ZResult<serde_json::Value> {
+        let file = match params.get("file") {
+            Some(file) => file,
+            None => bail!(r#"Missing "file" parameter for `restore` operation"#),
+        };
+        self.files_mgr.restore_quarantined_file(file).await?;
+        Ok(serde_json::json!({ "restored": file }))
+    }
+
+    // Backup the data-info database to the directory specified by the "dir" parameter (relative to the root),
+    // or restore it from this directory
+    async fn admin_backup(
+        &self,
+        params: &HashMap<String, String>,
+        restore: bool,
+    ) -> ZResult<serde_json::Value> {
+        let operation = if restore { "restore_backup" } else { "backup" };
+        let dir = match params.get("dir") {
+            Some(dir) => resolve_dir(&self.root, "dir", dir)?,
+            None => bail!(r#"Missing "dir" parameter for `{}` operation"#, operation),
+        };
+        if restore {
+            if self.read_only {
+                bail!("Operation `{}` refused on a read-only storage", operation)
+            }
+            self.files_mgr.restore_data_info(&dir).await?;
+        } else {
+            self.files_mgr.backup_data_info(&dir).await?;
+        }
+        Ok(serde_json::json!({ operation: dir.to_string_lossy() }))
+    }
+
+    // Compare this storage with the storage directory specified by the "dir" parameter (relative to the root)
+    async fn admin_diff(&self, params: &HashMap<String, String>) -> ZResult<serde_json::Value> {
+        let other_dir = match params.get("dir") {
+            Some(dir) => resolve_dir(&self.root, "dir", dir)?,
+            None => bail!(r#"Missing "dir" parameter for `diff` operation"#),
+        };
+        let method = match params.get("method").map(String::as_str) {
+            Some("sha256") | None => DiffMethod::Sha256,
+            Some("mtime") => DiffMethod::Mtime,
+            Some(m) => bail!(
+                r#"Unsupported "method" parameter for `diff` operation: {}. Must be either "sha256" or "mtime""#,
+                m
+            ),
+        };
+        // both directories are walked and their data-infos loaded: run it all out of the async executor
+        let files_mgr = self.files_mgr.clone();
+        let diff = task::spawn_blocking(move || task::block_on(files_mgr.diff(&other_dir, method)))
+            .await?;
+        Ok(serde_json::json!({
+            "only_in_self": diff.only_in_self.iter().map(|ke| ke.as_str()).collect::<Vec<_>>(),
+            "only_in_other": diff.only_in_other.iter().map(|ke| ke.as_str()).collect::<Vec<_>>(),
+            "conflicts": diff.conflicts.iter().map(|(ke, ts, other_ts)| serde_json::json!({
+                "key": ke.as_str(),
+                "timestamp": ts.to_string(),
+                "other_timestamp": other_ts.to_string(),
+            })).collect::<Vec<_>>(),
+        }))
+    }
+
+    // Reply the files matching a key expression, within the query's bounds: the search stops at the first file
+    // beyond the maximum number of replies, counted as omitted. Return false if the deadline was exceeded before the end.
+    async fn reply_with_matching_files(
+        &self,
+        query: &Query,
+        path_expr: &str,
+        accept_compressed: bool,
+        bounds: &mut QueryBounds,
+    ) -> bool {
+        // the files with an identical content (e.g. hard links) are read only once for the query
+        let cache = if self.query_read_cache_bytes > 0 {
+            Some(QueryReadCache::new(self.query_read_cache_bytes))
+        } else {
+            None
+        };
+        let cache = cache.as_ref();
+        match path_expr.try_into() {
+            Ok(ke) => {
+                if self.snapshot_queries {
+                    // capture the matching files and their timestamps before replying any of them,
+                    // so files modified while replying are skipped rather than served at a newer version
+                    let mut snapshot = Vec::new();
+                    for zfile in self.files_mgr.matching_files(ke) {
+                        if bounds.is_over_budget() {
+                            return false;
+                        }
+                        let trimmed_zpath = get_trimmed_keyexpr(zfile.zpath.as_ref());
+                        if !bounds.is_first_match(trimmed_zpath) {
+                            continue;
+                        }
+                        if !bounds.take_reply() {
+                            break;
+                        }
+                        let trimmed_zfile = self.files_mgr.to_zfile(trimmed_zpath);
+                        match self.files_mgr.get_timestamp(&trimmed_zfile).await {
+                            Ok(Some(ts)) => snapshot.push((trimmed_zpath.to_string(), ts)),
+                            Ok(None) => (), // file not found, do nothing
+                            Err(e) => warn!(
+                                "Replying to query on {} : failed to get timestamp of file {} : {}",
+                                query.selector(),
+                                trimmed_zfile,
+                                e
+                            ),
+                        }
+                    }
+                    for (zpath, ts) in snapshot {
+                        if bounds.is_over_budget() {
+                            return false;
+                        }
+                        let zfile = self.files_mgr.to_zfile(&zpath);
+                        self.reply_with_file(query, &zfile, Some(&ts), cache, accept_compressed)
+                            .await;
+                    }
+                } else {
+                    for zfile in self.files_mgr.matching_files(ke) {
+                        if bounds.is_over_budget() {
+                            return false;
+                        }
+                        let trimmed_zpath = get_trimmed_keyexpr(zfile.zpath.as_ref());
+                        if !bounds.is_first_match(trimmed_zpath) {
+                            continue;
+                        }
+                        if !bounds.take_reply() {
+                            break;
+                        }
+                        let trimmed_zfile = self.files_mgr.to_zfile(trimmed_zpath);
+                        self.reply_with_file(query, &trimmed_zfile, None, cache, accept_compressed)
+                            .await;
+                    }
+                }
+            }
+            Err(e) => tracing::error!("Couldn't convert `{}` to key expression: {}", path_expr, e),
+        }
+        true
+    }
+
+    // Reply the marker of a query truncated as exceeding "query_budget_ms", on the selector's key expression
+    async fn reply_truncated(&self, query: &Query, budget: Duration) -> ZResult<()> {
+        warn!(
+            "Query on {} truncated: it exceeded the budget of {:?}",
+            query.selector(),
+            budget
+        );
+        let key_expr: OwnedKeyExpr = query.selector().key_expr.as_keyexpr().to_owned();
+        let result = serde_json::json!({
+            "truncated": true,
+            PROP_STORAGE_QUERY_BUDGET_MS: budget.as_millis() as u64,
+        });
+        let value = Value::new(result.to_string().into_bytes().into())
+            .encoding(KnownEncoding::AppJson.into());
+        query.reply(Sample::new(key_expr, value)).res().await
+    }
+
+    // Reply the marker of a query whose matching files exceed "max_replies_per_query", with the number of files
+    // omitted, on the selector's key expression
+    async fn reply_overflow(&self, query: &Query, max_replies: u64, omitted: u64) -> ZResult<()> {
+        debug!(
+            "Query on {} capped to {} replies: {} matching files omitted",
+            query.selector(),
+            max_replies,
+            omitted
+        );
+        let key_expr: OwnedKeyExpr = query.selector().key_expr.as_keyexpr().to_owned();
+        let result = serde_json::json!({
+            "overflow": true,
+            PROP_STORAGE_MAX_REPLIES_PER_QUERY: max_replies,
+            "omitted": omitted,
+        });
+        let value = Value::new(result.to_string().into_bytes().into())
+            .encoding(KnownEncoding::AppJson.into());
+        query.reply(Sample::new(key_expr, value)).res().await
+    }
+
+    // Reply the number of stored keys matching the sub-keyexprs of a query, and the total size of their files,
+    // optionally grouped by their first `count_by` chunks. This only uses the files' metadata.
+    async fn reply_with_count(
+        &self,
+        query: &Query,
+        sub_keyexprs: &[&keyexpr],
+        count_by: Option<usize>,
+    ) -> ZResult<()> {
+        let mut count = 0u64;
+        let mut bytes = 0u64;
+        let mut by_prefix: BTreeMap<String, (u64, u64)> = BTreeMap::new();
+        for ke in sub_keyexprs {
+            let zfiles: Vec<ZFile> = if ke.contains('*') {
+                self.files_mgr.matching_files(ke).collect()
+            } else if self.files_mgr.is_reserved_path(ke) {
+                vec![]
+            } else {
+                vec![self.files_mgr.to_zfile(ke)]
+            };
+            for zfile in zfiles {
+                let size = match self.files_mgr.file_size(&zfile) {
+                    Some(size) => size,
+                    None => continue, // file not found, do nothing
+                };
+                count += 1;
+                bytes += size;
+                if let Some(depth) = count_by {
+                    // if strip_prefix is set, prefix it back to the zenoh path of this ZFile
+                    let zpath = match &self.config.strip_prefix {
+                        Some(prefix) => format!("{}/{}", prefix, zfile.zpath),
+                        None => zfile.zpath.to_string(),
+                    };
+                    let prefix = zpath.split('/').take(depth).collect::<Vec<_>>().join("/");
+                    let entry = by_prefix.entry(prefix).or_default();
+                    entry.0 += 1;
+                    entry.1 += size;
+                }
+            }
+        }
+
+        let mut result = serde_json::json!({ "count": count, "bytes": bytes });
+        if count_by.is_some() {
+            result["count_by"] = by_prefix
+                .into_iter()
+                .map(|(prefix, (count, bytes))| {
+                    (
+                        prefix,
+                        serde_json::json!({ "count": count, "bytes": bytes }),
+                    )
+                })
+                .collect::<serde_json::Map<_, _>>()
+                .into();
+        }
+        reply_json(query, &result).await
+    }
+
+    // Reply all the matching files in a single tar archive, each with its key as path
+    async fn reply_with_tar(&self, query: &Query, sub_keyexprs: &[&keyexpr]) -> ZResult<()> {
+        let cache = if self.query_read_cache_bytes > 0 {
+            Some(QueryReadCache::new(self.query_read_cache_bytes))
+        } else {
+            None
+        };
+        // (the archive can't contain the same path twice)
+        let mut bounds = self.query_bounds(sub_keyexprs.len() > 1);
+        let mut archive = ArchiveReply::new(if self.reply_fragment_bytes > 0 {
+            self.reply_fragment_bytes as usize
+        } else {
+            ARCHIVE_PART_BYTES
+        });
+        let mut truncated = false;
+        let mut oversized = false;
+        'keyexprs: for ke in sub_keyexprs {
+            for zpath in self.matching_zpaths(ke) {
+                if bounds.is_over_budget() {
+                    truncated = true;
+                    break 'keyexprs;
+                }
+                if !bounds.is_first_match(&zpath) {
+                    continue;
+                }
+                // (not a valid path in an archive)
+                if zpath.split('/').any(|chunk| chunk == "..") {
+                    debug!(
+                        "Replying to query on {} : key {} skipped from the archive",
+                        query.selector(),
+                        zpath
+                    );
+                    continue;
+                }
+                if !bounds.take_reply() {
+                    break 'keyexprs;
+                }
+                let zfile = self.files_mgr.to_zfile(&zpath);
+                let (value, timestamp) = match self.files_mgr.pin(&zfile).await {
+                    Ok(Some(mut pinned)) => {
+                        let read = self.read_pinned(&mut pinned, cache.as_ref(), false).await;
+                        self.files_mgr.release_bulk_read(&pinned);
+                        match read {
+                            Ok(read) => read,
+                            Err(e) => {
+                                warn!(
+                                    "Replying to query on {} : failed to read file {} : {}",
+                                    query.selector(),
+                                    zfile,
+                                    e
+                                );
+                                continue;
+                            }
+                        }
+                    }
+                    Ok(None) => continue, // file removed in the meantime
+                    Err(e) => {
+                        warn!(
+                            "Replying to query on {} : failed to read file {} : {}",
+                            query.selector(),
+                            zfile,
+                            e
+                        );
+                        continue;
+                    }
+                };
+                // if strip_prefix is set, prefix it back to the zenoh path of this ZFile
+                let key: OwnedKeyExpr = match &self.config.strip_prefix {
+                    Some(prefix) => prefix.join(&zpath)?,
+                    None => zpath.as_str().try_into()?,
+                };
+                let value = match self.before_reply(query, &key, value).await {
+                    Some(value) => value,
+                    None => continue,
+                };
+                let content = value.payload.contiguous();
+                if let Some(max) = self.max_archive_bytes {
+                    if archive.size + content.len() as u64 > max {
+                        oversized = true;
+                        break 'keyexprs;
+                    }
+                }
+                if let Err(e) = archive.append(key.as_str(), &content, &timestamp) {
+                    warn!(
+                        "Replying to query on {} : key {} skipped from the archive: {}",
+                        query.selector(),
+                        key,
+                        e
+                    );
+                    continue;
+                }
+                // reply the parts of the archive already built
+                while let Some(part) = archive.next_part() {
+                    self.send_archive_part(query, part).await?;
+                }
+            }
+        }
+        for part in archive.finish()? {
+            self.send_archive_part(query, part).await?;
+        }
+
+        if truncated {
+            return self
+                .reply_truncated(query, self.query_budget.unwrap())
+                .await;
+        }
+        if oversized {
+            warn!(
+                "Query on {} capped: its archive exceeded {} bytes",
+                query.selector(),
+                self.max_archive_bytes.unwrap()
+            );
+            let key_expr: OwnedKeyExpr = query.selector().key_expr.as_keyexpr().to_owned();
+            let result = serde_json::json!({
+                "overflow": true,
+                PROP_STORAGE_MAX_ARCHIVE_BYTES: self.max_archive_bytes.unwrap(),
+            });
+            let value = Value::new(result.to_string().into_bytes().into())
+                .encoding(KnownEncoding::AppJson.into());
+            return query.reply(Sample::new(key_expr, value)).res().await;
+        }
+        if bounds.omitted > 0 {
+            return self
+                .reply_overflow(query, self.max_replies_per_query.unwrap(), bounds.omitted)
+                .await;
+        }
+        Ok(())
+    }
+
+    // Reply a part of an archive, on the selector's key expression
+    async fn send_archive_part(&self, query: &Query, part: Value) -> ZResult<()> {
+        if let Some(throttle) = &self.reply_throttle {
+            throttle.acquire(part.payload.len()).await;
+        }
+        let key_expr: OwnedKeyExpr = query.selector().key_expr.as_keyexpr().to_owned();
+        query.reply(Sample::new(key_expr, part)).res().await
+    }
+
+    // The bounds of a GET query replying values, recording the zenoh paths replied if `dedup`
+    fn query_bounds(&self, dedup: bool) -> QueryBounds {
+        QueryBounds {
+            deadline: self.query_budget.map(|budget| Instant::now() + budget),
+            remaining: self.max_replies_per_query,
+            omitted: 0,
+            replied: if dedup { Some(HashSet::new()) } else { None },
+        }
+    }
+
+    // Iterate over the zenoh paths of the stored keys possibly matching a key expression (without the strip_prefix)
+    fn matching_zpaths<'a>(&self, ke: &'a keyexpr) -> Box<dyn Iterator<Item = String> + Send + 'a> {
+        if ke.contains('*') {
+            Box::new(
+                self.files_mgr
+                    .matching_files(ke)
+                    .map(|zfile| get_trimmed_keyexpr(zfile.zpath.as_ref()).to_string()),
+            )
+        } else if self.files_mgr.is_reserved_path(ke) {
+            Box::new(std::iter::empty())
+        } else {
+            Box::new(std::iter::once(ke.to_string()))
+        }
+    }
+
+    // Reply the metadata of each matching key as a JSON value: its timestamp, size and ETag
+    async fn reply_with_meta(&self, query: &Query, sub_keyexprs: &[&keyexpr]) -> ZResult<()> {
+        let mut bounds = self.query_bounds(sub_keyexprs.len() > 1);
+        'keyexprs: for ke in sub_keyexprs {
+            for zpath in self.matching_zpaths(ke) {
+                if bounds.is_over_budget() {
+                    return self
+                        .reply_truncated(query, self.query_budget.unwrap())
+                        .await;
+                }
+                if !bounds.is_first_match(&zpath) {
+                    continue;
+                }
+                if !bounds.take_reply() {
+                    break 'keyexprs;
+                }
+                let zfile = self.files_mgr.to_zfile(&zpath);
+                let (timestamp, etag) = match self.files_mgr.get_etag(&zfile).await {
+                    Ok(Some(meta)) => meta,
+                    Ok(None) => continue, // file not found, do nothing
+                    Err(e) => {
+                        warn!(
+                            "Replying to query on {} : failed to read file {} : {}",
+                            query.selector(),
+                            zfile,
+                            e
+                        );
+                        continue;
+                    }
+                };
+                // if strip_prefix is set, prefix it back to the zenoh path of this ZFile
+                let key: OwnedKeyExpr = match &self.config.strip_prefix {
+                    Some(prefix) => prefix.join(&zpath)?,
+                    None => zpath.as_str().try_into()?,
+                };
+                let sizes = self.files_mgr.get_sizes(&zfile).await.ok().flatten();
+                let meta = serde_json::json!({
+                    "key": key.as_str(),
+                    "timestamp": timestamp.to_string(),
+                    "size": sizes.map(|(_, disk_size)| disk_size),
+                    "logical_size": sizes.map(|(value_size, _)| value_size),
+                    "etag": etag,
+                });
+                let value = Value::new(meta.to_string().into_bytes().into())
+                    .encoding(KnownEncoding::AppJson.into());
+                if let Some(value) = self.before_reply(query, &key, value).await {
+                    query.reply(Sample::new(key, value)).res().await?;
+                }
+            }
+        }
+        if bounds.omitted > 0 {
+            return self
+                .reply_overflow(query, self.max_replies_per_query.unwrap(), bounds.omitted)
+                .await;
+        }
+        Ok(())
+    }
+
+    // Reply a DELETE (with an empty value) for each matching deleted key, with the timestamp of its deletion.
+    // The keys stored again since are not replied.
+    async fn reply_with_deleted(&self, query: &Query, sub_keyexprs: &[&keyexpr]) -> ZResult<()> {
+        for (zpath, ts) in self.files_mgr.get_deleted_entries(None).await {
+            let matches = match keyexpr::new(zpath.as_str()) {
+                Ok(key) => sub_keyexprs.iter().any(|ke| ke.intersects(key)),
+                Err(_) => false,
+            };
+            // (the key might be stored again in its conflict file)
+            if !matches
+                || self
+                    .files_mgr
+                    .file_size(&self.files_mgr.to_zfile(&zpath))
+                    .is_some()
+            {
+                continue;
+            }
+            // if strip_prefix is set, prefix it back to the zenoh path of this key
+            let key: OwnedKeyExpr = match &self.config.strip_prefix {
+                Some(prefix) => prefix.join(&zpath)?,
+                None => zpath.as_str().try_into()?,
+            };
+            let value = match self
+                .before_reply(query, &key, Value::new(Vec::<u8>::new().into()))
+                .await
+            {
+                Some(value) => value,
+                None => continue,
+            };
+            let mut sample = Sample::new(key, value).with_timestamp(ts);
+            sample.kind = SampleKind::Delete;
+            query.reply(sample).res().await?;
+        }
+        Ok(())
+    }
+
+    // Reply the metadata of each matching key as a JSON value, without reading the files' content:
+    // its size, modification time, encoding, timestamp and SHA-256 (if recorded when it was written)
+    async fn reply_with_stat(&self, query: &Query, sub_keyexprs: &[&keyexpr]) -> ZResult<()> {
+        let mut bounds = self.query_bounds(sub_keyexprs.len() > 1);
+        'keyexprs: for ke in sub_keyexprs {
+            for zpath in self.matching_zpaths(ke) {
+                if bounds.is_over_budget() {
+                    return self
+                        .reply_truncated(query, self.query_budget.unwrap())
+                        .await;
+                }
+                if !bounds.is_first_match(&zpath) {
+                    continue;
+                }
+                if !bounds.take_reply() {
+                    break 'keyexprs;
+                }
+                let zfile = self.files_mgr.to_zfile(&zpath);
+                let stat = match self.files_mgr.get_stat(&zfile).await {
+                    Ok(Some(stat)) => stat,
+                    Ok(None) => continue, // file not found, do nothing
+                    Err(e) => {
+                        warn!(
+                            "Replying to query on {} : failed to get metadata of file {} : {}",
+                            query.selector(),
+                            zfile,
+                            e
+                        );
+                        continue;
+                    }
+                };
+                // if strip_prefix is set, prefix it back to the zenoh path of this ZFile
+                let key: OwnedKeyExpr = match &self.config.strip_prefix {
+                    Some(prefix) => prefix.join(&zpath)?,
+                    None => zpath.as_str().try_into()?,
+                };
+                let result = serde_json::json!({
+                    "key": key.as_str(),
+                    "size": stat.size,
+                    "logical_size": stat.value_size,
+                    "mtime_ms": stat.mtime.map(|mtime| mtime.as_millis() as u64),
+                    "encoding": stat.encoding.to_string(),
+                    "timestamp": stat.timestamp.to_string(),
+                    "sha256": stat.sha256,
+                });
+                let value = Value::new(result.to_string().into_bytes().into())
+                    .encoding(KnownEncoding::AppJson.into());
+                if let Some(value) = self.before_reply(query, &key, value).await {
+                    query.reply(Sample::new(key, value)).res().await?;
+                }
+            }
+        }
+        if bounds.omitted > 0 {
+            return self
+                .reply_overflow(query, self.max_replies_per_query.unwrap(), bounds.omitted)
+                .await;
+        }
+        Ok(())
+    }
+
+    // Reply that the value of a key is unchanged since the ETag the query was sent with
+    async fn reply_not_modified(&self, query: &Query, zpath: &keyexpr, etag: &str) -> ZResult<()> {
+        // if strip_prefix is set, prefix it back to the zenoh path
+        let key: OwnedKeyExpr = match &self.config.strip_prefix {
+            Some(prefix) => prefix.join(zpath.as_str())?,
+            None => zpath.to_owned(),
+        };
+        let result = serde_json::json!({
+            "key": key.as_str(),
+            "etag": etag,
+            "not_modified": true,
+        });
+        let value = Value::new(result.to_string().into_bytes().into())
+            .encoding(KnownEncoding::AppJson.into());
+        match self.before_reply(query, &key, value).await {
+            Some(value) => query.reply(Sample::new(key, value)).res().await,
+            None => Ok(()),
+        }
+    }
+
+    // Reply the direct children of the query's key as a JSON list
+    async fn reply_with_dir_listing(&self, query: &Query) -> ZResult<()> {
+        let key = query.selector().key_expr.as_str().to_string();
+        if key.contains('*') {
+            bail!(
+                "Query on {} refused: `{}` requires a key without wildcard",
+                key,
+                LIST_DIR_QUERY_PARAM
+            )
+        }
+        // if strip_prefix is set, strip it from the key to get the prefix of the stored keys
+        let prefix = match &self.config.strip_prefix {
+            Some(prefix) if key == prefix.as_str() => "",
+            Some(prefix) => match key
+                .strip_prefix(prefix.as_str())
+                .and_then(|k| k.strip_prefix('/'))
+            {
+                Some(k) => k,
+                None => bail!(
+                    "Query on {} refused: the configured strip_prefix '{}' is not a prefix of this key",
+                    key,
+                    prefix
+                ),
+            },
+            None => &key,
+        };
+        if self.files_mgr.is_reserved_path(prefix) {
+            return reply_json(query, &serde_json::json!([])).await;
+        }
+
+        let mut entries = Vec::new();
+        for entry in self.files_mgr.list_directory(prefix).await? {
+            // if strip_prefix is set, prefix it back to the key
+            let key = match &self.config.strip_prefix {
+                Some(prefix) => prefix.join(entry.key.as_str())?,
+                None => entry.key,
+            };
+            let json = serde_json::json!({
+                "key": key.as_str(),
+                "is_directory": entry.is_directory,
+                "entry_count": entry.entry_count,
+                "last_modified": entry.last_modified.map(|ts| ts.to_string()),
+            });
+            // (each entry goes through the "before_reply" hook, as a reply on its key)
+            let value = Value::new(json.to_string().into_bytes().into())
+                .encoding(KnownEncoding::AppJson.into());
+            if let Some(value) = self.before_reply(query, &key, value).await {
+                match serde_json::from_slice(&value.payload.contiguous()) {
+                    Ok(json) => entries.push(json),
+                    Err(e) => warn!(
+                        "Replying to query on {} : invalid JSON for {} returned by hook: {}",
+                        query.selector(),
+                        key,
+                        e
+                    ),
+                }
+            }
+        }
+        reply_json(query, &serde_json::Value::Array(entries)).await
+    }
+
+    // Read a pinned file's content, transcoded if "read_encoding_override" is configured
+    async fn read_pinned(
+        &self,
+        pinned: &mut PinnedFile,
+        cache: Option<&QueryReadCache>,
+        keep_compressed: bool,
+    ) -> ZResult<(Value, Timestamp)> {
+        let (value, timestamp) = self
+            .files_mgr
+            .read_pinned(pinned, cache, keep_compressed)
+            .await?;
+        Ok((self.override_read_encoding(value)?, timestamp))
+    }
+
+    // Transcode a value read from a file if "read_encoding_override" is configured
+    fn override_read_encoding(&self, value: Value) -> ZResult<Value> {
+        match &self.read_encoding_override {
+            // a compressed content can't be transcoded
+            Some(_) if value.encoding.suffix().ends_with(GZIP_ENCODING_SUFFIX) => Ok(value),
+            Some(target) => transcode(value, target),
+            None => Ok(value),
+        }
+    }
+
+    // Reply to the query with the file's content. If a snapshot timestamp is specified,
+    // the file is replied only if it was not modified since this timestamp was captured.
+    async fn reply_with_file(
+        &self,
+        query: &Query,
+        zfile: &ZFile<'_>,
+        snapshot_ts: Option<&Timestamp>,
+        cache: Option<&QueryReadCache>,
+        accept_compressed: bool,
+    ) {
+        // the file is pinned until the reply is sent, to be read through the same handle
+        // even if it's deleted or replaced meanwhile
+        let read = match self.files_mgr.pin(zfile).await {
+            Ok(Some(mut pinned)) => self
+                .read_pinned(&mut pinned, cache, accept_compressed)
+                .await
+                .map(|(value, timestamp)| Some((value, timestamp, pinned))),
+            Ok(None) => Ok(None),
+            Err(e) => Err(e),
+        };
+        match read {
+            Ok(Some((_, timestamp, _))) if snapshot_ts.map_or(false, |ts| *ts != timestamp) => {
+                debug!(
+                    "Replying to query on {} : skip file {:?} modified after the query started",
+                    query.selector(),
+                    zfile,
+                );
+            }
+            Ok(Some((_, timestamp, _))) if !self.is_visible(&timestamp) => {
+                debug!(
+                    "Replying to query on {} : skip file {:?} modified after the snapshot view's timestamp",
+                    query.selector(),
+                    zfile,
+                );
+            }
+            // (the file is kept pinned until its value is sent)
+            Ok(Some((value, timestamp, _pinned))) => {
+                self.send_file_value(query, zfile, value, timestamp).await
+            }
+            Ok(None) => (), // file not found, do nothing
+            Err(e) => warn!(
+                "Replying to query on {} : failed to read file {} : {}",
+                query.selector(),
+                zfile,
+                e
+            ),
+        }
+    }
+
+    // Reply to the query with the content of files of known keys, pinned and read concurrently, in their order
+    async fn reply_with_files(&self, query: &Query, zfiles: &[ZFile<'_>]) {
+        // the files with an identical content (e.g. hard links) are read only once for the query
+        let cache = if self.query_read_cache_bytes > 0 {
+            Some(Arc::new(QueryReadCache::new(self.query_read_cache_bytes)))
+        } else {
+            None
+        };
+        let reads = self.files_mgr.batch_read(zfiles, cache).await;
+        for (zfile, read) in zfiles.iter().zip(reads) {
+            match read {
+                Ok(Some((_, timestamp, _))) if !self.is_visible(&timestamp) => {
+                    debug!(
+                        "Replying to query on {} : skip file {:?} modified after the snapshot view's timestamp",
+                        query.selector(),
+                        zfile,
+                    );
+                }
+                // (the file is kept pinned until its value is sent)
+                Ok(Some((value, timestamp, _pinned))) => match self.override_read_encoding(value) {
+                    Ok(value) => self.send_file_value(query, zfile, value, timestamp).await,
+                    Err(e) => warn!(
+                        "Replying to query on {} : failed to read file {} : {}",
+                        query.selector(),
+                        zfile,
+                        e
+                    ),
+                },
+                Ok(None) => (), // file not found, do nothing
+                Err(e) => warn!(
+                    "Replying to query on {} : failed to read file {} : {}",
+                    query.selector(),
+                    zfile,
+                    e
+                ),
+            }
+        }
+    }
+
+    // Pass a value to reply on a key to the "before_reply" hook, if any: None if the hook vetoed it.
+    // All the replies on a stored key (its value, its metadata or its deletion) go through this hook.
+    async fn before_reply(&self, query: &Query, key: &keyexpr, value: Value) -> Option<Value> {
+        match hooks::before_reply(self.hooks.as_deref(), key, value).await {
+            Ok(value) => Some(value),
+            Err(e) => {
+                debug!(
+                    "Replying to query on {} : {} vetoed by hook: {}",
+                    query.selector(),
+                    key,
+                    e
+                );
+                None
+            }
+        }
+    }
+
+    // Send a file's value as a reply to the query (fragmented if larger than "reply_fragment_bytes")
+    async fn send_file_value(
+        &self,
+        query: &Query,
+        zfile: &ZFile<'_>,
+        value: Value,
+        timestamp: Timestamp,
+    ) {
+        debug!(
+            "Replying to query on {} with file {:?}",
+            query.selector(),
+            zfile,
+        );
+        // if strip_prefix is set, prefix it back to the zenoh path of this ZFile
+        let zpath: OwnedKeyExpr = match &self.config.strip_prefix {
+            Some(prefix) => prefix.join(zfile.zpath.as_ref()).unwrap(),
+            None => zfile.zpath.as_ref().try_into().unwrap(),
+        };
+        let value = match self.before_reply(query, &zpath, value).await {
+            Some(value) => value,
+            None => return,
+        };
+        let values = if self.reply_fragment_bytes > 0
+            && value.payload.len() as u64 > self.reply_fragment_bytes
+        {
+            fragment_value(&value, self.reply_fragment_bytes as usize)
+        } else {
+            vec![value]
+        };
+        for value in values {
+            if let Some(throttle) = &self.reply_throttle {
+                throttle.acquire(value.payload.len()).await;
+            }
+            if let Err(e) = query
+                .reply(Sample::new(zpath.clone(), value).with_timestamp(timestamp))
+                .res()
+                .await
+            {
+                tracing::error!(
+                    "Error replying to query on {} with file {}: {}",
+                    query.selector(),
+                    zfile,
+                    e
+                );
+                break;
+            }
+        }
+        debug!("Reply sent !!!!!");
+    }
+
+    // Fail with a retry hint while the storage directory is replaced, until it's consistent again
+    fn check_not_waiting(&self) -> ZResult<()> {
+        if self.files_mgr.is_waiting() {
+            return Err(RetryLater::new(format!(
+                r#"File System Storage on "dir"={:?} is not available: it was replaced and is not consistent yet"#,
+                self.files_mgr.base_dir()
+            ))
+            .into());
+        }
+        Ok(())
+    }
+
+    // With "writability_check" = "none", while no PUT nor DELETE succeeded, a failed one might be due to
+    // the storage directory not being writeable: report it as such, identifying the storage
+    fn unverified_writability_error(
+        &self,
+        kind: SampleKind,
+        key_expr: &KeyExpr<'_>,
+        e: zenoh_core::Error,
+    ) -> zenoh_core::Error {
+        // (a stalled file system is not a writability issue)
+        if !self.writability_unverified || is_retry_later(&e) {
+            return e;
+        }
+        error!(
+            r#"{} on {} failed on File System Storage on "dir"={:?}, whose writability was not checked at creation ("{}"="none"): {}"#,
+            kind,
+            key_expr,
+            self.files_mgr.base_dir(),
+            PROP_STORAGE_WRITABILITY_CHECK,
+            e
+        );
+        zerror!(
+            r#"{} on {} failed: File System Storage on "dir"={:?} might not be writeable: {}"#,
+            kind,
+            key_expr,
+            self.files_mgr.base_dir(),
+            e
+        )
+        .into()
     }
 
-    // When receiving a Sample (i.e. on PUT or DELETE operations)
-    async fn on_sample(&mut self, sample: Sample) -> ZResult<StorageInsertionResult> {
+    // Store or delete a sample, depending on its kind
+    async fn store_sample(&mut self, sample: Sample) -> ZResult<StorageInsertionResult> {
+        if let Some(view_ts) = &self.snapshot_view_ts {
+            bail!(
+                "{} on {} refused: this is a read-only view of the storage as of {}",
+                sample.kind,
+                sample.key_expr,
+                view_ts
+            );
+        }
+        // with "publish_events", don't store the storage's own events if its key expression includes them
+        if self.files_mgr.is_event_key(&sample.key_expr) {
+            debug!(
+                "{} on {} ignored: storage event",
+                sample.kind, sample.key_expr
+            );
+            return Ok(StorageInsertionResult::Outdated);
+        }
+        // the storage manager should only deliver the samples for the storage's key expression
+        if self.strict_keyexpr && !sample.key_expr.intersects(&self.config.key_expr) {
+            self.samples_foreign += 1;
+            bail!(
+                "{} on {} refused: the key doesn't intersect the storage's key expression {}",
+                sample.kind,
+                sample.key_expr,
+                self.config.key_expr
+            );
+        }
         // if strip_prefix is set, strip it from the sample key_expr for this ZFile
-        let zfile = match &self.config.strip_prefix {
+        let zpath = match &self.config.strip_prefix {
             Some(prefix) => match sample.key_expr.strip_prefix(prefix).as_slice() {
-                [ke] => self.files_mgr.to_zfile(ke.as_str()),
+                [ke] => ke.as_str(),
                 _ => bail!(
                     "Received a Sample with keyexpr not starting with path_prefix '{}': '{}'",
                     prefix,
                     sample.key_expr
                 ),
             },
-            None => self.files_mgr.to_zfile(sample.key_expr.as_str()),
+            None => sample.key_expr.as_str(),
+        };
+        let canonical;
+        let zpath = if self.canonicalize_keys {
+            canonical = canonicalize_key(zpath)
+                .map_err(|e| zerror!("{} on {} refused: {}", sample.kind, sample.key_expr, e))?;
+            canonical.as_str()
+        } else {
+            zpath
+        };
+        let zfile = self.files_mgr.to_zfile(zpath);
+        if self.files_mgr.is_waiting() {
+            return Err(RetryLater::new(format!(
+                "{} on {} refused: the storage directory was replaced and is not consistent yet",
+                sample.kind, sample.key_expr
+            ))
+            .into());
+        }
+        if self.files_mgr.is_quiesced() {
+            return Err(RetryLater::new(format!(
+                "{} on {} refused: the storage is quiesced for maintenance",
+                sample.kind, sample.key_expr
+            ))
+            .into());
+        }
+        // fail fast while the file system is stalled, rather than stalling one more thread
+        self.files_mgr.check_io_health()?;
+        if self.files_mgr.is_reserved_path(&zfile.zpath) {
+            bail!(
+                "{} on {} refused: this key is reserved for the storage's internal use",
+                sample.kind,
+                sample.key_expr
+            );
+        }
+        if !self.files_mgr.is_encodable_path(&zfile.zpath) {
+            bail!(
+                "{} on {} refused: with `{}` \"os_string\", each '%' in the key must be followed by 2 uppercase hexadecimal digits (\"%25\" for a '%')",
+                sample.kind,
+                sample.key_expr,
+                PROP_STORAGE_FILENAME_ENCODING
+            );
+        }
+        if !self.files_mgr.is_mappable_path(&zfile.zpath) {
+            bail!(
+                "{} on {} refused: this key doesn't match the storage's `{}`",
+                sample.kind,
+                sample.key_expr,
+                PROP_STORAGE_PATH_TEMPLATE
+            );
+        }
+        if let Some(ext) = self.files_mgr.stripped_extension_of(&zfile.zpath) {
+            bail!(
+                "{} on {} refused: with `{}`, a key can't end with {:?} (its file would be read as another key)",
+                sample.kind,
+                sample.key_expr,
+                PROP_STORAGE_STRIP_EXTENSION,
+                ext
+            );
+        }
+        let schema = self.schema.clone();
+        let branch = schema.as_ref().and_then(|schema| schema.find(&zfile.zpath));
+        match (&schema, branch) {
+            (Some(schema), None) if schema.enforced => bail!(
+                "{} on {} refused: this key doesn't match any branch of the storage's `{}`",
+                sample.kind,
+                sample.key_expr,
+                PROP_STORAGE_SCHEMA
+            ),
+            (_, Some(branch)) if branch.read_only => bail!(
+                "{} on {} refused: this key is in a read-only branch of the storage's `{}`",
+                sample.kind,
+                sample.key_expr,
+                PROP_STORAGE_SCHEMA
+            ),
+            _ => (),
+        }
+
+        // in "pessimistic" locking mode, hold the file's lock from the timestamp check until the write,
+        // so no background task of the storage (e.g. a bulk deletion) writing the same file can interleave.
+        // In "optimistic" locking mode, the write relies on the timestamps ordering only, without lock.
+        let mut lock = if self.pessimistic_locking {
+            Some(self.files_mgr.lock_key(&zfile).await)
+        } else {
+            None
         };
 
-        // get latest timestamp for this file (if referenced in data-info db or if exists on disk)
-        // and drop incoming sample if older
-        let sample_ts = sample.timestamp.unwrap_or_else(new_reception_timestamp);
-        if let Some(old_ts) = self.files_mgr.get_timestamp(&zfile).await? {
-            if sample_ts < old_ts {
+        // a key made immutable by the "retention" operation can't be overwritten nor deleted until its date,
+        // whatever the sample's timestamp
+        if let Some(until_ms) = self.files_mgr.get_immutable_until(&zfile.zpath).await? {
+            bail!(
+                "{} on {} refused: this key is immutable until {} ms since the UNIX epoch",
+                sample.kind,
+                sample.key_expr,
+                until_ms
+            );
+        }
+
+        // get latest timestamp for this file (if referenced in data-info db, possibly as deleted,
+        // or if exists on disk) and drop incoming sample if older.
+        // In case of equal timestamps, a DELETE wins over a PUT (i.e. a PUT is dropped if the file
+        // was deleted with the same timestamp), so all replicas converge to the same state whatever
+        // the order of arrival of the samples.
+        let sample_ts = match (sample.timestamp, &self.missing_timestamp) {
+            (Some(ts), _) => ts,
+            (None, MissingTimestamp::Reception) => new_reception_timestamp(),
+            (None, MissingTimestamp::Reject) => bail!(
+                "{} on {} refused: the sample has no timestamp",
+                sample.kind,
+                sample.key_expr
+            ),
+            (None, MissingTimestamp::Epoch) => {
+                Timestamp::new(NTP64(0), *new_reception_timestamp().get_id())
+            }
+        };
+        let old_ts = self.files_mgr.get_timestamp(&zfile).await?;
+        if let Some(old_ts) = old_ts {
+            if sample_ts < old_ts
+                || (sample_ts == old_ts
+                    && matches!(sample.kind, SampleKind::Put)
+                    && !self.files_mgr.file_exists(&zfile))
+            {
                 debug!(
                     "{} on {} dropped: out-of-date",
                     sample.kind, sample.key_expr
                 );
+                match sample.kind {
+                    SampleKind::Put => self.puts_outdated += 1,
+                    SampleKind::Delete => self.deletes_outdated += 1,
+                }
+                self.log_conflict(&sample, &sample_ts, &old_ts).await;
                 return Ok(StorageInsertionResult::Outdated);
             }
         }
@@ -344,15 +4109,121 @@ impl Storage for FileSystemStorage {
         match sample.kind {
             SampleKind::Put => {
                 if !self.read_only {
+                    if self.key_dir_conflict_error && self.files_mgr.has_key_dir_conflict(&zfile) {
+                        bail!(
+                            "{} on {} refused: this key is a prefix of other stored keys, or one of its prefixes is a stored key",
+                            sample.kind,
+                            sample.key_expr
+                        );
+                    }
+                    let value = match &self.hooks {
+                        Some(hooks) => hooks.before_put(&sample.key_expr, sample.value).await?,
+                        None => sample.value,
+                    };
+                    if self.skip_identical
+                        && self
+                            .files_mgr
+                            .has_identical_content(&zfile, &value.payload, &value.encoding)
+                            .await?
+                    {
+                        debug!(
+                            "{} on {} dropped: identical to the stored value",
+                            sample.kind, sample.key_expr
+                        );
+                        self.puts_identical += 1;
+                        return Ok(StorageInsertionResult::Outdated);
+                    }
+                    if let Some(write_throttle) = &self.write_throttle {
+                        if write_throttle.is_throttled(&zfile.zpath) {
+                            debug!(
+                                "{} on {} dropped: sooner than `{}` after the last write",
+                                sample.kind, sample.key_expr, PROP_STORAGE_MIN_WRITE_INTERVAL_MS
+                            );
+                            self.puts_throttled += 1;
+                            return Ok(StorageInsertionResult::Outdated);
+                        }
+                    }
                     // write file
-                    self.files_mgr
-                        .write_file(
+                    let len = value.payload.len() as u64;
+                    let old_size = branch.and_then(|_| self.files_mgr.file_size(&zfile));
+                    if let (Some(schema), Some(branch)) = (&schema, branch) {
+                        branch.check_quota(schema.is_usage_ready(), old_size, len)?;
+                    }
+                    let content_sha256 = self
+                        .hash_chain
+                        .as_ref()
+                        .map(|_| content_sha256(&value.payload));
+                    if let Some(transaction) = &mut self.transaction {
+                        let op = self.files_mgr.stage_write(
                             &zfile,
-                            sample.value.payload,
-                            &sample.value.encoding,
+                            value.payload,
+                            &value.encoding,
                             &sample_ts,
-                        )
-                        .await?;
+                        )?;
+                        let staged = StagedSample {
+                            key_expr: sample.key_expr.as_keyexpr().to_owned(),
+                            kind: SampleKind::Put,
+                            timestamp: sample_ts,
+                            content_sha256,
+                            len,
+                        };
+                        if !transaction.stage(op, staged) {
+                            self.puts_outdated += 1;
+                            return Ok(StorageInsertionResult::Outdated);
+                        }
+                        if let Some(write_throttle) = &mut self.write_throttle {
+                            write_throttle.record_write(&zfile.zpath);
+                        }
+                        return Ok(StorageInsertionResult::Inserted);
+                    }
+                    let lock = lock.take();
+                    let zpath = zfile.zpath.to_string();
+                    let (payload, encoding) = (value.payload, value.encoding);
+                    if let Err(e) = self
+                        .files_mgr
+                        .isolated("write", move |files_mgr| async move {
+                            // (the lock, if any, is held until the write completes, even if it exceeds "io_deadline_ms")
+                            let _lock = lock;
+                            let zfile = files_mgr.to_zfile(&zpath);
+                            files_mgr
+                                .write_file(&zfile, payload, &encoding, &sample_ts)
+                                .await
+                        })
+                        .await
+                    {
+                        // the failure might be due to the storage directory being replaced: check it now
+                        // (unless the file system is stalled)
+                        if self.files_mgr.check_io_health().is_ok() {
+                            self.files_mgr.check_base_dir().await;
+                        }
+                        return Err(self.unverified_writability_error(
+                            sample.kind,
+                            &sample.key_expr,
+                            e,
+                        ));
+                    }
+                    self.writability_unverified = false;
+                    if let Some(branch) = branch {
+                        // the stored file may be larger than the value (e.g. with "text_safe_storage")
+                        let new_size = self
+                            .files_mgr
+                            .file_size(&self.files_mgr.to_zfile(&zfile.zpath))
+                            .unwrap_or(len);
+                        branch.record_put(old_size, new_size);
+                    }
+                    if let Some(write_throttle) = &mut self.write_throttle {
+                        write_throttle.record_write(&zfile.zpath);
+                    }
+                    if let Some(hash_chain) = &self.hash_chain {
+                        hash_chain.append(sample.key_expr.as_str(), &sample_ts, content_sha256)?;
+                    }
+                    if let Some(notifier) = &self.change_notifier {
+                        notifier
+                            .notify(&sample.key_expr, SampleKind::Put, &sample_ts)
+                            .await;
+                    }
+                    self.puts += 1;
+                    self.bytes_written += len;
                     Ok(StorageInsertionResult::Inserted)
                 } else {
                     warn!(
@@ -364,8 +4235,74 @@ impl Storage for FileSystemStorage {
             }
             SampleKind::Delete => {
                 if !self.read_only {
+                    if old_ts.is_none() && matches!(self.delete_missing, DeleteMissing::Ignore) {
+                        debug!(
+                            "{} on {} dropped: unknown key",
+                            sample.kind, sample.key_expr
+                        );
+                        return Ok(StorageInsertionResult::Deleted);
+                    }
+                    if let Some(hooks) = &self.hooks {
+                        hooks.before_delete(&sample.key_expr).await?;
+                    }
+                    if let Some(transaction) = &mut self.transaction {
+                        let op = self.files_mgr.stage_delete(&zfile, &sample_ts)?;
+                        let staged = StagedSample {
+                            key_expr: sample.key_expr.as_keyexpr().to_owned(),
+                            kind: SampleKind::Delete,
+                            timestamp: sample_ts,
+                            content_sha256: None,
+                            len: 0,
+                        };
+                        if !transaction.stage(op, staged) {
+                            self.deletes_outdated += 1;
+                            return Ok(StorageInsertionResult::Outdated);
+                        }
+                        if let Some(write_throttle) = &mut self.write_throttle {
+                            write_throttle.forget(&zfile.zpath);
+                        }
+                        return Ok(StorageInsertionResult::Deleted);
+                    }
                     // delete file
-                    self.files_mgr.delete_file(&zfile, &sample_ts).await?;
+                    let old_size = branch.and_then(|_| self.files_mgr.file_size(&zfile));
+                    let lock = lock.take();
+                    let zpath = zfile.zpath.to_string();
+                    if let Err(e) = self
+                        .files_mgr
+                        .isolated("delete", move |files_mgr| async move {
+                            let _lock = lock;
+                            let zfile = files_mgr.to_zfile(&zpath);
+                            files_mgr.delete_file(&zfile, &sample_ts).await
+                        })
+                        .await
+                    {
+                        // the failure might be due to the storage directory being replaced: check it now
+                        // (unless the file system is stalled)
+                        if self.files_mgr.check_io_health().is_ok() {
+                            self.files_mgr.check_base_dir().await;
+                        }
+                        return Err(self.unverified_writability_error(
+                            sample.kind,
+                            &sample.key_expr,
+                            e,
+                        ));
+                    }
+                    self.writability_unverified = false;
+                    if let Some(branch) = branch {
+                        branch.record_delete(old_size);
+                    }
+                    if let Some(write_throttle) = &mut self.write_throttle {
+                        write_throttle.forget(&zfile.zpath);
+                    }
+                    if let Some(hash_chain) = &self.hash_chain {
+                        hash_chain.append(sample.key_expr.as_str(), &sample_ts, None)?;
+                    }
+                    if let Some(notifier) = &self.change_notifier {
+                        notifier
+                            .notify(&sample.key_expr, SampleKind::Delete, &sample_ts)
+                            .await;
+                    }
+                    self.deletes += 1;
                     Ok(StorageInsertionResult::Deleted)
                 } else {
                     warn!(
@@ -377,12 +4314,175 @@ impl Storage for FileSystemStorage {
             }
         }
     }
+}
+
+#[async_trait]
+impl Storage for FileSystemStorage {
+    fn get_admin_status(&self) -> serde_json::Value {
+        let mut status = self.config.to_json_value();
+        if let serde_json::Value::Object(map) = &mut status {
+            if let Some(progress) = &self.publish_progress {
+                map.insert(
+                    PROP_STORAGE_PUBLISH_ON_START.into(),
+                    serde_json::json!({
+                        "published": progress.published.load(Ordering::Relaxed),
+                        "total": progress.total.load(Ordering::Relaxed),
+                        "done": progress.done.load(Ordering::Relaxed),
+                    }),
+                );
+            }
+            if self.bulk_delete.total.load(Ordering::Relaxed) > 0 {
+                map.insert("bulk_delete".into(), self.bulk_delete.status());
+            }
+            let quarantine = self.files_mgr.get_quarantine();
+            if !quarantine.is_empty() {
+                map.insert("quarantine".into(), serde_json::json!(quarantine));
+            }
+            if let Some(error) = self
+                .warmup_progress
+                .as_ref()
+                .and_then(|progress| progress.error.lock().unwrap().clone())
+            {
+                map.insert("warmup_error".into(), error.into());
+            }
+            map.insert(
+                "state".into(),
+                if self.files_mgr.is_waiting() {
+                    "waiting"
+                } else if self.files_mgr.is_quiesced() {
+                    "quiesced"
+                } else if self
+                    .warmup_progress
+                    .as_ref()
+                    .map_or(false, |progress| !progress.done.load(Ordering::Relaxed))
+                {
+                    "warming_up"
+                } else if self.files_mgr.check_io_health().is_err() {
+                    "unhealthy"
+                } else {
+                    "ready"
+                }
+                .into(),
+            );
+            let stalled_io = self.files_mgr.stalled_io();
+            if !stalled_io.is_empty() {
+                map.insert(
+                    "stalled_io".into(),
+                    stalled_io
+                        .iter()
+                        .map(|(op, elapsed)| {
+                            serde_json::json!({ "op": op, "elapsed_ms": elapsed.as_millis() as u64 })
+                        })
+                        .collect(),
+                );
+            }
+            map.insert(
+                "base_dir_transitions".into(),
+                self.files_mgr.base_dir_transitions().into(),
+            );
+            if let Some(hash_chain) = &self.hash_chain {
+                map.insert(PROP_STORAGE_HASH_CHAIN.into(), hash_chain.status());
+            }
+            if let Some(schema) = &self.schema {
+                map.insert(PROP_STORAGE_SCHEMA.into(), schema.status());
+            }
+            // (the exact count requires to list all the entries: it's only replied by the "entry_count" operation)
+            if let Ok(estimate) = self.files_mgr.get_entry_count() {
+                map.insert("entry_count_estimate".into(), estimate.into());
+            }
+            map.insert("puts_outdated".into(), self.puts_outdated.into());
+            map.insert("deletes_outdated".into(), self.deletes_outdated.into());
+            if self.strict_keyexpr {
+                map.insert("samples_foreign".into(), self.samples_foreign.into());
+            }
+            // (null if unlimited)
+            map.insert(
+                PROP_STORAGE_MAX_REPLIES_PER_QUERY.into(),
+                self.max_replies_per_query.into(),
+            );
+            map.insert("queries_overflowed".into(), self.queries_overflowed.into());
+            if self.skip_identical {
+                map.insert("puts_identical".into(), self.puts_identical.into());
+            }
+            if self.write_throttle.is_some() {
+                map.insert("puts_throttled".into(), self.puts_throttled.into());
+            }
+        }
+        status
+    }
+
+    // When receiving a Sample (i.e. on PUT or DELETE operations)
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(storage = %self.config.key_expr, op = %sample.kind, key = %sample.key_expr)
+    )]
+    async fn on_sample(&mut self, sample: Sample) -> ZResult<StorageInsertionResult> {
+        let result = self.store_sample(sample).await;
+        if result.is_err() {
+            self.sample_errors += 1;
+        }
+        result
+    }
 
     // When receiving a Query (i.e. on GET operations)
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(storage = %self.config.key_expr, op = "get", selector = %query.selector())
+    )]
     async fn on_query(&mut self, query: Query) -> ZResult<()> {
         // get the query's Selector
         let selector = query.selector();
 
+        // reply an error rather than an incomplete result while the storage directory is replaced
+        if self.files_mgr.is_waiting() {
+            return Err(RetryLater::new(format!(
+                "Query on {} refused: the storage directory was replaced and is not consistent yet",
+                selector
+            ))
+            .into());
+        }
+        // fail fast while the file system is stalled, rather than stalling one more thread
+        self.files_mgr.check_io_health()?;
+
+        let params = get_query_params(&selector);
+        // (in the snapshot view itself, the parameter is already applied)
+        if let (Some(snapshot), None) = (params.get(SNAPSHOT_QUERY_PARAM), &self.snapshot_view_ts) {
+            return self.reply_snapshot(query, snapshot).await;
+        }
+        // a snapshot view only replies the values (the other operations would expose its newer entries)
+        if self.snapshot_view_ts.is_some() {
+            if let Some(param) = [
+                self.admin_query_param.as_str(),
+                LIST_DIR_QUERY_PARAM,
+                COUNT_QUERY_PARAM,
+                META_QUERY_PARAM,
+                STAT_QUERY_PARAM,
+                DELETED_QUERY_PARAM,
+                AS_QUERY_PARAM,
+            ]
+            .iter()
+            .find(|param| params.contains_key(**param))
+            {
+                bail!(
+                    "Query on {} refused: `{}` is not supported on a snapshot view of the storage",
+                    selector,
+                    param
+                );
+            }
+        }
+        if let Some(operation) = params.get(&self.admin_query_param) {
+            return self.on_admin_query(&query, operation, &params).await;
+        }
+
+        if params
+            .get(LIST_DIR_QUERY_PARAM)
+            .map_or(false, |v| v.is_empty() || v == "true")
+        {
+            return self.reply_with_dir_listing(&query).await;
+        }
+
         // if strip_prefix is set, strip it from the Selector's keyexpr to get the list of sub-keyexpr
         // that will match the same stored keys than the selector, if those keys had the path_prefix.
         let sub_keyexpr = match &self.config.strip_prefix {
@@ -396,32 +4496,194 @@ impl Storage for FileSystemStorage {
             None => vec![selector.key_expr.as_keyexpr()],
         };
 
+        if params
+            .get(COUNT_QUERY_PARAM)
+            .map_or(false, |v| v.is_empty() || v == "true")
+        {
+            let count_by = match params.get(COUNT_BY_QUERY_PARAM) {
+                Some(depth) => Some(depth.parse::<usize>().map_err(|e| {
+                    zerror!(
+                        "Invalid `{}` query parameter {:?}: {}",
+                        COUNT_BY_QUERY_PARAM,
+                        depth,
+                        e
+                    )
+                })?),
+                None => None,
+            };
+            return self.reply_with_count(&query, &sub_keyexpr, count_by).await;
+        }
+
+        if params
+            .get(META_QUERY_PARAM)
+            .map_or(false, |v| v.is_empty() || v == "true")
+        {
+            return self.reply_with_meta(&query, &sub_keyexpr).await;
+        }
+
+        if params
+            .get(STAT_QUERY_PARAM)
+            .map_or(false, |v| v.is_empty() || v == "true")
+        {
+            return self.reply_with_stat(&query, &sub_keyexpr).await;
+        }
+
+        if params
+            .get(DELETED_QUERY_PARAM)
+            .map_or(false, |v| v.is_empty() || v == "true")
+        {
+            return self.reply_with_deleted(&query, &sub_keyexpr).await;
+        }
+
+        match params.get(AS_QUERY_PARAM).map(String::as_str) {
+            Some("tar") => return self.reply_with_tar(&query, &sub_keyexpr).await,
+            Some(format) => bail!(
+                "Query on {} refused: unsupported `{}` format {:?} (must be \"tar\")",
+                selector,
+                AS_QUERY_PARAM,
+                format
+            ),
+            None => (),
+        }
+
+        let accept_compressed = params
+            .get(ACCEPT_COMPRESSED_QUERY_PARAM)
+            .map_or(false, |v| v.is_empty() || v == "true");
+        let with_siblings = params
+            .get(WITH_SIBLINGS_QUERY_PARAM)
+            .map_or(false, |v| v.is_empty() || v == "true");
+        if with_siblings && selector.key_expr.contains('*') {
+            bail!(
+                "Query on {} refused: `{}` is only supported on a key without wildcard",
+                selector,
+                WITH_SIBLINGS_QUERY_PARAM
+            );
+        }
+        // (a single sub-keyexpr can't match a key twice)
+        let mut bounds = self.query_bounds(sub_keyexpr.len() > 1);
+        // the keys without wildcard are read concurrently if there are several,
+        // unless their reply depends on the file (kept compressed, or compared to an etag)
+        let mut batched: Option<Vec<ZFile>> = if !with_siblings
+            && !accept_compressed
+            && !params.contains_key(IF_NONE_MATCH_QUERY_PARAM)
+            && sub_keyexpr.iter().filter(|ke| !ke.contains('*')).count() > 1
+        {
+            Some(Vec::new())
+        } else {
+            None
+        };
         for ke in sub_keyexpr {
-            if ke.contains('*') {
-                self.reply_with_matching_files(&query, ke).await;
+            if ke.contains('*') || with_siblings {
+                // the keys batched before are replied first, in the order of the sub-keyexprs
+                if let Some(batched) = &mut batched {
+                    if !batched.is_empty() {
+                        if bounds.is_over_budget() {
+                            return self
+                                .reply_truncated(&query, self.query_budget.unwrap())
+                                .await;
+                        }
+                        self.reply_with_files(&query, &std::mem::take(batched))
+                            .await;
+                    }
+                }
+                // with "_with_siblings", the key and its siblings are the keys matching `*` under its parent
+                let path_expr = if with_siblings {
+                    match self.files_mgr.to_zfile(ke).parent(&self.files_mgr) {
+                        Some(parent) if !parent.zpath.is_empty() => format!("{}/*", parent.zpath),
+                        _ => "*".to_string(),
+                    }
+                } else {
+                    ke.to_string()
+                };
+                if !self
+                    .reply_with_matching_files(&query, &path_expr, accept_compressed, &mut bounds)
+                    .await
+                {
+                    return self
+                        .reply_truncated(&query, self.query_budget.unwrap())
+                        .await;
+                }
+                if bounds.is_capped() {
+                    break;
+                }
             } else {
+                if bounds.is_over_budget() {
+                    return self
+                        .reply_truncated(&query, self.query_budget.unwrap())
+                        .await;
+                }
                 // path_expr correspond to 1 single file.
-                // Convert it to ZFile and reply it.
+                // Convert it to ZFile and reply it (unless reserved for the storage's internal use).
+                if self.files_mgr.is_reserved_path(ke) {
+                    debug!("Query on reserved key {} ignored", ke);
+                    continue;
+                }
+                if !bounds.is_first_match(ke) {
+                    continue;
+                }
+                if !bounds.take_reply() {
+                    break;
+                }
                 let zfile = self.files_mgr.to_zfile(ke);
-                self.reply_with_file(&query, &zfile).await;
+                if let Some(batched) = &mut batched {
+                    batched.push(zfile);
+                    continue;
+                }
+                if let Some(etag) = params.get(IF_NONE_MATCH_QUERY_PARAM) {
+                    if let Some((ts, stored_etag)) = self.files_mgr.get_etag(&zfile).await? {
+                        if &stored_etag == etag && self.is_visible(&ts) {
+                            self.reply_not_modified(&query, ke, etag).await?;
+                            continue;
+                        }
+                    }
+                }
+                self.reply_with_file(&query, &zfile, None, None, accept_compressed)
+                    .await;
             }
         }
+        if let Some(batched) = batched.filter(|batched| !batched.is_empty()) {
+            if bounds.is_over_budget() {
+                return self
+                    .reply_truncated(&query, self.query_budget.unwrap())
+                    .await;
+            }
+            self.reply_with_files(&query, &batched).await;
+        }
+        if bounds.omitted > 0 {
+            self.queries_overflowed += 1;
+            return self
+                .reply_overflow(&query, self.max_replies_per_query.unwrap(), bounds.omitted)
+                .await;
+        }
 
         Ok(())
     }
 
     async fn get_all_entries(&self) -> ZResult<Vec<(OwnedKeyExpr, zenoh::time::Timestamp)>> {
+        // reply an error rather than incomplete entries while the storage directory is replaced
+        self.check_not_waiting()?;
         let mut result = Vec::new();
 
         // get all files in the filesystem
-        for zfile in self
+        for (i, zfile) in self
             .files_mgr
             .matching_files(unsafe { keyexpr::from_str_unchecked("**") })
+            .enumerate()
         {
+            // let the other tasks run regularly during the listing of a large storage
+            // (with "catchup_throttle_us", pause to smooth the IO of the listing)
+            if i > 0 && i % GET_ALL_ENTRIES_YIELD_PERIOD == 0 {
+                match self.catchup_throttle {
+                    Some(pause) => task::sleep(pause).await,
+                    None => task::yield_now().await,
+                }
+            }
             let trimmed_zpath = get_trimmed_keyexpr(zfile.zpath.as_ref());
             let trimmed_zfile = self.files_mgr.to_zfile(trimmed_zpath);
-            match self.files_mgr.read_file(&trimmed_zfile).await {
-                Ok(Some((_, timestamp))) => {
+            // only the timestamp is required: don't read the file's content
+            match self.files_mgr.read_file_timestamp(&trimmed_zfile).await {
+                Ok(Some(timestamp)) if !self.is_visible(&timestamp) => (),
+                Ok(Some(timestamp)) => {
                     // if strip_prefix is set, prefix it back to the zenoh path of this ZFile
                     let zpath = match &self.config.strip_prefix {
                         Some(prefix) => prefix.join(zfile.zpath.as_ref()).unwrap(),
@@ -437,7 +4699,14 @@ impl Storage for FileSystemStorage {
             }
         }
         // get deleted files information from rocksdb
-        for (zpath, ts) in self.files_mgr.get_deleted_entries().await {
+        for (zpath, ts) in self
+            .files_mgr
+            .get_deleted_entries(self.catchup_throttle)
+            .await
+        {
+            if !self.is_visible(&ts) {
+                continue;
+            }
             // if strip_prefix is set, prefix it back to the zenoh path of this ZFile
             let zpath = match &self.config.strip_prefix {
                 Some(prefix) => prefix.join(&zpath).unwrap(),
@@ -445,6 +4714,804 @@ impl Storage for FileSystemStorage {
             };
             result.push((zpath, ts));
         }
+        // (the directory might have been replaced during the listing)
+        self.check_not_waiting()?;
+        // sort by key, for a deterministic order whatever the directory listing order
+        // (unstable sort, as it doesn't allocate a buffer of half the entries)
+        result.sort_unstable_by(|a, b| a.0.as_str().cmp(b.0.as_str()).then(a.1.cmp(&b.1)));
+        result.shrink_to_fit();
         Ok(result)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+    use zenoh_backend_traits::config::PluginConfig;
+
+    lazy_static::lazy_static!(
+        // serializes the creation of the backends, as their root is set by an environment variable
+        static ref ROOT_ENV_VAR_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+    );
+
+    // A storage on "test/**" (stripped), in the "test" directory of a root
+    async fn test_storage(root: &Path) -> FileSystemStorage {
+        test_storage_with(root, serde_json::json!({})).await
+    }
+
+    // A storage on "test/**" (stripped), in the "test" directory of a root, with some storage properties
+    async fn test_storage_with(root: &Path, properties: serde_json::Value) -> FileSystemStorage {
+        try_test_storage_with(root, properties).await.unwrap()
+    }
+
+    // Try to create a storage on "test/**" (stripped), in the "test" directory of a root, with some storage properties
+    async fn try_test_storage_with(
+        root: &Path,
+        properties: serde_json::Value,
+    ) -> ZResult<FileSystemStorage> {
+        let mut volume = serde_json::json!({ "id": "fs", "dir": "test" });
+        volume
+            .as_object_mut()
+            .unwrap()
+            .extend(properties.as_object().unwrap().clone());
+        let config = PluginConfig::try_from((
+            "storage_manager",
+            &serde_json::json!({
+                "volumes": { "fs": {} },
+                "storages": {
+                    "test": {
+                        "key_expr": "test/**",
+                        "strip_prefix": "test",
+                        "volume": volume,
+                    },
+                },
+            }),
+        ))
+        .unwrap();
+        let mut backend = {
+            let _lock = ROOT_ENV_VAR_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+            std::env::set_var(SCOPE_ENV_VAR, root);
+            new_backend(&config.volumes[0], None).unwrap()
+        };
+        backend.new_storage(config.storages[0].clone()).await
+    }
+
+    fn put(key: &str, ts: Timestamp) -> Sample {
+        Sample::new(
+            OwnedKeyExpr::try_from(key.to_string()).unwrap(),
+            Value::new(key.as_bytes().to_vec().into()),
+        )
+        .with_timestamp(ts)
+    }
+
+    fn delete(key: &str, ts: Timestamp) -> Sample {
+        let mut sample = put(key, ts);
+        sample.kind = SampleKind::Delete;
+        sample
+    }
+
+    #[test]
+    fn a_delete_wins_over_a_put_with_the_same_timestamp_whatever_their_order() {
+        task::block_on(async {
+            let ts = new_reception_timestamp();
+            for put_first in [true, false] {
+                let root = tempfile::tempdir().unwrap();
+                let mut storage = test_storage(root.path()).await;
+                let (first, second) = if put_first {
+                    (put("test/a", ts), delete("test/a", ts))
+                } else {
+                    (delete("test/a", ts), put("test/a", ts))
+                };
+                storage.on_sample(first).await.unwrap();
+                let result = storage.on_sample(second).await.unwrap();
+                if put_first {
+                    assert!(matches!(result, StorageInsertionResult::Deleted));
+                } else {
+                    assert!(matches!(result, StorageInsertionResult::Outdated));
+                }
+                assert!(!storage
+                    .files_mgr
+                    .file_exists(&storage.files_mgr.to_zfile("a")));
+                assert_eq!(
+                    storage.files_mgr.get_deleted_entries(None).await,
+                    vec![("a".to_string(), ts)]
+                );
+            }
+        })
+    }
+
+    #[test]
+    fn deletes_of_unknown_keys_are_recorded_unless_ignored() {
+        task::block_on(async {
+            for (delete_missing, tombstoned) in [("tombstone", true), ("ignore", false)] {
+                let root = tempfile::tempdir().unwrap();
+                let mut storage = test_storage_with(
+                    root.path(),
+                    serde_json::json!({ "delete_missing": delete_missing }),
+                )
+                .await;
+                let (put_ts, deleted_ts) = (new_reception_timestamp(), new_reception_timestamp());
+                storage.on_sample(put("test/a", put_ts)).await.unwrap();
+                storage
+                    .on_sample(delete("test/a", deleted_ts))
+                    .await
+                    .unwrap();
+                let result = storage
+                    .on_sample(delete("test/b", new_reception_timestamp()))
+                    .await
+                    .unwrap();
+                assert!(matches!(result, StorageInsertionResult::Deleted));
+
+                // the deletion of a stored key is always recorded
+                let entries = storage.get_all_entries().await.unwrap();
+                let keys: Vec<&str> = entries.iter().map(|(ke, _)| ke.as_str()).collect();
+                if tombstoned {
+                    assert_eq!(keys, vec!["test/a", "test/b"]);
+                } else {
+                    assert_eq!(keys, vec!["test/a"]);
+                }
+                assert_eq!(entries[0].1, deleted_ts);
+            }
+        })
+    }
+
+    #[test]
+    fn out_of_order_samples_converge_whatever_their_order() {
+        task::block_on(async {
+            let id = *new_reception_timestamp().get_id();
+            let ts = |secs: u64| Timestamp::new(NTP64(secs << 32), id);
+            let samples = vec![
+                put("test/a", ts(1)),
+                delete("test/a", ts(2)),
+                put("test/a", ts(3)),
+                put("test/b", ts(2)),
+                delete("test/b", ts(1)),
+                delete("test/c", ts(2)),
+                delete("test/c", ts(1)),
+            ];
+            let mut interleaved = samples.clone();
+            interleaved.swap(0, 2);
+            interleaved.swap(3, 5);
+            let reversed: Vec<Sample> = samples.iter().rev().cloned().collect();
+
+            let mut final_entries = Vec::new();
+            for (samples, puts_outdated, deletes_outdated) in
+                [(samples, 0, 2), (interleaved, 1, 2), (reversed, 1, 1)]
+            {
+                let root = tempfile::tempdir().unwrap();
+                let mut storage = test_storage(root.path()).await;
+                for sample in samples {
+                    storage.on_sample(sample).await.unwrap();
+                }
+                assert_eq!(
+                    (storage.puts_outdated, storage.deletes_outdated),
+                    (puts_outdated, deletes_outdated)
+                );
+                final_entries.push(storage.get_all_entries().await.unwrap());
+            }
+            let expected: Vec<(OwnedKeyExpr, Timestamp)> = vec![
+                (keyexpr::new("test/a").unwrap().to_owned(), ts(3)),
+                (keyexpr::new("test/b").unwrap().to_owned(), ts(2)),
+                (keyexpr::new("test/c").unwrap().to_owned(), ts(2)),
+            ];
+            assert!(final_entries.iter().all(|entries| *entries == expected));
+        })
+    }
+
+    #[test]
+    fn reserved_paths_are_invisible_and_unwritable() {
+        task::block_on(async {
+            let root = tempfile::tempdir().unwrap();
+            let mut storage = test_storage(root.path()).await;
+            storage
+                .on_sample(put("test/a", new_reception_timestamp()))
+                .await
+                .unwrap();
+            // an internal file, e.g. quarantined
+            let quarantined = root.path().join("test").join(QUARANTINE_DIRNAME).join("b");
+            std::fs::create_dir_all(quarantined.parent().unwrap()).unwrap();
+            std::fs::write(&quarantined, "b").unwrap();
+
+            let quarantined_key = format!("test/{}/b", QUARANTINE_DIRNAME);
+            for key in ["test/.zenoh_x", "test/c/.zenoh", quarantined_key.as_str()] {
+                assert!(storage
+                    .on_sample(put(key, new_reception_timestamp()))
+                    .await
+                    .is_err());
+                assert!(storage
+                    .on_sample(delete(key, new_reception_timestamp()))
+                    .await
+                    .is_err());
+            }
+            assert!(quarantined.is_file());
+
+            let entries = storage.get_all_entries().await.unwrap();
+            assert_eq!(entries.len(), 1);
+            assert_eq!(entries[0].0.as_str(), "test/a");
+            let ke = keyexpr::new("**").unwrap();
+            assert_eq!(storage.matching_zpaths(ke).collect::<Vec<_>>(), vec!["a"]);
+        })
+    }
+
+    #[test]
+    fn snapshot_views_only_see_the_entries_before_their_timestamp() {
+        task::block_on(async {
+            let root = tempfile::tempdir().unwrap();
+            let mut storage = test_storage(root.path()).await;
+            storage
+                .on_sample(put("test/a", new_reception_timestamp()))
+                .await
+                .unwrap();
+            storage
+                .on_sample(put("test/b", new_reception_timestamp()))
+                .await
+                .unwrap();
+            let snapshot_ts = new_reception_timestamp();
+            let mut view = storage.with_snapshot_ts(snapshot_ts);
+            storage
+                .on_sample(put("test/b", new_reception_timestamp()))
+                .await
+                .unwrap();
+            storage
+                .on_sample(put("test/c", new_reception_timestamp()))
+                .await
+                .unwrap();
+
+            let keys = |entries: Vec<(OwnedKeyExpr, Timestamp)>| {
+                let mut keys: Vec<String> = entries.iter().map(|(k, _)| k.to_string()).collect();
+                keys.sort();
+                keys
+            };
+            assert_eq!(
+                keys(storage.get_all_entries().await.unwrap()),
+                vec!["test/a", "test/b", "test/c"]
+            );
+            // (b was modified after the snapshot: its previous version isn't kept)
+            assert_eq!(keys(view.get_all_entries().await.unwrap()), vec!["test/a"]);
+            assert!(view.is_visible(&snapshot_ts));
+            assert!(!view.is_visible(&new_reception_timestamp()));
+
+            // the view refuses the writes, even if the storage is writable
+            assert!(view
+                .on_sample(put("test/d", new_reception_timestamp()))
+                .await
+                .is_err());
+            assert!(!storage
+                .files_mgr
+                .file_exists(&storage.files_mgr.to_zfile("d")));
+        })
+    }
+
+    #[test]
+    fn archives_are_replied_by_parts() {
+        let ts = new_reception_timestamp();
+        let mut archive = ArchiveReply::new(1024);
+        archive.append("a/b", &[1u8; 1000], &ts).unwrap();
+        // (an invalid path leaves the archive unchanged)
+        assert!(archive.append("a/../b", b"x", &ts).is_err());
+        let mut parts = Vec::new();
+        while let Some(part) = archive.next_part() {
+            parts.push(part);
+        }
+        archive.append("c", &[2u8; 3000], &ts).unwrap();
+        while let Some(part) = archive.next_part() {
+            parts.push(part);
+        }
+        parts.extend(archive.finish().unwrap());
+
+        let count = parts.len();
+        assert!(count > 1);
+        for (i, part) in parts.iter().enumerate() {
+            let expected = if i + 1 < count {
+                format!("application/x-tar;part={}", i)
+            } else {
+                format!("application/x-tar;part={}/{}", i, count)
+            };
+            assert_eq!(part.encoding.to_string(), expected);
+        }
+        let content: Vec<u8> = parts
+            .iter()
+            .flat_map(|part| part.payload.contiguous().into_owned())
+            .collect();
+        let mut entries = Vec::new();
+        for entry in tar::Archive::new(&content[..]).entries().unwrap() {
+            let entry = entry.unwrap();
+            entries.push((
+                entry.path().unwrap().to_string_lossy().into_owned(),
+                entry.size(),
+            ));
+        }
+        assert_eq!(
+            entries,
+            vec![("a/b".to_string(), 1000), ("c".to_string(), 3000)]
+        );
+    }
+
+    #[test]
+    fn small_archives_are_replied_at_once() {
+        let mut archive = ArchiveReply::new(ARCHIVE_PART_BYTES);
+        archive
+            .append("a", b"value", &new_reception_timestamp())
+            .unwrap();
+        assert!(archive.next_part().is_none());
+        let parts = archive.finish().unwrap();
+        assert_eq!(parts.len(), 1);
+        assert_eq!(parts[0].encoding.to_string(), TAR_ENCODING);
+    }
+
+    #[test]
+    fn replies_are_capped_at_the_first_omitted_file() {
+        let mut bounds = QueryBounds {
+            deadline: None,
+            remaining: Some(2),
+            omitted: 0,
+            replied: Some(HashSet::new()),
+        };
+        assert!(bounds.is_first_match("a") && bounds.take_reply());
+        // (a key matched twice is only replied once, and not counted again)
+        assert!(!bounds.is_first_match("a"));
+        assert!(bounds.is_first_match("b") && bounds.take_reply());
+        assert!(!bounds.is_capped());
+        assert!(bounds.is_first_match("c") && !bounds.take_reply());
+        assert!(bounds.is_capped());
+        assert_eq!(bounds.omitted, 1);
+        assert!(!bounds.is_over_budget());
+    }
+
+    #[test]
+    fn keys_matching_overlapping_sub_keyexprs_are_replied_once() {
+        task::block_on(async {
+            let root = tempfile::tempdir().unwrap();
+            let mut storage = test_storage(root.path()).await;
+            for key in ["test/x", "test/a/x", "test/a/b/x", "test/a/y"] {
+                storage
+                    .on_sample(put(key, new_reception_timestamp()))
+                    .await
+                    .unwrap();
+            }
+            // with the strip_prefix "test", the selector is decomposed in "**/x" and "x", both matching "x"
+            let selector = keyexpr::new("test/**/x").unwrap();
+            let sub_keyexprs = selector.strip_prefix(keyexpr::new("test").unwrap());
+            assert!(sub_keyexprs.len() > 1);
+            let mut bounds = storage.query_bounds(sub_keyexprs.len() > 1);
+            let mut replied: Vec<String> = sub_keyexprs
+                .iter()
+                .flat_map(|ke| storage.matching_zpaths(ke))
+                .filter(|zpath| bounds.is_first_match(zpath))
+                .collect();
+            replied.sort();
+            assert_eq!(replied, vec!["a/b/x", "a/x", "x"]);
+        })
+    }
+
+    #[test]
+    fn queries_are_over_budget_after_their_deadline() {
+        let bounds = QueryBounds {
+            deadline: Some(Instant::now()),
+            remaining: None,
+            omitted: 0,
+            replied: None,
+        };
+        assert!(bounds.is_over_budget());
+    }
+
+    #[test]
+    fn modes_are_only_set_if_configured() {
+        let config: serde_json::Map<String, serde_json::Value> = serde_json::from_str(
+            r#"{"default_file_mode": "0640", "default_dir_mode": "750", "invalid": "0800", "number": 644}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            extract_mode(&config, PROP_STORAGE_DEFAULT_FILE_MODE).unwrap(),
+            Some(0o640)
+        );
+        assert_eq!(
+            extract_mode(&config, PROP_STORAGE_DEFAULT_DIR_MODE).unwrap(),
+            Some(0o750)
+        );
+        assert_eq!(
+            extract_mode(&serde_json::Map::new(), PROP_STORAGE_DEFAULT_FILE_MODE).unwrap(),
+            None
+        );
+        assert!(extract_mode(&config, "invalid").is_err());
+        assert!(extract_mode(&config, "number").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn timestamps_are_kept_whatever_the_form_of_a_symlinked_storage_directory() {
+        task::block_on(async {
+            let root = tempfile::tempdir().unwrap();
+            let real = root.path().join("real");
+            std::fs::create_dir(&real).unwrap();
+            std::os::unix::fs::symlink(&real, root.path().join("test")).unwrap();
+            let canonical = dunce::canonicalize(&real).unwrap();
+            let link = dunce::canonicalize(root.path()).unwrap().join("test");
+            let ts = new_reception_timestamp();
+            for (dir_symlinks, base_dir) in [
+                ("keep", link.clone()),
+                ("resolve", canonical.clone()),
+                ("keep", link.clone()),
+            ] {
+                let mut storage = test_storage_with(
+                    root.path(),
+                    serde_json::json!({ "allow_symlinked_base": true, "dir_symlinks": dir_symlinks }),
+                )
+                .await;
+                assert_eq!(storage.files_mgr.base_dir(), base_dir);
+                if dir_symlinks == "keep" && storage.get_all_entries().await.unwrap().is_empty() {
+                    storage.on_sample(put("test/a", ts)).await.unwrap();
+                }
+                // the file is in the linked directory, and its data-info is found with both forms of its path
+                assert!(canonical.join("a").is_file());
+                let entries = storage.get_all_entries().await.unwrap();
+                assert_eq!(entries.len(), 1);
+                assert_eq!((entries[0].0.as_str(), entries[0].1), ("test/a", ts));
+            }
+        })
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinked_storage_directories_are_refused_unless_allowed() {
+        task::block_on(async {
+            let root = tempfile::tempdir().unwrap();
+            for (link, dir) in [("test", "test"), ("parent", "parent/test")] {
+                let real = root.path().join(format!("real-{}", link));
+                std::fs::create_dir(&real).unwrap();
+                std::os::unix::fs::symlink(&real, root.path().join(link)).unwrap();
+                let error = try_test_storage_with(root.path(), serde_json::json!({ "dir": dir }))
+                    .await
+                    .err()
+                    .unwrap();
+                assert!(
+                    error
+                        .to_string()
+                        .contains(PROP_STORAGE_ALLOW_SYMLINKED_BASE),
+                    "{}",
+                    error
+                );
+                // nothing was created through the link
+                assert_eq!(real.read_dir().unwrap().count(), 0);
+
+                let storage = try_test_storage_with(
+                    root.path(),
+                    serde_json::json!({ "dir": dir, "allow_symlinked_base": true }),
+                )
+                .await
+                .unwrap();
+                assert!(storage
+                    .files_mgr
+                    .base_dir()
+                    .starts_with(dunce::canonicalize(&real).unwrap()));
+            }
+            // a storage directory without link is accepted
+            assert!(
+                try_test_storage_with(root.path(), serde_json::json!({ "dir": "other" }))
+                    .await
+                    .is_ok()
+            );
+        })
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    #[ignore = "mounts a tmpfs, which requires CAP_SYS_ADMIN (e.g. running as root): run with --ignored"]
+    fn puts_on_a_full_disk_are_refused_to_be_retried() {
+        use crate::tmpfs::{Tmpfs, DEFAULT_TMPFS_SIZE};
+        task::block_on(async {
+            let tmpfs = Tmpfs::mount(DEFAULT_TMPFS_SIZE);
+            let mut storage = test_storage(tmpfs.path()).await;
+            let ts = new_reception_timestamp();
+            storage.on_sample(put("test/a", ts)).await.unwrap();
+
+            let mut sample = put("test/a", new_reception_timestamp());
+            sample.value = Value::new(vec![0u8; 2 * DEFAULT_TMPFS_SIZE as usize].into());
+            let error = storage.on_sample(sample).await.err().unwrap();
+            assert!(is_retry_later(&error), "{}", error);
+            // the failed PUT is not recorded
+            let entries = storage.get_all_entries().await.unwrap();
+            assert_eq!(entries.len(), 1);
+            assert_eq!((entries[0].0.as_str(), entries[0].1), ("test/a", ts));
+        })
+    }
+
+    #[test]
+    fn metrics_count_the_stored_and_dropped_samples() {
+        task::block_on(async {
+            let root = tempfile::tempdir().unwrap();
+            let mut storage =
+                test_storage_with(root.path(), serde_json::json!({ "skip_identical": true })).await;
+            storage
+                .on_sample(put("test/a", new_reception_timestamp()))
+                .await
+                .unwrap();
+            // identical to the stored value
+            storage
+                .on_sample(put("test/a", new_reception_timestamp()))
+                .await
+                .unwrap();
+            storage
+                .on_sample(put("test/b", new_reception_timestamp()))
+                .await
+                .unwrap();
+            storage
+                .on_sample(delete("test/b", new_reception_timestamp()))
+                .await
+                .unwrap();
+            // out-of-date
+            let old = Timestamp::new(NTP64(1 << 32), *new_reception_timestamp().get_id());
+            storage.on_sample(put("test/b", old)).await.unwrap();
+
+            let metrics = storage.admin_metrics().await;
+            let value = |name: &str| -> u64 {
+                let prefix = format!(r#"{}_{}{{key_expr="test/**"}} "#, METRICS_PREFIX, name);
+                metrics
+                    .lines()
+                    .find_map(|line| line.strip_prefix(&prefix))
+                    .unwrap_or_else(|| panic!("no {} in:\n{}", name, metrics))
+                    .parse()
+                    .unwrap()
+            };
+            assert_eq!(value("puts_total"), 2);
+            assert_eq!(value("puts_identical_total"), 1);
+            assert_eq!(value("puts_outdated_total"), 1);
+            assert_eq!(value("deletes_total"), 1);
+            assert_eq!(value("errors_total"), 0);
+            assert_eq!(value("stored_files"), 1);
+            assert_eq!(value("tombstones"), 1);
+            assert_eq!(value("disk_usage_bytes"), "test/a".len() as u64);
+            // each metric is described
+            assert_eq!(metrics.matches("# HELP ").count(), 10);
+            assert_eq!(metrics.matches("# TYPE ").count(), 10);
+        })
+    }
+
+    // The names of the temporary files left in a directory by the transactions
+    fn transaction_tmp_files(dir: &Path) -> Vec<String> {
+        std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .filter(|name| name.starts_with(".zenoh_tx_"))
+            .collect()
+    }
+
+    fn put_value(key: &str, value: &str, ts: Timestamp) -> Sample {
+        let mut sample = put(key, ts);
+        sample.value = Value::new(value.as_bytes().to_vec().into());
+        sample
+    }
+
+    #[test]
+    fn a_transaction_is_applied_on_commit_and_discarded_on_rollback() {
+        task::block_on(async {
+            let root = tempfile::tempdir().unwrap();
+            let dir = root.path().join("test");
+            let mut storage = test_storage(root.path()).await;
+            for key in ["test/a", "test/b"] {
+                storage
+                    .on_sample(put(key, new_reception_timestamp()))
+                    .await
+                    .unwrap();
+            }
+
+            storage.begin_transaction().unwrap();
+            assert!(storage.begin_transaction().is_err());
+            storage
+                .on_sample(put_value("test/a", "a2", new_reception_timestamp()))
+                .await
+                .unwrap();
+            storage
+                .on_sample(delete("test/b", new_reception_timestamp()))
+                .await
+                .unwrap();
+            storage
+                .on_sample(put_value("test/c", "c1", new_reception_timestamp()))
+                .await
+                .unwrap();
+            // nothing is applied until committed
+            assert_eq!(std::fs::read(dir.join("a")).unwrap(), b"test/a");
+            assert!(dir.join("b").is_file());
+            assert!(!dir.join("c").exists());
+            storage.commit().await.unwrap();
+            assert_eq!(std::fs::read(dir.join("a")).unwrap(), b"a2");
+            assert!(!dir.join("b").exists());
+            assert_eq!(std::fs::read(dir.join("c")).unwrap(), b"c1");
+            assert!(transaction_tmp_files(&dir).is_empty());
+
+            storage.begin_transaction().unwrap();
+            storage
+                .on_sample(put_value("test/a", "a3", new_reception_timestamp()))
+                .await
+                .unwrap();
+            storage
+                .on_sample(delete("test/c", new_reception_timestamp()))
+                .await
+                .unwrap();
+            assert_eq!(transaction_tmp_files(&dir).len(), 1);
+            storage.rollback();
+            assert_eq!(std::fs::read(dir.join("a")).unwrap(), b"a2");
+            assert_eq!(std::fs::read(dir.join("c")).unwrap(), b"c1");
+            assert!(transaction_tmp_files(&dir).is_empty());
+            assert!(storage.commit().await.is_err());
+            // the samples are applied as usual once the transaction is over
+            storage
+                .on_sample(put_value("test/a", "a4", new_reception_timestamp()))
+                .await
+                .unwrap();
+            assert_eq!(std::fs::read(dir.join("a")).unwrap(), b"a4");
+        })
+    }
+
+    #[test]
+    fn a_transaction_failing_in_the_middle_leaves_the_stored_entries_unchanged() {
+        task::block_on(async {
+            let root = tempfile::tempdir().unwrap();
+            let dir = root.path().join("test");
+            let mut storage = test_storage(root.path()).await;
+            for key in ["test/a", "test/b"] {
+                storage
+                    .on_sample(put(key, new_reception_timestamp()))
+                    .await
+                    .unwrap();
+            }
+
+            // a PUT on test/b/c would move the stored file b to a conflict file: it can't be staged
+            storage.begin_transaction().unwrap();
+            storage
+                .on_sample(put_value("test/a", "a2", new_reception_timestamp()))
+                .await
+                .unwrap();
+            assert!(storage
+                .on_sample(put_value("test/b/c", "c1", new_reception_timestamp()))
+                .await
+                .is_err());
+            storage.rollback();
+            assert_eq!(std::fs::read(dir.join("a")).unwrap(), b"test/a");
+            assert_eq!(std::fs::read(dir.join("b")).unwrap(), b"test/b");
+            assert!(transaction_tmp_files(&dir).is_empty());
+
+            // a key written with a newer timestamp since it was staged aborts the whole commit
+            storage.begin_transaction().unwrap();
+            storage
+                .on_sample(put_value("test/a", "a2", new_reception_timestamp()))
+                .await
+                .unwrap();
+            storage
+                .on_sample(put_value("test/d", "d1", new_reception_timestamp()))
+                .await
+                .unwrap();
+            storage
+                .on_sample(delete("test/b", new_reception_timestamp()))
+                .await
+                .unwrap();
+            let zfile = storage.files_mgr.to_zfile("b");
+            storage
+                .files_mgr
+                .write_file(
+                    &zfile,
+                    b"b2".to_vec().into(),
+                    &Encoding::APP_OCTET_STREAM,
+                    &new_reception_timestamp(),
+                )
+                .await
+                .unwrap();
+            assert!(storage.commit().await.is_err());
+            assert_eq!(std::fs::read(dir.join("a")).unwrap(), b"test/a");
+            assert_eq!(std::fs::read(dir.join("b")).unwrap(), b"b2");
+            assert!(!dir.join("d").exists());
+            assert!(transaction_tmp_files(&dir).is_empty());
+        })
+    }
+
+    #[test]
+    fn the_listing_of_all_the_entries_pauses_between_its_chunks_with_catchup_throttle() {
+        task::block_on(async {
+            let root = tempfile::tempdir().unwrap();
+            let dir = root.path().join("test");
+            std::fs::create_dir_all(&dir).unwrap();
+            // files of 2 chunks, placed without zenoh
+            let count = GET_ALL_ENTRIES_YIELD_PERIOD + 1;
+            for i in 0..count {
+                std::fs::write(dir.join(format!("file{:05}", i)), b"x").unwrap();
+            }
+            let pause = Duration::from_millis(200);
+            let storage = test_storage_with(
+                root.path(),
+                serde_json::json!({ "catchup_throttle_us": pause.as_micros() as u64 }),
+            )
+            .await;
+            assert_eq!(storage.catchup_throttle, Some(pause));
+            let start = Instant::now();
+            let entries = storage.get_all_entries().await.unwrap();
+            assert_eq!(entries.len(), count);
+            assert!(start.elapsed() >= pause);
+        })
+    }
+
+    #[test]
+    fn the_etag_is_recorded_on_put_and_the_reads_are_counted_in_the_data_info() {
+        task::block_on(async {
+            let root = tempfile::tempdir().unwrap();
+            let mut storage = test_storage(root.path()).await;
+            storage
+                .on_sample(put("test/a", new_reception_timestamp()))
+                .await
+                .unwrap();
+            let params: HashMap<String, String> =
+                [("key".to_string(), "test/a".to_string())].into();
+            let zfile = storage.files_mgr.to_zfile("a");
+            let (_, etag) = storage.files_mgr.get_etag(&zfile).await.unwrap().unwrap();
+            let data_info = storage.admin_data_info(&params).await.unwrap();
+            assert_eq!(data_info["etag"], etag.as_str());
+            assert!(data_info.get("access_count").is_none(), "{}", data_info);
+
+            // each read (as for a GET) is counted
+            for _ in 0..2 {
+                let mut pinned = storage.files_mgr.pin(&zfile).await.unwrap().unwrap();
+                let (value, _) = storage
+                    .files_mgr
+                    .read_pinned(&mut pinned, None, false)
+                    .await
+                    .unwrap();
+                assert_eq!(value.payload.contiguous().as_ref(), b"test/a");
+            }
+            let data_info = storage.admin_data_info(&params).await.unwrap();
+            assert_eq!(data_info["access_count"], 2);
+            assert_eq!(data_info["etag"], etag.as_str());
+
+            // a new PUT records a new ETag and resets the count
+            storage
+                .on_sample(put_value("test/a", "b", new_reception_timestamp()))
+                .await
+                .unwrap();
+            let (_, new_etag) = storage.files_mgr.get_etag(&zfile).await.unwrap().unwrap();
+            assert_ne!(new_etag, etag);
+            let data_info = storage.admin_data_info(&params).await.unwrap();
+            assert_eq!(data_info["etag"], new_etag.as_str());
+            assert!(data_info.get("access_count").is_none(), "{}", data_info);
+        })
+    }
+
+    // A PUT and a DELETE on a key whose file is locked (as by a background task of the storage, e.g. a bulk
+    // deletion): in "pessimistic" locking mode they wait for the lock, in "optimistic" mode they take no lock
+    #[test]
+    fn only_the_pessimistic_locking_mode_waits_for_the_lock_of_the_file() {
+        task::block_on(async {
+            for mode in &["optimistic", "pessimistic"] {
+                let root = tempfile::tempdir().unwrap();
+                let mut storage =
+                    test_storage_with(root.path(), serde_json::json!({ "locking_mode": mode }))
+                        .await;
+                let file = root.path().join("test").join("a");
+                for sample in [
+                    put("test/a", new_reception_timestamp()),
+                    delete("test/a", new_reception_timestamp()),
+                ] {
+                    let lock = storage
+                        .files_mgr
+                        .lock_key(&storage.files_mgr.to_zfile("a"))
+                        .await;
+                    let written = file.is_file();
+                    let result = async_std::future::timeout(
+                        Duration::from_millis(500),
+                        storage.on_sample(sample.clone()),
+                    )
+                    .await;
+                    if *mode == "pessimistic" {
+                        // (cancelled while waiting for the lock)
+                        assert!(result.is_err());
+                        assert_eq!(file.is_file(), written);
+                        drop(lock);
+                        storage.on_sample(sample).await.unwrap();
+                    } else {
+                        assert!(result.unwrap().is_ok());
+                        drop(lock);
+                    }
+                    assert_eq!(file.is_file(), !written, "{}", mode);
+                }
+            }
+        })
+    }
+}
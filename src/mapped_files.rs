@@ -0,0 +1,138 @@
+//
+// Copyright (c) 2022 ZettaScale Technology
+//
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+// Contributors:
+//   ZettaScale Zenoh Team, <zenoh@zettascale.tech>
+//
+use crate::read_cache::ContentId;
+use memmap2::Mmap;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs::{File, Metadata};
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+// Number of independently locked shards of the mappings, so the reads of different files don't contend
+const SHARDS: usize = 64;
+
+// Maximum number of files kept memory-mapped, per shard
+const MAX_MAPPED_FILES_PER_SHARD: usize = 64;
+
+// The memory mapping of a whole file, with the identity of the content it maps.
+// As long as it's held, the mapped content remains readable even if its file is deleted or renamed over
+// (like an opened file, it keeps the inode alive).
+pub(crate) struct Mapping {
+    mmap: Mmap,
+    id: ContentId,
+    metadata: Metadata,
+}
+
+impl Mapping {
+    pub(crate) fn content(&self) -> &[u8] {
+        &self.mmap
+    }
+
+    // The metadata of the file when it was mapped
+    pub(crate) fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+}
+
+// The memory mappings of the files read with "mmap_reads", by path. A mapping is reused only while the file
+// at its path still has the same device, inode, size and modification time: a file rewritten (i.e. renamed over
+// by a temporary file) or replaced is re-mapped. The files written, deleted or renamed via zenoh are unmapped.
+pub(crate) struct MappedFiles {
+    // minimum size of the files to be mapped
+    min_size: u64,
+    shards: Vec<Mutex<HashMap<PathBuf, Arc<Mapping>>>>,
+}
+
+impl MappedFiles {
+    pub(crate) fn new(min_size: u64) -> Self {
+        MappedFiles {
+            min_size: min_size.max(1),
+            shards: (0..SHARDS).map(|_| Mutex::new(HashMap::new())).collect(),
+        }
+    }
+
+    fn shard(&self, file: &Path) -> &Mutex<HashMap<PathBuf, Arc<Mapping>>> {
+        let mut hasher = DefaultHasher::new();
+        file.hash(&mut hasher);
+        &self.shards[hasher.finish() as usize % SHARDS]
+    }
+
+    // Get the mapping of a file, if mapped with the same identity as its current metadata
+    pub(crate) fn get(&self, file: &Path, metadata: &Metadata) -> Option<Arc<Mapping>> {
+        let id = crate::files_mgt::inode_content_id(metadata)?;
+        let stale = {
+            let mut shard = self.shard(file).lock().unwrap();
+            match shard.get(file).cloned() {
+                Some(mapping) if mapping.id == id => return Some(mapping),
+                Some(_) => shard.remove(file),
+                None => return None,
+            }
+        };
+        // (the stale mapping is unmapped once released by its last reader, outside of the lock)
+        drop(stale);
+        None
+    }
+
+    // Map a file opened (and locked) for reading, if it's big enough and its identity is known
+    pub(crate) fn map(&self, file: &Path, f: &File) -> Option<Arc<Mapping>> {
+        let metadata = f.metadata().ok()?;
+        if metadata.len() < self.min_size {
+            return None;
+        }
+        let id = crate::files_mgt::inode_content_id(&metadata)?;
+        // Safety: the mapping is read only right after its identity was checked against the file's current
+        // metadata. The files replaced via zenoh are renamed over (so the mapped inode is left unchanged), but
+        // a mapped file truncated outside of zenoh could still crash the process (see README).
+        let mmap = match unsafe { Mmap::map(f) } {
+            Ok(mmap) => mmap,
+            Err(e) => {
+                tracing::debug!("Failed to map file {:?}, read it instead: {}", file, e);
+                return None;
+            }
+        };
+        let mapping = Arc::new(Mapping { mmap, id, metadata });
+        let evicted = {
+            let mut shard = self.shard(file).lock().unwrap();
+            let evicted = if shard.len() >= MAX_MAPPED_FILES_PER_SHARD && !shard.contains_key(file)
+            {
+                let victim = shard.keys().next().cloned();
+                victim.and_then(|victim| shard.remove(&victim))
+            } else {
+                None
+            };
+            (evicted, shard.insert(file.to_path_buf(), mapping.clone()))
+        };
+        drop(evicted);
+        Some(mapping)
+    }
+
+    // Drop the mapping of a file (e.g. written, deleted or renamed), if any
+    pub(crate) fn unmap(&self, file: &Path) {
+        let removed = self.shard(file).lock().unwrap().remove(file);
+        drop(removed);
+    }
+
+    pub(crate) fn clear(&self) {
+        for shard in &self.shards {
+            let removed = std::mem::take(&mut *shard.lock().unwrap());
+            drop(removed);
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.shards.iter().map(|s| s.lock().unwrap().len()).sum()
+    }
+}
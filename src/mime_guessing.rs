@@ -0,0 +1,364 @@
+//
+// Copyright (c) 2022 ZettaScale Technology
+//
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+// Contributors:
+//   ZettaScale Zenoh Team, <zenoh@zettascale.tech>
+//
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use zenoh::Result as ZResult;
+use zenoh_core::bail;
+
+// Size of the beginning of a file that is inspected to sniff its mime-type
+const SNIFF_LEN: usize = 512;
+
+// The signatures of the sniffed mime-types: the bytes at an offset of the content
+const SIGNATURES: &[(usize, &[u8], &str)] = &[
+    (0, b"\x89PNG\r\n\x1a\n", "image/png"),
+    (0, b"\xFF\xD8\xFF", "image/jpeg"),
+    (0, b"GIF87a", "image/gif"),
+    (0, b"GIF89a", "image/gif"),
+    (8, b"WEBP", "image/webp"),
+    (0, b"II*\x00", "image/tiff"),
+    (0, b"MM\x00*", "image/tiff"),
+    (0, b"%PDF-", "application/pdf"),
+    (0, b"\x1F\x8B", "application/gzip"),
+    (0, b"BZh", "application/x-bzip2"),
+    (0, b"\xFD7zXZ\x00", "application/x-xz"),
+    (0, b"\x28\xB5\x2F\xFD", "application/zstd"),
+    (0, b"7z\xBC\xAF\x27\x1C", "application/x-7z-compressed"),
+    (0, b"PK\x03\x04", "application/zip"),
+    (257, b"ustar", "application/x-tar"),
+    (0, b"\x7FELF", "application/x-executable"),
+    (0, b"\x00asm", "application/wasm"),
+    (0, b"SQLite format 3\x00", "application/vnd.sqlite3"),
+    (0, b"OggS", "audio/ogg"),
+    (0, b"fLaC", "audio/flac"),
+    (0, b"ID3", "audio/mpeg"),
+    (8, b"WAVE", "audio/wav"),
+    (4, b"ftyp", "video/mp4"),
+    (0, b"\x1A\x45\xDF\xA3", "video/webm"),
+    (0, b"wOFF", "font/woff"),
+    (0, b"wOF2", "font/woff2"),
+    (0, b"<?xml", "application/xml"),
+];
+
+// The compound extensions (e.g. "tar.gz" in "backup.tar.gz") with their encodings, by default priority:
+// the final extension alone would mislead about the content (a gzip of a tar, or a template of a JSON)
+const COMPOUND_EXTENSIONS: &[(&str, &str)] = &[
+    ("tar.gz", "application/gzip;inner=application/x-tar"),
+    ("tar.bz2", "application/x-bzip2;inner=application/x-tar"),
+    ("tar.xz", "application/x-xz;inner=application/x-tar"),
+    ("tar.zst", "application/zstd;inner=application/x-tar"),
+    ("json.tmpl", "text/plain"),
+    ("yaml.tmpl", "text/plain"),
+    ("yml.tmpl", "text/plain"),
+    ("xml.tmpl", "text/plain"),
+    ("html.tmpl", "text/plain"),
+    ("json.j2", "text/plain"),
+    ("yaml.j2", "text/plain"),
+    ("html.j2", "text/plain"),
+];
+
+// The source of the mime-type guessed for a file without data-info ("mime_source")
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum MimeSource {
+    // the file's extension
+    Extension,
+    // the signature at the beginning of the file's content, or else its extension
+    Content,
+}
+
+// Guessing of the mime-type of the files, by extension or by content ("mime_source"),
+// the extensions mapped to an encoding by "encoding_map" overriding the guess.
+// The compound extensions ("compound_extensions") take precedence over the final extension.
+pub(crate) struct MimeGuesser {
+    source: MimeSource,
+    // the encodings by extension (lowercase, without the leading '.')
+    encoding_map: HashMap<String, String>,
+    // the compound extensions considered, by priority (lowercase, without the leading '.')
+    compound_extensions: Vec<String>,
+}
+
+impl MimeGuesser {
+    // (with compound_extensions as None, the built-in ones are considered in their default priority order)
+    pub(crate) fn new(
+        source: MimeSource,
+        encoding_map: HashMap<String, String>,
+        compound_extensions: Option<Vec<String>>,
+    ) -> ZResult<Self> {
+        let encoding_map: HashMap<String, String> = encoding_map
+            .into_iter()
+            .map(|(ext, encoding)| (normalize_extension(&ext), encoding))
+            .collect();
+        let compound_extensions = match compound_extensions {
+            Some(exts) => exts.iter().map(|ext| normalize_extension(ext)).collect(),
+            None => default_compound_extensions(),
+        };
+        // (an extension with several parts is only matched as a compound extension)
+        for ext in encoding_map.keys() {
+            if ext.contains('.') && !compound_extensions.contains(ext) {
+                bail!(
+                    r#"Invalid extension "{}" in `encoding_map`: it has several parts but isn't one of the `compound_extensions`"#,
+                    ext
+                );
+            }
+        }
+        for ext in &compound_extensions {
+            if !ext.contains('.') {
+                bail!(
+                    r#"Invalid compound extension "{}": must have several parts (e.g. "tar.gz")"#,
+                    ext
+                );
+            }
+            if !encoding_map.contains_key(ext) && builtin_compound_encoding(ext).is_none() {
+                bail!(
+                    r#"Unknown compound extension "{}": it must be mapped to an encoding by `encoding_map`"#,
+                    ext
+                );
+            }
+        }
+        Ok(MimeGuesser {
+            source,
+            encoding_map,
+            compound_extensions,
+        })
+    }
+
+    // Get the encoding mapped by "encoding_map" to a file's compound extension, or else to its extension, if any
+    pub(crate) fn mapped_encoding(&self, file: &Path) -> Option<&str> {
+        if let Some(encoding) = self
+            .compound_extension(file)
+            .and_then(|ext| self.encoding_map.get(ext))
+        {
+            return Some(encoding.as_str());
+        }
+        let ext = file.extension()?.to_str()?;
+        self.encoding_map
+            .get(&normalize_extension(ext))
+            .map(String::as_str)
+    }
+
+    // Guess the mime-type of a file, from its compound extension, or else from the beginning of its content
+    // if known (or else read from the file)
+    pub(crate) fn guess(&self, file: &Path, content: Option<&[u8]>) -> Option<String> {
+        if let Some(encoding) = self
+            .compound_extension(file)
+            .and_then(builtin_compound_encoding)
+        {
+            return Some(encoding.to_string());
+        }
+        if let MimeSource::Content = self.source {
+            let sniffed = match content {
+                Some(content) => sniff(content),
+                None => read_head(file).as_deref().and_then(sniff),
+            };
+            if let Some(mime) = sniffed {
+                return Some(mime.to_string());
+            }
+        }
+        mime_guess::from_path(file)
+            .first()
+            .map(|mime| mime.essence_str().to_string())
+    }
+
+    // Check if a file with an extension is written for a mime-type (for "strip_extension")
+    pub(crate) fn is_extension_of(&self, ext: &str, mime: &str) -> bool {
+        let ext = normalize_extension(ext);
+        let encoding = self.encoding_map.get(&ext).map(String::as_str).or_else(|| {
+            self.compound_extensions
+                .contains(&ext)
+                .then(|| builtin_compound_encoding(&ext))
+                .flatten()
+        });
+        match encoding {
+            Some(encoding) => encoding.split(';').next().unwrap_or_default().trim() == mime,
+            None => mime_guess::from_ext(&ext)
+                .iter()
+                .any(|m| m.essence_str() == mime),
+        }
+    }
+
+    // Get the compound extension of a file with the highest priority, if any
+    // (the file name must have a stem before it, e.g. not ".tar.gz")
+    fn compound_extension(&self, file: &Path) -> Option<&str> {
+        if self.compound_extensions.is_empty() {
+            return None;
+        }
+        let name = file.file_name()?.to_str()?.to_ascii_lowercase();
+        self.compound_extensions
+            .iter()
+            .find(|ext| {
+                name.len() > ext.len() + 1
+                    && name.ends_with(ext.as_str())
+                    && name[..name.len() - ext.len()].ends_with('.')
+            })
+            .map(String::as_str)
+    }
+}
+
+// Get the built-in compound extensions, in their default priority order
+fn default_compound_extensions() -> Vec<String> {
+    COMPOUND_EXTENSIONS
+        .iter()
+        .map(|(ext, _)| ext.to_string())
+        .collect()
+}
+
+fn builtin_compound_encoding(ext: &str) -> Option<&'static str> {
+    COMPOUND_EXTENSIONS
+        .iter()
+        .find(|(compound, _)| *compound == ext)
+        .map(|(_, encoding)| *encoding)
+}
+
+fn normalize_extension(ext: &str) -> String {
+    ext.trim_start_matches('.').to_ascii_lowercase()
+}
+
+// Read the beginning of a file, or None if it can't be read
+fn read_head(file: &Path) -> Option<Vec<u8>> {
+    let mut head = Vec::with_capacity(SNIFF_LEN);
+    File::open(file)
+        .ok()?
+        .take(SNIFF_LEN as u64)
+        .read_to_end(&mut head)
+        .ok()?;
+    Some(head)
+}
+
+// Get the mime-type whose signature is at the beginning of a content, if any
+fn sniff(content: &[u8]) -> Option<&'static str> {
+    let head = &content[..content.len().min(SNIFF_LEN)];
+    SIGNATURES
+        .iter()
+        .find(|(offset, signature, _)| {
+            head.get(*offset..*offset + signature.len()) == Some(*signature)
+        })
+        .map(|(_, _, mime)| *mime)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compound_extensions_are_found_by_priority() {
+        let guesser = MimeGuesser::new(MimeSource::Extension, HashMap::new(), None).unwrap();
+        for (file, ext) in &[
+            ("backup.tar.gz", Some("tar.gz")),
+            ("BACKUP.TAR.GZ", Some("tar.gz")),
+            ("x.tar.gz", Some("tar.gz")),
+            ("a.b.tar.gz", Some("tar.gz")),
+            ("dir/sub/a.tar.gz", Some("tar.gz")),
+            ("dir.tar.gz/a.txt", None),
+            (".tar.gz", None),
+            ("tar.gz", None),
+            ("backuptar.gz", None),
+            ("backup.targz", None),
+            ("backup.tar.gz.bak", None),
+            ("a.tar.bz2", Some("tar.bz2")),
+            ("a.tar.xz", Some("tar.xz")),
+            ("a.tar.zst", Some("tar.zst")),
+            ("a.tar", None),
+            ("a.gz", None),
+            ("config.json.tmpl", Some("json.tmpl")),
+            ("config.yaml.tmpl", Some("yaml.tmpl")),
+            ("config.yml.tmpl", Some("yml.tmpl")),
+            ("config.xml.tmpl", Some("xml.tmpl")),
+            ("index.html.tmpl", Some("html.tmpl")),
+            ("config.json.j2", Some("json.j2")),
+            ("config.yaml.j2", Some("yaml.j2")),
+            ("index.html.j2", Some("html.j2")),
+            ("Index.Html.J2", Some("html.j2")),
+            ("config.tmpl", None),
+            ("config.tmpl.json", None),
+            ("config.j2", None),
+            ("config.json", None),
+            ("readme", None),
+            (".hidden", None),
+            ("a.", None),
+        ] {
+            assert_eq!(
+                guesser.compound_extension(Path::new(file)),
+                *ext,
+                "compound extension of {:?}",
+                file
+            );
+        }
+    }
+
+    #[test]
+    fn files_are_guessed_by_compound_extension_first() {
+        let guesser = MimeGuesser::new(MimeSource::Extension, HashMap::new(), None).unwrap();
+        for (file, mime) in &[
+            (
+                "backup.tar.gz",
+                Some("application/gzip;inner=application/x-tar"),
+            ),
+            ("config.json.tmpl", Some("text/plain")),
+            ("config.json", Some("application/json")),
+            ("notes.txt", Some("text/plain")),
+            ("image.png", Some("image/png")),
+            ("readme", None),
+        ] {
+            assert_eq!(
+                guesser.guess(Path::new(file), None).as_deref(),
+                *mime,
+                "guess of {:?}",
+                file
+            );
+        }
+    }
+
+    #[test]
+    fn compound_extensions_are_configurable() {
+        let encoding_map: HashMap<String, String> = vec![
+            (".TAR.GZ".to_string(), "application/x-custom".to_string()),
+            ("cfg.in".to_string(), "text/plain".to_string()),
+        ]
+        .into_iter()
+        .collect();
+        let guesser = MimeGuesser::new(
+            MimeSource::Extension,
+            encoding_map.clone(),
+            Some(vec!["cfg.in".to_string(), "tar.gz".to_string()]),
+        )
+        .unwrap();
+        assert_eq!(
+            guesser.mapped_encoding(Path::new("a.tar.gz")),
+            Some("application/x-custom")
+        );
+        assert_eq!(
+            guesser.mapped_encoding(Path::new("a.cfg.in")),
+            Some("text/plain")
+        );
+        // (the compound extensions not configured are ignored)
+        assert_eq!(guesser.compound_extension(Path::new("a.tar.bz2")), None);
+
+        // a mapped extension with several parts must be a compound extension
+        assert!(MimeGuesser::new(MimeSource::Extension, encoding_map, None).is_err());
+        // a compound extension must have several parts, and a known encoding
+        assert!(MimeGuesser::new(
+            MimeSource::Extension,
+            HashMap::new(),
+            Some(vec!["gz".to_string()])
+        )
+        .is_err());
+        assert!(MimeGuesser::new(
+            MimeSource::Extension,
+            HashMap::new(),
+            Some(vec!["cfg.in".to_string()])
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,175 @@
+//
+// Copyright (c) 2022 ZettaScale Technology
+//
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+// Contributors:
+//   ZettaScale Zenoh Team, <zenoh@zettascale.tech>
+//
+use regex::Regex;
+use zenoh::Result as ZResult;
+use zenoh_core::{bail, zerror};
+
+// A part of a path template
+enum Part {
+    Literal(String),
+    // "{chunk<N>}": the Nth chunk of the key
+    Chunk(usize),
+    // "{rest}": the chunks of the key after the ones used by "{chunk<N>}"
+    Rest,
+}
+
+// A template converting the zenoh paths to file paths (relative to the storage directory),
+// e.g. "{chunk0}/{chunk1}/{rest}.dat" converts the key "a/b/c/d" to the path "a/b/c/d.dat"
+// and the key "a/b/c" to "a/b/c.dat".
+pub(crate) struct PathTemplate {
+    parts: Vec<Part>,
+    // number of "{chunk<N>}" in the template
+    nb_chunks: usize,
+    has_rest: bool,
+    // regex matching the file paths built with this template, capturing the chunks and the rest
+    regex: Regex,
+}
+
+impl PathTemplate {
+    pub(crate) fn parse(template: &str) -> ZResult<Self> {
+        let mut parts = Vec::new();
+        let mut remaining = template;
+        while let Some(start) = remaining.find('{') {
+            if start > 0 {
+                parts.push(Part::Literal(remaining[..start].to_string()));
+            }
+            let end = match remaining[start..].find('}') {
+                Some(end) => start + end,
+                None => bail!("Invalid path template {:?}: unclosed '{{'", template),
+            };
+            let placeholder = &remaining[start + 1..end];
+            if placeholder == "rest" {
+                parts.push(Part::Rest);
+            } else if let Some(Ok(idx)) = placeholder.strip_prefix("chunk").map(str::parse) {
+                parts.push(Part::Chunk(idx));
+            } else {
+                bail!(
+                    "Invalid path template {:?}: unknown placeholder {{{}}} (must be {{chunk<N>}} or {{rest}})",
+                    template,
+                    placeholder
+                );
+            }
+            remaining = &remaining[end + 1..];
+        }
+        if !remaining.is_empty() {
+            parts.push(Part::Literal(remaining.to_string()));
+        }
+
+        // check the template
+        if template.starts_with('/') || template.split('/').any(|c| c == "..") {
+            bail!(
+                "Invalid path template {:?}: it must be a relative path without '..'",
+                template
+            );
+        }
+        if template.contains('\\') {
+            bail!(
+                "Invalid path template {:?}: use '/' as path separator",
+                template
+            );
+        }
+        let mut chunks: Vec<usize> = parts
+            .iter()
+            .filter_map(|p| match p {
+                Part::Chunk(idx) => Some(*idx),
+                _ => None,
+            })
+            .collect();
+        chunks.sort_unstable();
+        if chunks.iter().enumerate().any(|(i, idx)| i != *idx) {
+            bail!(
+                "Invalid path template {:?}: each of {{chunk0}} to {{chunk<N>}} must be used exactly once",
+                template
+            );
+        }
+        let nb_rest = parts.iter().filter(|p| matches!(p, Part::Rest)).count();
+        if nb_rest > 1 {
+            bail!(
+                "Invalid path template {:?}: {{rest}} can be used only once",
+                template
+            );
+        }
+        if chunks.is_empty() && nb_rest == 0 {
+            bail!(
+                "Invalid path template {:?}: it must use {{chunk<N>}} or {{rest}}",
+                template
+            );
+        }
+        // 2 consecutive placeholders can't be separated when reversing the template
+        if parts
+            .windows(2)
+            .any(|w| !matches!(w[0], Part::Literal(_)) && !matches!(w[1], Part::Literal(_)))
+        {
+            bail!(
+                "Invalid path template {:?}: placeholders must be separated",
+                template
+            );
+        }
+
+        let mut regex = String::from("^");
+        for part in &parts {
+            match part {
+                Part::Literal(s) => regex.push_str(&regex::escape(s)),
+                Part::Chunk(idx) => regex.push_str(&format!("(?P<chunk{}>[^/]+)", idx)),
+                Part::Rest => regex.push_str("(?P<rest>.+)"),
+            }
+        }
+        regex.push('$');
+        let regex = Regex::new(&regex)
+            .map_err(|e| zerror!("Invalid path template {:?}: {}", template, e))?;
+
+        Ok(PathTemplate {
+            parts,
+            nb_chunks: chunks.len(),
+            has_rest: nb_rest == 1,
+            regex,
+        })
+    }
+
+    // Convert a zenoh path to a file path, or None if the zenoh path doesn't have
+    // the number of chunks required by the template
+    pub(crate) fn to_path(&self, zpath: &str) -> Option<String> {
+        let chunks: Vec<&str> = zpath.split('/').collect();
+        let valid = if self.has_rest {
+            chunks.len() > self.nb_chunks
+        } else {
+            chunks.len() == self.nb_chunks
+        };
+        if !valid {
+            return None;
+        }
+        let mut result = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(s) => result.push_str(s),
+                Part::Chunk(idx) => result.push_str(chunks[*idx]),
+                Part::Rest => result.push_str(&chunks[self.nb_chunks..].join("/")),
+            }
+        }
+        Some(result)
+    }
+
+    // Convert a file path built with to_path() back to the zenoh path, or None if it doesn't match the template
+    pub(crate) fn to_zpath(&self, path: &str) -> Option<String> {
+        let captures = self.regex.captures(path)?;
+        let mut chunks = Vec::with_capacity(self.nb_chunks + 1);
+        for idx in 0..self.nb_chunks {
+            chunks.push(captures.name(&format!("chunk{}", idx))?.as_str());
+        }
+        if self.has_rest {
+            chunks.push(captures.name("rest")?.as_str());
+        }
+        Some(chunks.join("/"))
+    }
+}
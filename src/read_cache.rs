@@ -0,0 +1,109 @@
+//
+// Copyright (c) 2022 ZettaScale Technology
+//
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+// Contributors:
+//   ZettaScale Zenoh Team, <zenoh@zettascale.tech>
+//
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+// The identity of a file's content, known without reading it
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub(crate) enum ContentId {
+    // the same inode (e.g. hard links), unmodified
+    Inode {
+        dev: u64,
+        ino: u64,
+        len: u64,
+        mtime: u64,
+    },
+    // the content hash recorded by "skip_identical_writes", while the file is unmodified
+    Hash(String),
+}
+
+// A cache of the file contents read while replying to a single query ("query_read_cache_bytes"),
+// so the files with an identical content are read only once. Once its memory budget is reached,
+// the contents read afterwards are not cached.
+pub(crate) struct QueryReadCache {
+    budget: usize,
+    state: Mutex<CacheState>,
+}
+
+#[derive(Default)]
+struct CacheState {
+    used: usize,
+    contents: HashMap<ContentId, Arc<Vec<u8>>>,
+}
+
+impl QueryReadCache {
+    pub(crate) fn new(budget: usize) -> Self {
+        QueryReadCache {
+            budget,
+            state: Mutex::new(CacheState::default()),
+        }
+    }
+
+    pub(crate) fn get(&self, ids: &[ContentId]) -> Option<Arc<Vec<u8>>> {
+        let state = self.state.lock().unwrap();
+        ids.iter().find_map(|id| state.contents.get(id).cloned())
+    }
+
+    // Cache a content under all its identities, if it fits in the remaining budget
+    pub(crate) fn insert(&self, ids: Vec<ContentId>, content: &[u8]) {
+        if ids.is_empty() {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        if state.used + content.len() > self.budget {
+            return;
+        }
+        state.used += content.len();
+        let content = Arc::new(content.to_vec());
+        for id in ids {
+            state.contents.insert(id, content.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inode(ino: u64) -> ContentId {
+        ContentId::Inode {
+            dev: 1,
+            ino,
+            len: 5,
+            mtime: 42,
+        }
+    }
+
+    #[test]
+    fn contents_are_cached_under_all_their_ids_within_the_budget() {
+        let cache = QueryReadCache::new(8);
+        cache.insert(vec![inode(1), ContentId::Hash("h".into())], b"12345");
+        assert_eq!(cache.get(&[inode(1)]).unwrap().as_slice(), b"12345");
+        // another inode with the same recorded hash
+        assert_eq!(
+            cache
+                .get(&[inode(2), ContentId::Hash("h".into())])
+                .unwrap()
+                .as_slice(),
+            b"12345"
+        );
+        assert!(cache.get(&[inode(2)]).is_none());
+        assert!(cache.get(&[]).is_none());
+        // over the remaining budget: not cached
+        cache.insert(vec![inode(3)], b"1234");
+        assert!(cache.get(&[inode(3)]).is_none());
+        cache.insert(vec![inode(4)], b"123");
+        assert_eq!(cache.get(&[inode(4)]).unwrap().as_slice(), b"123");
+    }
+}
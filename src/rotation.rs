@@ -0,0 +1,232 @@
+//
+// Copyright (c) 2022 ZettaScale Technology
+//
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+// Contributors:
+//   ZettaScale Zenoh Team, <zenoh@zettascale.tech>
+//
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use zenoh::Result as ZResult;
+use zenoh_core::bail;
+
+const DATE_TEMPLATE_START: &str = "{date:";
+const DATE_TEMPLATE_END: &str = "}";
+
+// Rotation of the directory where the files are written, configured with a "dir" property
+// ending with a "{date:<format>}" chunk (e.g. "logs/{date:%Y-%m-%d}").
+// The rotation directories are the sub-directories of the static part of "dir" (e.g. "logs").
+pub(crate) struct DirRotation {
+    format: String,
+    // number of rotation directories to keep (0 to keep all)
+    keep: usize,
+}
+
+impl DirRotation {
+    pub(crate) fn new(format: String, keep: usize) -> Self {
+        DirRotation { format, keep }
+    }
+
+    // Split a "dir" property into its static part and the format of its "{date:<format>}" chunk, if any
+    pub(crate) fn parse_dir_template(dir: &str) -> ZResult<(String, Option<String>)> {
+        if !dir.contains(DATE_TEMPLATE_START) {
+            return Ok((dir.to_string(), None));
+        }
+        let (static_dir, template) = match dir.trim_end_matches('/').rsplit_once('/') {
+            Some((static_dir, template)) => (static_dir, template),
+            None => ("", dir),
+        };
+        let format = match template
+            .strip_prefix(DATE_TEMPLATE_START)
+            .and_then(|t| t.strip_suffix(DATE_TEMPLATE_END))
+        {
+            Some(format) if !static_dir.contains(DATE_TEMPLATE_START) => format,
+            _ => bail!(
+                r#"Invalid template in "dir"={:?}: only its last chunk can be a "{{date:<format>}}" template"#,
+                dir
+            ),
+        };
+        if format.is_empty() {
+            bail!(r#"Invalid template in "dir"={:?}: empty date format"#, dir);
+        }
+        // the rotation directories are all the sub-directories of the static part
+        if static_dir.trim_matches('/').is_empty() {
+            bail!(
+                r#"Invalid template in "dir"={:?}: the date template must be preceded by a directory"#,
+                dir
+            );
+        }
+        // check the format is supported
+        format_utc_date(format, SystemTime::now())?;
+        Ok((static_dir.to_string(), Some(format.to_string())))
+    }
+
+    pub(crate) fn keep(&self) -> usize {
+        self.keep
+    }
+
+    // The name of the directory where the files are written now
+    pub(crate) fn current_dir_name(&self) -> String {
+        // the format was checked at parsing
+        format_utc_date(&self.format, SystemTime::now()).unwrap_or_default()
+    }
+
+    // List the names of the rotation directories within base_dir, the newest first. The sub-directories whose
+    // name doesn't match the date format are not rotation directories, and are ignored.
+    pub(crate) fn list_dirs<F: Fn(&str) -> bool>(
+        &self,
+        base_dir: &Path,
+        is_reserved: F,
+    ) -> Vec<String> {
+        let mut dirs: Vec<(u64, String)> = match base_dir.read_dir() {
+            Ok(entries) => entries
+                .filter_map(Result::ok)
+                .filter(|e| e.file_type().map_or(false, |t| t.is_dir()))
+                .filter_map(|e| e.file_name().to_str().map(String::from))
+                .filter(|name| !is_reserved(name))
+                .filter_map(|name| self.parse_dir_name(&name).map(|date| (date, name)))
+                .collect(),
+            Err(_) => vec![],
+        };
+        dirs.sort_unstable_by(|a, b| b.cmp(a));
+        dirs.into_iter().map(|(_, name)| name).collect()
+    }
+
+    // Parse the name of a rotation directory with the date format, returning its date as a number sorting
+    // chronologically (YYYYmmddHH, the components missing from the format being 0)
+    fn parse_dir_name(&self, name: &str) -> Option<u64> {
+        let (mut year, mut month, mut day, mut hour) = (0u64, 0u64, 0u64, 0u64);
+        let mut rest = name;
+        let mut chars = self.format.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                rest = rest.strip_prefix(c)?;
+                continue;
+            }
+            let (field, len) = match chars.next()? {
+                'Y' => (&mut year, 4),
+                'm' => (&mut month, 2),
+                'd' => (&mut day, 2),
+                'H' => (&mut hour, 2),
+                '%' => {
+                    rest = rest.strip_prefix('%')?;
+                    continue;
+                }
+                _ => return None,
+            };
+            let digits = rest.get(..len)?;
+            if !digits.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            *field = digits.parse().ok()?;
+            rest = &rest[len..];
+        }
+        let in_range = |value: u64, specifier: &str, min: u64, max: u64| {
+            !self.format.contains(specifier) || (min..=max).contains(&value)
+        };
+        if !rest.is_empty()
+            || !in_range(month, "%m", 1, 12)
+            || !in_range(day, "%d", 1, 31)
+            || !in_range(hour, "%H", 0, 23)
+        {
+            return None;
+        }
+        Some(((year * 100 + month) * 100 + day) * 100 + hour)
+    }
+}
+
+// Format a time (in UTC) according to a format supporting %Y, %m, %d, %H and %%
+fn format_utc_date(format: &str, time: SystemTime) -> ZResult<String> {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    let hour = (secs % 86400) / 3600;
+
+    let mut result = String::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => result.push_str(&format!("{:04}", year)),
+            Some('m') => result.push_str(&format!("{:02}", month)),
+            Some('d') => result.push_str(&format!("{:02}", day)),
+            Some('H') => result.push_str(&format!("{:02}", hour)),
+            Some('%') => result.push('%'),
+            Some(c) => bail!(
+                "Unsupported specifier %{} in date format {:?}: must be %Y, %m, %d, %H or %%",
+                c,
+                format
+            ),
+            None => bail!("Invalid date format {:?}: ends with '%'", format),
+        }
+    }
+    if result.contains('/') || result.contains(std::path::MAIN_SEPARATOR) {
+        bail!(
+            "Invalid date format {:?}: contains a path separator",
+            format
+        );
+    }
+    Ok(result)
+}
+
+// Convert a number of days since 1970-01-01 to a (year, month, day) date
+// (see http://howardhinnant.github.io/date_algorithms.html#civil_from_days)
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = (if z >= 0 { z } else { z - 146096 }) / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::create_dir;
+
+    #[test]
+    fn dir_template_requires_a_static_part() {
+        assert!(DirRotation::parse_dir_template("{date:%Y}").is_err());
+        assert!(DirRotation::parse_dir_template("/{date:%Y}").is_err());
+        let (static_dir, format) = DirRotation::parse_dir_template("logs/{date:%Y-%m}").unwrap();
+        assert_eq!(static_dir, "logs");
+        assert_eq!(format.as_deref(), Some("%Y-%m"));
+    }
+
+    #[test]
+    fn rotation_dirs_are_sorted_by_date() {
+        let base_dir = tempfile::tempdir().unwrap();
+        // with %d before %m, the lexicographic order is not the chronological one
+        for name in &[
+            "31-01-2022",
+            "01-02-2022",
+            "15-12-2021",
+            "backup",
+            "32-01-2022",
+            ".zenoh",
+        ] {
+            create_dir(base_dir.path().join(name)).unwrap();
+        }
+        let rotation = DirRotation::new("%d-%m-%Y".into(), 0);
+        assert_eq!(
+            rotation.list_dirs(base_dir.path(), |name| name.starts_with(".zenoh")),
+            vec!["01-02-2022", "31-01-2022", "15-12-2021"]
+        );
+    }
+}
@@ -0,0 +1,99 @@
+//
+// Copyright (c) 2022 ZettaScale Technology
+//
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+// Contributors:
+//   ZettaScale Zenoh Team, <zenoh@zettascale.tech>
+//
+use std::convert::TryFrom;
+use std::sync::Weak;
+use tracing::{trace, warn};
+use zenoh::prelude::r#async::AsyncResolve;
+use zenoh::prelude::*;
+use zenoh::Result as ZResult;
+use zenoh::Session;
+
+// Chunk of the keys where the storage events are published, under the storage's key expression
+const EVENTS_CHUNK: &str = "@events";
+
+// Publication of an event on each file written or deleted by a storage ("publish_events").
+// The session is only weakly referenced, not to prevent its closure at shutdown.
+pub(crate) struct StorageEvents {
+    session: Weak<Session>,
+    strip_prefix: Option<OwnedKeyExpr>,
+    put_key: OwnedKeyExpr,
+    delete_key: OwnedKeyExpr,
+}
+
+impl StorageEvents {
+    // The events are published on `<prefix>/@events/put` and `<prefix>/@events/delete`, where `<prefix>` is
+    // the storage's key expression up to its first wildcard chunk (e.g. `demo/example` for `demo/example/**`)
+    pub(crate) fn new(
+        session: Weak<Session>,
+        storage_key_expr: &keyexpr,
+        strip_prefix: Option<OwnedKeyExpr>,
+    ) -> ZResult<Self> {
+        let prefix: Vec<&str> = storage_key_expr
+            .split('/')
+            .take_while(|chunk| !chunk.contains('*'))
+            .collect();
+        let events_key = |op: &str| {
+            let mut chunks = prefix.clone();
+            chunks.push(EVENTS_CHUNK);
+            chunks.push(op);
+            OwnedKeyExpr::try_from(chunks.join("/"))
+        };
+        Ok(StorageEvents {
+            session,
+            strip_prefix,
+            put_key: events_key("put")?,
+            delete_key: events_key("delete")?,
+        })
+    }
+
+    // Check if a key is one of the keys where the events are published
+    // (the storage must not store its own events if its key expression includes them)
+    pub(crate) fn is_event_key(&self, key: &keyexpr) -> bool {
+        key == &*self.put_key || key == &*self.delete_key
+    }
+
+    pub(crate) async fn put(&self, zpath: &str, bytes: u64) {
+        self.publish(&self.put_key, "put", zpath, Some(bytes)).await
+    }
+
+    pub(crate) async fn delete(&self, zpath: &str) {
+        self.publish(&self.delete_key, "delete", zpath, None).await
+    }
+
+    // Publish an event as a JSON value. A failure is only logged, as the operation itself succeeded.
+    async fn publish(&self, events_key: &OwnedKeyExpr, op: &str, zpath: &str, bytes: Option<u64>) {
+        let session = match self.session.upgrade() {
+            Some(session) => session,
+            None => return, // session closed: shutting down
+        };
+        // if strip_prefix is set, prefix it back to the zenoh path
+        let key = match &self.strip_prefix {
+            Some(prefix) => format!("{}/{}", prefix, zpath),
+            None => zpath.to_string(),
+        };
+        let mut event = serde_json::json!({ "op": op, "key": key });
+        if let Some(bytes) = bytes {
+            event["bytes"] = bytes.into();
+        }
+        trace!("Publish storage event on {}: {}", events_key, event);
+        let value = Value::new(event.to_string().into_bytes().into())
+            .encoding(KnownEncoding::AppJson.into());
+        if let Err(e) = session.put(events_key.clone(), value).res().await {
+            warn!(
+                "Failed to publish storage event of {} on {}: {}",
+                key, events_key, e
+            );
+        }
+    }
+}
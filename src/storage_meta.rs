@@ -0,0 +1,163 @@
+//
+// Copyright (c) 2022 ZettaScale Technology
+//
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+// Contributors:
+//   ZettaScale Zenoh Team, <zenoh@zettascale.tech>
+//
+use std::path::Path;
+use zenoh::Result as ZResult;
+use zenoh_core::zerror;
+
+// Name of the file recording the key expression of the storage, in the storage directory
+pub(crate) const STORAGE_META_FILENAME: &str = ".zenoh-storage-meta";
+
+// The key expression and strip_prefix of a storage, as recorded in its directory on the last run
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct StorageMeta {
+    pub(crate) key_expr: String,
+    pub(crate) strip_prefix: Option<String>,
+}
+
+impl StorageMeta {
+    // Load the meta-data recorded in a storage directory, if any
+    pub(crate) fn load(base_dir: &Path) -> ZResult<Option<Self>> {
+        let file = base_dir.join(STORAGE_META_FILENAME);
+        if !file.exists() {
+            return Ok(None);
+        }
+        let content = std::fs::read(&file)
+            .map_err(|e| zerror!("Failed to read storage meta-data {:?}: {}", file, e))?;
+        let value: serde_json::Value = serde_json::from_slice(&content)
+            .map_err(|e| zerror!("Invalid storage meta-data {:?}: {}", file, e))?;
+        let key_expr = value
+            .get("key_expr")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| zerror!("Invalid storage meta-data {:?}: no key_expr", file))?;
+        Ok(Some(StorageMeta {
+            key_expr: key_expr.to_string(),
+            strip_prefix: value
+                .get("strip_prefix")
+                .and_then(|v| v.as_str())
+                .map(str::to_string),
+        }))
+    }
+
+    pub(crate) fn save(&self, base_dir: &Path) -> ZResult<()> {
+        let file = base_dir.join(STORAGE_META_FILENAME);
+        let value = serde_json::json!({
+            "key_expr": self.key_expr,
+            "strip_prefix": self.strip_prefix,
+        });
+        std::fs::write(&file, value.to_string())
+            .map_err(|e| zerror!("Failed to write storage meta-data {:?}: {}", file, e).into())
+    }
+
+    // Map a zenoh path stored with this (previous) meta-data to the zenoh path of the same key with the current
+    // meta-data: the literal prefix of the key expression (before its first wildcard) is replaced by the current one.
+    // Return None if the key doesn't start with the previous literal prefix, or can't be stored with the current
+    // strip_prefix.
+    pub(crate) fn migrate_zpath(&self, current: &StorageMeta, zpath: &str) -> Option<String> {
+        let key = join_chunks(self.strip_prefix.as_deref().unwrap_or(""), zpath);
+        let suffix = strip_chunks(&key, &literal_prefix(&self.key_expr))?;
+        let key = join_chunks(&literal_prefix(&current.key_expr), suffix);
+        let new_zpath = strip_chunks(&key, current.strip_prefix.as_deref().unwrap_or(""))?;
+        if new_zpath.is_empty() {
+            None
+        } else {
+            Some(new_zpath.to_string())
+        }
+    }
+}
+
+// The chunks of a key expression before its first wildcard
+fn literal_prefix(key_expr: &str) -> String {
+    key_expr
+        .split('/')
+        .take_while(|chunk| !chunk.contains('*') && !chunk.contains('$'))
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn join_chunks(prefix: &str, suffix: &str) -> String {
+    match (prefix.is_empty(), suffix.is_empty()) {
+        (true, _) => suffix.to_string(),
+        (_, true) => prefix.to_string(),
+        _ => format!("{}/{}", prefix, suffix),
+    }
+}
+
+// Strip some leading chunks from a key (the result is empty if they're the whole key)
+fn strip_chunks<'a>(key: &'a str, prefix: &str) -> Option<&'a str> {
+    if prefix.is_empty() {
+        return Some(key);
+    }
+    match key.strip_prefix(prefix)? {
+        "" => Some(""),
+        rest => rest.strip_prefix('/'),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta(key_expr: &str, strip_prefix: Option<&str>) -> StorageMeta {
+        StorageMeta {
+            key_expr: key_expr.to_string(),
+            strip_prefix: strip_prefix.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn meta_is_saved_and_loaded() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(StorageMeta::load(dir.path()).unwrap(), None);
+        for m in [meta("demo/**", Some("demo")), meta("demo/*/x", None)] {
+            m.save(dir.path()).unwrap();
+            assert_eq!(StorageMeta::load(dir.path()).unwrap(), Some(m));
+        }
+    }
+
+    #[test]
+    fn zpaths_are_migrated_to_the_new_key_expr() {
+        let previous = meta("old/**", Some("old"));
+        for (current, zpath, expected) in [
+            (meta("new/**", Some("new")), "a/b", Some("a/b")),
+            (meta("new/a/**", Some("new")), "x/y", Some("a/x/y")),
+            (meta("new/**", None), "a/b", Some("new/a/b")),
+            (meta("new/**", Some("new/a")), "a/b", Some("b")),
+            // a key out of the new strip_prefix can't be stored
+            (meta("new/**", Some("new/a")), "c", None),
+            // a key stored as the new strip_prefix itself can't be stored
+            (meta("new/**", Some("new/a")), "a", None),
+            (meta("new/*/x", Some("new")), "a/x", Some("a/x")),
+        ] {
+            assert_eq!(
+                previous.migrate_zpath(&current, zpath).as_deref(),
+                expected,
+                "{} with {:?}",
+                zpath,
+                current
+            );
+        }
+        // a key out of the previous literal prefix is not migrated
+        let previous = meta("old/a/**", None);
+        assert_eq!(
+            previous.migrate_zpath(&meta("new/**", None), "old/b/c"),
+            None
+        );
+        assert_eq!(
+            previous
+                .migrate_zpath(&meta("new/**", None), "old/a/c")
+                .as_deref(),
+            Some("new/c")
+        );
+    }
+}
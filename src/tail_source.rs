@@ -0,0 +1,358 @@
+//
+// Copyright (c) 2022 ZettaScale Technology
+//
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+// Contributors:
+//   ZettaScale Zenoh Team, <zenoh@zettascale.tech>
+//
+use crate::files_mgt::FilesMgr;
+use async_std::task;
+use std::fs::{metadata, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+use tracing::{debug, warn};
+use zenoh::prelude::r#async::AsyncResolve;
+use zenoh::prelude::*;
+use zenoh::Result as ZResult;
+use zenoh::Session;
+use zenoh_core::{bail, zerror};
+
+// Name of the file where the position of the ingestion of the "tail_source" is saved, in the storage directory
+const TAIL_STATE_FILENAME: &str = ".zenoh_tail_state";
+
+// Default period of the polling of the "tail_source" file
+const DEFAULT_POLL_PERIOD_MS: u64 = 1000;
+
+// The configuration of the ingestion of a growing file ("tail_source"): each appended line is published
+// on `<key>/<index>` (to be stored as any PUT), the index being zero-padded so the keys are sorted in the lines order.
+pub(crate) struct TailSource {
+    file: PathBuf,
+    key: OwnedKeyExpr,
+    poll_period: Duration,
+}
+
+impl TailSource {
+    // Parse the "tail_source" property: the key must be a key (without wildcard) of the storage
+    pub(crate) fn parse(
+        property: &str,
+        value: &serde_json::Value,
+        storage_ke: &keyexpr,
+        strip_prefix: Option<&keyexpr>,
+    ) -> ZResult<Self> {
+        let file = match value.get("file") {
+            Some(serde_json::Value::String(file)) if Path::new(file).is_absolute() => {
+                PathBuf::from(file)
+            }
+            _ => bail!(
+                r#"Invalid property "{}": it requires a "file" absolute path"#,
+                property
+            ),
+        };
+        let key = match value.get("key").and_then(|key| key.as_str()) {
+            Some(key) => keyexpr::new(key)
+                .map_err(|e| zerror!(r#"Invalid property "{}": {}"#, property, e))?,
+            None => bail!(r#"Invalid property "{}": it requires a "key""#, property),
+        };
+        let sub_key = keyexpr::new(&format!("{}/0", key))
+            .map_err(|e| zerror!(r#"Invalid property "{}": {}"#, property, e))?;
+        if key.contains('*') || key.contains('$') || !storage_ke.includes(sub_key) {
+            bail!(
+                r#"Invalid property "{}": "key" {} must be a key without wildcard, whose sub-keys match the storage's key expression {}"#,
+                property,
+                key,
+                storage_ke
+            );
+        }
+        if let Some(prefix) = strip_prefix {
+            if !matches!(key.strip_prefix(prefix).as_slice(), [_]) {
+                bail!(
+                    r#"Invalid property "{}": "key" {} doesn't start with the storage's strip_prefix {}"#,
+                    property,
+                    key,
+                    prefix
+                );
+            }
+        }
+        let poll_period = match value.get("poll_period_ms") {
+            Some(serde_json::Value::Number(n)) if n.as_u64().map_or(false, |n| n > 0) => {
+                Duration::from_millis(n.as_u64().unwrap())
+            }
+            None => Duration::from_millis(DEFAULT_POLL_PERIOD_MS),
+            Some(v) => bail!(
+                r#"Invalid property "{}": "poll_period_ms" {} must be a positive integer"#,
+                property,
+                v
+            ),
+        };
+        Ok(TailSource {
+            file,
+            key: key.to_owned(),
+            poll_period,
+        })
+    }
+}
+
+// The identity of a file, to detect its rotation (i.e. replaced by a new file)
+type FileId = (u64, u64);
+
+#[cfg(unix)]
+fn file_id(file: &Path) -> Option<FileId> {
+    use std::os::unix::fs::MetadataExt;
+    metadata(file).ok().map(|m| (m.dev(), m.ino()))
+}
+
+// The rotation is detected only by the truncation of the file on non-Unix platforms
+#[cfg(not(unix))]
+fn file_id(_file: &Path) -> Option<FileId> {
+    None
+}
+
+// The position of the ingestion, saved after each ingested batch of lines
+#[derive(Default)]
+struct TailState {
+    file_id: Option<FileId>,
+    // the offset of the end of the last ingested line
+    offset: u64,
+    next_index: u64,
+}
+
+impl TailState {
+    fn load(state_file: &Path) -> Self {
+        let value: serde_json::Value = match std::fs::read(state_file)
+            .ok()
+            .and_then(|content| serde_json::from_slice(&content).ok())
+        {
+            Some(value) => value,
+            None => return TailState::default(),
+        };
+        let get = |name: &str| value.get(name).and_then(|v| v.as_u64());
+        TailState {
+            file_id: get("dev").zip(get("ino")),
+            offset: get("offset").unwrap_or(0),
+            next_index: get("next_index").unwrap_or(0),
+        }
+    }
+
+    fn save(&self, state_file: &Path) -> ZResult<()> {
+        let value = serde_json::json!({
+            "dev": self.file_id.map(|id| id.0),
+            "ino": self.file_id.map(|id| id.1),
+            "offset": self.offset,
+            "next_index": self.next_index,
+        });
+        std::fs::write(state_file, value.to_string())
+            .map_err(|e| zerror!("Failed to save tail_source state {:?}: {}", state_file, e).into())
+    }
+}
+
+struct Tailer {
+    source: TailSource,
+    state_file: PathBuf,
+    state: TailState,
+    // the file being ingested, and the bytes of its last line not terminated yet
+    file: Option<File>,
+    partial: Vec<u8>,
+}
+
+// The lines read from the file at a poll
+struct TailLines {
+    lines: Vec<Vec<u8>>,
+    // the number of bytes of the file consumed by these lines
+    consumed: u64,
+    // if true, the file was rotated and is completely read
+    rotated: bool,
+}
+
+impl Tailer {
+    // Read the lines appended to the file since the last poll (blocking)
+    fn read_lines(&mut self) -> ZResult<TailLines> {
+        let path = &self.source.file;
+        if self.file.is_none() {
+            let mut file = match File::open(path) {
+                Ok(file) => file,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    return Ok(TailLines {
+                        lines: Vec::new(),
+                        consumed: 0,
+                        rotated: false,
+                    })
+                }
+                Err(e) => bail!("Failed to open tail_source {:?}: {}", path, e),
+            };
+            let id = file_id(path);
+            let len = file.metadata().map(|m| m.len()).unwrap_or(0);
+            // resume at the saved offset if it's the same file, otherwise it's a new file (rotated)
+            if id == self.state.file_id && self.state.offset <= len {
+                file.seek(SeekFrom::Start(self.state.offset))
+                    .map_err(|e| zerror!("Failed to read tail_source {:?}: {}", path, e))?;
+            } else {
+                self.state.file_id = id;
+                self.state.offset = 0;
+            }
+            self.file = Some(file);
+            self.partial.clear();
+        }
+        let file = self.file.as_mut().unwrap();
+
+        // a file truncated in place is re-ingested from its start
+        let len = file.metadata().map(|m| m.len()).unwrap_or(0);
+        if len < self.state.offset + self.partial.len() as u64 {
+            debug!("tail_source {:?} was truncated: read it from start", path);
+            file.seek(SeekFrom::Start(0))
+                .map_err(|e| zerror!("Failed to read tail_source {:?}: {}", path, e))?;
+            self.state.offset = 0;
+            self.partial.clear();
+        }
+
+        // if the path now refers to another file (rotated), the current one is complete once read:
+        // its unterminated last line is ingested, and the new file is opened on next poll
+        let rotated = file_id(path) != self.state.file_id;
+
+        let mut appended = Vec::new();
+        file.read_to_end(&mut appended)
+            .map_err(|e| zerror!("Failed to read tail_source {:?}: {}", path, e))?;
+        self.partial.extend_from_slice(&appended);
+        let mut lines: Vec<Vec<u8>> = Vec::new();
+        let mut consumed = 0;
+        while let Some(end) = self.partial[consumed..].iter().position(|b| *b == b'\n') {
+            lines.push(self.partial[consumed..consumed + end].to_vec());
+            consumed += end + 1;
+        }
+        self.partial.drain(..consumed);
+
+        if rotated && !self.partial.is_empty() {
+            consumed += self.partial.len();
+            lines.push(std::mem::take(&mut self.partial));
+        }
+        for line in &mut lines {
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+        }
+        Ok(TailLines {
+            lines,
+            consumed: consumed as u64,
+            rotated,
+        })
+    }
+
+    // Publish the lines read, so they're stored by the storage as any PUT (i.e. with its hooks, throttling,
+    // retention and hash chain), and save the position of the ingestion
+    async fn publish_lines(&mut self, session: &Session, read: TailLines) -> ZResult<()> {
+        for line in read.lines {
+            let key_expr = self
+                .source
+                .key
+                .join(&format!("{:020}", self.state.next_index))?;
+            let value = Value::new(line.into()).encoding(KnownEncoding::TextPlain.into());
+            session.put(key_expr, value).res().await?;
+            self.state.next_index += 1;
+        }
+        if read.consumed > 0 {
+            self.state.offset += read.consumed;
+            self.state.save(&self.state_file)?;
+        }
+        if read.rotated {
+            debug!(
+                "tail_source {:?} was rotated: open the new file",
+                self.source.file
+            );
+            self.file = None;
+        }
+        Ok(())
+    }
+}
+
+// Ingest the lines appended to the "tail_source" file, until the storage is dropped
+pub(crate) async fn tail_file(
+    session: Arc<Session>,
+    files_mgr: Weak<FilesMgr>,
+    source: TailSource,
+) {
+    let state_file = match files_mgr.upgrade() {
+        Some(files_mgr) => files_mgr.base_dir().join(TAIL_STATE_FILENAME),
+        None => return,
+    };
+    let poll_period = source.poll_period;
+    let mut tailer = Tailer {
+        source,
+        state: TailState::load(&state_file),
+        state_file,
+        file: None,
+        partial: Vec::new(),
+    };
+    loop {
+        match files_mgr.upgrade() {
+            Some(files_mgr) => {
+                // (while the storage is quiesced or its directory replaced, the lines are ingested once it's resumed)
+                let write = files_mgr.begin_background_write();
+                if write.is_some() && !files_mgr.is_waiting() {
+                    // (the file is read in a blocking task, not to block the executor)
+                    let (read_tailer, read) = task::spawn_blocking(move || {
+                        let read = tailer.read_lines();
+                        (tailer, read)
+                    })
+                    .await;
+                    tailer = read_tailer;
+                    let result = match read {
+                        Ok(read) => tailer.publish_lines(&session, read).await,
+                        Err(e) => Err(e),
+                    };
+                    if let Err(e) = result {
+                        warn!("Failed to ingest tail_source: {}", e);
+                        // resume from the last saved position
+                        tailer.file = None;
+                        tailer.state = TailState::load(&tailer.state_file);
+                    }
+                }
+            }
+            None => return,
+        }
+        task::sleep(poll_period).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn only_the_terminated_lines_are_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("log");
+        let mut tailer = Tailer {
+            source: TailSource {
+                file: file.clone(),
+                key: keyexpr::new("a/log").unwrap().to_owned(),
+                poll_period: Duration::from_millis(DEFAULT_POLL_PERIOD_MS),
+            },
+            state_file: dir.path().join(TAIL_STATE_FILENAME),
+            state: TailState::default(),
+            file: None,
+            partial: Vec::new(),
+        };
+        // the file doesn't exist yet
+        assert!(tailer.read_lines().unwrap().lines.is_empty());
+
+        let mut writer = File::create(&file).unwrap();
+        writer.write_all(b"line 1\r\nline 2\nline").unwrap();
+        let read = tailer.read_lines().unwrap();
+        assert_eq!(read.lines, vec![b"line 1".to_vec(), b"line 2".to_vec()]);
+        assert_eq!(read.consumed, 15);
+        assert!(!read.rotated);
+
+        writer.write_all(b" 3\n").unwrap();
+        let read = tailer.read_lines().unwrap();
+        assert_eq!(read.lines, vec![b"line 3".to_vec()]);
+        assert_eq!(read.consumed, 7);
+    }
+}
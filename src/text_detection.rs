@@ -0,0 +1,115 @@
+//
+// Copyright (c) 2022 ZettaScale Technology
+//
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+// Contributors:
+//   ZettaScale Zenoh Team, <zenoh@zettascale.tech>
+//
+use std::collections::HashMap;
+use zenoh::prelude::*;
+
+// Size of the beginning of a file that is inspected to detect if it's text
+const DETECTION_LEN: usize = 8192;
+
+// Maximum ratio of control characters (other than whitespaces) in a text file
+const MAX_CONTROL_RATIO: f64 = 0.01;
+
+// Encodings of the scripts, by interpreter of their shebang line (e.g. "#!/bin/sh")
+const DEFAULT_SHEBANG_ENCODINGS: &[(&str, &str)] = &[
+    ("sh", "text/x-shellscript"),
+    ("bash", "text/x-shellscript"),
+    ("dash", "text/x-shellscript"),
+    ("ksh", "text/x-shellscript"),
+    ("zsh", "text/x-shellscript"),
+    ("python", "text/x-python"),
+    ("perl", "text/x-perl"),
+    ("ruby", "text/x-ruby"),
+    ("node", "application/javascript"),
+];
+
+// Detection of the text files whose encoding can't be guessed from their name ("text_detection"):
+// a content which is valid UTF-8 with few control characters is "text/plain", or the encoding
+// corresponding to the interpreter of its shebang line.
+pub(crate) struct TextDetection {
+    shebang_encodings: HashMap<String, String>,
+}
+
+impl TextDetection {
+    // The shebang encodings are added to (or replace) the default ones
+    pub(crate) fn new(shebang_encodings: HashMap<String, String>) -> Self {
+        let mut encodings: HashMap<String, String> = DEFAULT_SHEBANG_ENCODINGS
+            .iter()
+            .map(|(interpreter, encoding)| (interpreter.to_string(), encoding.to_string()))
+            .collect();
+        encodings.extend(shebang_encodings);
+        TextDetection {
+            shebang_encodings: encodings,
+        }
+    }
+
+    pub(crate) fn detect(&self, content: &[u8]) -> Option<Encoding> {
+        let text = detect_text(content)?;
+        match shebang_interpreter(text).and_then(|i| self.shebang_encoding(i)) {
+            Some(encoding) => Some(Encoding::from(encoding.to_string())),
+            None => Some(KnownEncoding::TextPlain.into()),
+        }
+    }
+
+    // Get the encoding of an interpreter, ignoring its version (e.g. "python3.11")
+    fn shebang_encoding(&self, interpreter: &str) -> Option<&str> {
+        self.shebang_encodings
+            .get(interpreter)
+            .or_else(|| {
+                self.shebang_encodings
+                    .get(interpreter.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.'))
+            })
+            .map(String::as_str)
+    }
+}
+
+// Get the beginning of a content as text, if it's valid UTF-8 with few control characters
+fn detect_text(content: &[u8]) -> Option<&str> {
+    if content.is_empty() {
+        return None;
+    }
+    let truncated = content.len() > DETECTION_LEN;
+    let head = &content[..content.len().min(DETECTION_LEN)];
+    let text = match std::str::from_utf8(head) {
+        Ok(text) => text,
+        // a character might be cut at the end of the inspected part
+        Err(e) if truncated && e.error_len().is_none() => {
+            std::str::from_utf8(&head[..e.valid_up_to()]).ok()?
+        }
+        Err(_) => return None,
+    };
+    let (mut chars, mut controls) = (0usize, 0usize);
+    for c in text.chars() {
+        chars += 1;
+        if c.is_control() && !matches!(c, '\n' | '\r' | '\t' | '\x0c') {
+            controls += 1;
+        }
+    }
+    if controls as f64 > chars as f64 * MAX_CONTROL_RATIO {
+        None
+    } else {
+        Some(text)
+    }
+}
+
+// Get the name of the interpreter of a shebang line, e.g. "python3" for "#!/usr/bin/env python3"
+fn shebang_interpreter(text: &str) -> Option<&str> {
+    let line = text.strip_prefix("#!")?.lines().next()?;
+    let mut args = line.split_whitespace();
+    let program = args.next()?.rsplit('/').next()?;
+    if program == "env" {
+        args.find(|arg| !arg.starts_with('-') && !arg.contains('='))
+    } else {
+        Some(program)
+    }
+}
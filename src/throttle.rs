@@ -0,0 +1,105 @@
+//
+// Copyright (c) 2022 ZettaScale Technology
+//
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+// Contributors:
+//   ZettaScale Zenoh Team, <zenoh@zettascale.tech>
+//
+use async_std::task;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// A token-bucket limiting the rate of bytes replied by a storage ("reply_max_bytes_per_sec").
+// The bucket holds at most 1 second of tokens, allowing a burst of this size after an idle period.
+pub(crate) struct ReplyThrottle {
+    bytes_per_sec: f64,
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl ReplyThrottle {
+    pub(crate) fn new(bytes_per_sec: u64) -> Self {
+        ReplyThrottle {
+            bytes_per_sec: bytes_per_sec as f64,
+            bucket: Mutex::new(Bucket {
+                tokens: bytes_per_sec as f64,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    // Take the tokens for a reply of this size, waiting as long as the bucket is in debt.
+    // A reply bigger than the bucket is allowed, but the following ones wait for its debt to be refilled.
+    pub(crate) async fn acquire(&self, bytes: usize) {
+        let wait = {
+            let mut bucket = self.bucket.lock().unwrap();
+            let now = Instant::now();
+            let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+            bucket.tokens = (bucket.tokens + elapsed * self.bytes_per_sec).min(self.bytes_per_sec);
+            bucket.last_refill = now;
+            bucket.tokens -= bytes as f64;
+            if bucket.tokens < 0.0 {
+                Duration::from_secs_f64(-bucket.tokens / self.bytes_per_sec)
+            } else {
+                Duration::ZERO
+            }
+        };
+        if !wait.is_zero() {
+            task::sleep(wait).await;
+        }
+    }
+}
+
+// The time of the last PUT written on each key, to drop the PUT arriving sooner than an interval after it
+// ("min_write_interval_ms"). A key not written for longer than the interval is forgotten, as its next PUT
+// is written anyway.
+pub(crate) struct WriteThrottle {
+    interval: Duration,
+    last_writes: HashMap<String, Instant>,
+    last_pruned: Instant,
+}
+
+impl WriteThrottle {
+    pub(crate) fn new(interval: Duration) -> Self {
+        WriteThrottle {
+            interval,
+            last_writes: HashMap::new(),
+            last_pruned: Instant::now(),
+        }
+    }
+
+    // Check if a PUT on a key arrives sooner than the interval after the last one written
+    pub(crate) fn is_throttled(&self, zpath: &str) -> bool {
+        self.last_writes
+            .get(zpath)
+            .map_or(false, |last| last.elapsed() < self.interval)
+    }
+
+    // Record a PUT written on a key
+    pub(crate) fn record_write(&mut self, zpath: &str) {
+        let now = Instant::now();
+        if now.duration_since(self.last_pruned) >= self.interval {
+            let interval = self.interval;
+            self.last_writes
+                .retain(|_, last| now.duration_since(*last) < interval);
+            self.last_pruned = now;
+        }
+        self.last_writes.insert(zpath.to_string(), now);
+    }
+
+    // Forget a deleted key, so a PUT re-creating it is written
+    pub(crate) fn forget(&mut self, zpath: &str) {
+        self.last_writes.remove(zpath);
+    }
+}
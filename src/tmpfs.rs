@@ -0,0 +1,86 @@
+//
+// Copyright (c) 2022 ZettaScale Technology
+//
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+// Contributors:
+//   ZettaScale Zenoh Team, <zenoh@zettascale.tech>
+//
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use tempfile::TempDir;
+
+// Size of the tmpfs mounted by the tests, unless specified
+pub(crate) const DEFAULT_TMPFS_SIZE: u64 = 8 * 1024 * 1024;
+
+// A RAM-based file system of a limited size, mounted on a temporary directory for the tests of the writes
+// on a full disk, and unmounted when dropped. Mounting requires CAP_SYS_ADMIN (e.g. running as root): thus the
+// tests using a tmpfs are ignored by default, and fail if run (with --ignored) without it.
+pub(crate) struct Tmpfs {
+    target: CString,
+    dir: TempDir,
+}
+
+impl Tmpfs {
+    // Mount a tmpfs of `size` bytes, or panic if not permitted
+    pub(crate) fn mount(size: u64) -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        let target = CString::new(dir.path().as_os_str().as_bytes()).unwrap();
+        let (source, fstype) = (
+            CString::new("tmpfs").unwrap(),
+            CString::new("tmpfs").unwrap(),
+        );
+        let data = CString::new(format!("size={}", size)).unwrap();
+        let result = unsafe {
+            libc::mount(
+                source.as_ptr(),
+                target.as_ptr(),
+                fstype.as_ptr(),
+                libc::MS_NOSUID | libc::MS_NODEV,
+                data.as_ptr() as *const libc::c_void,
+            )
+        };
+        if result != 0 {
+            panic!(
+                "Cannot mount a tmpfs on {:?}: {}",
+                dir.path(),
+                std::io::Error::last_os_error()
+            );
+        }
+        Tmpfs { target, dir }
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        self.dir.path()
+    }
+}
+
+impl Drop for Tmpfs {
+    fn drop(&mut self) {
+        // (detached, as files might still be opened, e.g. by a data-info database not yet closed)
+        unsafe { libc::umount2(self.target.as_ptr(), libc::MNT_DETACH) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[ignore = "mounts a tmpfs, which requires CAP_SYS_ADMIN (e.g. running as root): run with --ignored"]
+    fn writes_over_the_size_fail_with_no_space_left() {
+        let tmpfs = Tmpfs::mount(DEFAULT_TMPFS_SIZE);
+        let file = tmpfs.path().join("a");
+        std::fs::write(&file, vec![0u8; (DEFAULT_TMPFS_SIZE / 2) as usize]).unwrap();
+        let error = std::fs::write(&file, vec![0u8; (DEFAULT_TMPFS_SIZE * 2) as usize])
+            .err()
+            .unwrap();
+        assert!(crate::backpressure::is_disk_full(&error), "{}", error);
+    }
+}
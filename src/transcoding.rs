@@ -0,0 +1,124 @@
+//
+// Copyright (c) 2022 ZettaScale Technology
+//
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+// Contributors:
+//   ZettaScale Zenoh Team, <zenoh@zettascale.tech>
+//
+use zenoh::prelude::*;
+use zenoh::Result as ZResult;
+use zenoh_core::{bail, zerror};
+
+pub(crate) const MIME_APP_CBOR: &str = "application/cbor";
+pub(crate) const MIME_APP_OCTET_STREAM: &str = "application/octet-stream";
+
+// The encodings the stored values can be transcoded to on read
+#[derive(Clone)]
+pub(crate) enum TargetEncoding {
+    // from application/json or text/json
+    Cbor,
+    // from text/plain (UTF-8)
+    OctetStream,
+}
+
+impl TargetEncoding {
+    // Parse a mime-type (ignoring the case and the spaces)
+    pub(crate) fn from_mime(mime: &str) -> Option<Self> {
+        let mime: String = mime
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<String>()
+            .to_lowercase();
+        match mime.as_str() {
+            MIME_APP_CBOR => Some(TargetEncoding::Cbor),
+            MIME_APP_OCTET_STREAM => Some(TargetEncoding::OctetStream),
+            _ => None,
+        }
+    }
+
+    fn encoding(&self) -> Encoding {
+        match self {
+            TargetEncoding::Cbor => Encoding::from(MIME_APP_CBOR),
+            TargetEncoding::OctetStream => Encoding::APP_OCTET_STREAM,
+        }
+    }
+}
+
+// Transcode a Value to the target encoding.
+// A Value already in the target encoding is returned as is.
+pub(crate) fn transcode(value: Value, target: &TargetEncoding) -> ZResult<Value> {
+    let to = target.encoding();
+    if value.encoding == to {
+        return Ok(value);
+    }
+    match (target, *value.encoding.prefix()) {
+        (TargetEncoding::Cbor, KnownEncoding::AppJson)
+        | (TargetEncoding::Cbor, KnownEncoding::TextJson) => {
+            let json: serde_json::Value = serde_json::from_slice(&value.payload.contiguous())
+                .map_err(|e| {
+                    zerror!(
+                        "Failed to transcode {} value to {}: {}",
+                        value.encoding,
+                        to,
+                        e
+                    )
+                })?;
+            let mut cbor = Vec::new();
+            ciborium::ser::into_writer(&json, &mut cbor).map_err(|e| {
+                zerror!(
+                    "Failed to transcode {} value to {}: {}",
+                    value.encoding,
+                    to,
+                    e
+                )
+            })?;
+            Ok(Value::new(cbor.into()).encoding(to))
+        }
+        (TargetEncoding::OctetStream, KnownEncoding::TextPlain) => {
+            Ok(Value::new(value.payload).encoding(to))
+        }
+        _ => bail!(
+            "Transcoding a {} value to {} is not supported",
+            value.encoding,
+            to
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_values_are_transcoded_to_valid_cbor() {
+        let json = serde_json::json!({ "a": [1, 2.5, "b"], "c": { "d": null, "e": true } });
+        let value = Value::new(json.to_string().into_bytes().into()).encoding(Encoding::APP_JSON);
+        let target = TargetEncoding::from_mime(" Application/CBOR ").unwrap();
+        let transcoded = transcode(value, &target).unwrap();
+        assert_eq!(transcoded.encoding, Encoding::from(MIME_APP_CBOR));
+        let decoded: serde_json::Value =
+            ciborium::de::from_reader(transcoded.payload.contiguous().as_ref()).unwrap();
+        assert_eq!(decoded, json);
+
+        // an invalid JSON value can't be transcoded
+        let value = Value::new(b"{".to_vec().into()).encoding(Encoding::APP_JSON);
+        assert!(transcode(value, &target).is_err());
+    }
+
+    #[test]
+    fn only_the_supported_encodings_are_transcoded() {
+        let text = || Value::new(b"text".to_vec().into()).encoding(Encoding::TEXT_PLAIN);
+        let transcoded = transcode(text(), &TargetEncoding::OctetStream).unwrap();
+        assert_eq!(transcoded.encoding, Encoding::APP_OCTET_STREAM);
+        assert_eq!(transcoded.payload.contiguous().as_ref(), b"text");
+
+        assert!(transcode(text(), &TargetEncoding::Cbor).is_err());
+        assert!(TargetEncoding::from_mime("text/html").is_none());
+    }
+}
@@ -0,0 +1,113 @@
+//
+// Copyright (c) 2022 ZettaScale Technology
+//
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+// Contributors:
+//   ZettaScale Zenoh Team, <zenoh@zettascale.tech>
+//
+use std::io::Read;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+use zenoh::Result as ZResult;
+use zenoh_core::{bail, zerror};
+
+// Period of the checks for the termination of a hook command
+const HOOK_POLL_PERIOD: Duration = Duration::from_millis(10);
+
+// External command run before each file write, configured with the "pre_write_hook" property.
+// Its arguments can contain "%k" (replaced with the key) and "%f" (replaced with the path of the
+// temporary file being written). The write is aborted if the command fails.
+pub(crate) struct WriteHook {
+    args: Vec<String>,
+    // None if no timeout
+    timeout: Option<Duration>,
+}
+
+impl WriteHook {
+    pub(crate) fn parse(template: &str, timeout_ms: u64) -> ZResult<Self> {
+        let args: Vec<String> = template.split_whitespace().map(String::from).collect();
+        if args.is_empty() {
+            bail!(
+                "Invalid pre-write hook {:?}: the command is empty",
+                template
+            )
+        }
+        Ok(WriteHook {
+            args,
+            timeout: if timeout_ms > 0 {
+                Some(Duration::from_millis(timeout_ms))
+            } else {
+                None
+            },
+        })
+    }
+
+    // Run the command for a key and the temporary file being written, blocking until it terminates.
+    // Return an error with the command's error output if it fails or times out.
+    pub(crate) fn run(&self, key: &str, file: &Path) -> ZResult<()> {
+        let file = file.to_string_lossy();
+        let args: Vec<String> = self
+            .args
+            .iter()
+            .map(|arg| arg.replace("%k", key).replace("%f", &file))
+            .collect();
+        let mut child = Command::new(&args[0])
+            .args(&args[1..])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| zerror!("Failed to run pre-write hook {:?}: {}", args, e))?;
+
+        // read the error output in a thread, to not block the command if it fills the pipe
+        let mut stderr = child.stderr.take();
+        let stderr_reader = std::thread::spawn(move || {
+            let mut output = String::new();
+            if let Some(stderr) = stderr.as_mut() {
+                let _ = stderr.read_to_string(&mut output);
+            }
+            output
+        });
+
+        let start = Instant::now();
+        let status = loop {
+            match child.try_wait() {
+                Ok(Some(status)) => break status,
+                Ok(None) => {
+                    if let Some(timeout) = self.timeout {
+                        if start.elapsed() >= timeout {
+                            let _ = child.kill();
+                            let _ = child.wait();
+                            bail!(
+                                "Pre-write hook {:?} for {} timed out after {:?}",
+                                args,
+                                key,
+                                timeout
+                            )
+                        }
+                    }
+                    std::thread::sleep(HOOK_POLL_PERIOD);
+                }
+                Err(e) => bail!("Failed to wait for pre-write hook {:?}: {}", args, e),
+            }
+        };
+        let output = stderr_reader.join().unwrap_or_default();
+        if !status.success() {
+            bail!(
+                "Pre-write hook {:?} for {} failed ({}): {}",
+                args,
+                key,
+                status,
+                output.trim()
+            )
+        }
+        Ok(())
+    }
+}